@@ -550,6 +550,10 @@ void microtex_setDefaultMainFont(const char* name);
 bool microtex_hasGlyphPathRender(void);
 void microtex_setRenderGlyphUsePath(bool use);
 bool microtex_isRenderGlyphUsePath(void);
+void microtex_setMathSpacing(float medMuSkip, float thickMuSkip);
+void microtex_setLimitsMode(int mode);
+void microtex_setBoldMath(bool bold);
+void microtex_setOption(const char* key, const char* value);
 
 // Rendering functions
 RenderPtr microtex_parseRender(
@@ -560,9 +564,16 @@ RenderPtr microtex_parseRender(
     unsigned int color,
     bool fillWidth,
     bool enableOverrideTeXStyle,
-    unsigned int texStyle
+    unsigned int texStyle,
+    bool hasBackground,
+    unsigned int backgroundColor
 );
+const char* microtex_last_error(void);
 void microtex_deleteRender(RenderPtr render);
+int microtex_getRenderWidth(RenderPtr render);
+int microtex_getRenderHeight(RenderPtr render);
+int microtex_getRenderDepth(RenderPtr render);
+int microtex_getRenderAxisHeight(RenderPtr render);
 DrawingData microtex_getDrawingData(RenderPtr render);
 void microtex_freeDrawingData(DrawingData data);
 unsigned char* microtex_render_to_svg(RenderPtr render, unsigned long* len);
@@ -915,6 +926,39 @@ mod linker_config {
     }
 }
 
+mod build_info {
+    use std::process::Command;
+
+    /// Record where Cairo and Pango come from, and their versions, for the
+    /// `build_info()` API of the crate
+    pub fn emit() {
+        // Vendoring was removed: dependencies are system libraries, installed
+        // by vcpkg on Windows and Homebrew on macOS
+        let source = match std::env::var("CARGO_CFG_TARGET_OS").as_deref() {
+            Ok("windows") => "vcpkg",
+            Ok("macos") => "homebrew",
+            _ => "pkg-config",
+        };
+        println!("cargo:rustc-env=MICROTEX_DEPENDENCY_SOURCE={}", source);
+
+        for (package, var) in [
+            ("cairo", "MICROTEX_CAIRO_VERSION"),
+            ("pango", "MICROTEX_PANGO_VERSION"),
+        ] {
+            let version = Command::new("pkg-config")
+                .arg("--modversion")
+                .arg(package)
+                .output()
+                .ok()
+                .filter(|output| output.status.success())
+                .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string());
+            // An empty value means unknown
+            println!("cargo:rustc-env={}={}", var, version.unwrap_or_default());
+        }
+        println!("cargo:rerun-if-env-changed=PKG_CONFIG_PATH");
+    }
+}
+
 fn main() {
     // Always watch build.rs itself
     println!("cargo:rerun-if-changed=build.rs");
@@ -1080,5 +1124,8 @@ fn main() {
         std::process::exit(1);
     }
 
+    // Step 6: Record the dependency versions
+    build_info::emit();
+
     println!("cargo:warning=Build script completed successfully!");
 }
@@ -3,6 +3,8 @@
 
 using namespace microtex;
 
+LimitsType Atom::forcedLimitsType = LimitsType::normal;
+
 WrapAtom::WrapAtom(const sptr<Atom>& base) {
     if (base) {
       _type = base->_type;
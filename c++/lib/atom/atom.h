@@ -32,8 +32,22 @@ public:
   /** The alignment type of the atom (default value: none) */
   Alignment _alignment = Alignment::none;
 
+  /**
+   * The limits type forced on all big operators, affects all subsequent layouts.
+   * [LimitsType::normal] (the default) keeps the limits type of each operator.
+   */
+  static LimitsType forcedLimitsType;
+
   Atom() = default;
 
+  /** Get the limits type of this atom, with [forcedLimitsType] applied to big operators */
+  LimitsType limitsType() const {
+    if (forcedLimitsType != LimitsType::normal && leftType() == AtomType::bigOperator) {
+      return forcedLimitsType;
+    }
+    return _limitsType;
+  }
+
   /**
    * Get the type of the leftmost child atom. Most atoms have no child
    * atoms, so the "left type" and the "right type" are the same: the atom's
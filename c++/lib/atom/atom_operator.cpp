@@ -19,8 +19,8 @@ sptr<Box> OperatorAtom::createBox(Env& env) {
 
   // case 2: limits are shown as scripts
   // clang-format off
-  if ((_limitsType == LimitsType::noLimits)
-      || (_limitsType == LimitsType::normal && env.style() >= TexStyle::text)
+  if ((limitsType() == LimitsType::noLimits)
+      || (limitsType() == LimitsType::normal && env.style() >= TexStyle::text)
     ) {
     // clang-format on
     RowAtom* row = nullptr;
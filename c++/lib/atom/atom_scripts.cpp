@@ -22,8 +22,8 @@ sptr<Box> ScriptsAtom::createBox(Env& env) {
 
   // display as limits
   // clang-format off
-  if (_base->_limitsType == LimitsType::limits ||
-      (_base->_limitsType == LimitsType::normal && env.style() == TexStyle::display)
+  if (_base->limitsType() == LimitsType::limits ||
+      (_base->limitsType() == LimitsType::normal && env.style() == TexStyle::display)
     ) {
     // clang-format on
     const auto& over = StackArgs::autoSpace(_sup);
@@ -11,8 +11,8 @@ namespace {
 constexpr int TYPE_COUNT = 8;
 constexpr int STYLE_COUNT = 5;
 
-// contains the different glue types
-const Glue _glueTypes[4]{
+// contains the different glue types, the spaces are adjustable via Glue::setSpace
+Glue _glueTypes[4]{
   {0, 0, 0},
   {3, 0, 0},
   {4, 4, 2},
@@ -60,7 +60,7 @@ float getFactor(const Env& env) {
   return Units::fsize(UnitType::mu, 1.f, env);
 }
 
-const Glue& getGlue(SpaceType skipType) {
+Glue& getGlue(SpaceType skipType) {
   const i8 i = static_cast<i8>(skipType);
   return _glueTypes[i < 0 ? -i : i];
 }
@@ -106,3 +106,7 @@ float Glue::getSpace(SpaceType skipType, const Env& env) {
   const auto v = glue._space * getFactor(env);
   return static_cast<i8>(skipType) < 0 ? -v : v;
 }
+
+void Glue::setSpace(SpaceType skipType, float space) {
+  getGlue(skipType)._space = space;
+}
@@ -13,14 +13,15 @@ class GlueBox;
 class Glue {
 private:
   // the glue components, in "mu" unit
-  u16 _space, _stretch, _shrink;
+  float _space;
+  u16 _stretch, _shrink;
 
   sptr<GlueBox> createBox(const Env& env) const;
 
 public:
   no_copy_assign(Glue);
 
-  Glue(u16 space, u16 stretch, u16 shrink) noexcept
+  Glue(float space, u16 stretch, u16 shrink) noexcept
       : _space(space), _stretch(stretch), _shrink(shrink) {}
 
   /**
@@ -48,6 +49,12 @@ public:
 
   /** Get the space amount from the given skip-type according to the "glue rules" */
   static float getSpace(SpaceType skipType, const Env& env);
+
+  /**
+   * Set the natural width (in "mu" unit) of the given skip-type, affects all
+   * subsequent layouts. Negative skip-types set their positive counterpart.
+   */
+  static void setSpace(SpaceType skipType, float space);
 };
 
 }  // namespace microtex
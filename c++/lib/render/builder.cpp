@@ -9,6 +9,8 @@
 
 using namespace microtex;
 
+bool RenderBuilder::boldMath = false;
+
 RenderBuilder& RenderBuilder::setLineSpace(const Dimen& dimen) {
   if (!dimen.isValid()) {
     throw ex_invalid_state("Cannot set line space without having specified a width!");
@@ -44,6 +46,10 @@ Render* RenderBuilder::build(const sptr<Atom>& fc) {
   if (_lineSpace.isValid()) {
     env.setLineSpace(_lineSpace);
   }
+  if (boldMath) {
+    env.addMathFontStyle(FontStyle::bf);
+    env.addTextFontStyle(FontStyle::bf);
+  }
 
   Render* render;
   auto box = f->createBox(env);
@@ -63,6 +69,7 @@ Render* RenderBuilder::build(const sptr<Atom>& fc) {
     render = new Render(box, _textSize);
   }
 
+  render->setAxisHeight(env.axisHeight());
   if (!isTransparent(_fg)) render->setForeground(_fg);
   return render;
 }
@@ -23,6 +23,12 @@ private:
   std::string _mainFontName;
 
 public:
+  /**
+   * Whether formulas are built in bold (as if wrapped in \boldsymbol), affects all
+   * subsequent builds. Symbols without a bold variant in the math font stay regular.
+   */
+  static bool boldMath;
+
   RenderBuilder() = default;
 
   inline RenderBuilder& setStyle(TexStyle style) {
@@ -23,6 +23,7 @@ struct RenderData {
   float fixedScale;
   color fg;
   bool isSplit;
+  float axisHeight;
 };
 
 static sptr<BoxGroup> wrap(const sptr<Box>& box) {
@@ -66,7 +67,7 @@ buildDebug(const sptr<BoxGroup>& parent, const sptr<Box>& box, const BoxFilter&
 }  // namespace microtex
 
 Render::Render(const sptr<Box>& box, float textSize, bool isSplit) {
-  _data = new RenderData{box, textSize, textSize / Env::fixedTextSize(), black, isSplit};
+  _data = new RenderData{box, textSize, textSize / Env::fixedTextSize(), black, isSplit, 0.f};
   const auto& debugConfig = DebugConfig::INSTANCE;
   if (debugConfig.enable) {
     const auto group = microtex::wrap(box);
@@ -106,6 +107,14 @@ float Render::getBaseline() const {
   return box->_height / box->vlen();
 }
 
+int Render::getAxisHeight() const {
+  return (int)(_data->axisHeight * _data->fixedScale);
+}
+
+void Render::setAxisHeight(float axisHeight) {
+  _data->axisHeight = axisHeight;
+}
+
 bool Render::isSplit() const {
   return _data->isSplit;
 }
@@ -38,6 +38,15 @@ public:
   /** Get the ratio of ascent to total height. */
   float getBaseline() const;
 
+  /**
+   * Get the height of the math axis above the baseline, the line on which
+   * relations, binary operators and fraction bars are centered.
+   */
+  int getAxisHeight() const;
+
+  /** Set the math axis height in MicroTeX units, called by the builder. */
+  void setAxisHeight(float axisHeight);
+
   /** Test if is split to multi-lines. */
   bool isSplit() const;
 
@@ -8,6 +8,9 @@
 #include "box/box.h"
 #include "box/box_single.h"
 #include "box/box_group.h"
+#include "core/glue.h"
+#include "atom/atom.h"
+#include "render/builder.h"
 
 #include <mutex>
 #include <unordered_map>
@@ -26,6 +29,14 @@
 static std::mutex __buf_ref_mutex;
 static std::unordered_map<unsigned char *, int> __buf_refcounts;
 
+// Background colors (ARGB) of the renders that paint one
+static std::mutex __background_mutex;
+static std::unordered_map<void *, unsigned int> __backgrounds;
+
+// Message of the last failed microtex_parseRender, empty after a success
+static std::mutex __error_mutex;
+static std::string __last_error;
+
 using namespace microtex;
 
 #ifdef __cplusplus
@@ -164,6 +175,57 @@ extern "C"
     return MicroTeX::isRenderGlyphUsePath();
   }
 
+  MICROTEX_CAPI void microtex_setMathSpacing(float medMuSkip, float thickMuSkip)
+  {
+    Glue::setSpace(SpaceType::medMuSkip, medMuSkip);
+    Glue::setSpace(SpaceType::thickMuSkip, thickMuSkip);
+  }
+
+  MICROTEX_CAPI void microtex_setLimitsMode(int mode)
+  {
+    switch (mode)
+    {
+    case 1:
+      Atom::forcedLimitsType = LimitsType::limits;
+      break;
+    case 2:
+      Atom::forcedLimitsType = LimitsType::noLimits;
+      break;
+    default:
+      Atom::forcedLimitsType = LimitsType::normal;
+      break;
+    }
+  }
+
+  MICROTEX_CAPI void microtex_setBoldMath(bool bold)
+  {
+    RenderBuilder::boldMath = bold;
+  }
+
+  MICROTEX_CAPI void microtex_setOption(const char *key, const char *value)
+  {
+    if (key == nullptr || value == nullptr) return;
+    const std::string k(key);
+    const std::string v(value);
+    try
+    {
+      if (k == "defaultMainFont")
+        MicroTeX::setDefaultMainFont(v);
+      else if (k == "defaultMathFont")
+        MicroTeX::setDefaultMathFont(v);
+      else if (k == "renderGlyphUsePath")
+        MicroTeX::setRenderGlyphUsePath(v == "true" || v == "1");
+      else if (k == "medMuSkip")
+        Glue::setSpace(SpaceType::medMuSkip, std::stof(v));
+      else if (k == "thickMuSkip")
+        Glue::setSpace(SpaceType::thickMuSkip, std::stof(v));
+    }
+    catch (const std::exception &)
+    {
+      // Unparsable values are ignored, like unknown keys
+    }
+  }
+
   MICROTEX_CAPI RenderPtr microtex_parseRender(
       const char *tex,
       int width,
@@ -172,24 +234,59 @@ extern "C"
       unsigned int color,
       bool fillWidth,
       bool enableOverrideTeXStyle,
-      unsigned int texStyle)
+      unsigned int texStyle,
+      bool hasBackground,
+      unsigned int backgroundColor)
   {
 #ifdef HAVE_LOG
     logv("parse: %s\n", tex);
 #endif
-    auto r = MicroTeX::parse(
-        tex,
-        width,
-        textSize,
-        lineSpace,
-        color,
-        fillWidth,
-        {enableOverrideTeXStyle, static_cast<TexStyle>(texStyle)});
+    Render *r = nullptr;
+    {
+      std::lock_guard<std::mutex> lg(__error_mutex);
+      try
+      {
+        r = MicroTeX::parse(
+            tex,
+            width,
+            textSize,
+            lineSpace,
+            color,
+            fillWidth,
+            {enableOverrideTeXStyle, static_cast<TexStyle>(texStyle)});
+        __last_error.clear();
+      }
+      catch (const std::exception &e)
+      {
+        __last_error = e.what();
+        return nullptr;
+      }
+      catch (...)
+      {
+        __last_error = "unknown exception";
+        return nullptr;
+      }
+    }
+    if (hasBackground)
+    {
+      std::lock_guard<std::mutex> lg(__background_mutex);
+      __backgrounds[r] = backgroundColor;
+    }
     return reinterpret_cast<RenderPtr>(r);
   }
 
+  MICROTEX_CAPI const char *microtex_last_error()
+  {
+    std::lock_guard<std::mutex> lg(__error_mutex);
+    return __last_error.c_str();
+  }
+
   MICROTEX_CAPI void microtex_deleteRender(RenderPtr render)
   {
+    {
+      std::lock_guard<std::mutex> lg(__background_mutex);
+      __backgrounds.erase(render);
+    }
     auto r = reinterpret_cast<Render *>(render);
     delete r;
   }
@@ -231,6 +328,12 @@ extern "C"
     return r->getDepth();
   }
 
+  MICROTEX_CAPI int microtex_getRenderAxisHeight(RenderPtr render)
+  {
+    auto r = reinterpret_cast<Render *>(render);
+    return r->getAxisHeight();
+  }
+
   /// Get the baseline ratio (ascent / total height) of the rendered formula.
   /// This is useful for determining the visual distribution of the formula.
   /// Values close to 1.0 indicate tall formulas (many superscripts).
@@ -271,6 +374,26 @@ extern "C"
     return CAIRO_STATUS_SUCCESS;
   }
 
+  // Paints the canvas with the background color of the render, if it has one
+  static void paint_background(cairo_t *cr, Render *r)
+  {
+    unsigned int argb;
+    {
+      std::lock_guard<std::mutex> lg(__background_mutex);
+      auto it = __backgrounds.find(r);
+      if (it == __backgrounds.end())
+        return;
+      argb = it->second;
+    }
+    cairo_set_source_rgba(
+        cr,
+        ((argb >> 16) & 0xff) / 255.0,
+        ((argb >> 8) & 0xff) / 255.0,
+        (argb & 0xff) / 255.0,
+        ((argb >> 24) & 0xff) / 255.0);
+    cairo_paint(cr);
+  }
+
   MICROTEX_CAPI unsigned char *microtex_render_to_svg(RenderPtr render, unsigned long *out_len)
   {
     auto r = reinterpret_cast<Render *>(render);
@@ -291,6 +414,7 @@ extern "C"
       fprintf(stderr, "microtex_render_to_svg: failed to create cairo context\n");
     }
 
+    paint_background(cr, r);
     microtex::Graphics2D_cairo g2(cr);
     r->draw(g2, 0, 0);
 
@@ -349,7 +473,8 @@ extern "C"
         std::to_string(r->getWidth()) + ",\"height\":" +
         std::to_string(r->getHeight() + r->getDepth()) + ",\"depth\":" +
         std::to_string(r->getDepth()) + ",\"ascent\":" +
-        std::to_string(r->getHeight()) + "}}";
+        std::to_string(r->getHeight()) + ",\"math_axis_height\":" +
+        std::to_string(r->getAxisHeight()) + "}}";
 
     return json_result;
   }
@@ -391,6 +516,7 @@ extern "C"
       return nullptr;
     }
 
+    paint_background(cr, r);
     microtex::Graphics2D_cairo g2(cr);
     r->draw(g2, 0, 0);
 
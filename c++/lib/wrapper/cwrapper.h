@@ -123,11 +123,47 @@ extern "C"
    */
   MICROTEX_CAPI bool microtex_isRenderGlyphUsePath();
 
+  /**
+   * Set the spacing around binary operators (\medmuskip) and relations
+   * (\thickmuskip), in "mu" unit (1/18 of the math quad). TeX uses 4mu and 5mu.
+   * Affects all subsequent parses.
+   */
+  MICROTEX_CAPI void microtex_setMathSpacing(float medMuSkip, float thickMuSkip);
+
+  /**
+   * Force the placement of the limits of big operators (\sum, \int, ...):
+   * 0 keeps the placement of each operator (TeX's default), 1 puts limits
+   * above and below (\limits), 2 puts them beside as scripts (\nolimits).
+   * Affects all subsequent parses.
+   */
+  MICROTEX_CAPI void microtex_setLimitsMode(int mode);
+
+  /**
+   * Set whether formulas are rendered in bold, as if wrapped in \boldsymbol.
+   * Letters and digits use the bold variants of the math font; symbols
+   * without one stay regular. Affects all subsequent parses.
+   */
+  MICROTEX_CAPI void microtex_setBoldMath(bool bold);
+
+  /**
+   * Set a global option by name, affects all subsequent parses. Recognized keys:
+   * "defaultMainFont", "defaultMathFont" (font names), "renderGlyphUsePath"
+   * ("true"/"false"), "medMuSkip" and "thickMuSkip" (numbers, in "mu" unit).
+   * Unknown keys and unparsable values are silently ignored.
+   */
+  MICROTEX_CAPI void microtex_setOption(const char *key, const char *value);
+
   /**
    * Parse a (La)TeX string (in UTF-8 encoding) to Render. You must
    * call [microtex_deleteRender] after it has no usages.
    *
    * See [lib/microtex.h: MicroTeX::parse] for details.
+   *
+   * If hasBackground is true, [microtex_render_to_svg] and
+   * [microtex_render_to_svg_with_metrics] paint the whole canvas with
+   * backgroundColor (ARGB) before drawing the formula.
+   *
+   * Returns null if the source fails to parse, see [microtex_last_error].
    */
   MICROTEX_CAPI RenderPtr microtex_parseRender(
       const char *tex,
@@ -137,7 +173,16 @@ extern "C"
       unsigned int color,
       bool fillWidth,
       bool enableOverrideTeXStyle,
-      unsigned int texStyle);
+      unsigned int texStyle,
+      bool hasBackground,
+      unsigned int backgroundColor);
+
+  /**
+   * Get the message of the last failed [microtex_parseRender], or an empty
+   * string if the last parse succeeded. The string is owned by the library
+   * and stays valid until the next call to [microtex_parseRender].
+   */
+  MICROTEX_CAPI const char *microtex_last_error();
 
   /** Delete the render created by [microtex_parseRender] before. */
   MICROTEX_CAPI void microtex_deleteRender(RenderPtr render);
@@ -286,6 +331,9 @@ extern "C"
   /** Get the render depth. */
   MICROTEX_CAPI int microtex_getRenderDepth(RenderPtr render);
 
+  /** Get the height of the math axis above the baseline of the render. */
+  MICROTEX_CAPI int microtex_getRenderAxisHeight(RenderPtr render);
+
   /**
    * Get the baseline ratio of the rendered formula.
    *
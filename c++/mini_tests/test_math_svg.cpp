@@ -49,7 +49,7 @@ int main() {
 )LATEX";
 
   // Parse and render
-  RenderPtr r = microtex_parseRender(src, 720, 20.0f, 20.0f / 3.0f, 0xff000000u, false, false, 0);
+  RenderPtr r = microtex_parseRender(src, 720, 20.0f, 20.0f / 3.0f, 0xff000000u, false, false, 0, false, 0);
   if (!r) {
     fprintf(stderr, "microtex_parseRender failed\n");
     microtex_releaseFontMeta(meta);
@@ -0,0 +1,90 @@
+#include <cstdio>
+#include <fstream>
+#include <string>
+#include <vector>
+
+// Renders a formula with the delimiters mirrored the way
+// RenderConfig::mirror_delimiters rewrites them on the Rust side, and checks
+// that MicroTeX accepts the swapped pairs and lays them out like the originals.
+//
+// build like test_math_svg.cpp:
+// g++ -DHAVE_CWRAPPER ./test_mirrored_delimiters.cpp ../build/lib/libmicrotex.a
+// -o ./test_mirrored_delimiters -I../lib -I../build/lib $(pkg-config --cflags --libs cairo
+// pangocairo pango fontconfig freetype2 harfbuzz)
+#include "wrapper/cwrapper.h"
+
+static RenderPtr parse(const char* src) {
+  return microtex_parseRender(src, 720, 20.0f, 20.0f / 3.0f, 0xff000000u, false, false, 0, false, 0);
+}
+
+int main() {
+  const char* clm_path = "../res/xits/XITSMath-Regular.clm2";
+  std::ifstream f(clm_path, std::ios::binary);
+  if (!f) {
+    fprintf(stderr, "failed to open clm file: %s\n", clm_path);
+    return 1;
+  }
+  std::vector<unsigned char> clm_data(
+    (std::istreambuf_iterator<char>(f)),
+    std::istreambuf_iterator<char>()
+  );
+
+  FontMetaPtr meta = microtex_init(clm_data.size(), clm_data.data());
+  if (!meta) {
+    fprintf(stderr, "microtex_init failed\n");
+    return 2;
+  }
+  microtex_setRenderGlyphUsePath(true);
+
+  const char* original = R"LATEX(\[\left( a + \left[ b \right] \right) \langle u \rangle\])LATEX";
+  const char* mirrored = R"LATEX(\[\left) a + \left] b \right[ \right( \rangle u \langle\])LATEX";
+
+  RenderPtr r1 = parse(original);
+  RenderPtr r2 = parse(mirrored);
+  if (!r1 || !r2) {
+    fprintf(stderr, "microtex_parseRender failed\n");
+    microtex_releaseFontMeta(meta);
+    microtex_release();
+    return 3;
+  }
+
+  int status = 0;
+  // Mirrored pairs have the same metrics, so the layout must not change
+  if (microtex_getRenderWidth(r1) != microtex_getRenderWidth(r2) ||
+      microtex_getRenderHeight(r1) != microtex_getRenderHeight(r2) ||
+      microtex_getRenderDepth(r1) != microtex_getRenderDepth(r2)) {
+    fprintf(
+      stderr,
+      "mirrored layout differs: %dx%d+%d vs %dx%d+%d\n",
+      microtex_getRenderWidth(r1),
+      microtex_getRenderHeight(r1),
+      microtex_getRenderDepth(r1),
+      microtex_getRenderWidth(r2),
+      microtex_getRenderHeight(r2),
+      microtex_getRenderDepth(r2)
+    );
+    status = 4;
+  }
+
+  unsigned long out_len = 0;
+  unsigned char* out_buf = microtex_render_to_svg(r2, &out_len);
+  if (!out_buf || out_len == 0) {
+    fprintf(stderr, "microtex_render_to_svg returned empty buffer\n");
+    status = 5;
+  } else {
+    FILE* out = fopen("./out_mirrored.svg", "wb");
+    if (out) {
+      fwrite(out_buf, 1, out_len, out);
+      fclose(out);
+      fprintf(stderr, "Wrote ./out_mirrored.svg (%lu bytes)\n", out_len);
+    }
+    microtex_free_buffer(out_buf);
+  }
+
+  microtex_deleteRender(r1);
+  microtex_deleteRender(r2);
+  microtex_releaseFontMeta(meta);
+  microtex_release();
+
+  return status;
+}
@@ -1,5 +1,5 @@
 /// Example demonstrating batch rendering with different configurations.
-use microtex_rs::{MicroTex, RenderConfig};
+use microtex_rs::{Color, MicroTex, RenderConfig};
 use std::fs;
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -22,7 +22,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 dpi: 96,
                 line_width: 12.0,
                 line_height: 12.0 / 3.0,
-                text_color: 0xff000000,
+                text_color: Color::BLACK,
                 render_glyph_use_path: false,
                 ..Default::default()
             },
@@ -33,7 +33,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 dpi: 300,
                 line_width: 20.0,
                 line_height: 20.0 / 3.0,
-                text_color: 0xff000000,
+                text_color: Color::BLACK,
                 render_glyph_use_path: true,
                 ..Default::default()
             },
@@ -44,7 +44,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 dpi: 1440,
                 line_width: 40.0,
                 line_height: 40.0 / 3.0,
-                text_color: 0xff000000,
+                text_color: Color::BLACK,
                 render_glyph_use_path: true,
                 ..Default::default()
             },
@@ -55,7 +55,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 dpi: 720,
                 line_width: 20.0,
                 line_height: 20.0 / 3.0,
-                text_color: 0xffffffff, // white text
+                text_color: Color::WHITE, // white text
                 render_glyph_use_path: true,
                 ..Default::default()
             },
@@ -0,0 +1,78 @@
+/// Compares the throughput of `render_to_svg_with_metrics` and
+/// `render_measured_borrowed` on the same formulas.
+///
+/// Run in release mode for meaningful numbers:
+///
+/// ```text
+/// cargo run --release --example borrowed_render
+/// ```
+use microtex_rs::{MicroTex, RenderConfig};
+use std::time::{Duration, Instant};
+
+const ITERATIONS: u32 = 200;
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let renderer = MicroTex::new()?;
+    let config = RenderConfig::default();
+
+    let formulas = [
+        r#"\[E = mc^2\]"#,
+        r#"\[x = \frac{-b \pm \sqrt{b^2 - 4ac}}{2a}\]"#,
+        r#"\[\sum_{i=1}^{n} i = \frac{n(n+1)}{2}\]"#,
+        r#"\[\int_0^\infty e^{-x^2} dx = \frac{\sqrt{\pi}}{2}\]"#,
+    ];
+
+    // Warm up the font caches
+    for latex in &formulas {
+        renderer.render_to_svg_with_metrics(latex, &config)?;
+        renderer.render_measured_borrowed(latex, &config)?;
+    }
+
+    let mut owned_bytes = 0;
+    let owned = time(|| {
+        for latex in &formulas {
+            let result = renderer.render_to_svg_with_metrics(latex, &config)?;
+            owned_bytes += result.svg.len();
+        }
+        Ok(())
+    })?;
+
+    let mut borrowed_bytes = 0;
+    let borrowed = time(|| {
+        for latex in &formulas {
+            let svg = renderer.render_measured_borrowed(latex, &config)?;
+            borrowed_bytes += svg.len();
+        }
+        Ok(())
+    })?;
+
+    let renders = ITERATIONS * formulas.len() as u32;
+    println!("{} renders of {} formulas", renders, formulas.len());
+    println!(
+        "render_to_svg_with_metrics: {:?} per render, {} SVG bytes",
+        owned / renders,
+        owned_bytes
+    );
+    println!(
+        "render_measured_borrowed:   {:?} per render, {} SVG bytes",
+        borrowed / renders,
+        borrowed_bytes
+    );
+    println!(
+        "speedup: {:.2}x",
+        owned.as_secs_f64() / borrowed.as_secs_f64()
+    );
+
+    Ok(())
+}
+
+/// Runs `f` `ITERATIONS` times and returns the total elapsed time.
+fn time(
+    mut f: impl FnMut() -> Result<(), microtex_rs::RenderError>,
+) -> Result<Duration, microtex_rs::RenderError> {
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        f()?;
+    }
+    Ok(start.elapsed())
+}
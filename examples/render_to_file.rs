@@ -1,5 +1,5 @@
 /// Example demonstrating how to render LaTeX formulas to SVG files.
-use microtex_rs::{MicroTex, RenderConfig};
+use microtex_rs::{Color, MicroTex, RenderConfig};
 use std::fs;
 use std::path::Path;
 
@@ -49,7 +49,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         dpi: 720,
         line_width: 20.0,
         line_height: 20.0 / 3.0,
-        text_color: 0xff000000,
+        text_color: Color::BLACK,
         render_glyph_use_path: true,
         ..Default::default()
     };
@@ -1,87 +1,402 @@
 /// Command-line interface for MicroTeX LaTeX to SVG conversion.
 ///
-/// This simple CLI allows converting LaTeX formulas to SVG files.
+/// This simple CLI allows converting LaTeX formulas to SVG files, or PNG
+/// files with `--format png` when built with the `png` feature. The formula
+/// is read from stdin when it is omitted or given as `-`. With `--input-file`,
+/// each `name<TAB>latex` line of the file is rendered to `<name>.svg` (or
+/// `.png`) in `--output-dir`.
+///
+/// Rendering settings come from, in order of precedence: command-line flags,
+/// the JSON file given with `--config`, then the built-in defaults.
 use clap::{Parser, ValueEnum};
-use microtex_rs::{MicroTex, RenderConfig};
+use microtex_rs::{
+    available_embedded_clms, wrap_in_delimiters, LineEnding, MicroTex, OutputFormat, RenderConfig,
+};
+use serde::Deserialize;
 use std::fs;
-use std::path::PathBuf;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
 
 #[derive(Parser, Debug)]
 #[command(name = "microtex")]
 #[command(about = "Convert LaTeX formulas to SVG", long_about = None)]
 struct Args {
-    /// LaTeX formula to render
+    /// LaTeX formula to render; read from stdin if omitted or `-`
     #[arg(value_name = "LATEX")]
-    formula: String,
+    formula: Option<String>,
 
-    /// Output SVG file path
+    /// Output file path [default: output.svg or output.png]
     #[arg(short, long, value_name = "FILE")]
     output: Option<PathBuf>,
 
-    /// DPI (dots per inch) for rendering
-    #[arg(short, long, default_value = "720")]
-    dpi: i32,
+    /// Embedded math font to render with, as listed by --list-fonts
+    /// [default: XITSMath-Regular.clm2 if available]
+    #[arg(long, value_name = "NAME")]
+    font: Option<String>,
+
+    /// Print the embedded fonts usable with --font and exit
+    #[arg(long)]
+    list_fonts: bool,
 
-    /// Line width in pixels
-    #[arg(long, default_value = "20.0")]
-    line_width: f32,
+    /// Output format; png rasterizes at --dpi and needs the `png` feature
+    #[arg(long, value_enum, default_value = "svg")]
+    format: Format,
 
-    /// Line height in pixels
-    #[arg(long, default_value = "6.666667")]
-    line_height: f32,
+    /// Render each `name<TAB>latex` line of FILE to `<name>.svg` (or `.png`)
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["formula", "output", "stdout"])]
+    input_file: Option<PathBuf>,
 
-    /// Text color (ARGB hex, e.g., 0xff000000 for black)
-    #[arg(long, default_value = "0xff000000")]
-    color: String,
+    /// Directory receiving the `--input-file` output files [default: .]
+    #[arg(long, value_name = "DIR", requires = "input_file")]
+    output_dir: Option<PathBuf>,
 
-    /// Enable path-based glyph rendering
-    #[arg(long, default_value = "true")]
-    use_path: bool,
+    /// JSON file with rendering settings, overridden by the flags below
+    #[arg(long, value_name = "FILE")]
+    config: Option<PathBuf>,
 
-    /// Print SVG to stdout instead of file
+    /// DPI (dots per inch) for rendering [default: 720]
+    #[arg(short, long)]
+    dpi: Option<i32>,
+
+    /// Line width in pixels [default: 20.0]
+    #[arg(long)]
+    line_width: Option<f32>,
+
+    /// Line height in pixels [default: 6.666667]
+    #[arg(long)]
+    line_height: Option<f32>,
+
+    /// Text color: ARGB hex (0xff000000), #RGB, #RRGGBB, #AARRGGBB or a
+    /// name (black, white, red, green, blue) [default: 0xff000000]
+    #[arg(long)]
+    color: Option<String>,
+
+    /// Enable path-based glyph rendering [default: true]
+    #[arg(long, num_args = 0..=1, default_missing_value = "true")]
+    use_path: Option<bool>,
+
+    /// Print the output to stdout instead of a file
     #[arg(short, long)]
     stdout: bool,
+
+    /// Trim leading and trailing whitespace from the formula
+    #[arg(long)]
+    trim: bool,
+
+    /// Wrap the formula in math delimiters before rendering
+    #[arg(long, value_enum, default_value = "none")]
+    wrap: WrapMode,
+
+    /// Write the SVG file with CRLF (Windows) line endings [default: false]
+    #[arg(long, num_args = 0..=1, default_missing_value = "true")]
+    crlf: Option<bool>,
+}
+
+/// Rendering settings read from a `--config` file.
+///
+/// Keys are named after the matching flags, all are optional, and a flag
+/// given on the command line wins over its key:
+///
+/// ```json
+/// { "dpi": 1440, "line_width": 30.0, "color": "0xff202020", "crlf": true }
+/// ```
+#[derive(Deserialize, Debug, Default, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+struct ConfigFile {
+    dpi: Option<i32>,
+    line_width: Option<f32>,
+    line_height: Option<f32>,
+    color: Option<String>,
+    use_path: Option<bool>,
+    crlf: Option<bool>,
+}
+
+impl ConfigFile {
+    /// Load the settings from a JSON file.
+    fn load(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| format!("Cannot read config {}: {}", path.display(), e))?;
+        serde_json::from_str(&text)
+            .map_err(|e| format!("Invalid config {}: {}", path.display(), e).into())
+    }
+}
+
+/// Delimiters to wrap a bare formula in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum WrapMode {
+    /// Use the formula verbatim
+    None,
+    /// Inline math: `$...$`
+    Inline,
+    /// Display math: `\[...\]`
+    Display,
+}
+
+/// The embedded font names accepted by `--font`, one per line.
+fn font_list() -> String {
+    available_embedded_clms()
+        .iter()
+        .map(|name| format!("{}\n", name))
+        .collect()
+}
+
+/// Create the renderer, with the `--font` font if given.
+fn create_renderer(args: &Args) -> Result<MicroTex, Box<dyn std::error::Error>> {
+    match &args.font {
+        None => Ok(MicroTex::new()?),
+        Some(font) if available_embedded_clms().contains(&font.as_str()) => {
+            Ok(MicroTex::with_font(font)?)
+        }
+        Some(font) => Err(format!(
+            "Unknown font {}; available fonts: {}",
+            font,
+            available_embedded_clms().join(", ")
+        )
+        .into()),
+    }
+}
+
+/// Output file format.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum Format {
+    /// SVG, as rendered by MicroTeX
+    Svg,
+    /// PNG, rasterized from the SVG at `--dpi`
+    Png,
+}
+
+impl Format {
+    /// The library format this output format corresponds to.
+    fn output_format(self) -> OutputFormat {
+        match self {
+            Format::Svg => OutputFormat::Svg,
+            Format::Png => OutputFormat::Png,
+        }
+    }
+}
+
+/// Render a formula to PNG bytes at `config.dpi`.
+type PngRenderer =
+    fn(&MicroTex, &str, &RenderConfig) -> Result<Vec<u8>, Box<dyn std::error::Error>>;
+
+/// Render a formula to PNG with the library rasterizer, or fail if the crate
+/// was built without the `png` feature.
+fn render_png(
+    renderer: &MicroTex,
+    formula: &str,
+    config: &RenderConfig,
+) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    #[cfg(feature = "png")]
+    {
+        Ok(renderer.render_to_png(formula, config)?)
+    }
+    #[cfg(not(feature = "png"))]
+    {
+        let _ = (renderer, formula, config);
+        Err("PNG output is not available: microtex was built without the `png` feature".into())
+    }
+}
+
+/// Render `formula` in `format`, returning the bytes to write.
+fn render_output(
+    renderer: &MicroTex,
+    formula: &str,
+    config: &RenderConfig,
+    format: Format,
+    png: PngRenderer,
+) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    match format {
+        Format::Svg => {
+            let svg = renderer.render(formula, config)?;
+            Ok(config.line_ending.apply(&svg).into_bytes())
+        }
+        Format::Png => png(renderer, formula, config),
+    }
+}
+
+/// Return the formula argument, or the whole of `stdin` when it is omitted
+/// or `-`, without the final line ending left by `echo` and editors.
+fn read_formula(args: &Args, mut stdin: impl Read) -> std::io::Result<String> {
+    match args.formula.as_deref() {
+        Some(formula) if formula != "-" => Ok(formula.to_string()),
+        _ => {
+            let mut formula = String::new();
+            stdin.read_to_string(&mut formula)?;
+            let len = formula.trim_end_matches(['\r', '\n']).len();
+            formula.truncate(len);
+            Ok(formula)
+        }
+    }
 }
 
+/// Apply the `--trim` and `--wrap` options to the formula.
+fn prepare_formula(args: &Args, formula: &str) -> String {
+    let formula = if args.trim { formula.trim() } else { formula };
+
+    match args.wrap {
+        WrapMode::None => formula.to_string(),
+        WrapMode::Inline => wrap_in_delimiters(formula, false),
+        WrapMode::Display => wrap_in_delimiters(formula, true),
+    }
+}
+
+/// Parse a color into ARGB. `#RGB`, `#RRGGBB` and named colors are opaque;
+/// `#AARRGGBB`, `0x`-prefixed and bare hex values give the alpha channel first.
 fn parse_color(s: &str) -> Result<u32, String> {
+    let named = match s.to_ascii_lowercase().as_str() {
+        "black" => Some(0xff000000),
+        "white" => Some(0xffffffff),
+        "red" => Some(0xffff0000),
+        "green" => Some(0xff008000),
+        "blue" => Some(0xff0000ff),
+        _ => None,
+    };
+    if let Some(argb) = named {
+        return Ok(argb);
+    }
+
+    let invalid = |e: std::num::ParseIntError| format!("Invalid color: {}", e);
+    if let Some(hex) = s.strip_prefix('#') {
+        return match hex.len() {
+            3 => {
+                // #RGB repeats each digit: #f80 is #ff8800
+                let rgb = u32::from_str_radix(hex, 16).map_err(invalid)?;
+                let (r, g, b) = ((rgb >> 8) & 0xf, (rgb >> 4) & 0xf, rgb & 0xf);
+                Ok(0xff000000 | ((r * 0x11) << 16) | ((g * 0x11) << 8) | (b * 0x11))
+            }
+            6 => Ok(0xff000000 | u32::from_str_radix(hex, 16).map_err(invalid)?),
+            8 => u32::from_str_radix(hex, 16).map_err(invalid),
+            _ => Err(format!(
+                "Invalid color: '{}' must be #RGB, #RRGGBB or #AARRGGBB",
+                s
+            )),
+        };
+    }
+
     let s = s.trim_start_matches("0x");
-    u32::from_str_radix(s, 16).map_err(|e| format!("Invalid color: {}", e))
+    u32::from_str_radix(s, 16).map_err(invalid)
 }
 
-/// Run the CLI logic given parsed `Args`. Returns the rendered SVG string on success.
-fn run_with_args(args: &Args) -> Result<String, Box<dyn std::error::Error>> {
-    // Parse color
-    let text_color = parse_color(&args.color)?;
+/// Build the rendering configuration from the flags, the `--config` file
+/// and the defaults, in that order of precedence.
+fn build_config(args: &Args) -> Result<RenderConfig, Box<dyn std::error::Error>> {
+    let file = match &args.config {
+        Some(path) => ConfigFile::load(path)?,
+        None => ConfigFile::default(),
+    };
+    let defaults = RenderConfig::default();
 
-    // Create renderer
-    let renderer = MicroTex::new()?;
+    let text_color = match args.color.as_ref().or(file.color.as_ref()) {
+        Some(color) => parse_color(color)?.into(),
+        None => defaults.text_color,
+    };
+    let crlf = args.crlf.or(file.crlf).unwrap_or(false);
 
-    // Create config
-    let config = RenderConfig {
-        dpi: args.dpi,
-        line_width: args.line_width,
-        line_height: args.line_height,
+    Ok(RenderConfig {
+        dpi: args.dpi.or(file.dpi).unwrap_or(defaults.dpi),
+        line_width: args
+            .line_width
+            .or(file.line_width)
+            .unwrap_or(defaults.line_width),
+        line_height: args
+            .line_height
+            .or(file.line_height)
+            .unwrap_or(defaults.line_height),
         text_color,
-        render_glyph_use_path: args.use_path,
-        ..Default::default()
-    };
+        render_glyph_use_path: args.use_path.or(file.use_path).unwrap_or(true),
+        line_ending: if crlf {
+            LineEnding::CrLf
+        } else {
+            LineEnding::Lf
+        },
+        ..defaults
+    })
+}
+
+/// Run the CLI logic given parsed `Args`, reading the formula from `stdin`
+/// if needed. Returns the rendered SVG or PNG bytes on success.
+fn run_with_args(args: &Args, stdin: impl Read) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    run_with_png_renderer(args, stdin, render_png)
+}
+
+/// [`run_with_args`] with the PNG rasterizer to use for `--format png`.
+fn run_with_png_renderer(
+    args: &Args,
+    stdin: impl Read,
+    png: PngRenderer,
+) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    // Create config
+    let config = build_config(args)?;
+    let formula = read_formula(args, stdin)?;
+
+    // Create renderer
+    let renderer = create_renderer(args)?;
 
     // Render
-    let svg = renderer.render(&args.formula, &config)?;
+    let output = render_output(
+        &renderer,
+        &prepare_formula(args, &formula),
+        &config,
+        args.format,
+        png,
+    )?;
 
     // Output
-    if args.stdout {
-        // When stdout is requested, just return the svg string
-        Ok(svg)
-    } else {
-        let output_path = args
-            .output
-            .clone()
-            .unwrap_or_else(|| PathBuf::from("output.svg"));
+    if !args.stdout {
+        let output_path = args.output.clone().unwrap_or_else(|| {
+            PathBuf::from("output").with_extension(args.format.output_format().extension())
+        });
 
-        fs::write(&output_path, &svg)?;
-        Ok(svg)
+        fs::write(&output_path, &output)?;
     }
+    Ok(output)
+}
+
+/// The `(line number, written path or error)` of each `--input-file` line.
+type BatchResults = Vec<(usize, Result<PathBuf, String>)>;
+
+/// Render each `name<TAB>latex` line of `input` to `<out_dir>/<name>.svg`,
+/// applying `--trim` and `--wrap` to every formula.
+///
+/// Blank lines are skipped. Returns one `(line number, result)` pair per
+/// other line, holding the written path or why the line failed. Fails as a
+/// whole only if the renderer or `out_dir` cannot be created.
+fn run_batch(
+    args: &Args,
+    config: &RenderConfig,
+    input: &str,
+    out_dir: &Path,
+) -> Result<BatchResults, Box<dyn std::error::Error>> {
+    fs::create_dir_all(out_dir)?;
+    let renderer = create_renderer(args)?;
+
+    let results = input
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(index, line)| {
+            let result = line
+                .split_once('\t')
+                .ok_or_else(|| "expected `name<TAB>latex`".to_string())
+                .and_then(|(name, latex)| {
+                    // Names become file names inside `out_dir`, never paths
+                    let name = name.trim();
+                    if name.is_empty() || name == ".." || name.contains(['/', '\\']) {
+                        return Err(format!("invalid name {:?}", name));
+                    }
+                    let formula = prepare_formula(args, latex);
+                    let output =
+                        render_output(&renderer, &formula, config, args.format, render_png)
+                            .map_err(|e| e.to_string())?;
+                    let extension = args.format.output_format().extension();
+                    let path = out_dir.join(format!("{}.{}", name, extension));
+                    fs::write(&path, output).map_err(|e| e.to_string())?;
+                    Ok(path)
+                });
+            (index + 1, result)
+        })
+        .collect();
+    Ok(results)
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -92,14 +407,49 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     let args = Args::parse();
 
+    if args.list_fonts {
+        print!("{}", font_list());
+        return Ok(());
+    }
+
     eprintln!("Initializing MicroTeX renderer...");
 
-    let svg = run_with_args(&args)?;
+    if let Some(input_file) = &args.input_file {
+        let config = build_config(&args)?;
+        let input = fs::read_to_string(input_file)?;
+        let out_dir = args
+            .output_dir
+            .clone()
+            .unwrap_or_else(|| PathBuf::from("."));
+        let results = run_batch(&args, &config, &input, &out_dir)?;
+
+        let mut failed = 0;
+        for (line, result) in &results {
+            match result {
+                Ok(path) => eprintln!("✓ {}", path.display()),
+                Err(e) => {
+                    failed += 1;
+                    eprintln!("✗ line {}: {}", line, e);
+                }
+            }
+        }
+        eprintln!("{} rendered, {} failed", results.len() - failed, failed);
+        if failed > 0 {
+            return Err(format!("{} of {} formulas failed", failed, results.len()).into());
+        }
+        return Ok(());
+    }
+
+    let output = run_with_args(&args, std::io::stdin().lock())?;
 
-    eprintln!("✓ Rendering successful! ({} bytes)", svg.len());
+    eprintln!("✓ Rendering successful! ({} bytes)", output.len());
 
     if args.stdout {
-        println!("{}", svg);
+        let mut stdout = std::io::stdout().lock();
+        stdout.write_all(&output)?;
+        if args.format == Format::Svg {
+            writeln!(stdout)?;
+        }
     } else if let Some(output) = args.output {
         eprintln!("✓ Saved to: {}", output.display());
     }
@@ -118,9 +468,23 @@ mod tests {
         assert_eq!(parse_color("ff000000").unwrap(), 0xff000000);
     }
 
+    #[test]
+    fn test_parse_color_css() {
+        assert_eq!(parse_color("#fff").unwrap(), 0xffffffff);
+        assert_eq!(parse_color("#f80").unwrap(), 0xffff8800);
+        assert_eq!(parse_color("#112233").unwrap(), 0xff112233);
+        assert_eq!(parse_color("#80112233").unwrap(), 0x80112233);
+        assert_eq!(parse_color("red").unwrap(), 0xffff0000);
+        assert_eq!(parse_color("White").unwrap(), 0xffffffff);
+    }
+
     #[test]
     fn test_parse_color_err() {
         assert!(parse_color("zzzz").is_err());
+        assert!(parse_color("purple").is_err());
+        assert!(parse_color("#ffff").is_err());
+        assert!(parse_color("#ggg").is_err());
+        assert!(parse_color("#").is_err());
     }
 
     #[test]
@@ -132,17 +496,261 @@ mod tests {
         tc::set_buffer(b"<svg>cli</svg>");
 
         let args = Args {
-            formula: "x".to_string(),
+            formula: Some("x".to_string()),
             output: None,
-            dpi: 720,
-            line_width: 20.0,
-            line_height: 20.0 / 3.0,
-            color: "0xff000000".to_string(),
-            use_path: true,
+            font: None,
+            list_fonts: false,
+            format: Format::Svg,
+            input_file: None,
+            output_dir: None,
+            config: None,
+            dpi: Some(720),
+            line_width: Some(20.0),
+            line_height: Some(20.0 / 3.0),
+            color: Some("0xff000000".to_string()),
+            use_path: Some(true),
             stdout: true,
+            trim: false,
+            wrap: WrapMode::None,
+            crlf: None,
         };
 
-        let svg = run_with_args(&args).expect("run should succeed");
-        assert!(svg.contains("<svg"));
+        let svg = run_with_args(&args, std::io::empty()).expect("run should succeed");
+        assert!(String::from_utf8_lossy(&svg).contains("<svg"));
+    }
+
+    /// Stands in for the resvg rasterizer, which the shim cannot drive.
+    fn stub_png(
+        renderer: &MicroTex,
+        formula: &str,
+        config: &RenderConfig,
+    ) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let svg = renderer.render(formula, config)?;
+        let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+        png.extend_from_slice(format!("{} dpi {} bytes", config.dpi, svg.len()).as_bytes());
+        Ok(png)
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_run_with_args_png() {
+        let _g = tc::lock_test();
+        tc::set_init_succeed(true);
+        tc::set_parse_succeed(true);
+        tc::set_return_empty(false);
+        tc::set_buffer(b"<svg>png</svg>");
+
+        let path = std::env::temp_dir().join(format!("microtex-cli-{}.png", std::process::id()));
+        let output_arg = path.to_str().unwrap();
+        let args = Args::parse_from([
+            "microtex", "--format", "png", "--dpi", "300", "-o", output_arg, "x",
+        ]);
+        let png =
+            run_with_png_renderer(&args, std::io::empty(), stub_png).expect("run should succeed");
+        let written = fs::read(&path).expect("PNG should be written");
+        fs::remove_file(&path).ok();
+
+        assert!(png.starts_with(b"\x89PNG"));
+        assert!(String::from_utf8_lossy(&png).contains("300 dpi"));
+        assert_eq!(written, png);
+
+        if !cfg!(feature = "png") {
+            let err = run_with_args(&args, std::io::empty()).expect_err("png is disabled");
+            assert!(err.to_string().contains("`png` feature"));
+        }
+    }
+
+    #[test]
+    fn test_run_with_args_stdin() {
+        let _g = tc::lock_test();
+        tc::set_init_succeed(true);
+        tc::set_parse_succeed(true);
+        tc::set_return_empty(false);
+        tc::set_buffer(b"<svg>stdin</svg>");
+
+        let args = Args::parse_from(["microtex", "--stdout"]);
+        assert_eq!(args.formula, None);
+        let svg = run_with_args(&args, "\\frac{a}{b}\n".as_bytes()).expect("run should succeed");
+        assert!(String::from_utf8_lossy(&svg).contains("<svg"));
+
+        let args = Args::parse_from(["microtex", "--stdout", "-"]);
+        let svg = run_with_args(&args, "x".as_bytes()).expect("run should succeed");
+        assert!(String::from_utf8_lossy(&svg).contains("<svg"));
+    }
+
+    #[test]
+    fn test_run_batch() {
+        let _g = tc::lock_test();
+        tc::set_init_succeed(true);
+        tc::set_parse_succeed(true);
+        tc::set_return_empty(false);
+        tc::set_buffer(b"<svg>batch</svg>");
+
+        let out_dir =
+            std::env::temp_dir().join(format!("microtex-cli-batch-{}", std::process::id()));
+        let args = Args::parse_from(["microtex", "--input-file", "formulas.tsv", "--trim"]);
+        let config = build_config(&args).expect("config should load");
+        let input = "euler\te^{i\\pi} + 1 = 0\n\nsum\t\\sum_{i=1}^n i\nno tab\n../up\tx\n";
+
+        let results = run_batch(&args, &config, input, &out_dir).expect("batch should run");
+        let written = fs::read_dir(&out_dir)
+            .map(|entries| entries.count())
+            .unwrap_or(0);
+        fs::remove_dir_all(&out_dir).ok();
+
+        let lines: Vec<usize> = results.iter().map(|(line, _)| *line).collect();
+        assert_eq!(lines, [1, 3, 4, 5]);
+        assert_eq!(results[0].1.as_ref().unwrap(), &out_dir.join("euler.svg"));
+        assert_eq!(results[1].1.as_ref().unwrap(), &out_dir.join("sum.svg"));
+        assert!(results[2].1.is_err());
+        assert!(results[3].1.is_err());
+        assert_eq!(written, 2);
+    }
+
+    #[test]
+    fn test_font_list() {
+        let args = Args::parse_from(["microtex", "--list-fonts"]);
+        assert!(args.list_fonts);
+        assert_eq!(args.formula, None);
+
+        let list = font_list();
+        let names: Vec<&str> = list.lines().collect();
+        assert_eq!(names, available_embedded_clms());
+    }
+
+    #[test]
+    fn test_run_with_args_unknown_font() {
+        let _g = tc::lock_test();
+        tc::set_init_succeed(true);
+
+        let args = Args::parse_from(["microtex", "--stdout", "--font", "NoSuchFont.clm2", "x"]);
+        let err = run_with_args(&args, std::io::empty()).expect_err("unknown font should fail");
+        let message = err.to_string();
+        assert!(message.contains("NoSuchFont.clm2"));
+        for name in available_embedded_clms() {
+            assert!(message.contains(name), "{} missing from {}", name, message);
+        }
+    }
+
+    #[test]
+    fn test_run_with_args_font() {
+        let _g = tc::lock_test();
+        tc::set_init_succeed(true);
+        tc::set_parse_succeed(true);
+        tc::set_return_empty(false);
+        tc::set_buffer(b"<svg>font</svg>");
+
+        let font = available_embedded_clms()[0];
+        let args = Args::parse_from(["microtex", "--stdout", "--font", font, "x"]);
+        let svg = run_with_args(&args, std::io::empty()).expect("run should succeed");
+        assert!(String::from_utf8_lossy(&svg).contains("<svg"));
+    }
+
+    #[test]
+    fn test_format_flag_parsing() {
+        let args = Args::parse_from(["microtex", "x"]);
+        assert_eq!(args.format, Format::Svg);
+        let args = Args::parse_from(["microtex", "--format", "png", "x"]);
+        assert_eq!(args.format, Format::Png);
+        assert_eq!(args.format.output_format().extension(), "png");
+        assert!(Args::try_parse_from(["microtex", "--format", "jpeg", "x"]).is_err());
+    }
+
+    #[test]
+    fn test_input_file_flag_parsing() {
+        let args = Args::parse_from(["microtex", "--input-file", "f.tsv", "--output-dir", "out"]);
+        assert_eq!(args.input_file, Some(PathBuf::from("f.tsv")));
+        assert_eq!(args.output_dir, Some(PathBuf::from("out")));
+
+        assert!(Args::try_parse_from(["microtex", "--input-file", "f.tsv", "x"]).is_err());
+        assert!(Args::try_parse_from(["microtex", "--output-dir", "out"]).is_err());
+    }
+
+    #[test]
+    fn test_read_formula() {
+        let args = Args::parse_from(["microtex"]);
+        let stdin = "\\begin{aligned}\na &= b \\\\\nc &= d\n\\end{aligned}\r\n";
+        assert_eq!(
+            read_formula(&args, stdin.as_bytes()).unwrap(),
+            "\\begin{aligned}\na &= b \\\\\nc &= d\n\\end{aligned}"
+        );
+
+        let args = Args::parse_from(["microtex", "-"]);
+        assert_eq!(read_formula(&args, " x \n".as_bytes()).unwrap(), " x ");
+
+        // A formula argument wins, stdin is not read
+        let args = Args::parse_from(["microtex", "y"]);
+        assert_eq!(read_formula(&args, "x".as_bytes()).unwrap(), "y");
+    }
+
+    #[test]
+    fn test_build_config_precedence() {
+        let path = std::env::temp_dir().join(format!("microtex-cli-{}.json", std::process::id()));
+        fs::write(
+            &path,
+            r#"{"dpi": 300, "line_width": 30.0, "color": "ff0000ff", "crlf": true}"#,
+        )
+        .unwrap();
+
+        let config_arg = path.to_str().unwrap();
+        let args = Args::parse_from(["microtex", "--config", config_arg, "--dpi", "1440", "x"]);
+        let config = build_config(&args).expect("config should load");
+
+        // The flag wins over the file, the file over the defaults
+        assert_eq!(config.dpi, 1440);
+        assert_eq!(config.line_width, 30.0);
+        assert_eq!(config.text_color, 0xff0000ff);
+        assert_eq!(config.line_ending, LineEnding::CrLf);
+        assert_eq!(config.line_height, RenderConfig::default().line_height);
+        assert!(config.render_glyph_use_path);
+
+        // `--crlf=false` overrides `"crlf": true`
+        let args = Args::parse_from(["microtex", "--config", config_arg, "--crlf=false", "x"]);
+        let config = build_config(&args).expect("config should load");
+        assert_eq!(config.line_ending, LineEnding::Lf);
+        fs::remove_file(&path).ok();
+
+        let args = Args::parse_from(["microtex", "--config", config_arg, "x"]);
+        assert!(build_config(&args).is_err());
+    }
+
+    #[test]
+    fn test_use_path_flag_parsing() {
+        assert_eq!(Args::parse_from(["microtex", "x"]).use_path, None);
+        let args = Args::parse_from(["microtex", "x", "--use-path"]);
+        assert_eq!(args.use_path, Some(true));
+        let args = Args::parse_from(["microtex", "--use-path=false", "x"]);
+        assert_eq!(args.use_path, Some(false));
+
+        assert_eq!(Args::parse_from(["microtex", "x"]).crlf, None);
+        assert_eq!(
+            Args::parse_from(["microtex", "x", "--crlf"]).crlf,
+            Some(true)
+        );
+    }
+
+    #[test]
+    fn test_prepare_formula_wrap_modes() {
+        let mut args = Args::parse_from(["microtex", " x^2 "]);
+        assert_eq!(args.wrap, WrapMode::None);
+        assert_eq!(prepare_formula(&args, " x^2 "), " x^2 ");
+
+        args.trim = true;
+        assert_eq!(prepare_formula(&args, " x^2 "), "x^2");
+
+        args.wrap = WrapMode::Inline;
+        assert_eq!(prepare_formula(&args, " x^2 "), "$x^2$");
+
+        args.wrap = WrapMode::Display;
+        assert_eq!(prepare_formula(&args, " x^2 "), r"\[x^2\]");
+    }
+
+    #[test]
+    fn test_wrap_flag_parsing() {
+        let args = Args::parse_from(["microtex", "--trim", "--wrap", "display", "x"]);
+        assert!(args.trim);
+        assert_eq!(args.wrap, WrapMode::Display);
+
+        let args = Args::parse_from(["microtex", "--wrap", "inline", "x"]);
+        assert_eq!(args.wrap, WrapMode::Inline);
+    }
+}
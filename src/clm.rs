@@ -0,0 +1,263 @@
+//! Pure-Rust parser for the header of CLM font files.
+//!
+//! CLM is the binary font format MicroTeX generates from OpenType fonts
+//! (`.clm1` without glyph outlines, `.clm2` with them). This module reads the
+//! metadata at the start of a CLM file without going through the C++ library,
+//! so fonts can be inspected and validated independently of rendering.
+//!
+//! # Header layout
+//!
+//! All integers are big-endian.
+//!
+//! | Field       | Type                  | Notes                                   |
+//! |-------------|-----------------------|-----------------------------------------|
+//! | magic       | 3 bytes               | `b"clm"`                                |
+//! | major       | `u16`                 | format version, must be 5               |
+//! | minor       | `u8`                  | 2 if glyph paths are included, else 1   |
+//! | name        | NUL-terminated string | font name, e.g. `XITS Math`             |
+//! | family      | NUL-terminated string | font family                             |
+//! | is_math     | `u8`                  | non-zero for OpenType math fonts        |
+//! | style       | `u16`                 | MicroTeX font style bits                |
+//! | em          | `u16`                 | units per em                            |
+//! | x_height    | `u16`                 | in font units                           |
+//! | ascent      | `u16`                 | in font units                           |
+//! | descent     | `u16`                 | in font units                           |
+//!
+//! The header is followed by the character map, read by
+//! [`parse_clm_unicodes`]: a `u16` count, then that many `(u32 code point,
+//! u16 glyph id)` pairs. Kerning, ligature, math constant and glyph tables
+//! come next and are not parsed here.
+
+use byteorder::{BigEndian, ReadBytesExt};
+use std::io::{BufRead, Cursor};
+use thiserror::Error;
+
+/// The CLM major version supported by the bundled MicroTeX.
+pub const CLM_VERSION_MAJOR: u16 = 5;
+
+/// Metadata read from the header of a CLM font file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClmHeader {
+    /// Format major version (see [`CLM_VERSION_MAJOR`]).
+    pub major_version: u16,
+
+    /// Format minor version: 2 when glyph paths are included, 1 otherwise.
+    pub minor_version: u8,
+
+    /// The font name, e.g. `XITS Math`.
+    pub name: String,
+
+    /// The font family name.
+    pub family: String,
+
+    /// Whether the font is an OpenType math font (has a `MATH` table).
+    pub is_math_font: bool,
+
+    /// MicroTeX font style bits.
+    pub style: u16,
+
+    /// Units per em.
+    pub em: u16,
+
+    /// Height of lowercase letters, in font units.
+    pub x_height: u16,
+
+    /// Ascent above the baseline, in font units.
+    pub ascent: u16,
+
+    /// Descent below the baseline, in font units.
+    pub descent: u16,
+}
+
+impl ClmHeader {
+    /// Returns true if the file contains glyph outlines (CLM minor version 2).
+    pub fn has_glyph_paths(&self) -> bool {
+        self.minor_version == 2
+    }
+}
+
+/// Errors that can occur when parsing a CLM header.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum ClmParseError {
+    /// The data does not start with the `clm` magic bytes.
+    #[error("invalid CLM data: missing 'clm' magic bytes")]
+    InvalidMagic,
+
+    /// The major version is not supported by this crate.
+    #[error("unsupported CLM version {0} (expected {CLM_VERSION_MAJOR})")]
+    UnsupportedVersion(u16),
+
+    /// The data ended before the header was complete.
+    #[error("CLM data is truncated")]
+    UnexpectedEof,
+
+    /// The font name or family is not valid UTF-8.
+    #[error("CLM font name is not valid UTF-8")]
+    InvalidString,
+}
+
+impl From<std::io::Error> for ClmParseError {
+    fn from(_: std::io::Error) -> Self {
+        ClmParseError::UnexpectedEof
+    }
+}
+
+/// Reads a NUL-terminated UTF-8 string.
+fn read_string(reader: &mut Cursor<&[u8]>) -> Result<String, ClmParseError> {
+    let mut bytes = Vec::new();
+    reader.read_until(0, &mut bytes)?;
+    if bytes.pop() != Some(0) {
+        return Err(ClmParseError::UnexpectedEof);
+    }
+    String::from_utf8(bytes).map_err(|_| ClmParseError::InvalidString)
+}
+
+/// Parses the header of a CLM font file.
+///
+/// # Arguments
+///
+/// * `data` - The CLM file content (only the header bytes are read)
+///
+/// # Errors
+///
+/// Returns a [`ClmParseError`] if the magic bytes or version don't match, or
+/// if the data is truncated.
+///
+/// # Example
+///
+/// ```rust
+/// use microtex_rs::{get_embedded_clm, parse_clm_header};
+///
+/// let data = get_embedded_clm("XITSMath-Regular.clm2").expect("embedded font");
+/// let header = parse_clm_header(data)?;
+/// assert!(header.is_math_font);
+/// println!("{} ({} units per em)", header.name, header.em);
+/// # Ok::<(), microtex_rs::ClmParseError>(())
+/// ```
+pub fn parse_clm_header(data: &[u8]) -> Result<ClmHeader, ClmParseError> {
+    read_header(&mut Cursor::new(data))
+}
+
+/// Parses the character map of a CLM font file.
+///
+/// # Arguments
+///
+/// * `data` - The CLM file content (only the header and character map are read)
+///
+/// # Returns
+///
+/// The Unicode code points the font has a glyph for, in ascending order.
+///
+/// # Errors
+///
+/// Returns the same errors as [`parse_clm_header`], or
+/// [`ClmParseError::UnexpectedEof`] if the character map is truncated.
+///
+/// # Example
+///
+/// ```rust
+/// use microtex_rs::{get_embedded_clm, parse_clm_unicodes};
+///
+/// let data = get_embedded_clm("XITSMath-Regular.clm2").expect("embedded font");
+/// let unicodes = parse_clm_unicodes(data)?;
+/// assert!(unicodes.binary_search(&('ℝ' as u32)).is_ok());
+/// # Ok::<(), microtex_rs::ClmParseError>(())
+/// ```
+pub fn parse_clm_unicodes(data: &[u8]) -> Result<Vec<u32>, ClmParseError> {
+    let mut reader = Cursor::new(data);
+    read_header(&mut reader)?;
+
+    let count = reader.read_u16::<BigEndian>()?;
+    let mut unicodes = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        unicodes.push(reader.read_u32::<BigEndian>()?);
+        reader.read_u16::<BigEndian>()?;
+    }
+    unicodes.sort_unstable();
+    Ok(unicodes)
+}
+
+/// Reads the header, leaving `reader` on the first byte after it.
+fn read_header(reader: &mut Cursor<&[u8]>) -> Result<ClmHeader, ClmParseError> {
+    let data = *reader.get_ref();
+    if data.len() < 3 || &data[..3] != b"clm" {
+        return Err(ClmParseError::InvalidMagic);
+    }
+
+    reader.set_position(3);
+
+    let major_version = reader.read_u16::<BigEndian>()?;
+    if major_version != CLM_VERSION_MAJOR {
+        return Err(ClmParseError::UnsupportedVersion(major_version));
+    }
+    let minor_version = reader.read_u8()?;
+
+    Ok(ClmHeader {
+        major_version,
+        minor_version,
+        name: read_string(reader)?,
+        family: read_string(reader)?,
+        is_math_font: reader.read_u8()? != 0,
+        style: reader.read_u16::<BigEndian>()?,
+        em: reader.read_u16::<BigEndian>()?,
+        x_height: reader.read_u16::<BigEndian>()?,
+        ascent: reader.read_u16::<BigEndian>()?,
+        descent: reader.read_u16::<BigEndian>()?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_embedded_xits_math_header() {
+        let data = crate::get_embedded_clm("XITSMath-Regular.clm2").expect("XITS Math embedded");
+        let header = parse_clm_header(data).expect("valid header");
+
+        assert_eq!(header.major_version, CLM_VERSION_MAJOR);
+        assert!(header.has_glyph_paths());
+        assert_eq!(header.name, "XITS Math");
+        assert_eq!(header.family, "XITS Math");
+        assert!(header.is_math_font);
+        assert_eq!(header.em, 1000);
+        assert_eq!(header.x_height, 450);
+        assert_eq!(header.ascent, 750);
+        assert_eq!(header.descent, 250);
+    }
+
+    #[test]
+    fn test_parse_embedded_xits_math_unicodes() {
+        let data = crate::get_embedded_clm("XITSMath-Regular.clm2").expect("XITS Math embedded");
+        let unicodes = parse_clm_unicodes(data).expect("valid character map");
+
+        assert!(unicodes.windows(2).all(|w| w[0] <= w[1]));
+        for c in ['x', 'ℝ', '𝔸', '𝒜'] {
+            assert!(unicodes.binary_search(&(c as u32)).is_ok(), "{}", c);
+        }
+        // Truncated right after the header
+        let header_len = 3 + 2 + 1 + "XITS Math".len() * 2 + 2 + 1 + 2 * 5;
+        assert_eq!(
+            parse_clm_unicodes(&data[..header_len + 1]),
+            Err(ClmParseError::UnexpectedEof)
+        );
+    }
+
+    #[test]
+    fn test_parse_clm_header_errors() {
+        assert_eq!(parse_clm_header(b"otf"), Err(ClmParseError::InvalidMagic));
+        assert_eq!(parse_clm_header(b""), Err(ClmParseError::InvalidMagic));
+        assert_eq!(
+            parse_clm_header(b"clm\x00\x04\x02"),
+            Err(ClmParseError::UnsupportedVersion(4))
+        );
+        assert_eq!(
+            parse_clm_header(b"clm\x00\x05\x02Name"),
+            Err(ClmParseError::UnexpectedEof)
+        );
+        assert_eq!(
+            parse_clm_header(b"clm\x00\x05\x02\xff\x00"),
+            Err(ClmParseError::InvalidString)
+        );
+    }
+}
@@ -11,9 +11,13 @@ mod ffi {
     include!(concat!(env!("OUT_DIR"), "/bindings.rs"));
 }
 
+mod clm;
+
+pub use clm::{parse_clm_header, parse_clm_unicodes, ClmHeader, ClmParseError, CLM_VERSION_MAJOR};
+
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::fmt;
-use std::os::raw::c_char;
 use thiserror::Error;
 
 // Re-export CLM helpers generated at build time
@@ -21,7 +25,7 @@ include!(concat!(env!("OUT_DIR"), "/embedded_clms.rs"));
 
 /// Runtime test control helpers (always compiled) used to configure shim behavior from tests.
 pub mod test_control {
-    use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+    use std::sync::atomic::{AtomicBool, AtomicI32, AtomicI64, AtomicU64, Ordering};
     use std::sync::Mutex;
 
     static INIT_SUCCEED: AtomicBool = AtomicBool::new(true);
@@ -30,6 +34,20 @@ pub mod test_control {
     static OUT_LEN: AtomicU64 = AtomicU64::new(0);
     static TEST_LOCK: Mutex<()> = Mutex::new(());
     static TEST_BUFFER: Mutex<Vec<u8>> = Mutex::new(Vec::new());
+    static MATH_SPACING: Mutex<(f32, f32)> = Mutex::new((0.0, 0.0));
+    static OPTIONS: Mutex<Vec<(String, String)>> = Mutex::new(Vec::new());
+    static LIMITS_MODE: AtomicI32 = AtomicI32::new(0);
+    static BOLD_MATH: AtomicBool = AtomicBool::new(false);
+    static INITED: AtomicBool = AtomicBool::new(false);
+    static INIT_CALLS: AtomicU64 = AtomicU64::new(0);
+    static BACKGROUND: Mutex<Option<u32>> = Mutex::new(None);
+    static MAIN_FONT: Mutex<String> = Mutex::new(String::new());
+    static MATH_FONT: Mutex<String> = Mutex::new(String::new());
+    static GLYPH_USE_PATH: AtomicBool = AtomicBool::new(false);
+    static LAST_ERROR: Mutex<String> = Mutex::new(String::new());
+    static FFI_CALLS: Mutex<Vec<&'static str>> = Mutex::new(Vec::new());
+    static LIVE_BUFFERS: AtomicI64 = AtomicI64::new(0);
+    static PARSE_DELAY_MS: AtomicU64 = AtomicU64::new(0);
 
     /// Acquire a lock to serialize tests that touch global test control state.
     pub fn lock_test() -> std::sync::MutexGuard<'static, ()> {
@@ -61,6 +79,124 @@ pub mod test_control {
     pub fn get_return_empty() -> bool {
         RETURN_EMPTY.load(Ordering::SeqCst)
     }
+    /// Records the `(op, rel)` spacing passed to the shim.
+    pub fn set_math_spacing(op: f32, rel: f32) {
+        *MATH_SPACING.lock().unwrap() = (op, rel);
+    }
+    /// Returns the last `(op, rel)` spacing passed to the shim.
+    pub fn get_math_spacing() -> (f32, f32) {
+        *MATH_SPACING.lock().unwrap()
+    }
+    /// Records the limits mode code passed to the shim.
+    pub fn set_limits_mode(mode: i32) {
+        LIMITS_MODE.store(mode, Ordering::SeqCst);
+    }
+    /// Returns the last limits mode code passed to the shim.
+    pub fn get_limits_mode() -> i32 {
+        LIMITS_MODE.load(Ordering::SeqCst)
+    }
+    /// Records a successful or released initialization of the shim.
+    pub fn set_inited(v: bool) {
+        if v {
+            INIT_CALLS.fetch_add(1, Ordering::SeqCst);
+        }
+        INITED.store(v, Ordering::SeqCst);
+    }
+    /// Returns true if the shim is initialized and not released.
+    pub fn get_inited() -> bool {
+        INITED.load(Ordering::SeqCst)
+    }
+    /// Returns the number of successful shim initializations so far.
+    pub fn get_init_calls() -> u64 {
+        INIT_CALLS.load(Ordering::SeqCst)
+    }
+    /// Records the bold math flag passed to the shim.
+    pub fn set_bold_math(bold: bool) {
+        BOLD_MATH.store(bold, Ordering::SeqCst);
+    }
+    /// Returns the last bold math flag passed to the shim.
+    pub fn get_bold_math() -> bool {
+        BOLD_MATH.load(Ordering::SeqCst)
+    }
+    /// Records the default main font passed to the shim.
+    pub fn set_main_font(name: &str) {
+        *MAIN_FONT.lock().unwrap() = name.to_string();
+    }
+    /// Returns the last default main font passed to the shim.
+    pub fn get_main_font() -> String {
+        MAIN_FONT.lock().unwrap().clone()
+    }
+    /// Records the default math font passed to the shim.
+    pub fn set_math_font(name: &str) {
+        *MATH_FONT.lock().unwrap() = name.to_string();
+    }
+    /// Returns the last default math font passed to the shim.
+    pub fn get_math_font() -> String {
+        MATH_FONT.lock().unwrap().clone()
+    }
+    /// Records whether glyphs are rendered as paths.
+    pub fn set_render_glyph_use_path(use_path: bool) {
+        GLYPH_USE_PATH.store(use_path, Ordering::SeqCst);
+    }
+    /// Returns the last glyph path setting passed to the shim.
+    pub fn get_render_glyph_use_path() -> bool {
+        GLYPH_USE_PATH.load(Ordering::SeqCst)
+    }
+    /// Sets the message the shim reports for a failed parse.
+    pub fn set_last_error(message: &str) {
+        *LAST_ERROR.lock().unwrap() = message.to_string();
+    }
+    /// Returns the message the shim reports for a failed parse.
+    pub fn get_last_error() -> String {
+        LAST_ERROR.lock().unwrap().clone()
+    }
+    /// Records the background color passed to the shim, `None` if disabled.
+    pub fn set_background(background: Option<u32>) {
+        *BACKGROUND.lock().unwrap() = background;
+    }
+    /// Returns the background color last passed to the shim.
+    pub fn get_background() -> Option<u32> {
+        *BACKGROUND.lock().unwrap()
+    }
+    /// Records a `(key, value)` option passed to the shim.
+    pub fn push_option(key: &str, value: &str) {
+        OPTIONS
+            .lock()
+            .unwrap()
+            .push((key.to_string(), value.to_string()));
+    }
+    /// Returns and clears the options passed to the shim so far.
+    pub fn take_options() -> Vec<(String, String)> {
+        std::mem::take(&mut *OPTIONS.lock().unwrap())
+    }
+    /// Records a call to a shim rendering function.
+    pub fn push_ffi_call(name: &'static str) {
+        FFI_CALLS.lock().unwrap().push(name);
+    }
+    /// Returns and clears the shim rendering functions called so far.
+    pub fn take_ffi_calls() -> Vec<&'static str> {
+        std::mem::take(&mut *FFI_CALLS.lock().unwrap())
+    }
+    /// Records that the shim handed out an output buffer.
+    pub fn buffer_handed_out() {
+        LIVE_BUFFERS.fetch_add(1, Ordering::SeqCst);
+    }
+    /// Records that an output buffer was passed back to the shim to be freed.
+    pub fn buffer_freed() {
+        LIVE_BUFFERS.fetch_sub(1, Ordering::SeqCst);
+    }
+    /// Returns the number of output buffers handed out and not yet freed.
+    pub fn live_buffers() -> i64 {
+        LIVE_BUFFERS.load(Ordering::SeqCst)
+    }
+    /// Sets how long the shim takes to parse a formula.
+    pub fn set_parse_delay(delay: std::time::Duration) {
+        PARSE_DELAY_MS.store(delay.as_millis() as u64, Ordering::SeqCst);
+    }
+    /// Returns how long the shim takes to parse a formula.
+    pub fn get_parse_delay() -> std::time::Duration {
+        std::time::Duration::from_millis(PARSE_DELAY_MS.load(Ordering::SeqCst))
+    }
     /// Returns a pointer to the internal test buffer and its length.
     /// The buffer is owned by the static inside `test_control` and will remain
     /// valid until modified by `set_buffer` (tests should use `lock_test()` to
@@ -96,17 +232,48 @@ mod shim {
         super::ffi::microtex_setDefaultMainFont(ptr as *const _);
     }
 
+    #[cfg(not(test))]
+    pub unsafe fn microtex_set_default_math_font(ptr: *const c_char) {
+        super::ffi::microtex_setDefaultMathFont(ptr as *const _);
+    }
+
+    #[cfg(not(test))]
+    pub unsafe fn microtex_get_font_name(meta: *mut c_void) -> *const c_char {
+        super::ffi::microtex_getFontName(meta as *mut _)
+    }
+
     #[cfg(not(test))]
     pub unsafe fn microtex_set_render_glyph_use_path(val: bool) {
         super::ffi::microtex_setRenderGlyphUsePath(val);
     }
 
+    #[cfg(not(test))]
+    pub unsafe fn microtex_set_math_spacing(op: f32, rel: f32) {
+        super::ffi::microtex_setMathSpacing(op, rel);
+    }
+
+    #[cfg(not(test))]
+    pub unsafe fn microtex_set_limits_mode(mode: i32) {
+        super::ffi::microtex_setLimitsMode(mode);
+    }
+
+    #[cfg(not(test))]
+    pub unsafe fn microtex_set_bold_math(bold: bool) {
+        super::ffi::microtex_setBoldMath(bold);
+    }
+
+    #[cfg(not(test))]
+    pub unsafe fn microtex_set_option(key: *const c_char, value: *const c_char) {
+        super::ffi::microtex_setOption(key, value);
+    }
+
     #[cfg(not(test))]
     pub unsafe fn microtex_release_font_meta(meta: *mut c_void) {
         super::ffi::microtex_releaseFontMeta(meta as *mut _);
     }
 
     #[cfg(not(test))]
+    #[allow(clippy::too_many_arguments)]
     pub unsafe fn microtex_parse_render(
         src: *const c_char,
         dpi: i32,
@@ -114,6 +281,7 @@ mod shim {
         line_height: f32,
         text_color: u32,
         has_background: bool,
+        background_color: u32,
         render_glyph_use_path: bool,
     ) -> *mut c_void {
         super::ffi::microtex_parseRender(
@@ -122,9 +290,12 @@ mod shim {
             line_width,
             line_height,
             text_color,
-            has_background,
+            // fillWidth: lay the formula out at its natural width
+            false,
             render_glyph_use_path,
             0,
+            has_background,
+            background_color,
         )
     }
 
@@ -176,11 +347,32 @@ mod shim {
         super::ffi::microtex_render_to_svg_with_metrics(render_ptr as *mut _, out_len)
     }
 
+    /// Returns the message of the last failed parse, empty if unavailable.
+    #[cfg(not(test))]
+    pub unsafe fn microtex_last_error() -> String {
+        let ptr = super::ffi::microtex_last_error();
+        if ptr.is_null() {
+            return String::new();
+        }
+        std::ffi::CStr::from_ptr(ptr).to_string_lossy().into_owned()
+    }
+
     #[cfg(not(test))]
     pub unsafe fn microtex_delete_render(render_ptr: *mut c_void) {
         super::ffi::microtex_deleteRender(render_ptr as *mut _);
     }
 
+    /// Returns the width, ascent, depth and math axis height of a render.
+    #[cfg(not(test))]
+    pub unsafe fn microtex_get_render_dimensions(render_ptr: *mut c_void) -> (i32, i32, i32, i32) {
+        (
+            super::ffi::microtex_getRenderWidth(render_ptr as *mut _),
+            super::ffi::microtex_getRenderHeight(render_ptr as *mut _),
+            super::ffi::microtex_getRenderDepth(render_ptr as *mut _),
+            super::ffi::microtex_getRenderAxisHeight(render_ptr as *mut _),
+        )
+    }
+
     #[cfg(not(test))]
     pub unsafe fn microtex_free_buffer(buf: *mut u8) {
         super::ffi::microtex_free_buffer(buf as *mut _);
@@ -218,6 +410,11 @@ mod shim {
         super::ffi::microtex_release();
     }
 
+    #[cfg(not(test))]
+    pub unsafe fn microtex_is_inited() -> bool {
+        super::ffi::microtex_isInited()
+    }
+
     // --------- Test-controlled implementations ---------
     #[cfg(test)]
     mod test_impl {
@@ -254,33 +451,66 @@ mod shim {
 
         pub unsafe fn microtex_init(_len: u64, _ptr: *const u8) -> *mut c_void {
             if crate::test_control::get_init_succeed() {
+                crate::test_control::set_inited(true);
                 1 as *mut c_void
             } else {
                 std::ptr::null_mut()
             }
         }
 
-        pub unsafe fn microtex_set_default_main_font(_ptr: *const c_char) {
-            // noop in tests
+        pub unsafe fn microtex_set_default_main_font(ptr: *const c_char) {
+            let name = std::ffi::CStr::from_ptr(ptr).to_string_lossy();
+            crate::test_control::set_main_font(&name)
         }
 
-        pub unsafe fn microtex_set_render_glyph_use_path(_val: bool) {
-            // noop in tests
+        pub unsafe fn microtex_set_default_math_font(ptr: *const c_char) {
+            let name = std::ffi::CStr::from_ptr(ptr).to_string_lossy();
+            crate::test_control::set_math_font(&name)
+        }
+
+        pub unsafe fn microtex_get_font_name(_meta: *mut c_void) -> *const c_char {
+            c"XITS Math".as_ptr()
+        }
+
+        pub unsafe fn microtex_set_render_glyph_use_path(val: bool) {
+            crate::test_control::set_render_glyph_use_path(val)
+        }
+
+        pub unsafe fn microtex_set_math_spacing(op: f32, rel: f32) {
+            crate::test_control::set_math_spacing(op, rel)
+        }
+
+        pub unsafe fn microtex_set_limits_mode(mode: i32) {
+            crate::test_control::set_limits_mode(mode)
+        }
+
+        pub unsafe fn microtex_set_bold_math(bold: bool) {
+            crate::test_control::set_bold_math(bold)
+        }
+
+        pub unsafe fn microtex_set_option(key: *const c_char, value: *const c_char) {
+            let key = std::ffi::CStr::from_ptr(key).to_string_lossy();
+            let value = std::ffi::CStr::from_ptr(value).to_string_lossy();
+            crate::test_control::push_option(&key, &value)
         }
 
         pub unsafe fn microtex_release_font_meta(_meta: *mut c_void) {
             // noop in tests
         }
 
+        #[allow(clippy::too_many_arguments)]
         pub unsafe fn microtex_parse_render(
             _src: *const c_char,
             _dpi: i32,
             _line_width: f32,
             _line_height: f32,
             _text_color: u32,
-            _has_background: bool,
+            has_background: bool,
+            background_color: u32,
             _render_glyph_use_path: bool,
         ) -> *mut c_void {
+            crate::test_control::set_background(has_background.then_some(background_color));
+            std::thread::sleep(crate::test_control::get_parse_delay());
             if crate::test_control::get_parse_succeed() {
                 2 as *mut c_void
             } else {
@@ -292,6 +522,7 @@ mod shim {
             _render_ptr: *mut c_void,
             out_len: &mut u64,
         ) -> *mut u8 {
+            crate::test_control::push_ffi_call("render_to_svg");
             if crate::test_control::get_return_empty() {
                 *out_len = 0;
                 std::ptr::null_mut()
@@ -301,6 +532,7 @@ mod shim {
                 if len == 0 || ptr.is_null() {
                     std::ptr::null_mut()
                 } else {
+                    crate::test_control::buffer_handed_out();
                     ptr as *mut u8
                 }
             }
@@ -314,6 +546,7 @@ mod shim {
             _render_ptr: *mut c_void,
             out_len: &mut u64,
         ) -> *mut u8 {
+            crate::test_control::push_ffi_call("render_to_svg_with_metrics");
             if crate::test_control::get_return_empty() {
                 *out_len = 0;
                 std::ptr::null_mut()
@@ -323,6 +556,7 @@ mod shim {
                 if len == 0 || ptr.is_null() {
                     std::ptr::null_mut()
                 } else {
+                    crate::test_control::buffer_handed_out();
                     ptr as *mut u8
                 }
             }
@@ -336,6 +570,7 @@ mod shim {
             _render_ptr: *mut c_void,
             out_len: &mut u64,
         ) -> *mut u8 {
+            crate::test_control::push_ffi_call("get_key_char_metrics");
             if crate::test_control::get_return_empty() {
                 *out_len = 0;
                 std::ptr::null_mut()
@@ -345,21 +580,34 @@ mod shim {
                 if len == 0 || ptr.is_null() {
                     std::ptr::null_mut()
                 } else {
+                    crate::test_control::buffer_handed_out();
                     ptr as *mut u8
                 }
             }
         }
 
+        pub unsafe fn microtex_last_error() -> String {
+            crate::test_control::get_last_error()
+        }
+
         pub unsafe fn microtex_delete_render(_ptr: *mut c_void) {
             // noop
         }
 
+        pub unsafe fn microtex_get_render_dimensions(_ptr: *mut c_void) -> (i32, i32, i32, i32) {
+            (40, 16, 4, 5)
+        }
+
         pub unsafe fn microtex_free_buffer(_buf: *mut u8) {
-            // noop
+            crate::test_control::buffer_freed();
         }
 
         pub unsafe fn microtex_release() {
-            // noop
+            crate::test_control::set_inited(false);
+        }
+
+        pub unsafe fn microtex_is_inited() -> bool {
+            crate::test_control::get_inited()
         }
     }
 
@@ -400,14 +648,39 @@ mod shim {
         test_impl::microtex_set_default_main_font(ptr)
     }
     #[cfg(test)]
+    pub unsafe fn microtex_set_default_math_font(ptr: *const c_char) {
+        test_impl::microtex_set_default_math_font(ptr)
+    }
+    #[cfg(test)]
+    pub unsafe fn microtex_get_font_name(meta: *mut c_void) -> *const c_char {
+        test_impl::microtex_get_font_name(meta)
+    }
+    #[cfg(test)]
     pub unsafe fn microtex_set_render_glyph_use_path(val: bool) {
         test_impl::microtex_set_render_glyph_use_path(val)
     }
     #[cfg(test)]
+    pub unsafe fn microtex_set_math_spacing(op: f32, rel: f32) {
+        test_impl::microtex_set_math_spacing(op, rel)
+    }
+    #[cfg(test)]
+    pub unsafe fn microtex_set_limits_mode(mode: i32) {
+        test_impl::microtex_set_limits_mode(mode)
+    }
+    #[cfg(test)]
+    pub unsafe fn microtex_set_bold_math(bold: bool) {
+        test_impl::microtex_set_bold_math(bold)
+    }
+    #[cfg(test)]
+    pub unsafe fn microtex_set_option(key: *const c_char, value: *const c_char) {
+        test_impl::microtex_set_option(key, value)
+    }
+    #[cfg(test)]
     pub unsafe fn microtex_release_font_meta(meta: *mut c_void) {
         test_impl::microtex_release_font_meta(meta)
     }
     #[cfg(test)]
+    #[allow(clippy::too_many_arguments)]
     pub unsafe fn microtex_parse_render(
         src: *const c_char,
         dpi: i32,
@@ -415,6 +688,7 @@ mod shim {
         line_height: f32,
         text_color: u32,
         has_background: bool,
+        background_color: u32,
         render_glyph_use_path: bool,
     ) -> *mut c_void {
         test_impl::microtex_parse_render(
@@ -424,6 +698,7 @@ mod shim {
             line_height,
             text_color,
             has_background,
+            background_color,
             render_glyph_use_path,
         )
     }
@@ -452,10 +727,18 @@ mod shim {
         test_impl::microtex_get_key_char_metrics(render_ptr, out_len)
     }
     #[cfg(test)]
+    pub unsafe fn microtex_last_error() -> String {
+        test_impl::microtex_last_error()
+    }
+    #[cfg(test)]
     pub unsafe fn microtex_delete_render(render_ptr: *mut c_void) {
         test_impl::microtex_delete_render(render_ptr)
     }
     #[cfg(test)]
+    pub unsafe fn microtex_get_render_dimensions(render_ptr: *mut c_void) -> (i32, i32, i32, i32) {
+        test_impl::microtex_get_render_dimensions(render_ptr)
+    }
+    #[cfg(test)]
     pub unsafe fn microtex_free_buffer(buf: *mut u8) {
         test_impl::microtex_free_buffer(buf)
     }
@@ -463,6 +746,10 @@ mod shim {
     pub unsafe fn microtex_release() {
         test_impl::microtex_release()
     }
+    #[cfg(test)]
+    pub unsafe fn microtex_is_inited() -> bool {
+        test_impl::microtex_is_inited()
+    }
 }
 
 // Expose test helpers to other crates during test builds so integration/unit tests
@@ -509,8 +796,25 @@ pub enum RenderError {
     InitializationFailed,
 
     /// The provided LaTeX source failed to parse or render.
-    #[error("failed to parse and render LaTeX source")]
-    ParseRenderFailed,
+    ///
+    /// Holds MicroTeX's message, such as the unknown macro or unbalanced
+    /// bracket, or an empty string when none is available.
+    #[error("failed to parse and render LaTeX source{}", error_detail(.0))]
+    ParseRenderFailed(String),
+
+    /// The LaTeX source is empty or contains only whitespace.
+    #[error("LaTeX source is empty")]
+    EmptyInput,
+
+    /// The LaTeX source uses a package MicroTeX cannot render (see
+    /// [`MicroTex::render`] for the detected environments).
+    #[error("unsupported LaTeX: {0}")]
+    Unsupported(String),
+
+    /// The LaTeX source cannot be handed to MicroTeX, for example because it
+    /// contains a NUL byte.
+    #[error("invalid LaTeX source: {0}")]
+    InvalidInput(String),
 
     /// The SVG rendering produced no output.
     #[error("SVG rendering returned empty output")]
@@ -523,1240 +827,10438 @@ pub enum RenderError {
     /// Failed to parse the JSON metrics response from the C++ renderer.
     #[error("failed to parse JSON metrics: {0}")]
     ParseJsonFailed(String),
-}
-
-/// Configuration for rendering LaTeX to SVG.
-///
-/// This structure holds all parameters needed to control how LaTeX
-/// formulas are rendered to SVG format.
-#[derive(Debug, Clone)]
-pub struct RenderConfig {
-    /// DPI (dots per inch) for the output. Default: 720
-    pub dpi: i32,
 
-    /// Line width in pixels. Default: 20.0
-    pub line_width: f32,
+    /// Failed to parse the JSON metrics response, with the offending payload.
+    ///
+    /// Returned instead of [`RenderError::ParseJsonFailed`] when
+    /// [`RenderConfig::capture_raw_json`] is enabled.
+    #[error("failed to parse JSON metrics: {error}\nraw JSON: {raw}")]
+    ParseJsonFailedRaw {
+        /// Description of the parsing failure.
+        error: String,
+        /// The JSON exactly as returned by the C++ wrapper.
+        raw: String,
+    },
+
+    /// Failed to rasterize the rendered SVG to PNG (`png` feature).
+    #[error("failed to rasterize SVG: {0}")]
+    Rasterization(String),
+
+    /// The rendered SVG could not be scaled to the requested size.
+    #[error("cannot scale SVG: {0}")]
+    Scaling(String),
+
+    /// The LaTeX source nests groups deeper than
+    /// [`RenderConfig::max_nesting_depth`] allows.
+    #[error("LaTeX source nesting depth {depth} exceeds the limit of {max}")]
+    NestingTooDeep {
+        /// The deepest brace nesting found in the source.
+        depth: u32,
+        /// The configured limit.
+        max: u32,
+    },
+
+    /// The LaTeX source is longer than [`RenderConfig::max_source_len`]
+    /// allows.
+    #[error("LaTeX source length {len} exceeds the limit of {max} bytes")]
+    SourceTooLong {
+        /// The length of the source in bytes, after macro expansion.
+        len: usize,
+        /// The configured limit.
+        max: usize,
+    },
+
+    /// The LaTeX source uses a command listed in
+    /// [`RenderConfig::disallowed_commands`].
+    #[error("LaTeX command \\{0} is not allowed")]
+    DisallowedCommand(String),
+
+    /// The rendered formula is larger than
+    /// [`RenderConfig::max_output_dimensions`] allows.
+    #[error("rendered output {width}x{height} exceeds the limit of {}x{}", limit.0, limit.1)]
+    OutputTooLarge {
+        /// The rendered width in pixels.
+        width: i32,
+        /// The rendered total height in pixels.
+        height: i32,
+        /// The configured `(width, height)` limit.
+        limit: (i32, i32),
+    },
+
+    /// MicroTeX's output is longer than [`RenderConfig::max_output_bytes`]
+    /// allows.
+    #[error("rendered output of {actual} bytes exceeds the limit of {limit} bytes")]
+    OutputBytesTooLarge {
+        /// The configured limit in bytes.
+        limit: usize,
+        /// The length of MicroTeX's output in bytes.
+        actual: usize,
+    },
+
+    /// The [`RenderConfig`] is not renderable (see [`RenderConfig::validate`]).
+    #[error("invalid render configuration: {0}")]
+    InvalidConfig(#[from] ConfigError),
+
+    /// The render took longer than [`RenderConfig::render_timeout`] allows.
+    #[error("rendering did not finish within {0:?}")]
+    Timeout(std::time::Duration),
+
+    /// Failed to write the rendered output to disk.
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+}
 
-    /// Line height in pixels. Default: 20.0/3.0 (~6.67)
-    pub line_height: f32,
+/// Fails with [`RenderError::OutputBytesTooLarge`] when `len` bytes of
+/// MicroTeX output exceed [`RenderConfig::max_output_bytes`].
+fn check_output_len(len: u64, config: &RenderConfig) -> Result<(), RenderError> {
+    match config.max_output_bytes {
+        Some(limit) if len > limit as u64 => Err(RenderError::OutputBytesTooLarge {
+            limit,
+            actual: len as usize,
+        }),
+        _ => Ok(()),
+    }
+}
 
-    /// Text color as ARGB (0xAARRGGBB). Default: 0xff000000 (opaque black)
-    pub text_color: u32,
+/// Copies the `out_len` bytes of a buffer returned by MicroTeX into a
+/// `String` and frees it.
+///
+/// Fails with [`RenderError::EmptyOutput`] for a null or empty buffer, and
+/// with the error of [`check_output_len`] before copying anything.
+///
+/// # Safety
+///
+/// `out_buf` must be null or a buffer of `out_len` bytes allocated by
+/// MicroTeX and not freed yet.
+unsafe fn take_output(
+    out_buf: *mut u8,
+    out_len: u64,
+    config: &RenderConfig,
+) -> Result<String, RenderError> {
+    if out_buf.is_null() {
+        return Err(RenderError::EmptyOutput);
+    }
+    let bytes = match check_output_len(out_len, config) {
+        Ok(()) if out_len > 0 => {
+            Ok(std::slice::from_raw_parts(out_buf as *const u8, out_len as usize).to_vec())
+        }
+        Ok(()) => Err(RenderError::EmptyOutput),
+        Err(error) => Err(error),
+    };
+    shim::microtex_free_buffer(out_buf);
+    Ok(String::from_utf8(bytes?)?)
+}
 
-    /// Whether to enable background color rendering.
-    pub has_background: bool,
+/// Formats the MicroTeX message of [`RenderError::ParseRenderFailed`] as a
+/// `": message"` suffix, or nothing when the message is empty.
+fn error_detail(message: &str) -> String {
+    if message.is_empty() {
+        String::new()
+    } else {
+        format!(": {}", message)
+    }
+}
 
-    /// Whether to use path-based glyph rendering for better fallback when
-    /// system fonts are not available.
-    pub render_glyph_use_path: bool,
+/// Errors reported by [`RenderConfig::validate`], one per invalid field.
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum ConfigError {
+    /// `dpi` must be positive.
+    #[error("dpi must be positive, got {0}")]
+    InvalidDpi(i32),
+
+    /// `line_width` must be a positive, finite number.
+    #[error("line_width must be positive and finite, got {0}")]
+    InvalidLineWidth(f32),
+
+    /// `line_height` must be a positive, finite number.
+    #[error("line_height must be positive and finite, got {0}")]
+    InvalidLineHeight(f32),
+
+    /// `force_ascent_px` must not be negative.
+    #[error("force_ascent_px must not be negative, got {0}")]
+    InvalidForcedAscent(i32),
+
+    /// Both `max_output_dimensions` bounds must be positive.
+    #[error("max_output_dimensions must be positive, got {0}x{1}")]
+    InvalidMaxOutputDimensions(i32, i32),
+
+    /// `max_output_bytes` must be positive.
+    #[error("max_output_bytes must be positive")]
+    InvalidMaxOutputBytes,
+
+    /// Macro names must be non-empty and made of ASCII letters.
+    #[error("invalid macro name {0:?}")]
+    InvalidMacroName(String),
+
+    /// `extra_options` keys and values must not contain NUL bytes.
+    #[error("invalid extra option {0:?}")]
+    InvalidOption(String),
+
+    /// `disallowed_commands` names must be non-empty and made of ASCII letters.
+    #[error("invalid command name {0:?}")]
+    InvalidCommandName(String),
+
+    /// `min_stroke_width_px` must be a positive, finite number.
+    #[error("min_stroke_width_px must be positive and finite, got {0}")]
+    InvalidMinStrokeWidth(f32),
+
+    /// `svg_attributes` names must be well-formed XML names, and must not be
+    /// one of the attributes the renderer writes itself.
+    #[error("invalid SVG attribute name {0:?}")]
+    InvalidSvgAttribute(String),
+
+    /// `main_font` must not contain NUL bytes.
+    #[error("invalid main font name {0:?}")]
+    InvalidMainFont(String),
+
+    /// `render_timeout` must not be zero.
+    #[error("render_timeout must not be zero")]
+    InvalidRenderTimeout,
+}
 
-    /// Whether to enable formula numbering.
-    pub enable_formula_numbering: bool,
+/// Line ending style used when writing SVG files.
+///
+/// Rendered SVG strings always use `\n` internally; the line ending is only
+/// applied when the SVG is written out (see [`MicroTex::render_to_path`]).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum LineEnding {
+    /// Unix line endings (`\n`).
+    #[default]
+    Lf,
+
+    /// Windows line endings (`\r\n`).
+    CrLf,
 }
 
-impl Default for RenderConfig {
-    fn default() -> Self {
-        Self {
-            dpi: 720,
-            line_width: 20.0,
-            line_height: 20.0 / 3.0,
-            text_color: 0xff000000,
-            has_background: false,
-            render_glyph_use_path: true,
-            enable_formula_numbering: false,
+impl LineEnding {
+    /// Converts the `\n` line endings of `text` to this style.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use microtex_rs::LineEnding;
+    ///
+    /// assert_eq!(LineEnding::CrLf.apply("<svg>\n</svg>\n"), "<svg>\r\n</svg>\r\n");
+    /// assert_eq!(LineEnding::Lf.apply("<svg>\n</svg>"), "<svg>\n</svg>");
+    /// ```
+    pub fn apply(self, text: &str) -> String {
+        match self {
+            LineEnding::Lf => text.to_string(),
+            LineEnding::CrLf => text.replace('\n', "\r\n"),
         }
     }
 }
 
-/// Dimensional metrics from rendering a LaTeX formula to SVG.
+/// Placement of the limits of big operators such as `\sum` and `\int`.
 ///
-/// This structure contains the precise dimensional information of a rendered
-/// formula, useful for proper scaling and positioning in PDF documents.
-#[derive(Debug, Clone)]
-pub struct RenderMetrics {
-    /// The width of the rendered formula in pixels.
-    pub width: i32,
+/// Set through [`RenderConfig::limits_mode`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum LimitsMode {
+    /// Each operator keeps its own placement: `\sum` puts its limits above
+    /// and below in display style and beside in inline style, `\int` always
+    /// puts them beside, and `\limits`/`\nolimits` are honored.
+    #[default]
+    Default,
+
+    /// Limits always go above and below, as if every operator were followed
+    /// by `\limits`.
+    Always,
+
+    /// Limits always go beside, as scripts, as if every operator were
+    /// followed by `\nolimits`.
+    Never,
+}
 
-    /// The total height of the rendered formula in pixels (height + depth).
-    pub height: i32,
+/// A color with an alpha channel, such as [`RenderConfig::text_color`].
+///
+/// MicroTeX takes colors packed as ARGB (`0xAARRGGBB`); [`Color::from_argb`]
+/// and [`Color::to_argb`] convert to and from that layout, and the
+/// `From<u32>`/`Into<u32>` conversions do the same. Serialized as the packed
+/// ARGB number.
+///
+/// # Example
+///
+/// ```rust
+/// use microtex_rs::Color;
+///
+/// let red = Color::rgb(0xff, 0, 0);
+/// assert_eq!(red.to_argb(), 0xffff0000);
+/// assert_eq!(Color::from(0x80ff0000), Color::rgba(0xff, 0, 0, 0x80));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(from = "u32", into = "u32")]
+pub struct Color {
+    /// Alpha channel, 0 is transparent and 255 opaque.
+    pub a: u8,
 
-    /// The depth of the rendered formula below the baseline in pixels.
-    pub depth: i32,
+    /// Red channel.
+    pub r: u8,
 
-    /// The ascent of the rendered formula (height without depth) in pixels.
-    pub ascent: i32,
+    /// Green channel.
+    pub g: u8,
+
+    /// Blue channel.
+    pub b: u8,
 }
 
-impl RenderMetrics {
-    /// Creates a new RenderMetrics instance with the specified dimensions.
-    ///
-    /// # Arguments
-    /// * `width` - The width in pixels
-    /// * `height` - The total height (height + depth) in pixels
-    /// * `depth` - The depth below baseline in pixels
-    /// * `ascent` - The ascent (height without depth) in pixels
-    pub fn new(width: i32, height: i32, depth: i32, ascent: i32) -> Self {
-        Self {
-            width,
-            height,
-            depth,
-            ascent,
-        }
+impl Color {
+    /// Opaque black.
+    pub const BLACK: Color = Color::rgb(0, 0, 0);
+
+    /// Opaque white.
+    pub const WHITE: Color = Color::rgb(0xff, 0xff, 0xff);
+
+    /// Fully transparent black.
+    pub const TRANSPARENT: Color = Color::rgba(0, 0, 0, 0);
+
+    /// Creates an opaque color.
+    pub const fn rgb(r: u8, g: u8, b: u8) -> Self {
+        Color { a: 0xff, r, g, b }
     }
 
-    /// Returns the effective visual height of the rendered content.
-    ///
-    /// This is the total height including both ascent and descent.
-    pub fn total_height(&self) -> f32 {
-        self.height as f32
+    /// Creates a color with the given alpha channel.
+    pub const fn rgba(r: u8, g: u8, b: u8, a: u8) -> Self {
+        Color { a, r, g, b }
     }
 
-    /// Returns the aspect ratio (width / height) of the rendered content.
-    ///
-    /// Useful for maintaining proportional scaling when resizing.
-    pub fn aspect_ratio(&self) -> f32 {
-        if self.height > 0 {
-            self.width as f32 / self.height as f32
-        } else {
-            1.0
-        }
+    /// Unpacks a color from ARGB (`0xAARRGGBB`).
+    pub const fn from_argb(argb: u32) -> Self {
+        let [a, r, g, b] = argb.to_be_bytes();
+        Color { a, r, g, b }
     }
 
-    /// Returns the baseline ratio (ascent / total height) of the rendered content.
-    ///
-    /// This indicates how much of the formula's height is above the baseline.
-    /// - Values close to 1.0: tall formulas with many superscripts
-    /// - Values close to 0.5: balanced formulas
-    /// - Values close to 0.0: deep formulas with many subscripts or fractions
-    pub fn baseline_ratio(&self) -> f32 {
-        if self.height > 0 {
-            self.ascent as f32 / self.height as f32
-        } else {
-            0.5
-        }
+    /// Packs the color as ARGB (`0xAARRGGBB`), the layout MicroTeX takes.
+    pub const fn to_argb(self) -> u32 {
+        u32::from_be_bytes([self.a, self.r, self.g, self.b])
     }
 }
 
-/// Result type containing both SVG content and dimensional metrics.
-///
-/// Returned by rendering functions that need to provide both the rendered
-/// SVG string and precise dimensional information for further processing.
-#[derive(Debug, Clone)]
-pub struct RenderResult {
-    /// The SVG content as a UTF-8 string.
-    pub svg: String,
+impl From<u32> for Color {
+    fn from(argb: u32) -> Self {
+        Color::from_argb(argb)
+    }
+}
 
-    /// The dimensional metrics of the rendered formula.
-    pub metrics: RenderMetrics,
+impl From<Color> for u32 {
+    fn from(color: Color) -> Self {
+        color.to_argb()
+    }
+}
 
-    /// Metrics of key characters in the formula (optional).
-    /// Available when rendering with KeyCharMetrics extraction.
-    pub key_char_metrics: Option<KeyCharMetrics>,
+impl PartialEq<u32> for Color {
+    fn eq(&self, argb: &u32) -> bool {
+        self.to_argb() == *argb
+    }
 }
 
-impl RenderResult {
-    /// Creates a new RenderResult with SVG content and metrics.
-    pub fn new(svg: String, metrics: RenderMetrics) -> Self {
-        Self {
-            svg,
-            metrics,
-            key_char_metrics: None,
+/// An output format a formula can be rendered to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum OutputFormat {
+    /// SVG, always available.
+    Svg,
+
+    /// PNG, available with the `png` feature.
+    Png,
+}
+
+impl OutputFormat {
+    /// Returns the usual file extension for the format, without the dot.
+    pub fn extension(self) -> &'static str {
+        match self {
+            OutputFormat::Svg => "svg",
+            OutputFormat::Png => "png",
         }
     }
+}
 
-    /// Creates a new RenderResult with SVG content, metrics, and key character metrics.
-    pub fn with_key_char_metrics(
-        svg: String,
-        metrics: RenderMetrics,
-        key_char_metrics: KeyCharMetrics,
-    ) -> Self {
-        Self {
-            svg,
-            metrics,
-            key_char_metrics: Some(key_char_metrics),
-        }
+/// Returns the output formats this build of the crate can produce.
+///
+/// SVG is produced by MicroTeX's Cairo backend and is always present; the
+/// other formats depend on the crate features enabled at compile time.
+///
+/// # Example
+///
+/// ```rust
+/// use microtex_rs::{supported_output_formats, OutputFormat};
+///
+/// let formats = supported_output_formats();
+/// assert!(formats.contains(&OutputFormat::Svg));
+/// ```
+pub fn supported_output_formats() -> Vec<OutputFormat> {
+    let mut formats = vec![OutputFormat::Svg];
+    if cfg!(feature = "png") {
+        formats.push(OutputFormat::Png);
     }
+    formats
 }
 
-/// Metrics for key characters extracted from the formula's BOX TREE.
+/// How this build of the crate was made, returned by [`build_info`].
 ///
-/// Contains the heights of actual character boxes at the top level of the
-/// formula structure, excluding decorative elements and nested structures.
-/// This is used to calculate more accurate scaling factors that account
-/// for formula complexity (fractions, subscripts, etc.).
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct KeyCharMetrics {
-    /// Heights of individual key characters in the formula
-    pub key_char_heights: Vec<i32>,
+/// Include it in bug reports: rendering differences between machines often
+/// come from different Cairo or Pango versions.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct BuildInfo {
+    /// Version of this crate.
+    pub crate_version: &'static str,
 
-    /// Number of key characters found
-    pub key_char_count: i32,
+    /// Version of the Cairo library found at build time, if known.
+    pub cairo_version: Option<&'static str>,
 
-    /// Average height of key characters
-    pub average_char_height: f32,
+    /// Version of the Pango library found at build time, if known.
+    pub pango_version: Option<&'static str>,
 
-    /// Maximum character height
-    pub max_char_height: i32,
+    /// Whether Cairo and Pango were built from vendored sources. Always
+    /// false: the crate links the system libraries.
+    pub vendored: bool,
 
-    /// Minimum character height
-    pub min_char_height: i32,
+    /// Where the system libraries come from: `"pkg-config"`, `"homebrew"`
+    /// or `"vcpkg"`, or `"unknown"`.
+    pub dependency_source: &'static str,
 
-    /// Total height of BOX TREE root in MicroTeX units (used for normalization)
-    pub box_tree_height: f32,
+    /// Whether the `png` feature is enabled.
+    pub png: bool,
 }
 
-impl KeyCharMetrics {
-    /// Creates new KeyCharMetrics from parsed JSON data.
-    pub fn new(
-        key_char_heights: Vec<i32>,
-        key_char_count: i32,
-        average_char_height: f32,
-        max_char_height: i32,
-        min_char_height: i32,
-        box_tree_height: f32,
-    ) -> Self {
-        Self {
-            key_char_heights,
-            key_char_count,
-            average_char_height,
-            max_char_height,
-            min_char_height,
-            box_tree_height,
-        }
+impl fmt::Display for BuildInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "microtex_rs {}, Cairo {}, Pango {} ({}, {}), png {}",
+            self.crate_version,
+            self.cairo_version.unwrap_or("unknown"),
+            self.pango_version.unwrap_or("unknown"),
+            if self.vendored { "vendored" } else { "system" },
+            self.dependency_source,
+            if self.png { "on" } else { "off" }
+        )
     }
+}
 
-    /// Parses KeyCharMetrics from a JSON string returned from C++.
-    pub fn from_json(json: &str) -> Result<Self, Box<dyn std::error::Error>> {
-        let value: serde_json::Value = serde_json::from_str(json)?;
-
-        let key_char_heights: Vec<i32> = value
-            .get("key_char_heights")
-            .and_then(|v| v.as_array())
-            .map(|arr| {
-                arr.iter()
-                    .filter_map(|v| v.as_i64())
-                    .map(|v| v as i32)
-                    .collect()
-            })
-            .unwrap_or_default();
-
-        let key_char_count = value
-            .get("key_char_count")
-            .and_then(|v| v.as_i64())
-            .unwrap_or(0) as i32;
-
-        let average_char_height = value
-            .get("average_char_height")
-            .and_then(|v| v.as_f64())
-            .unwrap_or(0.0) as f32;
-
-        let max_char_height = value
-            .get("max_char_height")
-            .and_then(|v| v.as_i64())
-            .unwrap_or(0) as i32;
-
-        let min_char_height = value
-            .get("min_char_height")
-            .and_then(|v| v.as_i64())
-            .unwrap_or(0) as i32;
+/// Returns how this build of the crate was made: the versions of Cairo and
+/// Pango it was built against, where they come from, and its features.
+///
+/// The same information is logged once per process, at the `info` level,
+/// when the first [`MicroTex`] instance is initialized.
+///
+/// # Example
+///
+/// ```rust
+/// use microtex_rs::build_info;
+///
+/// let info = build_info();
+/// assert_eq!(info.crate_version, env!("CARGO_PKG_VERSION"));
+/// println!("{}", info);
+/// ```
+pub fn build_info() -> BuildInfo {
+    let known = |version: Option<&'static str>| version.filter(|v| !v.is_empty());
+    BuildInfo {
+        crate_version: env!("CARGO_PKG_VERSION"),
+        cairo_version: known(option_env!("MICROTEX_CAIRO_VERSION")),
+        pango_version: known(option_env!("MICROTEX_PANGO_VERSION")),
+        vendored: false,
+        dependency_source: option_env!("MICROTEX_DEPENDENCY_SOURCE").unwrap_or("unknown"),
+        png: cfg!(feature = "png"),
+    }
+}
 
-        let box_tree_height = value
-            .get("box_tree_height")
-            .and_then(|v| v.as_f64())
-            .unwrap_or(0.0) as f32;
+/// An embedded font, as listed by [`embedded_font_info`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FontInfo {
+    /// File name of the font, as accepted by [`get_embedded_clm`] and
+    /// [`MicroTex::with_font`].
+    pub filename: String,
+
+    /// Font family from the CLM header, such as `"XITS Math"`. Falls back to
+    /// the file name up to the first `-` if the header does not parse.
+    pub family: Option<String>,
+
+    /// Whether the font is an OpenType math font, usable to render formulas.
+    /// Falls back to whether the file name contains `math` if the header
+    /// does not parse.
+    pub is_math: bool,
+}
 
-        Ok(Self {
-            key_char_heights,
-            key_char_count,
-            average_char_height,
-            max_char_height,
-            min_char_height,
-            box_tree_height,
-        })
+impl FontInfo {
+    /// Reads the family and math flag of the font `filename` from its data.
+    fn from_clm(filename: &str, data: &[u8]) -> Self {
+        let (family, is_math) = match parse_clm_header(data) {
+            Ok(header) => (Some(header.family), header.is_math_font),
+            Err(_) => {
+                let stem = filename.split('.').next().unwrap_or_default();
+                let family = stem.split('-').next().unwrap_or_default();
+                (
+                    Some(family.to_string()).filter(|family| !family.is_empty()),
+                    filename.to_ascii_lowercase().contains("math"),
+                )
+            }
+        };
+        FontInfo {
+            filename: filename.to_string(),
+            family,
+            is_math,
+        }
     }
 }
 
+/// Lists the embedded fonts with their family names, in the order of
+/// [`available_embedded_clms`], for example to fill a font picker.
 ///
-///
-/// This struct manages the lifecycle of a MicroTeX instance and provides
-/// safe methods to render LaTeX strings to SVG format. It automatically
-/// handles initialization and cleanup of the underlying C++ library.
-///
-/// **Important:** The MicroTeX engine must be initialized **only once**.
-/// Calling `MicroTex::new()` multiple times (concurrently or sequentially)
-/// can crash the underlying C++ engine. Prefer using a thread-safe
-/// singleton for global access. Example:
+/// # Example
 ///
 /// ```rust
-/// use std::sync::OnceLock;
-/// use microtex_rs::{MicroTex, RenderConfig, RenderError};
-///
-/// // Global MicroTeX renderer instance - initialized only once
-/// static MICROTEX_RENDERER: OnceLock<MicroTex> = OnceLock::new();
-///
-/// // Get or initialize the MicroTeX renderer (thread-safe singleton).
-/// // `MicroTex::new()` is fallible and must only be called once.
-/// fn get_microtex_renderer() -> Result<&'static MicroTex, RenderError> {
-///     if let Some(renderer) = MICROTEX_RENDERER.get() {
-///         return Ok(renderer);
-///     }
+/// use microtex_rs::embedded_font_info;
 ///
-///     // Initialize once and store the instance in the OnceLock
-///     let renderer = MicroTex::new()?;
-///     MICROTEX_RENDERER
-///         .set(renderer)
-///         .map_err(|_| RenderError::InitializationFailed)?;
-///     Ok(MICROTEX_RENDERER.get().unwrap())
+/// for font in embedded_font_info().iter().filter(|font| font.is_math) {
+///     println!("{}: {}", font.filename, font.family.as_deref().unwrap_or("?"));
 /// }
-///
-/// // Use the shared renderer
-/// let renderer = get_microtex_renderer()?;
-/// let config = RenderConfig::default();
-/// let svg = renderer.render(r#"\[E = mc^2\]"#, &config)?;
-/// assert!(svg.contains("<svg"));
-/// # Ok::<(), Box<dyn std::error::Error>>(())
 /// ```
-pub struct MicroTex {
-    _private: (),
+pub fn embedded_font_info() -> Vec<FontInfo> {
+    available_embedded_clms()
+        .into_iter()
+        .map(|name| FontInfo::from_clm(name, get_embedded_clm(name).unwrap_or_default()))
+        .collect()
 }
 
-/// Adds DPI metadata to an SVG string as a `data-dpi` attribute.
-///
-/// This function injects the rendering DPI value into the SVG root element
-/// as a `data-dpi` attribute. This metadata is useful for downstream processors
-/// that need to know the DPI at which the SVG was rendered, particularly when
-/// converting to other formats (e.g., PDF) where proper sizing depends on
-/// knowing the original DPI.
-///
-/// # Arguments
-///
-/// * `svg` - The SVG content as a string
-/// * `dpi` - The DPI value to embed (typically 720 for MicroTeX)
-///
-/// # Returns
+/// Retrieves embedded CLM font data by file name, ignoring ASCII case and
+/// accepting the name without its extension.
 ///
-/// A modified SVG string with the `data-dpi` attribute added to the `<svg>` element.
-/// If the SVG doesn't contain an `<svg` opening tag, the original string is returned unchanged.
+/// `xitsmath-regular`, `XITSMath-Regular` and `xitsmath-regular.CLM2` all
+/// find `XITSMath-Regular.clm2`. An exact match wins; otherwise the first
+/// match in the order of [`available_embedded_clms`] is returned. Use
+/// [`get_embedded_clm`] for a strict lookup.
 ///
 /// # Example
 ///
 /// ```rust
-/// use microtex_rs::add_dpi_to_svg;
+/// use microtex_rs::{get_embedded_clm, get_embedded_clm_fuzzy};
 ///
-/// let svg = r#"<svg width="100" height="50" xmlns="http://www.w3.org/2000/svg"></svg>"#;
-/// let dpi = 720;
-/// let modified = add_dpi_to_svg(svg, dpi);
-/// assert!(modified.contains(r#"data-dpi="720""#));
+/// if let Some(data) = get_embedded_clm("XITSMath-Regular.clm2") {
+///     assert_eq!(get_embedded_clm_fuzzy("xitsmath-regular"), Some(data));
+/// }
 /// ```
-pub fn add_dpi_to_svg(svg: &str, dpi: i32) -> String {
-    // Find the opening <svg tag
-    if let Some(svg_start) = svg.find("<svg") {
-        if let Some(close_bracket) = svg[svg_start..].find('>') {
-            let insert_pos = svg_start + close_bracket;
-            let mut result = String::with_capacity(svg.len() + 20);
-            result.push_str(&svg[..insert_pos]);
-            result.push_str(&format!(r#" data-dpi="{}""#, dpi));
-            result.push_str(&svg[insert_pos..]);
-            return result;
-        }
+pub fn get_embedded_clm_fuzzy(name: &str) -> Option<&'static [u8]> {
+    if let Some(data) = get_embedded_clm(name) {
+        return Some(data);
     }
-    // If no <svg tag found or malformed, return original
-    svg.to_string()
+    available_embedded_clms()
+        .into_iter()
+        .find(|candidate| {
+            let stem = candidate
+                .rsplit_once('.')
+                .map_or(*candidate, |(stem, _)| stem);
+            candidate.eq_ignore_ascii_case(name) || stem.eq_ignore_ascii_case(name)
+        })
+        .and_then(get_embedded_clm)
 }
 
-/// Extracts all Y coordinates from SVG path elements, accounting for transformations.
-///
-/// This function parses all `<path>` elements in an SVG and extracts Y coordinates
-/// from the path data (M, L, C, Q, etc. commands). It applies any `transform="matrix(...)"`
-/// attributes to get the actual Y coordinates after transformation.
-///
-/// # Arguments
-///
-/// * `svg` - The SVG content as a string
-///
-/// # Returns
-///
-/// A vector of all Y coordinate values found in path data after applying transformations.
-/// Returns an empty vector if no paths or coordinates are found.
-///
-/// # Example
+/// Configuration for rendering LaTeX to SVG.
 ///
-/// ```rust
-/// use microtex_rs::extract_y_coordinates;
+/// This structure holds all parameters needed to control how LaTeX
+/// formulas are rendered to SVG format.
 ///
-/// let svg = r#"<svg><path d="M 10 20 L 30 40 Z"/></svg>"#;
-/// let y_coords = extract_y_coordinates(svg);
-/// assert!(y_coords.contains(&20.0));
-/// assert!(y_coords.contains(&40.0));
-/// ```
-pub fn extract_y_coordinates(svg: &str) -> Vec<f32> {
-    let mut y_coords = Vec::new();
+/// The configuration can be serialized, for example to persist user
+/// preferences as JSON. Fields missing when deserializing take their
+/// [`Default`] value, so data saved by older versions still loads.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RenderConfig {
+    /// DPI (dots per inch) for the output. Default: 720
+    pub dpi: i32,
 
-    // Find all <path> elements
-    let mut search_start = 0;
-    while let Some(path_start) = svg[search_start..].find("<path") {
-        let path_start = search_start + path_start;
+    /// Line width in pixels. Default: 20.0
+    pub line_width: f32,
 
-        // Extract the transform matrix if present
-        // Look for transform="matrix(a, b, c, d, e, f)"
-        let transform_matrix =
-            if let Some(transform_idx) = svg[path_start..].find(r#"transform="matrix("#) {
-                let transform_start = path_start + transform_idx + 18; // Skip 'transform="matrix('
-                if let Some(close_paren) = svg[transform_start..].find(')') {
-                    let matrix_str = &svg[transform_start..transform_start + close_paren];
-                    // Parse matrix values: a, b, c, d, e, f
-                    let values: Vec<f32> = matrix_str
-                        .split(',')
-                        .filter_map(|s| s.trim().parse::<f32>().ok())
-                        .collect();
+    /// Line height in pixels. Default: 20.0/3.0 (~6.67)
+    pub line_height: f32,
 
-                    if values.len() >= 6 {
-                        Some((
-                            values[0], values[1], values[2], values[3], values[4], values[5],
-                        ))
-                    } else {
-                        None
-                    }
-                } else {
-                    None
-                }
-            } else {
-                None
-            };
+    /// Text color. Default: [`Color::BLACK`]
+    pub text_color: Color,
 
-        // Find the d=" attribute
-        if let Some(d_attr_start) = svg[path_start..].find(r#"d=""#) {
-            let d_start = path_start + d_attr_start + 3; // Skip d="
+    /// Whether to enable background color rendering.
+    pub has_background: bool,
 
-            // Find the closing quote of the d attribute
-            if let Some(d_end) = svg[d_start..].find('"') {
-                let d_content = &svg[d_start..d_start + d_end];
+    /// Background color, painted over the whole canvas when
+    /// `has_background` is true. Default: [`Color::TRANSPARENT`]
+    pub background_color: Color,
 
-                // Parse the path data
-                let mut chars = d_content.chars().peekable();
-                let mut current_num = String::new();
-                let mut coords = Vec::new();
+    /// Whether to use path-based glyph rendering for better fallback when
+    /// system fonts are not available.
+    pub render_glyph_use_path: bool,
 
-                while let Some(ch) = chars.next() {
-                    match ch {
-                        '0'..='9' | '-' | '.' => {
-                            current_num.push(ch);
-                        }
-                        ' ' | ',' | '\n' | '\t' | '\r' => {
-                            if !current_num.is_empty() {
-                                if let Ok(num) = current_num.parse::<f32>() {
-                                    coords.push(num);
-                                }
-                                current_num.clear();
-                            }
-                        }
-                        'M' | 'L' | 'H' | 'V' | 'C' | 'S' | 'Q' | 'T' | 'A' | 'Z' | 'm' | 'l'
-                        | 'h' | 'v' | 'c' | 's' | 'q' | 't' | 'a' | 'z' => {
-                            if !current_num.is_empty() {
-                                if let Ok(num) = current_num.parse::<f32>() {
-                                    coords.push(num);
-                                }
-                                current_num.clear();
-                            }
-                        }
-                        _ => {
-                            if !current_num.is_empty() {
-                                if let Ok(num) = current_num.parse::<f32>() {
-                                    coords.push(num);
-                                }
-                                current_num.clear();
-                            }
-                        }
-                    }
-                }
-
-                // Handle the last number if any
-                if !current_num.is_empty() {
-                    if let Ok(num) = current_num.parse::<f32>() {
-                        coords.push(num);
-                    }
-                }
-
-                // Parse coordinates based on SVG path commands
-                // Most commands have Y coordinates at specific positions
-                // For simplicity, we assume coordinates alternate X, Y in most cases
-                // This is a heuristic approach - we collect every other coordinate as Y
-                let mut i = 0;
-                while i < coords.len() {
-                    // Most path commands use X, Y pairs
-                    // We extract Y coordinates (every second value in most cases)
-                    if i + 1 < coords.len() {
-                        let mut y = coords[i + 1]; // Y coordinate
-
-                        // Apply transformation matrix if present
-                        if let Some((a, b, c, d, e, f)) = transform_matrix {
-                            let x = coords[i]; // X coordinate for transformation
-                                               // y' = b*x + d*y + f
-                            y = b * x + d * y + f;
-                        }
-
-                        y_coords.push(y);
-                        i += 2;
-                    } else {
-                        i += 1;
-                    }
-                }
-
-                search_start = d_start + d_end + 1;
-            } else {
-                search_start = path_start + 1;
-            }
-        } else {
-            search_start = path_start + 1;
-        }
-    }
-
-    y_coords
-}
-
-/// Adjusts SVG height and viewBox, then centers content with a transform group.
-///
-/// This function analyzes the actual Y coordinates in the SVG, increases the height
-/// if needed, and wraps the content in a `<g>` element with a vertical translation
-/// to center the content. This prevents clipping of glyphs that exceed the declared height.
-///
-/// # Arguments
-///
-/// * `svg` - The SVG content as a string
-///
-/// # Returns
-///
-/// A modified SVG string with adjusted height/viewBox and centered content, or the
-/// original SVG if max_y < 0.02 (within tolerance).
-///
-/// # Algorithm
-///
-/// 1. Extract all Y coordinates (accounting for transformations)
-/// 2. Find max_y value
-/// 3. If max_y < 0.02, return SVG unchanged (within tolerance)
-/// 4. Otherwise:
-///    - Calculate new_height = ceil(max_y)
-///    - Update height and viewBox height attributes
-///    - Wrap all path elements in a `<g>` with translate(0, -max_y/2)
-/// 5. Return modified SVG
-///
-/// # Example
-///
-/// ```rust
-/// use microtex_rs::adjust_svg_height_and_center;
-///
-/// let svg = r#"<svg width="188" height="39" viewBox="0 0 188 39">
-///   <path d="M 10 20 L 30 39.121094 Z"/>
-/// </svg>"#;
-/// let adjusted = adjust_svg_height_and_center(svg);
-/// // adjusted now has height="40" and viewBox="0 0 188 40"
-/// // and content wrapped in <g transform="translate(0, -19.560547)">
-/// ```
-pub fn adjust_svg_height_and_center(svg: &str) -> String {
-    use quick_xml::events::{BytesEnd, BytesStart, Event};
-    use quick_xml::Reader;
-    use quick_xml::Writer;
-    use std::io::Cursor;
-
-    // Extract Y coordinates and find max
-    let y_coords = extract_y_coordinates(svg);
-    if y_coords.is_empty() {
-        return svg.to_string();
-    }
-
-    let max_y = y_coords.iter().copied().fold(f32::NEG_INFINITY, f32::max);
-
-    // If max_y is within tolerance, return SVG unchanged
-    if max_y < 0.02 {
-        return svg.to_string();
-    }
-
-    // Calculate new height
-    let new_height = max_y.ceil() as i32;
-    let translate_y = (new_height as f32 - max_y) / 2.0;
-    let height_str = new_height.to_string();
-    let transform_str = format!("translate(0, {})", translate_y);
+    /// Whether to enable formula numbering.
+    pub enable_formula_numbering: bool,
 
-    // Parse and rebuild SVG with quick-xml
-    let mut reader = Reader::from_str(svg);
-    let mut writer = Writer::new(Cursor::new(Vec::new()));
-    let mut buffer = Vec::new();
-    let mut in_svg = false;
-    let mut g_opened = false;
-    let mut found_svg_end = false;
+    /// Whether to pad the SVG vertically so the baseline sits at exactly half
+    /// of the output height.
+    ///
+    /// The padding is derived from the formula's ascent and depth: deep
+    /// formulas get padding at the top, tall ones at the bottom. Only the
+    /// metrics render path ([`MicroTex::render_to_svg_with_metrics`]) applies
+    /// it, since it needs [`RenderMetrics`]. Default: false
+    pub pad_for_depth: bool,
 
-    loop {
-        buffer.clear();
-        match reader.read_event_into(&mut buffer) {
-            Ok(Event::Text(e)) => {
-                let _ = writer.write_event(Event::Text(e));
-            }
-            Ok(Event::Start(e)) => {
-                let name = e.name();
+    /// Line ending style used when writing SVG files. Default: [`LineEnding::Lf`]
+    pub line_ending: LineEnding,
 
-                // Handle SVG tag
-                if name.as_ref() == b"svg" {
-                    in_svg = true;
-                    let mut svg_start = BytesStart::new("svg");
-                    let mut viewbox_new = String::new();
+    /// Whether metrics JSON errors should carry the raw JSON returned by the
+    /// C++ wrapper ([`RenderError::ParseJsonFailedRaw`]). Useful to diagnose
+    /// wrapper/version mismatches. Default: false
+    pub capture_raw_json: bool,
 
-                    // Process attributes
-                    for attr_result in e.attributes() {
-                        if let Ok(attr) = attr_result {
-                            let key_str = std::str::from_utf8(attr.key.as_ref()).unwrap_or("");
-                            let value_str = std::str::from_utf8(&attr.value).unwrap_or("");
+    /// Maximum brace nesting depth accepted in the LaTeX source.
+    ///
+    /// The depth is the largest number of simultaneously open `{` groups,
+    /// ignoring escaped `\{` and `\}`. Since every `\frac{..}{..}`, script and
+    /// command argument is a brace group, this bounds nested fractions (e.g.
+    /// continued fractions) and scripts. Sources over the limit are rejected
+    /// with [`RenderError::NestingTooDeep`] before reaching MicroTeX.
+    /// Default: None (no limit)
+    pub max_nesting_depth: Option<u32>,
+
+    /// Maximum length of the LaTeX source in bytes, measured after macro
+    /// expansion. Longer sources are rejected with
+    /// [`RenderError::SourceTooLong`] before reaching MicroTeX.
+    /// Default: None (no limit)
+    pub max_source_len: Option<usize>,
+
+    /// Commands rejected with [`RenderError::DisallowedCommand`] before the
+    /// source reaches MicroTeX, given by name with or without the leading
+    /// backslash (`"newcommand"` or `"\\newcommand"`). The source is checked
+    /// after macro expansion. Default: empty
+    pub disallowed_commands: Vec<String>,
+
+    /// Forces the baseline to sit this many pixels below the top of the SVG.
+    ///
+    /// Applied after [`adjust_svg_height_and_center`]: the top edge of the
+    /// adjusted SVG is extended, or cropped if the formula is taller than the
+    /// forced ascent, while the depth below the baseline is kept. This replaces
+    /// the natural centering for baseline-grid layouts and takes precedence
+    /// over [`pad_for_depth`](Self::pad_for_depth). Only the metrics render
+    /// path ([`MicroTex::render_to_svg_with_metrics`]) applies it.
+    /// Default: None
+    pub force_ascent_px: Option<i32>,
+
+    /// Argument-less macros as `(name, expansion)` pairs, e.g.
+    /// `("RR", r"\mathbb{R}")` for `\newcommand{\RR}{\mathbb{R}}`.
+    ///
+    /// Expansion is done in Rust by textual substitution before the source is
+    /// handed to MicroTeX (MicroTeX's own `\newcommand` definitions are global
+    /// and cannot be redefined on every render). A leading backslash in the
+    /// name is optional, and `\RR` does not match inside `\RRx`. Macros are
+    /// expanded in order, so later expansions may use earlier macros only if
+    /// they are listed first. Default: empty
+    pub macros: Vec<(String, String)>,
+
+    /// Maximum `(width, height)` of the rendered formula in pixels.
+    ///
+    /// When the measured [`RenderMetrics`] width or total height exceed the
+    /// bound, rendering fails with [`RenderError::OutputTooLarge`]. Only the
+    /// metrics render path ([`MicroTex::render_to_svg_with_metrics`]) checks
+    /// it, since it needs the metrics. Default: None
+    pub max_output_dimensions: Option<(i32, i32)>,
 
-                            if key_str == "height" {
-                                continue;
-                            } else if key_str == "viewBox" {
-                                let parts: Vec<&str> = value_str.split_whitespace().collect();
-                                if parts.len() == 4 {
-                                    viewbox_new = format!(
-                                        "{} {} {} {}",
-                                        parts[0], parts[1], parts[2], new_height
-                                    );
-                                    svg_start.push_attribute(("viewBox", viewbox_new.as_str()));
-                                } else {
-                                    svg_start.push_attribute((key_str, value_str));
-                                }
-                            } else {
-                                svg_start.push_attribute((key_str, value_str));
-                            }
-                        }
-                    }
+    /// Maximum length of MicroTeX's output in bytes.
+    ///
+    /// Checked before the output is copied into a `String`, so a formula
+    /// that produces enormous output fails with
+    /// [`RenderError::OutputBytesTooLarge`] without the copy. For
+    /// [`MicroTex::render_to_svg_with_metrics`] the limit applies to the
+    /// JSON payload holding the SVG and its metrics. Default: None
+    pub max_output_bytes: Option<usize>,
+
+    /// Space around binary operators such as `+`, in mu (TeX's `\medmuskip`).
+    ///
+    /// A mu is 1/18 of the math em (the `quad` of the math font at the
+    /// current size), so the spacing scales with the formula. Default: 4.0
+    pub op_spacing: f32,
 
-                    svg_start.push_attribute(("height", height_str.as_str()));
-                    let _ = writer.write_event(Event::Start(svg_start));
-                } else if in_svg && !g_opened {
-                    // Open <g> before first non-SVG child
-                    let mut g_start = BytesStart::new("g");
-                    g_start.push_attribute(("transform", transform_str.as_str()));
-                    let _ = writer.write_event(Event::Start(g_start));
-                    g_opened = true;
+    /// Space around relations such as `=`, in mu (TeX's `\thickmuskip`).
+    ///
+    /// See [`op_spacing`](Self::op_spacing) for the unit. Default: 5.0
+    pub rel_spacing: f32,
 
-                    // Write the current element
-                    let _ = writer.write_event(Event::Start(e));
-                } else {
-                    let _ = writer.write_event(Event::Start(e));
-                }
-            }
-            Ok(Event::End(e)) => {
-                let name = e.name();
+    /// Placement of the limits of big operators, regardless of the math style.
+    ///
+    /// [`LimitsMode::Always`] and [`LimitsMode::Never`] override explicit
+    /// `\limits` and `\nolimits` too. The setting applies only to renders
+    /// using this config. Default: [`LimitsMode::Default`]
+    pub limits_mode: LimitsMode,
 
-                if in_svg && name.as_ref() == b"svg" {
-                    // Close <g> before closing </svg>
-                    if g_opened {
-                        let _ = writer.write_event(Event::End(BytesEnd::new("g")));
-                    }
-                    let _ = writer.write_event(Event::End(e));
-                    found_svg_end = true;
-                    break; // Now we can break after processing </svg>
-                } else {
-                    let _ = writer.write_event(Event::End(e));
-                }
-            }
-            Ok(event) => {
-                match &event {
-                    Event::Empty(e) => {
-                        let name = e.name();
+    /// Renders the whole formula in bold, as if wrapped in `\boldsymbol`.
+    ///
+    /// Letters, digits and Greek letters use the bold variants of the math
+    /// font (its Mathematical Bold Unicode alphanumerics). Symbols without a
+    /// bold variant, such as most operators, stay regular: there is no
+    /// synthetic emboldening. The setting applies only to renders using this
+    /// config. Default: false
+    pub bold: bool,
+
+    /// Font family used for text mode, such as `\text{...}`, by name (for
+    /// example `"Sans"` or `"DejaVu Serif"`).
+    ///
+    /// Set before each render, so renders with different fonts can share an
+    /// instance. `None` uses `"Serif"`, the font MicroTeX is initialized
+    /// with. A `defaultMainFont` entry in
+    /// [`extra_options`](Self::extra_options) takes precedence. Must not
+    /// contain NUL bytes. Default: None
+    pub main_font: Option<String>,
+
+    /// Raw options forwarded to MicroTeX as `key = value` pairs before parsing.
+    ///
+    /// An escape hatch for MicroTeX settings this crate does not wrap yet.
+    /// The C wrapper recognizes `defaultMainFont`, `defaultMathFont`,
+    /// `renderGlyphUsePath`, `medMuSkip` and `thickMuSkip`; unknown keys and
+    /// unparsable values are silently ignored. Options are applied after the
+    /// typed fields, in key order, so they take precedence. Like the typed
+    /// settings they apply only to renders using this config: the settings
+    /// they change are reset before each render. Default: empty
+    pub extra_options: HashMap<String, String>,
+
+    /// Extra attributes added to the root `<svg>` element, such as
+    /// `("class", "math")`, for CSS targeting or scripting.
+    ///
+    /// Values are escaped. Names must be well-formed XML names and must not
+    /// be one the renderer writes itself (`width`, `height`, `viewBox`,
+    /// `data-dpi`, `data-latex` and `xmlns` declarations). Default: empty
+    pub svg_attributes: Vec<(String, String)>,
+
+    /// Stores the LaTeX source in a `data-latex` attribute on the root
+    /// `<svg>` element, so that scripts (a "copy LaTeX" button, for example)
+    /// can read it back.
+    ///
+    /// The source is stored as given, before macro expansion, and is escaped
+    /// for the attribute value. Default: false
+    pub embed_source: bool,
+
+    /// Replaces `\mathbb`, `\mathcal`, `\mathscr` and `\mathfrak` applied to
+    /// a single letter (or digit, for `\mathbb`) with the matching character
+    /// of the Unicode Mathematical Alphanumeric Symbols block, such as
+    /// `\mathbb{R}` with `ℝ`.
+    ///
+    /// Letters that Unicode encodes earlier, in the Letterlike Symbols block
+    /// (`ℂ ℍ ℕ ℙ ℚ ℝ ℤ`, `ℬ ℰ ℱ ℋ ℐ ℒ ℳ ℛ ℯ ℊ ℴ`, `ℭ ℌ ℑ ℜ ℨ`), use those
+    /// code points. `\mathcal` and `\mathscr` both map to the script letters.
+    /// A command is only replaced if the font has a glyph for the character;
+    /// otherwise it is left for MicroTeX to render as usual. Other arguments,
+    /// such as `\mathbb{AB}`, are left unchanged too. Default: false
+    pub unicode_math_alphabets: bool,
+
+    /// Swaps opening and closing delimiters, for formulas placed in a
+    /// right-to-left layout.
+    ///
+    /// Following the Unicode bidirectional mirroring pairs, `(` and `)`,
+    /// `[` and `]`, `\{` and `\}` (`\lbrace`/`\rbrace`), `\lbrack` and
+    /// `\rbrack`, `\langle` and `\rangle`, `\lfloor` and `\rfloor`, and
+    /// `\lceil` and `\rceil` are exchanged before rendering, including
+    /// after `\left`, `\right` and the `\big` family. Other characters,
+    /// such as `<` and `>` used as relations, are not mirrored, and neither
+    /// are the `\[`/`\]` display delimiters or the `[...]` optional
+    /// arguments of commands and environments, such as `\sqrt[3]{x}`,
+    /// `\\[2pt]` or `\begin{aligned}[t]`.
+    ///
+    /// MicroTeX only lays formulas out from left to right: this mirrors the
+    /// delimiter glyphs, but does not reverse the order of the symbols, so
+    /// `(a+b)` renders as `)a+b(`. It is meant for callers that arrange the
+    /// pieces of a right-to-left formula themselves. Default: false
+    pub mirror_delimiters: bool,
 
-                        // If we haven't opened <g> yet and we're in SVG, open it now
-                        if in_svg && !g_opened {
-                            let mut g_start = BytesStart::new("g");
-                            g_start.push_attribute(("transform", transform_str.as_str()));
-                            let _ = writer.write_event(Event::Start(g_start));
-                            g_opened = true;
-                        }
+    /// Minimum width of stroked lines, in output pixels.
+    ///
+    /// Rules such as fraction bars are drawn as strokes, which can become
+    /// thinner than a pixel once scaled down and vanish when rasterized.
+    /// Thinner strokes are widened to this width, taking the path transforms
+    /// into account. Must be positive and finite. Default: None
+    pub min_stroke_width_px: Option<f32>,
+
+    /// Shrinks the SVG with [`minify_svg`]: drops the whitespace between
+    /// tags and default opacities, and shortens colors, which MicroTeX
+    /// repeats on every path. Applied by every render method that
+    /// post-processes the SVG. Default: false
+    pub minify: bool,
+
+    /// Removes scripts, event handlers and foreign content from the SVG with
+    /// [`sanitize_svg`], for output embedded in pages that also show other
+    /// users' content. Applied by every render method that post-processes
+    /// the SVG, after [`svg_attributes`](Self::svg_attributes) are added.
+    /// Default: false
+    pub sanitize: bool,
+
+    /// Maximum time to wait for MicroTeX to parse and render a formula.
+    ///
+    /// When set, the render runs on a worker thread, and a render that takes
+    /// longer fails with [`RenderError::Timeout`]. MicroTeX cannot be
+    /// interrupted: the worker finishes the render in the background, and
+    /// later renders wait for it to release MicroTeX, which counts towards
+    /// their own timeout. Must not be zero. Default: None (renders run on
+    /// the calling thread)
+    pub render_timeout: Option<std::time::Duration>,
+}
 
-                        let _ = writer.write_event(event);
-                    }
-                    _ => {
-                        let _ = writer.write_event(event);
-                    }
-                }
-            }
-            Err(_) => break,
-        }
+/// Commands rejected by [`RenderConfig::safe_profile`]: definitions, which
+/// stay in effect in MicroTeX for later renders and can recurse without
+/// bound (MicroTeX expands `\DeclareMathOperator` to `\newcommand`), and
+/// `\includegraphics`, which reads files from disk.
+const SAFE_PROFILE_DISALLOWED_COMMANDS: &[&str] = &[
+    "newcommand",
+    "renewcommand",
+    "newenvironment",
+    "renewenvironment",
+    "DeclareMathOperator",
+    "includegraphics",
+];
+
+impl RenderConfig {
+    /// Returns [`text_color`](Self::text_color) packed as ARGB
+    /// (`0xAARRGGBB`), the layout MicroTeX takes.
+    pub fn text_color_argb(&self) -> u32 {
+        self.text_color.to_argb()
     }
 
-    let cursor = writer.into_inner();
-    let bytes = cursor.into_inner();
-    String::from_utf8_lossy(&bytes).to_string()
-}
+    /// Returns [`background_color`](Self::background_color) packed as ARGB
+    /// (`0xAARRGGBB`), the layout MicroTeX takes.
+    pub fn background_color_argb(&self) -> u32 {
+        self.background_color.to_argb()
+    }
 
-impl MicroTex {
-    /// Creates a new MicroTeX renderer instance with embedded fonts.
+    /// Returns the default configuration hardened for rendering untrusted
+    /// input, such as in a public-facing rendering service.
     ///
-    /// This initializes the MicroTeX library with the XITS Math font
-    /// that is embedded at compile time. The renderer will automatically
-    /// clean up resources when dropped.
+    /// On top of [`RenderConfig::default`], the profile enforces:
     ///
-    /// # Errors
+    /// - [`max_source_len`](Self::max_source_len): 4096 bytes
+    /// - [`max_nesting_depth`](Self::max_nesting_depth): 32
+    /// - [`disallowed_commands`](Self::disallowed_commands): `\newcommand`,
+    ///   `\renewcommand`, `\newenvironment`, `\renewenvironment`,
+    ///   `\DeclareMathOperator` (definitions persist across renders and can
+    ///   recurse forever) and
+    ///   `\includegraphics` (reads files from disk)
+    /// - [`max_output_dimensions`](Self::max_output_dimensions): 8192x8192
+    ///   pixels, checked by [`MicroTex::render_to_svg_with_metrics`]
+    /// - [`render_timeout`](Self::render_timeout): 5 seconds
+    /// - [`sanitize`](Self::sanitize): scripts, event handlers and foreign
+    ///   content are stripped from the SVG (except by
+    ///   [`MicroTex::render_measured_borrowed`], which returns MicroTeX's
+    ///   output as is)
     ///
-
-    /// Returns [`RenderError::InitializationFailed`] if the font metadata
-    /// cannot be loaded or the MicroTeX library initialization fails.
+    /// [`macros`](Self::macros) and [`extra_options`](Self::extra_options)
+    /// are left empty.
     ///
     /// # Example
     ///
-    /// The MicroTeX renderer must only be initialized once; prefer using a global
-    /// singleton (for example, `OnceLock`) to avoid multiple initializations. Example:
-    ///
     /// ```rust
-    /// use std::sync::OnceLock;
-    /// use microtex_rs::{MicroTex, RenderError};
-    ///
-    /// static MICROTEX_RENDERER: OnceLock<MicroTex> = OnceLock::new();
-    ///
-    /// fn get_microtex_renderer() -> Result<&'static MicroTex, RenderError> {
-    ///     if let Some(r) = MICROTEX_RENDERER.get() { return Ok(r); }
-    ///     let renderer = MicroTex::new()?;
-    ///     MICROTEX_RENDERER.set(renderer).map_err(|_| RenderError::InitializationFailed)?;
-    ///     Ok(MICROTEX_RENDERER.get().unwrap())
-    /// }
+    /// use microtex_rs::{MicroTex, RenderConfig, RenderError};
     ///
-    /// let _renderer = get_microtex_renderer()?;
+    /// let renderer = MicroTex::new()?;
+    /// let config = RenderConfig::safe_profile();
+    /// assert!(matches!(
+    ///     renderer.render(r#"\newcommand{\x}{\x}\x"#, &config),
+    ///     Err(RenderError::DisallowedCommand(_))
+    /// ));
     /// # Ok::<(), Box<dyn std::error::Error>>(())
     /// ```
-    pub fn new() -> Result<Self, RenderError> {
-        // Try to find a suitable math font from the embedded CLM files
-        // Note: We search in a specific order, preferring XITS which is well-tested
-        // IMPORTANT: Math fonts must come before non-math fonts!
-        // XITSMath-Regular is the math font version, not XITS-Regular
-        let font_candidates = [
-            "XITSMath-Regular.clm2",
-            "FiraMath-Regular.clm2",
-            "latinmodern-math.clm2",
-            "texgyredejavu-math.clm2",
-        ];
-
-        let mut clm_data = None;
-        for font_name in &font_candidates {
-            if let Some(data) = get_embedded_clm(font_name) {
-                clm_data = Some(data);
-                break;
-            }
-        }
-
-        let clm_data = clm_data.ok_or_else(|| {
-            eprintln!(
-                "No suitable math fonts found in embedded CLM files. Available: {:?}",
-                available_embedded_clms()
-            );
-            RenderError::InitializationFailed
-        })?;
-
-        unsafe {
-            // Critical: Initialize MicroTeX with font data (via shim)
-            // This call may throw C++ exceptions if the data is invalid
-            let meta = shim::microtex_init(clm_data.len() as u64, clm_data.as_ptr());
-            if meta.is_null() {
-                eprintln!("microtex_init returned null");
-                return Err(RenderError::InitializationFailed);
-            }
-
-            // Set reasonable defaults
-            let default_font = std::ffi::CStr::from_bytes_with_nul(b"Serif\0")
-                .unwrap()
-                .as_ptr();
-            shim::microtex_set_default_main_font(default_font as *const c_char);
-            shim::microtex_set_render_glyph_use_path(true);
-
-            // Important: release the font metadata after initialization
-            shim::microtex_release_font_meta(meta);
+    pub fn safe_profile() -> Self {
+        Self {
+            max_source_len: Some(4096),
+            max_nesting_depth: Some(32),
+            disallowed_commands: SAFE_PROFILE_DISALLOWED_COMMANDS
+                .iter()
+                .map(|command| command.to_string())
+                .collect(),
+            max_output_dimensions: Some((8192, 8192)),
+            sanitize: true,
+            render_timeout: Some(std::time::Duration::from_secs(5)),
+            ..Default::default()
         }
-
-        Ok(MicroTex { _private: () })
     }
 
-    /// Renders a LaTeX formula string to SVG format.
+    /// Checks that the configuration is renderable.
     ///
-    /// # Arguments
+    /// The render methods call this before parsing, so an invalid
+    /// configuration fails with [`RenderError::InvalidConfig`] regardless of
+    /// the formula. Calling it directly lets a batch fail fast before any
+    /// formula is rendered.
     ///
-    /// * `latex_source` - The LaTeX source string to render.
-    /// * `config` - Rendering configuration parameters.
+    /// # Errors
     ///
-    /// # Returns
+    /// Returns the [`ConfigError`] for the first invalid field found.
     ///
-    /// A string containing the SVG representation of the rendered formula,
-    /// or an error if parsing/rendering fails.
+    /// # Example
     ///
-    /// # Errors
+    /// ```rust
+    /// use microtex_rs::{ConfigError, RenderConfig};
     ///
-    /// Returns errors if:
-    /// - The LaTeX source cannot be parsed
-    /// - The rendering process fails
-    /// - The SVG output is empty
-    /// - The SVG buffer cannot be converted to valid UTF-8
+    /// assert!(RenderConfig::default().validate().is_ok());
+    ///
+    /// let config = RenderConfig {
+    ///     dpi: -1,
+    ///     ..Default::default()
+    /// };
+    /// assert_eq!(config.validate(), Err(ConfigError::InvalidDpi(-1)));
+    /// ```
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if self.dpi <= 0 {
+            return Err(ConfigError::InvalidDpi(self.dpi));
+        }
+        if !(self.line_width.is_finite() && self.line_width > 0.0) {
+            return Err(ConfigError::InvalidLineWidth(self.line_width));
+        }
+        if !(self.line_height.is_finite() && self.line_height > 0.0) {
+            return Err(ConfigError::InvalidLineHeight(self.line_height));
+        }
+        if let Some(ascent) = self.force_ascent_px.filter(|ascent| *ascent < 0) {
+            return Err(ConfigError::InvalidForcedAscent(ascent));
+        }
+        if let Some((width, height)) = self.max_output_dimensions {
+            if width <= 0 || height <= 0 {
+                return Err(ConfigError::InvalidMaxOutputDimensions(width, height));
+            }
+        }
+        if self.max_output_bytes == Some(0) {
+            return Err(ConfigError::InvalidMaxOutputBytes);
+        }
+        for (name, _) in &self.macros {
+            let command = name.trim_start_matches('\\');
+            if command.is_empty() || !command.chars().all(|c| c.is_ascii_alphabetic()) {
+                return Err(ConfigError::InvalidMacroName(name.clone()));
+            }
+        }
+        for name in &self.disallowed_commands {
+            let command = name.trim_start_matches('\\');
+            if command.is_empty() || !command.chars().all(|c| c.is_ascii_alphabetic()) {
+                return Err(ConfigError::InvalidCommandName(name.clone()));
+            }
+        }
+        for (key, value) in &self.extra_options {
+            if key.is_empty() || key.contains('\0') || value.contains('\0') {
+                return Err(ConfigError::InvalidOption(key.clone()));
+            }
+        }
+        if let Some(width) = self
+            .min_stroke_width_px
+            .filter(|width| !(width.is_finite() && *width > 0.0))
+        {
+            return Err(ConfigError::InvalidMinStrokeWidth(width));
+        }
+        for (name, _) in &self.svg_attributes {
+            if !is_settable_svg_attribute(name) {
+                return Err(ConfigError::InvalidSvgAttribute(name.clone()));
+            }
+        }
+        if let Some(font) = self.main_font.as_ref().filter(|font| font.contains('\0')) {
+            return Err(ConfigError::InvalidMainFont(font.clone()));
+        }
+        if self.render_timeout == Some(std::time::Duration::ZERO) {
+            return Err(ConfigError::InvalidRenderTimeout);
+        }
+        Ok(())
+    }
+}
+
+/// Root attributes written by the renderer, which `svg_attributes` must not
+/// duplicate.
+const RESERVED_SVG_ATTRIBUTES: &[&str] = &[
+    "width",
+    "height",
+    "viewBox",
+    "data-dpi",
+    "data-latex",
+    "xmlns",
+];
+
+/// Returns true if `name` is a well-formed XML attribute name that
+/// [`RenderConfig::svg_attributes`] may set.
+fn is_settable_svg_attribute(name: &str) -> bool {
+    let mut chars = name.chars();
+    let well_formed = chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_' || c == ':')
+        && chars.all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | ':' | '-' | '.'));
+    well_formed && !RESERVED_SVG_ATTRIBUTES.contains(&name) && !name.starts_with("xmlns:")
+}
+
+impl Default for RenderConfig {
+    fn default() -> Self {
+        Self {
+            dpi: 720,
+            line_width: 20.0,
+            line_height: 20.0 / 3.0,
+            text_color: Color::BLACK,
+            has_background: false,
+            background_color: Color::TRANSPARENT,
+            render_glyph_use_path: true,
+            enable_formula_numbering: false,
+            pad_for_depth: false,
+            line_ending: LineEnding::Lf,
+            capture_raw_json: false,
+            max_nesting_depth: None,
+            max_source_len: None,
+            disallowed_commands: Vec::new(),
+            force_ascent_px: None,
+            macros: Vec::new(),
+            max_output_dimensions: None,
+            max_output_bytes: None,
+            op_spacing: 4.0,
+            rel_spacing: 5.0,
+            limits_mode: LimitsMode::Default,
+            bold: false,
+            main_font: None,
+            extra_options: HashMap::new(),
+            svg_attributes: Vec::new(),
+            embed_source: false,
+            unicode_math_alphabets: false,
+            mirror_delimiters: false,
+            min_stroke_width_px: None,
+            minify: false,
+            sanitize: false,
+            render_timeout: None,
+        }
+    }
+}
+
+/// Scaled points per TeX point (`1pt = 65536sp`).
+pub const SP_PER_PT: f64 = 65536.0;
+
+/// TeX points per inch (`1in = 72.27pt`).
+pub const PT_PER_INCH: f64 = 72.27;
+
+/// Formula dimensions in TeX scaled points, as returned by [`RenderMetrics::to_sp`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SpDimensions {
+    /// The width of the formula in sp.
+    pub width: i64,
+
+    /// The total height (height + depth) of the formula in sp.
+    pub height: i64,
+
+    /// The depth below the baseline in sp.
+    pub depth: i64,
+
+    /// The ascent above the baseline in sp.
+    pub ascent: i64,
+}
+
+/// Dimensional metrics from rendering a LaTeX formula to SVG.
+///
+/// This structure contains the precise dimensional information of a rendered
+/// formula, useful for proper scaling and positioning in PDF documents.
+///
+/// Serializes as its fields only; derived values such as
+/// [`aspect_ratio`](Self::aspect_ratio) stay methods.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RenderMetrics {
+    /// The width of the rendered formula in pixels.
+    pub width: i32,
+
+    /// The total height of the rendered formula in pixels (height + depth).
+    pub height: i32,
+
+    /// The depth of the rendered formula below the baseline in pixels.
+    pub depth: i32,
+
+    /// The ascent of the rendered formula (height without depth) in pixels.
+    pub ascent: i32,
+
+    /// The height of the math axis above the baseline in pixels.
+    ///
+    /// This is the line on which relations (`=`), binary operators (`+`) and
+    /// fraction bars are centered. It is 0 when the renderer did not report it.
+    #[serde(default)]
+    pub math_axis_height: i32,
+}
+
+impl RenderMetrics {
+    /// Creates a new RenderMetrics instance with the specified dimensions.
+    ///
+    /// # Arguments
+    /// * `width` - The width in pixels
+    /// * `height` - The total height (height + depth) in pixels
+    /// * `depth` - The depth below baseline in pixels
+    /// * `ascent` - The ascent (height without depth) in pixels
+    pub fn new(width: i32, height: i32, depth: i32, ascent: i32) -> Self {
+        Self {
+            width,
+            height,
+            depth,
+            ascent,
+            math_axis_height: 0,
+        }
+    }
+
+    /// Returns the effective visual height of the rendered content.
+    ///
+    /// This is the total height including both ascent and descent.
+    pub fn total_height(&self) -> f32 {
+        self.height as f32
+    }
+
+    /// Returns the aspect ratio (width / height) of the rendered content.
+    ///
+    /// Useful for maintaining proportional scaling when resizing.
+    pub fn aspect_ratio(&self) -> f32 {
+        if self.height > 0 {
+            self.width as f32 / self.height as f32
+        } else {
+            1.0
+        }
+    }
+
+    /// Converts the pixel metrics to TeX scaled points.
+    ///
+    /// A pixel is `1/dpi` inch, so `sp = px / dpi * 72.27 * 65536` (see
+    /// [`PT_PER_INCH`] and [`SP_PER_PT`]), rounded to the nearest sp.
+    ///
+    /// # Arguments
+    /// * `dpi` - The resolution the metrics were rendered at
     ///
     /// # Example
     ///
     /// ```rust
-    /// use microtex_rs::{MicroTex, RenderConfig};
+    /// use microtex_rs::RenderMetrics;
     ///
-    /// let renderer = MicroTex::new()?;
-    /// let config = RenderConfig::default();
-    /// let svg = renderer.render(r#"\[x = \frac{-b \pm \sqrt{b^2-4ac}}{2a}\]"#, &config)?;
-    /// assert!(svg.contains("<svg"));
-    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// // One inch at 72 dpi is 72.27pt
+    /// let sp = RenderMetrics::new(72, 72, 0, 72).to_sp(72);
+    /// assert_eq!(sp.width, (72.27_f64 * 65536.0).round() as i64);
     /// ```
-    pub fn render(&self, latex_source: &str, config: &RenderConfig) -> Result<String, RenderError> {
-        let latex_cstr = std::ffi::CString::new(latex_source)
-            .unwrap_or_else(|_| std::ffi::CString::new("").unwrap());
+    pub fn to_sp(&self, dpi: i32) -> SpDimensions {
+        let sp_per_px = PT_PER_INCH * SP_PER_PT / dpi as f64;
+        let to_sp = |px: i32| (px as f64 * sp_per_px).round() as i64;
+
+        SpDimensions {
+            width: to_sp(self.width),
+            height: to_sp(self.height),
+            depth: to_sp(self.depth),
+            ascent: to_sp(self.ascent),
+        }
+    }
 
-        unsafe {
-            let render_ptr = shim::microtex_parse_render(
-                latex_cstr.as_ptr(),
-                config.dpi,
-                config.line_width,
-                config.line_height,
-                config.text_color,
-                config.has_background,
-                config.render_glyph_use_path,
-            );
+    /// Returns the `(width, height)` in PostScript points (1/72 inch), the
+    /// unit of PDF page coordinates.
+    ///
+    /// `pt = px / dpi * 72`; [`to_sp`](Self::to_sp) uses TeX points (1/72.27
+    /// inch) instead. Returns `(0.0, 0.0)` if `dpi <= 0`.
+    ///
+    /// # Arguments
+    /// * `dpi` - The resolution the metrics were rendered at
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use microtex_rs::RenderMetrics;
+    ///
+    /// let metrics = RenderMetrics::new(720, 360, 90, 270);
+    /// assert_eq!(metrics.to_points(720), (72.0, 36.0));
+    /// ```
+    pub fn to_points(&self, dpi: i32) -> (f32, f32) {
+        self.to_physical(dpi, 72.0)
+    }
 
-            if render_ptr.is_null() {
-                return Err(RenderError::ParseRenderFailed);
-            }
+    /// Returns the `(width, height)` in millimeters.
+    ///
+    /// `mm = px / dpi * 25.4`. Returns `(0.0, 0.0)` if `dpi <= 0`.
+    ///
+    /// # Arguments
+    /// * `dpi` - The resolution the metrics were rendered at
+    pub fn to_millimeters(&self, dpi: i32) -> (f32, f32) {
+        self.to_physical(dpi, 25.4)
+    }
 
-            let mut out_len = 0u64;
-            let out_buf = shim::microtex_render_to_svg(render_ptr, &mut out_len);
+    /// Scales the width and total height from pixels to a unit with
+    /// `units_per_inch` units in an inch.
+    fn to_physical(&self, dpi: i32, units_per_inch: f32) -> (f32, f32) {
+        if dpi <= 0 {
+            return (0.0, 0.0);
+        }
+        let scale = units_per_inch / dpi as f32;
+        (self.width as f32 * scale, self.height as f32 * scale)
+    }
 
-            if out_buf.is_null() || out_len == 0 {
-                shim::microtex_delete_render(render_ptr);
-                return Err(RenderError::EmptyOutput);
-            }
+    /// Returns the baseline ratio (ascent / total height) of the rendered content.
+    ///
+    /// This indicates how much of the formula's height is above the baseline.
+    /// - Values close to 1.0: tall formulas with many superscripts
+    /// - Values close to 0.5: balanced formulas
+    /// - Values close to 0.0: deep formulas with many subscripts or fractions
+    pub fn baseline_ratio(&self) -> f32 {
+        if self.height > 0 {
+            self.ascent as f32 / self.height as f32
+        } else {
+            0.5
+        }
+    }
+
+    /// Returns the fraction of the total height below the baseline
+    /// (depth / height), the offset to shift a formula down by when it is
+    /// placed inline with text, relative to its height.
+    ///
+    /// Returns 0.0 if the height is zero.
+    pub fn baseline_offset_fraction(&self) -> f32 {
+        if self.height > 0 {
+            self.depth as f32 / self.height as f32
+        } else {
+            0.0
+        }
+    }
+
+    /// Returns the factor that scales the ascent to `target_cap_px` pixels,
+    /// to match a formula to the cap height of the surrounding text.
+    ///
+    /// Returns 1.0 (no scaling) if the ascent is zero.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use microtex_rs::RenderMetrics;
+    ///
+    /// let metrics = RenderMetrics::new(100, 40, 10, 30);
+    /// assert_eq!(metrics.scale_to_cap_height(15.0), 0.5);
+    /// ```
+    pub fn scale_to_cap_height(&self, target_cap_px: f32) -> f32 {
+        if self.ascent > 0 {
+            target_cap_px / self.ascent as f32
+        } else {
+            1.0
+        }
+    }
+}
+
+impl fmt::Display for RenderMetrics {
+    /// Formats the metrics for logs, as `188x39 (ascent=25, depth=14,
+    /// aspect=4.82)`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}x{} (ascent={}, depth={}, aspect={:.2})",
+            self.width,
+            self.height,
+            self.ascent,
+            self.depth,
+            self.aspect_ratio()
+        )
+    }
+}
+
+/// Result type containing both SVG content and dimensional metrics.
+///
+/// Returned by rendering functions that need to provide both the rendered
+/// SVG string and precise dimensional information for further processing.
+///
+/// Serializes as its fields, with `key_char_metrics` and `precise_bounds` as
+/// `null` when absent, so a service can return it as is.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RenderResult {
+    /// The SVG content as a UTF-8 string.
+    pub svg: String,
 
-            // Convert the buffer to a Rust string
-            let svg_slice = std::slice::from_raw_parts(out_buf as *const u8, out_len as usize);
-            let mut svg_string = String::from_utf8(svg_slice.to_vec())?;
+    /// The dimensional metrics of the rendered formula.
+    pub metrics: RenderMetrics,
+
+    /// Metrics of key characters in the formula (optional).
+    /// Available when rendering with KeyCharMetrics extraction.
+    pub key_char_metrics: Option<KeyCharMetrics>,
 
-            // Add DPI metadata to SVG
-            svg_string = add_dpi_to_svg(&svg_string, config.dpi);
+    /// Sub-pixel bounding box of the SVG paths as `(min_x, min_y, max_x, max_y)`.
+    ///
+    /// Computed from the path coordinates of the final SVG (in SVG user units),
+    /// so unlike the integer [`RenderMetrics`] it keeps fractional precision.
+    /// `None` when the SVG contains no paths.
+    pub precise_bounds: Option<(f32, f32, f32, f32)>,
 
-            // Adjust SVG height and center content to prevent glyph clipping
-            svg_string = adjust_svg_height_and_center(&svg_string);
+    /// Number of `<path>` elements in the SVG.
+    ///
+    /// Each glyph is drawn as one path, so a formula of N symbols should give
+    /// roughly N; a large mismatch hints at a rendering problem such as
+    /// missing glyphs. Fraction bars, rules, radical overlines and other
+    /// decorations are paths too and inflate the count.
+    pub rendered_glyph_count: usize,
+}
 
-            // Clean up
-            shim::microtex_free_buffer(out_buf);
-            shim::microtex_delete_render(render_ptr);
+impl RenderResult {
+    /// Creates a new RenderResult with SVG content and metrics.
+    ///
+    /// [`precise_bounds`](Self::precise_bounds) and
+    /// [`rendered_glyph_count`](Self::rendered_glyph_count) are computed from
+    /// the SVG paths.
+    pub fn new(svg: String, metrics: RenderMetrics) -> Self {
+        let precise_bounds = extract_bounding_box(&svg);
+        let rendered_glyph_count = count_svg_paths(&svg);
+        Self {
+            svg,
+            metrics,
+            key_char_metrics: None,
+            precise_bounds,
+            rendered_glyph_count,
+        }
+    }
 
-            Ok(svg_string)
+    /// Creates a new RenderResult with SVG content, metrics, and key character metrics.
+    pub fn with_key_char_metrics(
+        svg: String,
+        metrics: RenderMetrics,
+        key_char_metrics: KeyCharMetrics,
+    ) -> Self {
+        let precise_bounds = extract_bounding_box(&svg);
+        let rendered_glyph_count = count_svg_paths(&svg);
+        Self {
+            svg,
+            metrics,
+            key_char_metrics: Some(key_char_metrics),
+            precise_bounds,
+            rendered_glyph_count,
         }
     }
 
-    /// Renders a LaTeX formula string to SVG format with dimensional metrics.
+    /// Wraps the SVG in an HTML `<figure>` snippet ready to embed in a page.
     ///
-    /// This function is similar to [`render()`](Self::render), but also returns
-    /// precise dimensional information (width, height, depth, ascent) extracted
-    /// from the MicroTeX BOX TREE before SVG rendering. This is useful for
-    /// accurate scaling and positioning of the rendered formula.
+    /// The SVG is inlined with its own `width`, `height` and `viewBox`, so it
+    /// keeps the size and aspect ratio of the adjusted output. A figure is a
+    /// block on its own, so no baseline alignment is applied; use
+    /// [`to_web_component`](Self::to_web_component) for formulas inside a
+    /// line of text. The `alt` text is exposed to assistive technology through
+    /// `role="img"` and `aria-label`, and is shown as a `<figcaption>` unless
+    /// it is empty. Any XML declaration in the SVG is dropped.
     ///
     /// # Arguments
     ///
-    /// * `latex_source` - The LaTeX source string to render.
-    /// * `config` - Rendering configuration parameters.
+    /// * `alt` - Textual description of the formula (HTML-escaped on output)
     ///
-    /// # Returns
+    /// # Example
     ///
-    /// A [`RenderResult`] containing both the SVG string and the metrics,
-    /// or an error if parsing/rendering fails.
+    /// ```rust
+    /// use microtex_rs::{RenderMetrics, RenderResult};
     ///
-    /// # Errors
+    /// let result = RenderResult::new(
+    ///     r#"<svg viewBox="0 0 100 50"><path d="M 0 0 Z"/></svg>"#.to_string(),
+    ///     RenderMetrics::new(100, 50, 10, 40),
+    /// );
+    /// let html = result.to_html_figure("x squared");
+    /// assert!(html.starts_with("<figure><svg"));
+    /// assert!(html.ends_with("<figcaption>x squared</figcaption></figure>"));
+    /// ```
+    pub fn to_html_figure(&self, alt: &str) -> String {
+        let svg = self.inline_svg(&[("role", "img"), ("aria-label", alt)]);
+        let caption = if alt.is_empty() {
+            String::new()
+        } else {
+            format!(
+                "<figcaption>{}</figcaption>",
+                quick_xml::escape::escape(alt)
+            )
+        };
+
+        format!("<figure>{}{}</figure>", svg, caption)
+    }
+
+    /// Wraps the SVG in a custom element, for web components and frontend
+    /// frameworks.
     ///
-    /// Returns errors if:
-    /// - The LaTeX source cannot be parsed
-    /// - The rendering process fails
-    /// - The output is empty
-    /// - The SVG or metrics JSON cannot be parsed
-    /// - Invalid UTF-8 is encountered
+    /// The generated structure is:
+    ///
+    /// ```html
+    /// <tag width="W" height="H" depth="D" ascent="A"
+    ///      style="display: inline-block; vertical-align: -Dpx">
+    ///   <svg ... width="W" height="H">...</svg>
+    /// </tag>
+    /// ```
+    ///
+    /// The sizing attributes are the [`RenderMetrics`] in pixels: `width` and
+    /// `height` are the formula box (`height` includes the depth), `depth`
+    /// is the part below the baseline and `ascent` the part above it. The
+    /// inline `vertical-align` puts the formula baseline on the surrounding
+    /// text baseline; a component may read the attributes to lay it out
+    /// differently. The SVG is inlined without its XML declaration.
+    ///
+    /// # Arguments
+    ///
+    /// * `tag` - Name of the element, written as given. Custom element names
+    ///   must start with a lowercase letter and contain a hyphen, such as
+    ///   `math-formula`.
     ///
     /// # Example
     ///
     /// ```rust
-    /// use microtex_rs::{MicroTex, RenderConfig};
+    /// use microtex_rs::{RenderMetrics, RenderResult};
     ///
-    /// let renderer = MicroTex::new()?;
-    /// let config = RenderConfig::default();
-    /// let result = renderer.render_to_svg_with_metrics(r#"\[x^2\]"#, &config)?;
-    /// println!("Width: {}, Height: {}", result.metrics.width, result.metrics.height);
-    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// let result = RenderResult::new(
+    ///     r#"<svg viewBox="0 0 100 50"><path d="M 0 0 Z"/></svg>"#.to_string(),
+    ///     RenderMetrics::new(100, 50, 10, 40),
+    /// );
+    /// let html = result.to_web_component("math-formula");
+    /// assert!(html.starts_with(r#"<math-formula width="100" height="50" depth="10" ascent="40""#));
+    /// assert!(html.ends_with("</svg></math-formula>"));
     /// ```
-    pub fn render_to_svg_with_metrics(
-        &self,
-        latex_source: &str,
-        config: &RenderConfig,
-    ) -> Result<RenderResult, RenderError> {
-        let latex_cstr = std::ffi::CString::new(latex_source)
-            .unwrap_or_else(|_| std::ffi::CString::new("").unwrap());
+    pub fn to_web_component(&self, tag: &str) -> String {
+        let RenderMetrics {
+            width,
+            height,
+            depth,
+            ascent,
+            ..
+        } = self.metrics;
+        let width_str = width.to_string();
+        let height_str = height.to_string();
+
+        let svg = self.inline_svg(&[("width", &width_str), ("height", &height_str)]);
+        format!(
+            r#"<{tag} width="{width}" height="{height}" depth="{depth}" ascent="{ascent}" style="display: inline-block; vertical-align: -{depth}px">{svg}</{tag}>"#
+        )
+    }
+
+    /// Returns the SVG without its XML declaration and with `attributes`
+    /// set on the root, replacing any existing value.
+    fn inline_svg(&self, attributes: &[(&str, &str)]) -> String {
+        use quick_xml::events::{BytesStart, Event};
+        use quick_xml::Reader;
+        use quick_xml::Writer;
+        use std::io::Cursor;
+
+        let mut reader = Reader::from_str(&self.svg);
+        let mut writer = Writer::new(Cursor::new(Vec::new()));
+        let mut root_seen = false;
+
+        loop {
+            match reader.read_event() {
+                Ok(Event::Start(e)) if !root_seen && e.name().as_ref() == b"svg" => {
+                    root_seen = true;
+                    let mut svg_start = BytesStart::new("svg");
+                    for attr in e.attributes().flatten() {
+                        if !attributes
+                            .iter()
+                            .any(|(key, _)| key.as_bytes() == attr.key.as_ref())
+                        {
+                            svg_start.push_attribute(attr);
+                        }
+                    }
+                    for attribute in attributes {
+                        svg_start.push_attribute(*attribute);
+                    }
+                    let _ = writer.write_event(Event::Start(svg_start));
+                }
+                Ok(Event::Decl(_)) | Ok(Event::DocType(_)) => {}
+                Ok(Event::Eof) | Err(_) => break,
+                Ok(event) => {
+                    let _ = writer.write_event(event);
+                }
+            }
+        }
+
+        String::from_utf8_lossy(&writer.into_inner().into_inner())
+            .trim()
+            .to_string()
+    }
+}
+
+/// An SVG rendered by [`MicroTex::render_measured_borrowed`], read in place
+/// from the buffer MicroTeX allocated.
+///
+/// The buffer is released when the value is dropped. It is checked to be
+/// valid UTF-8 once, when the value is created, so [`as_str`](Self::as_str)
+/// and [`Deref`](std::ops::Deref) cost nothing. Use [`to_string`](ToString)
+/// or [`into_render_result`](Self::into_render_result) to get an owned copy.
+///
+/// # Lifetime
+///
+/// The buffer is owned by this value alone: it does not borrow from the
+/// [`MicroTex`] instance or the render that produced it, so it stays valid
+/// after both are dropped, and until this value is. `&str`s obtained from it
+/// borrow from it, so the compiler rejects any use of them after the drop.
+pub struct BorrowedSvg {
+    /// Start of the buffer allocated by `microtex_render_to_svg`.
+    ptr: std::ptr::NonNull<u8>,
+
+    /// Length of the buffer in bytes, which hold valid UTF-8.
+    len: usize,
+
+    metrics: RenderMetrics,
+}
+
+// SAFETY: the buffer is exclusively owned and only read through `&self`,
+// and it is freed holding `FFI_LOCK`, like every other call into MicroTeX.
+unsafe impl Send for BorrowedSvg {}
+unsafe impl Sync for BorrowedSvg {}
+
+impl BorrowedSvg {
+    /// Returns the SVG.
+    pub fn as_str(&self) -> &str {
+        // SAFETY: `ptr` points to `len` bytes that live until `self` is
+        // dropped and were checked to be UTF-8 in `render_measured_borrowed`
+        unsafe {
+            std::str::from_utf8_unchecked(std::slice::from_raw_parts(self.ptr.as_ptr(), self.len))
+        }
+    }
+
+    /// Returns the metrics of the formula.
+    pub fn metrics(&self) -> &RenderMetrics {
+        &self.metrics
+    }
+
+    /// Copies the SVG into a [`RenderResult`] and releases the buffer.
+    pub fn into_render_result(self) -> RenderResult {
+        RenderResult::new(self.as_str().to_string(), self.metrics.clone())
+    }
+}
+
+impl std::ops::Deref for BorrowedSvg {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl AsRef<str> for BorrowedSvg {
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl fmt::Debug for BorrowedSvg {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BorrowedSvg")
+            .field("len", &self.len)
+            .field("metrics", &self.metrics)
+            .finish()
+    }
+}
+
+impl Drop for BorrowedSvg {
+    fn drop(&mut self) {
+        // A `BorrowedSvg` is never dropped while the lock is held: renders
+        // return it after releasing the lock
+        let _ffi = lock_ffi();
+        // SAFETY: the buffer came from `microtex_render_to_svg` and is freed
+        // once, here
+        unsafe { shim::microtex_free_buffer(self.ptr.as_ptr()) }
+    }
+}
+
+/// Selects the measurements [`MicroTex::render_with_options`] collects.
+///
+/// Each flag enables one more call into MicroTeX; the default collects
+/// nothing, which renders like [`MicroTex::render`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct RenderOptions {
+    /// Collect the [`RenderMetrics`] of the formula.
+    pub metrics: bool,
+
+    /// Collect the [`KeyCharMetrics`] of the formula.
+    pub key_char_metrics: bool,
+}
+
+/// SVG and the optional measurements returned by
+/// [`MicroTex::render_with_options`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RenderOutput {
+    /// The SVG content as a UTF-8 string.
+    pub svg: String,
+
+    /// The metrics, if [`RenderOptions::metrics`] was set.
+    pub metrics: Option<RenderMetrics>,
+
+    /// The key character metrics, if [`RenderOptions::key_char_metrics`]
+    /// was set and MicroTeX reported them.
+    pub key_char_metrics: Option<KeyCharMetrics>,
+}
+
+/// Position of a formula in a sprite sheet, see [`SpriteAtlas`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Sprite {
+    /// The LaTeX source of the formula.
+    pub source: String,
+
+    /// Left edge of the formula in the sheet, in pixels.
+    pub x: u32,
+
+    /// Top edge of the formula in the sheet, in pixels.
+    pub y: u32,
+
+    /// Width of the cropped formula in pixels.
+    pub width: u32,
+
+    /// Height of the cropped formula in pixels.
+    pub height: u32,
+}
+
+/// Atlas of a sprite sheet produced by [`MicroTex::render_sprite_sheet`].
+///
+/// Serializes as the sheet size and one entry per formula, in the order the
+/// formulas were given:
+///
+/// ```json
+/// {"width": 120, "height": 48, "sprites": [
+///     {"source": "\\[x^2\\]", "x": 4, "y": 4, "width": 30, "height": 22}
+/// ]}
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SpriteAtlas {
+    /// Width of the sheet in pixels.
+    pub width: u32,
+
+    /// Height of the sheet in pixels.
+    pub height: u32,
+
+    /// The position of each formula.
+    pub sprites: Vec<Sprite>,
+}
+
+/// Metrics for key characters extracted from the formula's BOX TREE.
+///
+/// Contains the heights of actual character boxes at the top level of the
+/// formula structure, excluding decorative elements and nested structures.
+/// This is used to calculate more accurate scaling factors that account
+/// for formula complexity (fractions, subscripts, etc.).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeyCharMetrics {
+    /// Heights of individual key characters in the formula
+    pub key_char_heights: Vec<i32>,
+
+    /// Number of key characters found
+    pub key_char_count: i32,
+
+    /// Average height of key characters
+    pub average_char_height: f32,
+
+    /// Maximum character height
+    pub max_char_height: i32,
+
+    /// Minimum character height
+    pub min_char_height: i32,
+
+    /// Total height of BOX TREE root in MicroTeX units (used for normalization)
+    pub box_tree_height: f32,
+}
+
+impl KeyCharMetrics {
+    /// Creates new KeyCharMetrics from parsed JSON data.
+    pub fn new(
+        key_char_heights: Vec<i32>,
+        key_char_count: i32,
+        average_char_height: f32,
+        max_char_height: i32,
+        min_char_height: i32,
+        box_tree_height: f32,
+    ) -> Self {
+        Self {
+            key_char_heights,
+            key_char_count,
+            average_char_height,
+            max_char_height,
+            min_char_height,
+            box_tree_height,
+        }
+    }
+
+    /// Parses KeyCharMetrics from a JSON string returned from C++.
+    pub fn from_json(json: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let value: serde_json::Value = serde_json::from_str(json)?;
+
+        let key_char_heights: Vec<i32> = value
+            .get("key_char_heights")
+            .and_then(|v| v.as_array())
+            .map(|arr| {
+                arr.iter()
+                    .filter_map(|v| v.as_i64())
+                    .map(|v| v as i32)
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        let key_char_count = value
+            .get("key_char_count")
+            .and_then(|v| v.as_i64())
+            .unwrap_or(0) as i32;
+
+        let average_char_height = value
+            .get("average_char_height")
+            .and_then(|v| v.as_f64())
+            .unwrap_or(0.0) as f32;
+
+        let max_char_height = value
+            .get("max_char_height")
+            .and_then(|v| v.as_i64())
+            .unwrap_or(0) as i32;
+
+        let min_char_height = value
+            .get("min_char_height")
+            .and_then(|v| v.as_i64())
+            .unwrap_or(0) as i32;
+
+        let box_tree_height = value
+            .get("box_tree_height")
+            .and_then(|v| v.as_f64())
+            .unwrap_or(0.0) as f32;
+
+        Ok(Self {
+            key_char_heights,
+            key_char_count,
+            average_char_height,
+            max_char_height,
+            min_char_height,
+            box_tree_height,
+        })
+    }
+}
+
+///
+///
+/// This struct manages the lifecycle of a MicroTeX instance and provides
+/// safe methods to render LaTeX strings to SVG format. It automatically
+/// handles initialization and cleanup of the underlying C++ library, which
+/// is released when the last instance is dropped.
+///
+/// `MicroTex` deliberately does not implement [`Default`]: initialization can
+/// fail, and a `Default` impl would have to panic. Use [`MicroTex::new`] and
+/// handle the [`RenderError`].
+///
+/// `MicroTex` is [`Send`] and [`Sync`]: it can be shared between threads,
+/// for example in an `Arc`. Calls into MicroTeX are serialized by an internal
+/// lock, so renders from several threads run one at a time.
+///
+/// Rendering any number of times with one instance is safe: each render
+/// holds that lock for its whole use of MicroTeX, parses the formula into a
+/// render object of its own and deletes it before releasing the lock, so no
+/// C++ render state is shared between renders.
+/// [`render_count`](MicroTex::render_count) reports how many formulas an
+/// instance has parsed.
+///
+/// **Important:** The MicroTeX engine must be initialized **only once**.
+/// Calling `MicroTex::new()` multiple times (concurrently or sequentially)
+/// can crash the underlying C++ engine. Prefer using a thread-safe
+/// singleton for global access, such as [`MicroTex::shared`], or your own:
+///
+/// ```rust
+/// use std::sync::OnceLock;
+/// use microtex_rs::{MicroTex, RenderConfig, RenderError};
+///
+/// // Global MicroTeX renderer instance - initialized only once
+/// static MICROTEX_RENDERER: OnceLock<MicroTex> = OnceLock::new();
+///
+/// // Get or initialize the MicroTeX renderer (thread-safe singleton).
+/// // `MicroTex::new()` is fallible and must only be called once.
+/// fn get_microtex_renderer() -> Result<&'static MicroTex, RenderError> {
+///     if let Some(renderer) = MICROTEX_RENDERER.get() {
+///         return Ok(renderer);
+///     }
+///
+///     // Initialize once and store the instance in the OnceLock
+///     let renderer = MicroTex::new()?;
+///     MICROTEX_RENDERER
+///         .set(renderer)
+///         .map_err(|_| RenderError::InitializationFailed)?;
+///     Ok(MICROTEX_RENDERER.get().unwrap())
+/// }
+///
+/// // Use the shared renderer
+/// let renderer = get_microtex_renderer()?;
+/// let config = RenderConfig::default();
+/// let svg = renderer.render(r#"\[E = mc^2\]"#, &config)?;
+/// assert!(svg.contains("<svg"));
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub struct MicroTex {
+    /// Configuration used by [`MicroTex::render_default`].
+    config: RenderConfig,
+
+    /// Whether the font MicroTeX was initialized with is a math font.
+    math_font: bool,
+
+    /// Code points the font has a glyph for, in ascending order.
+    font_unicodes: Vec<u32>,
+
+    /// Number of formulas parsed by this instance.
+    renders: std::sync::atomic::AtomicU64,
+}
+
+/// Process-wide state of the MicroTeX engine, shared by every [`MicroTex`].
+struct Engine {
+    /// The embedded font loaded by [`MicroTex::new_cached`], while MicroTeX
+    /// still holds it.
+    cached_font: Option<&'static str>,
+
+    /// Number of live [`MicroTex`] handles, whatever constructor made them.
+    handles: usize,
+}
+
+/// The engine state. Held while creating or dropping a [`MicroTex`], so
+/// MicroTeX is never released under a renderer being initialized.
+static ENGINE: std::sync::Mutex<Engine> = std::sync::Mutex::new(Engine {
+    cached_font: None,
+    handles: 0,
+});
+
+/// Locks [`ENGINE`]. The state is only updated after the FFI call it
+/// describes succeeded, so a poisoned lock is still usable.
+fn lock_engine() -> std::sync::MutexGuard<'static, Engine> {
+    ENGINE
+        .lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner)
+}
+
+/// Serializes calls into MicroTeX, whose C++ state is global to the process.
+///
+/// Never lock [`ENGINE`] while holding this lock.
+static FFI_LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+
+/// Name of the math font MicroTeX was first initialized with, which stays
+/// its default math font: later initializations keep the first font.
+static DEFAULT_MATH_FONT: std::sync::OnceLock<std::ffi::CString> = std::sync::OnceLock::new();
+
+/// Locks [`FFI_LOCK`]. A panic in another render leaves no partial state
+/// behind, so a poisoned lock is still usable.
+fn lock_ffi() -> std::sync::MutexGuard<'static, ()> {
+    FFI_LOCK
+        .lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner)
+}
+
+/// Applies the global options of `config` and parses `latex_cstr` into a
+/// MicroTeX render.
+///
+/// Taking the [`FFI_LOCK`] guard ensures no other render runs until the
+/// caller is done with the returned render.
+///
+/// # Safety
+///
+/// The returned render must be deleted with `microtex_delete_render`
+/// before `_ffi` is dropped.
+unsafe fn parse_render(
+    _ffi: &std::sync::MutexGuard<'static, ()>,
+    latex_cstr: &std::ffi::CStr,
+    config: &RenderConfig,
+) -> Result<*mut std::ffi::c_void, RenderError> {
+    apply_global_options(config);
+    let render_ptr = shim::microtex_parse_render(
+        latex_cstr.as_ptr(),
+        config.dpi,
+        config.line_width,
+        config.line_height,
+        config.text_color_argb(),
+        config.has_background,
+        config.background_color_argb(),
+        config.render_glyph_use_path,
+    );
+
+    if render_ptr.is_null() {
+        return Err(RenderError::ParseRenderFailed(shim::microtex_last_error()));
+    }
+    Ok(render_ptr)
+}
+
+/// Parses `latex_cstr`, passes the render to `read` and deletes it, holding
+/// [`FFI_LOCK`] throughout.
+///
+/// `read` must free the buffers it gets from MicroTeX, or hand them to a
+/// value that frees them, such as [`BorrowedSvg`].
+fn render_parsed<T>(
+    latex_cstr: &std::ffi::CStr,
+    config: &RenderConfig,
+    read: impl FnOnce(*mut std::ffi::c_void, &RenderConfig) -> Result<T, RenderError>,
+) -> Result<T, RenderError> {
+    let ffi = lock_ffi();
+    unsafe {
+        let render_ptr = parse_render(&ffi, latex_cstr, config)?;
+        let result = read(render_ptr, config);
+        shim::microtex_delete_render(render_ptr);
+        result
+    }
+}
+
+/// Runs `render` on a worker thread, failing with [`RenderError::Timeout`]
+/// if it has not finished after `timeout`.
+///
+/// MicroTeX cannot be interrupted, so a render that times out keeps running
+/// on the worker, which drops its result when done. A panic in `render` is
+/// resumed on the calling thread.
+fn run_with_timeout<T: Send + 'static>(
+    timeout: std::time::Duration,
+    render: impl FnOnce() -> Result<T, RenderError> + Send + 'static,
+) -> Result<T, RenderError> {
+    let (sender, receiver) = std::sync::mpsc::channel();
+    let worker = std::thread::Builder::new()
+        .name("microtex-render".to_string())
+        .spawn(move || {
+            // The receiver is gone if the render timed out
+            let _ = sender.send(render());
+        })?;
+
+    match receiver.recv_timeout(timeout) {
+        Ok(result) => result,
+        Err(std::sync::mpsc::RecvTimeoutError::Timeout) => Err(RenderError::Timeout(timeout)),
+        Err(std::sync::mpsc::RecvTimeoutError::Disconnected) => std::panic::resume_unwind(
+            worker
+                .join()
+                .expect_err("the worker sends a result unless it panics"),
+        ),
+    }
+}
+
+/// A LaTeX source checked and converted once to the C string handed to
+/// MicroTeX, for rendering the same formula repeatedly.
+///
+/// Created by [`MicroTex::prepare_source`] and rendered with
+/// [`MicroTex::render_prepared`], which skips the per-render checks of the
+/// source and the `CString` allocation.
+///
+/// A `PreparedSource` owns its string and does not borrow the renderer: it
+/// can outlive the [`MicroTex`] that prepared it and be rendered by any
+/// instance. MicroTeX only reads the string during a render call, so the
+/// source may be dropped as soon as the call returns.
+#[derive(Debug, Clone)]
+pub struct PreparedSource {
+    source: String,
+    c_source: std::ffi::CString,
+}
+
+impl PreparedSource {
+    /// Returns the LaTeX source.
+    pub fn as_str(&self) -> &str {
+        &self.source
+    }
+}
+
+/// Post-processing applied to the SVGs of [`MicroTex::render_batch`].
+///
+/// Every setting is optional: `None` leaves it to the batch-wide options, or
+/// disables it when the batch-wide options leave it unset too. Steps run in
+/// field order.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BatchOptions {
+    /// Replaces stroked paths with filled outlines, see [`strokes_to_fills`].
+    pub strokes_to_fills: Option<bool>,
+
+    /// Padding added around the formula as `(top, right, bottom, left)`, in
+    /// SVG user units, see [`pad_svg`].
+    pub padding: Option<(f32, f32, f32, f32)>,
+
+    /// Rewrites the SVG in canonical form, see [`canonicalize_svg`].
+    pub canonicalize: Option<bool>,
+}
+
+impl BatchOptions {
+    /// Returns these options, with unset settings taken from `shared`.
+    fn or(&self, shared: &BatchOptions) -> BatchOptions {
+        BatchOptions {
+            strokes_to_fills: self.strokes_to_fills.or(shared.strokes_to_fills),
+            padding: self.padding.or(shared.padding),
+            canonicalize: self.canonicalize.or(shared.canonicalize),
+        }
+    }
+
+    /// Applies the enabled post-processing steps to `svg`.
+    fn apply(&self, mut svg: String) -> String {
+        if self.strokes_to_fills == Some(true) {
+            svg = strokes_to_fills(&svg);
+        }
+        if let Some((top, right, bottom, left)) = self.padding {
+            svg = pad_svg(&svg, top, right, bottom, left);
+        }
+        if self.canonicalize == Some(true) {
+            svg = canonicalize_svg(&svg);
+        }
+        svg
+    }
+}
+
+/// One formula of a [`MicroTex::render_batch`] call.
+#[derive(Debug, Clone, Default)]
+pub struct BatchItem {
+    /// The LaTeX source to render.
+    pub latex: String,
+
+    /// Rendering configuration of this formula, such as its DPI.
+    pub config: RenderConfig,
+
+    /// Post-processing settings of this formula, which take precedence over
+    /// the batch-wide options.
+    pub overrides: BatchOptions,
+}
+
+impl<S: Into<String>> From<(S, RenderConfig)> for BatchItem {
+    fn from((latex, config): (S, RenderConfig)) -> Self {
+        BatchItem::new(latex, config)
+    }
+}
+
+impl BatchItem {
+    /// Creates an item rendered with `config` and the batch-wide
+    /// post-processing.
+    pub fn new(latex: impl Into<String>, config: RenderConfig) -> Self {
+        BatchItem {
+            latex: latex.into(),
+            config,
+            overrides: BatchOptions::default(),
+        }
+    }
+}
+
+/// Wraps a bare LaTeX formula in math delimiters.
+///
+/// MicroTeX picks the formula style from its delimiters: `\[...\]` renders
+/// display math, `$...$` renders inline (text style) math. Surrounding
+/// whitespace is trimmed before wrapping.
+///
+/// # Arguments
+///
+/// * `latex` - The formula without delimiters, e.g. `x^2`
+/// * `display` - `true` for display math, `false` for inline math
+///
+/// # Example
+///
+/// ```rust
+/// use microtex_rs::wrap_in_delimiters;
+///
+/// assert_eq!(wrap_in_delimiters(" x^2 ", true), r"\[x^2\]");
+/// assert_eq!(wrap_in_delimiters("x^2", false), "$x^2$");
+/// ```
+pub fn wrap_in_delimiters(latex: &str, display: bool) -> String {
+    let latex = latex.trim();
+    if display {
+        format!("\\[{}\\]", latex)
+    } else {
+        format!("${}$", latex)
+    }
+}
+
+/// Detects the math mode of a source from its delimiters and rewrites it
+/// with the delimiters MicroTeX expects, see [`MicroTex::render_auto`].
+fn normalize_math_delimiters(source: &str) -> String {
+    let trimmed = source.trim();
+    let delimited = |open: &str, close: &str| {
+        trimmed
+            .strip_prefix(open)
+            .and_then(|rest| rest.strip_suffix(close))
+    };
+
+    // `$$` must be tried before `$`, which it starts with
+    if let Some(body) = delimited("\\[", "\\]").or_else(|| delimited("$$", "$$")) {
+        return wrap_in_delimiters(body, true);
+    }
+    if let Some(body) = delimited("\\(", "\\)")
+        .or_else(|| delimited("$", "$").filter(|body| !body.is_empty() && !body.contains('$')))
+    {
+        return wrap_in_delimiters(body, false);
+    }
+    wrap_in_delimiters(trimmed, true)
+}
+
+/// Adds DPI metadata to an SVG string as a `data-dpi` attribute.
+///
+/// This function injects the rendering DPI value into the SVG root element
+/// as a `data-dpi` attribute. This metadata is useful for downstream processors
+/// that need to know the DPI at which the SVG was rendered, particularly when
+/// converting to other formats (e.g., PDF) where proper sizing depends on
+/// knowing the original DPI.
+///
+/// # Arguments
+///
+/// * `svg` - The SVG content as a string
+/// * `dpi` - The DPI value to embed (typically 720 for MicroTeX)
+///
+/// # Returns
+///
+/// A modified SVG string with the `data-dpi` attribute added to the `<svg>` element.
+/// If the element already has a `data-dpi` attribute, its value is replaced,
+/// so processing the same SVG twice keeps a single attribute.
+/// If the SVG doesn't contain an `<svg` opening tag, the original string is returned unchanged.
+///
+/// # Example
+///
+/// ```rust
+/// use microtex_rs::add_dpi_to_svg;
+///
+/// let svg = r#"<svg width="100" height="50" xmlns="http://www.w3.org/2000/svg"></svg>"#;
+/// let dpi = 720;
+/// let modified = add_dpi_to_svg(svg, dpi);
+/// assert!(modified.contains(r#"data-dpi="720""#));
+/// ```
+pub fn add_dpi_to_svg(svg: &str, dpi: i32) -> String {
+    let mut result = String::with_capacity(svg.len() + 20);
+    push_svg_with_dpi(svg, dpi, &mut result);
+    result
+}
+
+/// Encodes an SVG as a base64 `data:` URI, ready to use as the `src` of an
+/// `<img>` or to embed in JSON.
+///
+/// # Example
+///
+/// ```rust
+/// use microtex_rs::svg_to_data_uri;
+///
+/// assert_eq!(svg_to_data_uri("<svg/>"), "data:image/svg+xml;base64,PHN2Zy8+");
+/// ```
+pub fn svg_to_data_uri(svg: &str) -> String {
+    use base64::Engine;
+
+    format!(
+        "data:image/svg+xml;base64,{}",
+        base64::engine::general_purpose::STANDARD.encode(svg)
+    )
+}
+
+/// Appends `svg` to `out` with a `data-dpi` attribute added to, or updated
+/// in, the opening `<svg` tag (see [`add_dpi_to_svg`]).
+fn push_svg_with_dpi(svg: &str, dpi: i32, out: &mut String) {
+    use std::fmt::Write;
+
+    // Find the opening <svg tag
+    if let Some(svg_start) = svg.find("<svg") {
+        if let Some(close_bracket) = svg[svg_start..].find('>') {
+            let insert_pos = svg_start + close_bracket;
+            // Update an existing attribute in place
+            let existing = svg[svg_start..insert_pos]
+                .find(r#" data-dpi=""#)
+                .map(|pos| svg_start + pos + r#" data-dpi=""#.len())
+                .and_then(|value_start| {
+                    svg[value_start..insert_pos]
+                        .find('"')
+                        .map(|len| (value_start, value_start + len))
+                });
+            if let Some((value_start, value_end)) = existing {
+                out.push_str(&svg[..value_start]);
+                let _ = write!(out, "{}", dpi);
+                out.push_str(&svg[value_end..]);
+                return;
+            }
+            out.push_str(&svg[..insert_pos]);
+            let _ = write!(out, r#" data-dpi="{}""#, dpi);
+            out.push_str(&svg[insert_pos..]);
+            return;
+        }
+    }
+    // If no <svg tag found or malformed, keep the original
+    out.push_str(svg);
+}
+
+/// Applies the post-processing shared by every render method: the root
+/// attributes of `config`, the minimum stroke width, sanitization, then
+/// minification.
+fn apply_config_postprocessing(svg: String, latex_source: &str, config: &RenderConfig) -> String {
+    let mut svg = add_root_attributes(svg, &root_attributes(latex_source, config));
+    if let Some(min_px) = config.min_stroke_width_px {
+        svg = enforce_min_stroke_width(&svg, min_px);
+    }
+    if config.sanitize {
+        svg = sanitize_svg(&svg);
+    }
+    if config.minify {
+        svg = minify_svg(&svg);
+    }
+    svg
+}
+
+/// Returns the attributes [`add_root_attributes`] adds for `config`: the
+/// configured `svg_attributes`, then `data-latex` if the source is embedded.
+fn root_attributes(latex_source: &str, config: &RenderConfig) -> Vec<(String, String)> {
+    let mut attributes = config.svg_attributes.clone();
+    if config.embed_source {
+        attributes.push(("data-latex".to_string(), latex_source.to_string()));
+    }
+    attributes
+}
+
+/// Adds `attributes` to the opening `<svg` tag, escaping their values.
+///
+/// Names are expected to have been checked by [`RenderConfig::validate`].
+/// Returns the SVG unchanged if there are no attributes or no `<svg` tag.
+fn add_root_attributes(svg: String, attributes: &[(String, String)]) -> String {
+    use std::fmt::Write;
+
+    if attributes.is_empty() {
+        return svg;
+    }
+    let Some(insert_pos) = svg.find("<svg").and_then(|start| {
+        let tag = &svg[start..];
+        let end = tag.find('>')?;
+        // Keep a self-closing `/>` intact
+        Some(
+            start
+                + if tag[..end].ends_with('/') {
+                    end - 1
+                } else {
+                    end
+                },
+        )
+    }) else {
+        return svg;
+    };
+
+    let mut inserted = String::new();
+    for (name, value) in attributes {
+        let _ = write!(
+            inserted,
+            r#" {}="{}""#,
+            name,
+            quick_xml::escape::escape(value.as_str())
+        );
+    }
+    let mut result = svg;
+    result.insert_str(insert_pos, &inserted);
+    result
+}
+
+/// Extracts all Y coordinates from SVG path elements, accounting for transformations.
+///
+/// This function parses all `<path>` elements in an SVG and extracts Y coordinates
+/// from the path data (M, L, C, Q, etc. commands). It applies any `transform="matrix(...)"`
+/// attributes to get the actual Y coordinates after transformation.
+///
+/// Each command contributes the points it passes through or is shaped by:
+/// the end point of every segment and the control points of curves, but not
+/// the radii, rotation or flags of arcs. Relative (lowercase) commands are
+/// resolved against the current point, and `H`/`h` keep the current Y.
+///
+/// # Arguments
+///
+/// * `svg` - The SVG content as a string
+///
+/// # Returns
+///
+/// A vector of all Y coordinate values found in path data after applying transformations.
+/// Returns an empty vector if no paths or coordinates are found.
+///
+/// # Example
+///
+/// ```rust
+/// use microtex_rs::extract_y_coordinates;
+///
+/// let svg = r#"<svg><path d="M 10 20 L 30 40 Z"/></svg>"#;
+/// let y_coords = extract_y_coordinates(svg);
+/// assert!(y_coords.contains(&20.0));
+/// assert!(y_coords.contains(&40.0));
+/// ```
+pub fn extract_y_coordinates(svg: &str) -> Vec<f32> {
+    extract_path_points(svg)
+        .into_iter()
+        .map(|(_, y)| y)
+        .collect()
+}
+
+/// Extracts all `(x, y)` points from SVG path elements, accounting for transformations.
+///
+/// This is the parser behind [`extract_y_coordinates`]; see [`path_points`]
+/// for the points each command contributes.
+fn extract_path_points(svg: &str) -> Vec<(f32, f32)> {
+    let mut points = Vec::new();
+
+    // Find all <path> elements
+    let mut search_start = 0;
+    while let Some(path_start) = svg[search_start..].find("<path") {
+        let path_start = search_start + path_start;
+
+        // Extract the transform matrix if present
+        // Look for transform="matrix(a, b, c, d, e, f)"
+        let transform_matrix =
+            if let Some(transform_idx) = svg[path_start..].find(r#"transform="matrix("#) {
+                let transform_start = path_start + transform_idx + 18; // Skip 'transform="matrix('
+                if let Some(close_paren) = svg[transform_start..].find(')') {
+                    let matrix_str = &svg[transform_start..transform_start + close_paren];
+                    // Parse matrix values: a, b, c, d, e, f
+                    let values: Vec<f32> = matrix_str
+                        .split(',')
+                        .filter_map(|s| s.trim().parse::<f32>().ok())
+                        .collect();
+
+                    if values.len() >= 6 {
+                        Some((
+                            values[0], values[1], values[2], values[3], values[4], values[5],
+                        ))
+                    } else {
+                        None
+                    }
+                } else {
+                    None
+                }
+            } else {
+                None
+            };
+
+        // Find the d=" attribute
+        if let Some(d_attr_start) = svg[path_start..].find(r#"d=""#) {
+            let d_start = path_start + d_attr_start + 3; // Skip d="
+
+            // Find the closing quote of the d attribute
+            if let Some(d_end) = svg[d_start..].find('"') {
+                let d_content = &svg[d_start..d_start + d_end];
+
+                points.extend(path_points(d_content, transform_matrix));
+
+                search_start = d_start + d_end + 1;
+            } else {
+                search_start = path_start + 1;
+            }
+        } else {
+            search_start = path_start + 1;
+        }
+    }
+
+    points
+}
+
+/// Splits SVG path data into its numeric arguments, dropping the command letters.
+///
+/// Handles the compact forms SVG allows without separators: a sign starts a
+/// new number (`10-5` is `10, -5`) unless it follows an exponent marker, and
+/// a second decimal point does too (`1.5.5` is `1.5, 0.5`).
+fn parse_path_numbers(d_content: &str) -> Vec<f32> {
+    let mut current_num = String::new();
+    let mut coords = Vec::new();
+
+    let flush = |current_num: &mut String, coords: &mut Vec<f32>| {
+        if !current_num.is_empty() {
+            if let Ok(num) = current_num.parse::<f32>() {
+                coords.push(num);
+            }
+            current_num.clear();
+        }
+    };
+
+    for ch in d_content.chars() {
+        let in_exponent = current_num.contains(['e', 'E']);
+        match ch {
+            '0'..='9' => current_num.push(ch),
+            // A sign continues the number only right after an exponent marker
+            '-' | '+' => {
+                if !current_num.ends_with(['e', 'E']) {
+                    flush(&mut current_num, &mut coords);
+                }
+                current_num.push(ch);
+            }
+            // A second decimal point, or one in the exponent, starts a new number
+            '.' => {
+                if current_num.contains('.') || in_exponent {
+                    flush(&mut current_num, &mut coords);
+                }
+                current_num.push(ch);
+            }
+            // Exponent of a number such as `1.5e-3`, not the start of a new token
+            'e' | 'E'
+                if current_num.ends_with(|c: char| c.is_ascii_digit() || c == '.')
+                    && !in_exponent =>
+            {
+                current_num.push(ch);
+            }
+            // Separators and command letters end the current number
+            _ => flush(&mut current_num, &mut coords),
+        }
+    }
+
+    // Handle the last number if any
+    flush(&mut current_num, &mut coords);
+
+    coords
+}
+
+/// Returns the absolute points of SVG path data, applying an optional
+/// `matrix(a, b, c, d, e, f)` transform.
+///
+/// Every command consumes its own number of arguments, repeated when more
+/// follow (extra pairs after `M` are lines). The points are the end point of
+/// each segment and the control points of `C`, `S` and `Q`; `H` and `V`
+/// give the current point with only X or Y changed, and arcs only their end
+/// point. Relative commands are offset by the current point, and `Z` moves
+/// it back to the start of the subpath. Arc flags must be separated from the
+/// following numbers.
+fn path_points(d_content: &str, transform_matrix: Option<AffineMatrix>) -> Vec<(f32, f32)> {
+    let mut points = Vec::new();
+    let mut current = (0.0, 0.0);
+    let mut subpath_start = (0.0, 0.0);
+
+    // Split at command letters; `e`/`E` are exponents, not commands
+    let mut commands = Vec::new();
+    for (i, c) in d_content.char_indices() {
+        if c.is_ascii_alphabetic() && !matches!(c, 'e' | 'E') {
+            commands.push(i);
+        }
+    }
+
+    for (n, &start) in commands.iter().enumerate() {
+        let end = commands.get(n + 1).copied().unwrap_or(d_content.len());
+        let command = d_content.as_bytes()[start] as char;
+        let args = parse_path_numbers(&d_content[start + 1..end]);
+        let relative = command.is_ascii_lowercase();
+
+        let arity = match command.to_ascii_uppercase() {
+            'M' | 'L' | 'T' => 2,
+            'H' | 'V' => 1,
+            'S' | 'Q' => 4,
+            'C' => 6,
+            'A' => 7,
+            'Z' => {
+                current = subpath_start;
+                continue;
+            }
+            _ => continue,
+        };
+
+        for (k, group) in args.chunks_exact(arity).enumerate() {
+            let origin = if relative { current } else { (0.0, 0.0) };
+            let point = |i: usize| (origin.0 + group[i], origin.1 + group[i + 1]);
+            let segment: Vec<(f32, f32)> = match command.to_ascii_uppercase() {
+                'H' => vec![(origin.0 + group[0], current.1)],
+                'V' => vec![(current.0, if relative { current.1 } else { 0.0 } + group[0])],
+                'A' => vec![point(5)],
+                _ => (0..arity).step_by(2).map(point).collect(),
+            };
+            current = *segment.last().expect("at least one point per segment");
+            if command.eq_ignore_ascii_case(&'M') && k == 0 {
+                subpath_start = current;
+            }
+            points.extend(segment);
+        }
+    }
+
+    match transform_matrix {
+        // x' = a*x + c*y + e, y' = b*x + d*y + f
+        Some((a, b, c, d, e, f)) => points
+            .into_iter()
+            .map(|(x, y)| (a * x + c * y + e, b * x + d * y + f))
+            .collect(),
+        None => points,
+    }
+}
+
+/// Pairs path numbers into `(x, y)` points, applying an optional
+/// `matrix(a, b, c, d, e, f)` transform.
+fn pair_path_points(coords: &[f32], transform_matrix: Option<AffineMatrix>) -> Vec<(f32, f32)> {
+    let mut points = Vec::with_capacity(coords.len() / 2);
+
+    // Parse coordinates based on SVG path commands
+    // For simplicity, we assume coordinates alternate X, Y in most cases
+    // This is a heuristic approach - we pair every two coordinates
+    let mut i = 0;
+    while i < coords.len() {
+        // Most path commands use X, Y pairs
+        if i + 1 < coords.len() {
+            let mut x = coords[i];
+            let mut y = coords[i + 1];
+
+            // Apply transformation matrix if present
+            if let Some((a, b, c, d, e, f)) = transform_matrix {
+                // x' = a*x + c*y + e, y' = b*x + d*y + f
+                (x, y) = (a * x + c * y + e, b * x + d * y + f);
+            }
+
+            points.push((x, y));
+            i += 2;
+        } else {
+            i += 1;
+        }
+    }
+
+    points
+}
+
+/// A 2D affine transform `matrix(a, b, c, d, e, f)`.
+type AffineMatrix = (f32, f32, f32, f32, f32, f32);
+
+const IDENTITY_MATRIX: AffineMatrix = (1.0, 0.0, 0.0, 1.0, 0.0, 0.0);
+
+/// Composes two transforms so that `outer` is applied after `inner`.
+fn compose_matrices(outer: AffineMatrix, inner: AffineMatrix) -> AffineMatrix {
+    let (a1, b1, c1, d1, e1, f1) = outer;
+    let (a2, b2, c2, d2, e2, f2) = inner;
+    (
+        a1 * a2 + c1 * b2,
+        b1 * a2 + d1 * b2,
+        a1 * c2 + c1 * d2,
+        b1 * c2 + d1 * d2,
+        a1 * e2 + c1 * f2 + e1,
+        b1 * e2 + d1 * f2 + f1,
+    )
+}
+
+/// Parses a `transform` attribute made of `matrix(...)`, `translate(...)` and
+/// `scale(...)` functions. Unknown functions are ignored.
+fn parse_transform(value: &str) -> AffineMatrix {
+    let mut matrix = IDENTITY_MATRIX;
+    for function in value.split(')') {
+        let Some((name, args)) = function.split_once('(') else {
+            continue;
+        };
+        let args: Vec<f32> = args
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter_map(|arg| arg.parse::<f32>().ok())
+            .collect();
+        let step = match (name.trim(), args.as_slice()) {
+            ("matrix", [a, b, c, d, e, f, ..]) => (*a, *b, *c, *d, *e, *f),
+            ("translate", [tx]) => (1.0, 0.0, 0.0, 1.0, *tx, 0.0),
+            ("translate", [tx, ty, ..]) => (1.0, 0.0, 0.0, 1.0, *tx, *ty),
+            ("scale", [s]) => (*s, 0.0, 0.0, *s, 0.0, 0.0),
+            ("scale", [sx, sy, ..]) => (*sx, 0.0, 0.0, *sy, 0.0, 0.0),
+            _ => continue,
+        };
+        matrix = compose_matrices(matrix, step);
+    }
+    matrix
+}
+
+/// Counts the `<path>` elements of an SVG.
+fn count_svg_paths(svg: &str) -> usize {
+    use quick_xml::events::Event;
+    use quick_xml::Reader;
+
+    let mut reader = Reader::from_str(svg);
+    let mut count = 0;
+    loop {
+        match reader.read_event() {
+            Ok(Event::Start(e)) | Ok(Event::Empty(e)) if e.name().as_ref() == b"path" => count += 1,
+            Ok(Event::Eof) | Err(_) => break,
+            Ok(_) => {}
+        }
+    }
+    count
+}
+
+/// Computes the bounding box of all path points in an SVG, as floats.
+///
+/// This gives the full extent of the content, for example to place a
+/// formula when converting it to PDF. Path data is parsed as by
+/// [`extract_y_coordinates`], but the element tree is walked so that the
+/// `transform` attributes of paths and of their enclosing `<g>` elements
+/// (such as the centering group added by [`adjust_svg_height_and_center`])
+/// are applied. Curve control points are included, so the box may be
+/// slightly larger than the inked area; stroke widths are not.
+///
+/// Returns `(min_x, min_y, max_x, max_y)` in SVG user units, or `None` if the
+/// SVG contains no path coordinates.
+///
+/// # Example
+///
+/// ```rust
+/// use microtex_rs::extract_bounding_box;
+///
+/// let svg = r#"<svg>
+///   <path d="M 10 20 L 30 40"/>
+///   <path d="M 0 0 L 5 5" transform="matrix(1, 0, 0, 1, 100, 50)"/>
+/// </svg>"#;
+/// assert_eq!(extract_bounding_box(svg), Some((10.0, 20.0, 105.0, 55.0)));
+/// assert_eq!(extract_bounding_box("<svg></svg>"), None);
+/// ```
+pub fn extract_bounding_box(svg: &str) -> Option<(f32, f32, f32, f32)> {
+    use quick_xml::events::Event;
+    use quick_xml::Reader;
+
+    let mut reader = Reader::from_str(svg);
+    let mut transforms: Vec<AffineMatrix> = Vec::new();
+    let mut bounds: Option<(f32, f32, f32, f32)> = None;
+
+    loop {
+        let (element, is_empty) = match reader.read_event() {
+            Ok(Event::Start(e)) => (e, false),
+            Ok(Event::Empty(e)) => (e, true),
+            Ok(Event::End(_)) => {
+                transforms.pop();
+                continue;
+            }
+            Ok(Event::Eof) | Err(_) => break,
+            Ok(_) => continue,
+        };
+
+        let parent = transforms.last().copied().unwrap_or(IDENTITY_MATRIX);
+        let mut matrix = parent;
+        let mut d_content = None;
+        for attr in element.attributes().flatten() {
+            match attr.key.as_ref() {
+                b"transform" => {
+                    matrix = compose_matrices(
+                        parent,
+                        parse_transform(&String::from_utf8_lossy(&attr.value)),
+                    );
+                }
+                b"d" if element.name().as_ref() == b"path" => {
+                    d_content = Some(String::from_utf8_lossy(&attr.value).into_owned());
+                }
+                _ => {}
+            }
+        }
+
+        if let Some(d_content) = d_content {
+            for (x, y) in path_points(&d_content, Some(matrix)) {
+                bounds = Some(match bounds {
+                    Some((min_x, min_y, max_x, max_y)) => {
+                        (min_x.min(x), min_y.min(y), max_x.max(x), max_y.max(y))
+                    }
+                    None => (x, y, x, y),
+                });
+            }
+        }
+
+        if !is_empty {
+            transforms.push(matrix);
+        }
+    }
+
+    bounds
+}
+
+/// A `<path>` element extracted by [`extract_svg_paths`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct SvgPath {
+    /// The path data (`d` attribute).
+    pub d: String,
+
+    /// The `fill` attribute, if present.
+    pub fill: Option<String>,
+
+    /// The `stroke` attribute, if present.
+    pub stroke: Option<String>,
+
+    /// The transform `matrix(a, b, c, d, e, f)` mapping the path data to SVG
+    /// user units, combining the path's own `transform` with those of its
+    /// enclosing groups. `None` when no transform applies.
+    pub transform: Option<(f32, f32, f32, f32, f32, f32)>,
+}
+
+/// Extracts every `<path>` element of an SVG, in document order.
+///
+/// This exposes the drawing primitives of a rendered formula for external
+/// rasterizers or geometry engines, without parsing the SVG again.
+///
+/// # Example
+///
+/// ```rust
+/// use microtex_rs::extract_svg_paths;
+///
+/// let svg = r#"<svg><g transform="translate(0, 2)">
+///   <path fill="none" stroke="black" d="M 0 0 L 10 0" transform="scale(2)"/>
+/// </g></svg>"#;
+/// let paths = extract_svg_paths(svg);
+/// assert_eq!(paths.len(), 1);
+/// assert_eq!(paths[0].d, "M 0 0 L 10 0");
+/// assert_eq!(paths[0].stroke.as_deref(), Some("black"));
+/// assert_eq!(paths[0].transform, Some((2.0, 0.0, 0.0, 2.0, 0.0, 2.0)));
+/// ```
+pub fn extract_svg_paths(svg: &str) -> Vec<SvgPath> {
+    use quick_xml::events::Event;
+    use quick_xml::Reader;
+
+    let mut reader = Reader::from_str(svg);
+    let mut transforms: Vec<Option<AffineMatrix>> = Vec::new();
+    let mut paths = Vec::new();
+
+    loop {
+        let (element, is_empty) = match reader.read_event() {
+            Ok(Event::Start(e)) => (e, false),
+            Ok(Event::Empty(e)) => (e, true),
+            Ok(Event::End(_)) => {
+                transforms.pop();
+                continue;
+            }
+            Ok(Event::Eof) | Err(_) => break,
+            Ok(_) => continue,
+        };
+
+        let parent = transforms.last().copied().flatten();
+        let mut matrix = parent;
+        let mut path = SvgPath {
+            d: String::new(),
+            fill: None,
+            stroke: None,
+            transform: None,
+        };
+        for attr in element.attributes().flatten() {
+            let value = String::from_utf8_lossy(&attr.value).into_owned();
+            match attr.key.as_ref() {
+                b"transform" => {
+                    matrix = Some(compose_matrices(
+                        parent.unwrap_or(IDENTITY_MATRIX),
+                        parse_transform(&value),
+                    ));
+                }
+                b"d" => path.d = value,
+                b"fill" => path.fill = Some(value),
+                b"stroke" => path.stroke = Some(value),
+                _ => {}
+            }
+        }
+
+        if element.name().as_ref() == b"path" {
+            path.transform = matrix;
+            paths.push(path);
+        }
+
+        if !is_empty {
+            transforms.push(matrix);
+        }
+    }
+
+    paths
+}
+
+/// Finds the placeholder boxes MicroTeX draws for missing glyphs.
+///
+/// When the font has no glyph for a character, MicroTeX draws the font's
+/// fallback glyph (`.notdef`) in red instead. In the fonts MicroTeX ships,
+/// as in most OpenType fonts, that glyph is a hollow rectangle: an outline
+/// made of an outer rectangle and an inner one cut out of it. A path is
+/// reported when its data is exactly that shape: two closed subpaths drawn
+/// with straight `M`/`L` segments, each an axis-aligned rectangle, the
+/// second strictly inside the first (or the reverse). Filled rectangles,
+/// such as fraction bars or `\rule`, have a single subpath and are not
+/// reported, nor are the glyphs of real characters, which use curves.
+///
+/// The heuristic does not rely on the fill color, which the formula may
+/// override, and can be fooled by a font whose real glyph is such a box.
+/// Callers can warn, or render again with another font, for example with
+/// [`MicroTex::with_font`].
+///
+/// # Returns
+///
+/// The indices, in [`extract_svg_paths`] order, of the placeholder paths.
+///
+/// # Example
+///
+/// ```rust
+/// use microtex_rs::detect_missing_glyph_boxes;
+///
+/// let svg = r#"<svg>
+///   <path d="M 0 0 L 10 0 L 10 4 L 0 4 Z"/>
+///   <path fill="rgb(100%,0%,0%)" d="M 0 0 L 8 0 L 8 10 L 0 10 Z M 1 1 L 1 9 L 7 9 L 7 1 Z"/>
+/// </svg>"#;
+/// assert_eq!(detect_missing_glyph_boxes(svg), vec![1]);
+/// ```
+pub fn detect_missing_glyph_boxes(svg: &str) -> Vec<usize> {
+    extract_svg_paths(svg)
+        .iter()
+        .enumerate()
+        .filter(|(_, path)| is_hollow_box(&path.d))
+        .map(|(i, _)| i)
+        .collect()
+}
+
+/// Returns true if path data is a rectangle outline with a rectangular hole,
+/// see [`detect_missing_glyph_boxes`].
+fn is_hollow_box(d: &str) -> bool {
+    if d.contains(|c: char| c.is_ascii_alphabetic() && !matches!(c, 'M' | 'L' | 'Z' | 'e' | 'E')) {
+        return false;
+    }
+
+    let mut subpaths = d.split('Z').map(str::trim);
+    let rectangles: Vec<_> = subpaths
+        .by_ref()
+        .take(2)
+        .map(|subpath| {
+            if !subpath.starts_with('M') || subpath[1..].contains('M') {
+                return None;
+            }
+            path_rectangle(&parse_path_numbers(subpath))
+        })
+        .collect();
+    // Exactly two subpaths, both closed
+    if subpaths.any(|rest| !rest.is_empty()) {
+        return false;
+    }
+
+    match rectangles[..] {
+        [Some(a), Some(b)] => {
+            let inside = |inner: (f32, f32, f32, f32), outer: (f32, f32, f32, f32)| {
+                inner.0 > outer.0 && inner.1 > outer.1 && inner.2 < outer.2 && inner.3 < outer.3
+            };
+            inside(a, b) || inside(b, a)
+        }
+        _ => false,
+    }
+}
+
+/// Returns the `(min_x, min_y, max_x, max_y)` extent of a polygon if it is
+/// an axis-aligned rectangle with a non-zero area.
+fn path_rectangle(coords: &[f32]) -> Option<(f32, f32, f32, f32)> {
+    const EPSILON: f32 = 1e-3;
+    let same =
+        |a: (f32, f32), b: (f32, f32)| (a.0 - b.0).abs() < EPSILON && (a.1 - b.1).abs() < EPSILON;
+
+    if !coords.len().is_multiple_of(2) {
+        return None;
+    }
+    let mut points = pair_path_points(coords, None);
+    // An explicit line back to the start duplicates it
+    if points.len() == 5 && same(points[0], points[4]) {
+        points.pop();
+    }
+    if points.len() != 4 {
+        return None;
+    }
+
+    // Every edge, including the closing one, is horizontal or vertical, and
+    // they alternate
+    let horizontal = |i: usize| (points[i].1 - points[(i + 1) % 4].1).abs() < EPSILON;
+    let vertical = |i: usize| (points[i].0 - points[(i + 1) % 4].0).abs() < EPSILON;
+    let alternating = (0..4).all(|i| {
+        if i % 2 == 0 {
+            horizontal(i)
+        } else {
+            vertical(i)
+        }
+    }) || (0..4).all(|i| {
+        if i % 2 == 0 {
+            vertical(i)
+        } else {
+            horizontal(i)
+        }
+    });
+    if !alternating {
+        return None;
+    }
+
+    let min_x = points.iter().map(|p| p.0).fold(f32::INFINITY, f32::min);
+    let max_x = points.iter().map(|p| p.0).fold(f32::NEG_INFINITY, f32::max);
+    let min_y = points.iter().map(|p| p.1).fold(f32::INFINITY, f32::min);
+    let max_y = points.iter().map(|p| p.1).fold(f32::NEG_INFINITY, f32::max);
+    (max_x - min_x > EPSILON && max_y - min_y > EPSILON).then_some((min_x, min_y, max_x, max_y))
+}
+
+/// The height change and centering translation applied by
+/// [`adjust_svg_height_and_center`].
+///
+/// Returned by [`svg_centering_transform`] and
+/// [`MicroTex::render_with_transform_info`] so callers can apply the
+/// adjustment in their own coordinate system.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SvgTransform {
+    /// Vertical translation applied to the content, in SVG user units.
+    pub translate_y: f32,
+
+    /// The adjusted `height` (and `viewBox` height) of the SVG.
+    pub new_height: i32,
+
+    /// The root `height` of the SVG before adjustment (0 if missing).
+    pub original_height: f32,
+}
+
+/// Computes `(new_height, translate_y)`, or `None` if the SVG is left unchanged.
+fn centering_transform(svg: &str) -> Option<(i32, f32)> {
+    let (top, max_y) = vertical_extent(svg, 0.02)?;
+    let new_height = (max_y - top).ceil() as i32;
+    Some((new_height, (new_height as f32 - (max_y - top)) / 2.0 - top))
+}
+
+/// Computes `(top, max_y)` of the content, where `top` is the lowest Y
+/// coordinate but at most 0, or `None` if there are no paths or `max_y` is
+/// below `tolerance`.
+fn vertical_extent(svg: &str, tolerance: f32) -> Option<(f32, f32)> {
+    let (min_y, max_y) = extract_y_coordinates(svg)
+        .into_iter()
+        .fold((f32::INFINITY, f32::NEG_INFINITY), |(min_y, max_y), y| {
+            (min_y.min(y), max_y.max(y))
+        });
+
+    // No paths, or max_y within tolerance
+    if !max_y.is_finite() || max_y < tolerance {
+        return None;
+    }
+
+    // Content above the top edge (e.g. an \overbrace label) must be brought
+    // into view, so the height must cover it too
+    Some((min_y.min(0.0), max_y))
+}
+
+/// Describes what [`adjust_svg_height_and_center`] would do to an SVG,
+/// without modifying it.
+///
+/// When the adjustment would leave the SVG unchanged, `translate_y` is 0 and
+/// `new_height` is the original height rounded up.
+///
+/// # Example
+///
+/// ```rust
+/// use microtex_rs::svg_centering_transform;
+///
+/// let svg = r#"<svg width="188" height="39" viewBox="0 0 188 39">
+///   <path d="M 10 20 L 30 39.5 Z"/>
+/// </svg>"#;
+/// let transform = svg_centering_transform(svg);
+/// assert_eq!(transform.new_height, 40);
+/// assert_eq!(transform.translate_y, 0.25);
+/// assert_eq!(transform.original_height, 39.0);
+/// ```
+pub fn svg_centering_transform(svg: &str) -> SvgTransform {
+    let original_height = svg_root_attribute(svg, "height")
+        .and_then(|height| split_svg_length(&height).map(|(value, _)| value))
+        .unwrap_or(0.0);
+    let (new_height, translate_y) =
+        centering_transform(svg).unwrap_or((original_height.ceil() as i32, 0.0));
+
+    SvgTransform {
+        translate_y,
+        new_height,
+        original_height,
+    }
+}
+
+/// Adjusts SVG height and viewBox, then centers content with a transform group.
+///
+/// This function analyzes the actual Y coordinates in the SVG, increases the height
+/// if needed, and wraps the content in a `<g>` element with a vertical translation
+/// to center the content. This prevents clipping of glyphs that exceed the declared
+/// height, and of content drawn above the top edge (negative Y coordinates, such as
+/// the label of an `\overbrace`), which is shifted down into view.
+///
+/// # Arguments
+///
+/// * `svg` - The SVG content as a string
+///
+/// # Returns
+///
+/// A modified SVG string with adjusted height/viewBox and centered content, or the
+/// original SVG if max_y < 0.02 (within tolerance).
+///
+/// # Algorithm
+///
+/// 1. Extract all Y coordinates (accounting for transformations)
+/// 2. Find min_y and max_y values, with top = min(min_y, 0)
+/// 3. If max_y < 0.02, return SVG unchanged (within tolerance)
+/// 4. Otherwise:
+///    - Calculate new_height = ceil(max_y - top)
+///    - Update height and viewBox height attributes
+///    - Wrap all path elements in a `<g>` with
+///      translate(0, (new_height - (max_y - top)) / 2 - top)
+/// 5. Return modified SVG
+///
+/// # Preserved nodes
+///
+/// Only the root `<svg>` start tag is rebuilt. Everything else is copied through
+/// verbatim, without unescaping: text (including entity references such as
+/// `&amp;`), CDATA sections, comments, processing instructions, the XML
+/// declaration and the doctype.
+///
+/// # Example
+///
+/// ```rust
+/// use microtex_rs::adjust_svg_height_and_center;
+///
+/// let svg = r#"<svg width="188" height="39" viewBox="0 0 188 39">
+///   <path d="M 10 20 L 30 39.121094 Z"/>
+/// </svg>"#;
+/// let adjusted = adjust_svg_height_and_center(svg);
+/// // adjusted now has height="40" and viewBox="0 0 188 40"
+/// // and content wrapped in <g transform="translate(0, -19.560547)">
+/// ```
+pub fn adjust_svg_height_and_center(svg: &str) -> String {
+    adjust_svg_height_and_center_report(svg).0
+}
+
+/// What [`adjust_svg_height_and_center_report`] did to an SVG.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AdjustReport {
+    /// The root `height` before adjustment, rounded to whole pixels, or
+    /// `None` if it is missing or not a length.
+    pub original_height: Option<i32>,
+
+    /// The `height` (and `viewBox` height) after adjustment. When the SVG is
+    /// left unchanged, the original height rounded up (0 if missing).
+    pub new_height: i32,
+
+    /// Vertical translation applied to the content, 0 if unchanged.
+    pub translate_y: f32,
+
+    /// Whether the height was adjusted and the content wrapped in a
+    /// centering group. False when the content is within tolerance.
+    pub changed: bool,
+}
+
+/// Adjusts SVG height and centers content like
+/// [`adjust_svg_height_and_center`], also reporting whether and by how much
+/// the SVG was changed.
+///
+/// # Example
+///
+/// ```rust
+/// use microtex_rs::adjust_svg_height_and_center_report;
+///
+/// let svg = r#"<svg width="188" height="39" viewBox="0 0 188 39"><path d="M 10 20 L 30 39.5 Z"/></svg>"#;
+/// let (adjusted, report) = adjust_svg_height_and_center_report(svg);
+/// assert!(report.changed);
+/// assert_eq!((report.original_height, report.new_height), (Some(39), 40));
+/// assert!(adjusted.contains(r#"height="40""#));
+/// ```
+pub fn adjust_svg_height_and_center_report(svg: &str) -> (String, AdjustReport) {
+    let original_height = svg_root_attribute(svg, "height")
+        .and_then(|height| split_svg_length(&height).map(|(value, _)| value));
+
+    let Some((new_height, translate_y)) = centering_transform(svg) else {
+        let report = AdjustReport {
+            original_height: original_height.map(|height| height.round() as i32),
+            new_height: original_height.map_or(0, |height| height.ceil() as i32),
+            translate_y: 0.0,
+            changed: false,
+        };
+        return (svg.to_string(), report);
+    };
+
+    let placement = ContentPlacement::Translate(translate_y);
+    let bytes = write_centered_svg(svg, new_height, placement, None, Vec::new());
+    let report = AdjustReport {
+        original_height: original_height.map(|height| height.round() as i32),
+        new_height,
+        translate_y,
+        changed: true,
+    };
+    (String::from_utf8_lossy(&bytes).to_string(), report)
+}
+
+/// Adjusts SVG height and viewBox like [`adjust_svg_height_and_center`], with
+/// a custom tolerance and optional centering.
+///
+/// # Arguments
+///
+/// * `svg` - The SVG content as a string
+/// * `tolerance` - The SVG is returned unchanged when the largest Y coordinate
+///   of its content is below this value. [`adjust_svg_height_and_center`]
+///   uses 0.02.
+/// * `center` - Whether to center the content vertically with a
+///   `<g transform="translate(...)">` group. When false, no group is added:
+///   the height is set to the exact extent of the content rounded up, and
+///   content above the top edge is brought into view by starting the
+///   `viewBox` at its lowest Y coordinate instead.
+///
+/// # Example
+///
+/// ```rust
+/// use microtex_rs::adjust_svg_height_and_center_with;
+///
+/// let svg = r#"<svg width="188" height="39" viewBox="0 0 188 39"><path d="M 10 -2 L 30 39.5 Z"/></svg>"#;
+/// let adjusted = adjust_svg_height_and_center_with(svg, 0.02, false);
+/// assert!(adjusted.contains(r#"viewBox="0 -2 188 42""#));
+/// assert!(!adjusted.contains("<g"));
+/// ```
+pub fn adjust_svg_height_and_center_with(svg: &str, tolerance: f32, center: bool) -> String {
+    let Some((top, max_y)) = vertical_extent(svg, tolerance) else {
+        return svg.to_string();
+    };
+    let new_height = (max_y - top).ceil() as i32;
+    let placement = if center {
+        ContentPlacement::Translate((new_height as f32 - (max_y - top)) / 2.0 - top)
+    } else {
+        ContentPlacement::ViewBoxTop(top)
+    };
+
+    let bytes = write_centered_svg(svg, new_height, placement, None, Vec::new());
+    String::from_utf8_lossy(&bytes).to_string()
+}
+
+/// Runs the DPI and centering post-processing passes in one go, writing the
+/// result into a caller-provided buffer.
+///
+/// The output is identical to
+/// `adjust_svg_height_and_center(&add_dpi_to_svg(svg, dpi))`, but it is built
+/// in a single pass directly into `out`, without intermediate strings.
+///
+/// # Buffer reuse
+///
+/// `out` is cleared before writing; its previous content is discarded but its
+/// capacity is kept. Reusing one `String` across calls therefore stops
+/// reallocating the output once the buffer has grown to the largest SVG
+/// processed. A few small, fixed-size allocations (attribute values and path
+/// coordinates) remain per call.
+///
+/// # Example
+///
+/// ```rust
+/// use microtex_rs::{add_dpi_to_svg, adjust_svg_height_and_center, postprocess_svg_into};
+///
+/// let svg = r#"<svg width="188" height="39" viewBox="0 0 188 39"><path d="M 10 20 L 30 39.5 Z"/></svg>"#;
+/// let mut out = String::new();
+/// postprocess_svg_into(svg, 720, &mut out);
+/// assert_eq!(out, adjust_svg_height_and_center(&add_dpi_to_svg(svg, 720)));
+/// ```
+pub fn postprocess_svg_into(svg: &str, dpi: i32, out: &mut String) {
+    out.clear();
+    match centering_transform(svg) {
+        Some((new_height, translate_y)) => {
+            let bytes = std::mem::take(out).into_bytes();
+            let placement = ContentPlacement::Translate(translate_y);
+            let bytes = write_centered_svg(svg, new_height, placement, Some(dpi), bytes);
+            *out = String::from_utf8(bytes)
+                .unwrap_or_else(|err| String::from_utf8_lossy(err.as_bytes()).into_owned());
+        }
+        None => push_svg_with_dpi(svg, dpi, out),
+    }
+}
+
+/// How [`write_centered_svg`] brings the content into view.
+enum ContentPlacement {
+    /// Wrap the content in a group translated down by this amount.
+    Translate(f32),
+
+    /// Start the `viewBox` at this Y coordinate when it is negative.
+    ViewBoxTop(f32),
+}
+
+/// Writes the resized and centered SVG into `out` (which must be empty),
+/// optionally adding a `data-dpi` attribute to the root element.
+fn write_centered_svg(
+    svg: &str,
+    new_height: i32,
+    placement: ContentPlacement,
+    dpi: Option<i32>,
+    out: Vec<u8>,
+) -> Vec<u8> {
+    use quick_xml::events::{BytesEnd, BytesStart, Event};
+    use quick_xml::Reader;
+    use quick_xml::Writer;
+    use std::io::Cursor;
+
+    let dpi_str = dpi.map(|dpi| dpi.to_string());
+    let height_str = new_height.to_string();
+    let (transform_str, view_top) = match placement {
+        ContentPlacement::Translate(translate_y) => {
+            (Some(format!("translate(0, {})", translate_y)), None)
+        }
+        ContentPlacement::ViewBoxTop(top) => (None, Some(top).filter(|top| *top < 0.0)),
+    };
+
+    // Parse and rebuild SVG with quick-xml
+    let mut reader = Reader::from_str(svg);
+    let mut writer = Writer::new(Cursor::new(out));
+    let mut in_svg = false;
+    let mut g_opened = false;
+    let mut found_svg_end = false;
+
+    loop {
+        match reader.read_event() {
+            Ok(Event::Text(e)) => {
+                let _ = writer.write_event(Event::Text(e));
+            }
+            // Entity references and CDATA are written back as-is, never unescaped
+            Ok(Event::GeneralRef(e)) => {
+                let _ = writer.write_event(Event::GeneralRef(e));
+            }
+            Ok(Event::CData(e)) => {
+                let _ = writer.write_event(Event::CData(e));
+            }
+            Ok(Event::Eof) => break,
+            Ok(Event::Start(e)) => {
+                let name = e.name();
+
+                // Handle SVG tag
+                if name.as_ref() == b"svg" {
+                    in_svg = true;
+                    let mut svg_start = BytesStart::new("svg");
+                    let mut viewbox_new = String::new();
+
+                    // Process attributes
+                    for attr_result in e.attributes() {
+                        if let Ok(attr) = attr_result {
+                            let key_str = std::str::from_utf8(attr.key.as_ref()).unwrap_or("");
+                            let value_str = std::str::from_utf8(&attr.value).unwrap_or("");
+
+                            if key_str == "data-dpi" && dpi_str.is_some() {
+                                // Replaced by the new value below
+                                continue;
+                            } else if key_str == "height" {
+                                continue;
+                            } else if key_str == "viewBox" {
+                                let parts: Vec<&str> = value_str.split_whitespace().collect();
+                                if parts.len() == 4 {
+                                    let y = view_top
+                                        .map_or(parts[1].to_string(), |top| top.to_string());
+                                    viewbox_new =
+                                        format!("{} {} {} {}", parts[0], y, parts[2], new_height);
+                                    svg_start.push_attribute(("viewBox", viewbox_new.as_str()));
+                                } else {
+                                    svg_start.push_attribute((key_str, value_str));
+                                }
+                            } else {
+                                svg_start.push_attribute((key_str, value_str));
+                            }
+                        }
+                    }
+
+                    if let Some(dpi_str) = &dpi_str {
+                        svg_start.push_attribute(("data-dpi", dpi_str.as_str()));
+                    }
+                    svg_start.push_attribute(("height", height_str.as_str()));
+                    let _ = writer.write_event(Event::Start(svg_start));
+                } else if let Some(transform) =
+                    transform_str.as_deref().filter(|_| in_svg && !g_opened)
+                {
+                    // Open <g> before first non-SVG child
+                    let mut g_start = BytesStart::new("g");
+                    g_start.push_attribute(("transform", transform));
+                    let _ = writer.write_event(Event::Start(g_start));
+                    g_opened = true;
+
+                    // Write the current element
+                    let _ = writer.write_event(Event::Start(e));
+                } else {
+                    let _ = writer.write_event(Event::Start(e));
+                }
+            }
+            Ok(Event::End(e)) => {
+                let name = e.name();
+
+                if in_svg && name.as_ref() == b"svg" {
+                    // Close <g> before closing </svg>
+                    if g_opened {
+                        let _ = writer.write_event(Event::End(BytesEnd::new("g")));
+                    }
+                    let _ = writer.write_event(Event::End(e));
+                    found_svg_end = true;
+                    break; // Now we can break after processing </svg>
+                } else {
+                    let _ = writer.write_event(Event::End(e));
+                }
+            }
+            Ok(event) => {
+                match &event {
+                    Event::Empty(e) => {
+                        let name = e.name();
+
+                        // If we haven't opened <g> yet and we're in SVG, open it now
+                        if let Some(transform) =
+                            transform_str.as_deref().filter(|_| in_svg && !g_opened)
+                        {
+                            let mut g_start = BytesStart::new("g");
+                            g_start.push_attribute(("transform", transform));
+                            let _ = writer.write_event(Event::Start(g_start));
+                            g_opened = true;
+                        }
+
+                        let _ = writer.write_event(event);
+                    }
+                    _ => {
+                        let _ = writer.write_event(event);
+                    }
+                }
+            }
+            Err(_) => break,
+        }
+    }
+
+    writer.into_inner().into_inner()
+}
+
+/// Removes the `<g>` elements of an SVG, moving their transforms and
+/// presentation attributes onto their children.
+///
+/// Some tools cannot handle nested transforms, such as the centering group
+/// added by [`adjust_svg_height_and_center`] around paths that carry their
+/// own `matrix(...)`. Each element under a removed group gets the composed
+/// transform as a single `transform="matrix(a, b, c, d, e, f)"` (omitted
+/// when it is the identity), and inherits the group's other attributes,
+/// such as `fill`, unless it sets them itself; `style` attributes are
+/// concatenated, the group's first.
+///
+/// The content of `<defs>`, `<symbol>`, `<clipPath>`, `<mask>`, `<pattern>`,
+/// `<marker>`, gradients, filters and `<style>` is copied unchanged, since
+/// it is drawn in the coordinate system of whatever references it. Groups
+/// with an `id`, `clip-path`, `mask`, `filter` or `opacity` attribute cannot
+/// be dissolved without changing the rendering: they are kept, with their
+/// transform composed the same way, and their content is flattened relative
+/// to them.
+///
+/// # Example
+///
+/// ```rust
+/// use microtex_rs::flatten_svg_transforms;
+///
+/// let svg = r#"<svg><g transform="translate(0, 5)" fill="red"><path d="M 0 0 L 1 1" transform="scale(2)"/></g></svg>"#;
+/// assert_eq!(
+///     flatten_svg_transforms(svg),
+///     r#"<svg><path d="M 0 0 L 1 1" fill="red" transform="matrix(2, 0, 0, 2, 0, 5)"/></svg>"#
+/// );
+/// ```
+pub fn flatten_svg_transforms(svg: &str) -> String {
+    use quick_xml::events::attributes::Attribute;
+    use quick_xml::events::{BytesStart, Event};
+    use quick_xml::name::QName;
+    use quick_xml::Reader;
+    use quick_xml::Writer;
+    use std::io::Cursor;
+
+    const DEFINITIONS: [&[u8]; 11] = [
+        b"defs",
+        b"symbol",
+        b"clipPath",
+        b"mask",
+        b"pattern",
+        b"marker",
+        b"linearGradient",
+        b"radialGradient",
+        b"filter",
+        b"style",
+        b"script",
+    ];
+    const GROUP_EFFECTS: [&[u8]; 5] = [b"id", b"clip-path", b"mask", b"filter", b"opacity"];
+
+    /// What applies to the children of an open element.
+    struct Frame {
+        matrix: AffineMatrix,
+        /// Attributes inherited from removed groups, as raw key and value
+        inherited: Vec<(Vec<u8>, Vec<u8>)>,
+        /// Whether the element was removed, so its end tag is dropped too
+        removed: bool,
+    }
+
+    // Rebuilds an element with the composed transform and inherited
+    // attributes of the removed groups around it
+    let rebuild = |e: &BytesStart, frame: &Frame| -> BytesStart<'static> {
+        let mut matrix = frame.matrix;
+        let mut own: Vec<(Vec<u8>, Vec<u8>)> = Vec::new();
+        for attr in e.attributes().flatten() {
+            if attr.key.as_ref() == b"transform" {
+                let value = String::from_utf8_lossy(&attr.value);
+                matrix = compose_matrices(frame.matrix, parse_transform(&value));
+            } else {
+                own.push((attr.key.as_ref().to_vec(), attr.value.to_vec()));
+            }
+        }
+        for (key, value) in &frame.inherited {
+            match own.iter_mut().find(|(k, _)| k == key) {
+                Some((_, own_value)) if key == b"style" => {
+                    let mut style = value.clone();
+                    style.push(b';');
+                    style.extend_from_slice(own_value);
+                    *own_value = style;
+                }
+                Some(_) => {}
+                None => own.push((key.clone(), value.clone())),
+            }
+        }
+
+        let name = String::from_utf8_lossy(e.name().as_ref()).into_owned();
+        let mut element = BytesStart::new(name);
+        for (key, value) in &own {
+            element.push_attribute(Attribute {
+                key: QName(key),
+                value: value.as_slice().into(),
+            });
+        }
+        if matrix != IDENTITY_MATRIX {
+            let (a, b, c, d, e, f) = matrix;
+            let transform = format!("matrix({}, {}, {}, {}, {}, {})", a, b, c, d, e, f);
+            element.push_attribute(("transform", transform.as_str()));
+        }
+        element
+    };
+
+    let mut reader = Reader::from_str(svg);
+    let mut writer = Writer::new(Cursor::new(Vec::new()));
+    let mut frames: Vec<Frame> = Vec::new();
+    // Depth inside definition elements, copied unchanged
+    let mut in_definitions = 0usize;
+
+    loop {
+        match reader.read_event() {
+            Ok(Event::Start(e)) => {
+                let name = e.name();
+                let is_definition = DEFINITIONS.contains(&name.as_ref());
+                let Some(parent) = frames.last().filter(|_| in_definitions == 0) else {
+                    // The root element, or inside definitions
+                    in_definitions += usize::from(in_definitions > 0 || is_definition);
+                    frames.push(Frame {
+                        matrix: IDENTITY_MATRIX,
+                        inherited: Vec::new(),
+                        removed: false,
+                    });
+                    let _ = writer.write_event(Event::Start(e));
+                    continue;
+                };
+
+                if is_definition {
+                    in_definitions = 1;
+                    frames.push(Frame {
+                        matrix: IDENTITY_MATRIX,
+                        inherited: Vec::new(),
+                        removed: false,
+                    });
+                    let _ = writer.write_event(Event::Start(e));
+                    continue;
+                }
+
+                let is_group = name.as_ref() == b"g";
+                let has_effect = e
+                    .attributes()
+                    .flatten()
+                    .any(|attr| GROUP_EFFECTS.contains(&attr.key.as_ref()));
+                if is_group && !has_effect {
+                    // Dissolve the group into the frame of its children
+                    let mut matrix = parent.matrix;
+                    let mut inherited = parent.inherited.clone();
+                    for attr in e.attributes().flatten() {
+                        let key = attr.key.as_ref();
+                        if key == b"transform" {
+                            let value = String::from_utf8_lossy(&attr.value);
+                            matrix = compose_matrices(matrix, parse_transform(&value));
+                            continue;
+                        }
+                        let value = attr.value.to_vec();
+                        match inherited.iter_mut().find(|(k, _)| k == key) {
+                            Some((_, outer)) if key == b"style" => {
+                                outer.push(b';');
+                                outer.extend_from_slice(&value);
+                            }
+                            Some((_, outer)) => *outer = value,
+                            None => inherited.push((key.to_vec(), value)),
+                        }
+                    }
+                    frames.push(Frame {
+                        matrix,
+                        inherited,
+                        removed: true,
+                    });
+                } else {
+                    let element = rebuild(&e, parent);
+                    frames.push(Frame {
+                        matrix: IDENTITY_MATRIX,
+                        inherited: Vec::new(),
+                        removed: false,
+                    });
+                    let _ = writer.write_event(Event::Start(element));
+                }
+            }
+            Ok(Event::Empty(e)) => match frames.last().filter(|_| in_definitions == 0) {
+                Some(parent) if e.name().as_ref() != b"g" => {
+                    let _ = writer.write_event(Event::Empty(rebuild(&e, parent)));
+                }
+                // An empty group draws nothing
+                Some(_) => {}
+                None => {
+                    let _ = writer.write_event(Event::Empty(e));
+                }
+            },
+            Ok(Event::End(e)) => {
+                in_definitions = in_definitions.saturating_sub(1);
+                if !frames.pop().is_some_and(|frame| frame.removed) {
+                    let _ = writer.write_event(Event::End(e));
+                }
+            }
+            Ok(Event::Eof) | Err(_) => break,
+            Ok(event) => {
+                let _ = writer.write_event(event);
+            }
+        }
+    }
+
+    String::from_utf8(writer.into_inner().into_inner()).unwrap_or_else(|_| svg.to_string())
+}
+
+/// Returns the raw value of an attribute on the root `<svg>` element.
+fn svg_root_attribute(svg: &str, key: &str) -> Option<String> {
+    use quick_xml::events::Event;
+    use quick_xml::Reader;
+
+    let mut reader = Reader::from_str(svg);
+    loop {
+        match reader.read_event() {
+            Ok(Event::Start(e)) | Ok(Event::Empty(e)) if e.name().as_ref() == b"svg" => {
+                return e
+                    .attributes()
+                    .flatten()
+                    .find(|attr| attr.key.as_ref() == key.as_bytes())
+                    .and_then(|attr| String::from_utf8(attr.value.into_owned()).ok());
+            }
+            Ok(Event::Eof) | Err(_) => return None,
+            Ok(_) => {}
+        }
+    }
+}
+
+/// Splits an SVG length such as `"39"`, `"39.5pt"` into its number and unit suffix.
+fn split_svg_length(value: &str) -> Option<(f32, &str)> {
+    let value = value.trim();
+    let unit_start = value
+        .find(|c: char| c.is_ascii_alphabetic() || c == '%')
+        .unwrap_or(value.len());
+    let number = value[..unit_start].trim().parse::<f32>().ok()?;
+    Some((number, &value[unit_start..]))
+}
+
+/// Returns the number of pixels in one `unit` of an SVG length, at the CSS
+/// resolution of 96 pixels per inch, or `None` for a relative unit such as
+/// `em` or `%`.
+fn svg_unit_to_px(unit: &str) -> Option<f32> {
+    match unit {
+        "" | "px" => Some(1.0),
+        "pt" => Some(96.0 / 72.0),
+        "pc" => Some(16.0),
+        "in" => Some(96.0),
+        "cm" => Some(96.0 / 2.54),
+        "mm" => Some(96.0 / 25.4),
+        _ => None,
+    }
+}
+
+/// Pads an SVG by growing its canvas and shifting its content.
+///
+/// The root `width`, `height` and `viewBox` are enlarged by the given amounts
+/// (in SVG user units, CSS order: top, right, bottom, left). When `top` or
+/// `left` is non-zero, the content is wrapped in a `<g>` translated by
+/// `(left, top)` so it keeps its position relative to the new canvas.
+///
+/// # Arguments
+///
+/// * `svg` - The SVG content as a string
+/// * `top`, `right`, `bottom`, `left` - The padding to add on each side
+///
+/// # Returns
+///
+/// The padded SVG, or the original SVG if all paddings are zero.
+///
+/// # Example
+///
+/// ```rust
+/// use microtex_rs::pad_svg;
+///
+/// let svg = r#"<svg width="100" height="50" viewBox="0 0 100 50"><path d="M 0 0 L 10 10 Z"/></svg>"#;
+/// let padded = pad_svg(svg, 5.0, 0.0, 15.0, 0.0);
+/// assert!(padded.contains(r#"height="70""#));
+/// assert!(padded.contains(r#"viewBox="0 0 100 70""#));
+/// assert!(padded.contains(r#"<g transform="translate(0, 5)">"#));
+/// ```
+pub fn pad_svg(svg: &str, top: f32, right: f32, bottom: f32, left: f32) -> String {
+    use quick_xml::events::{BytesEnd, BytesStart, Event};
+    use quick_xml::Reader;
+    use quick_xml::Writer;
+    use std::io::Cursor;
+
+    if top == 0.0 && right == 0.0 && bottom == 0.0 && left == 0.0 {
+        return svg.to_string();
+    }
+
+    let wrap_content = top != 0.0 || left != 0.0;
+    let transform_str = format!("translate({}, {})", left, top);
+
+    let mut reader = Reader::from_str(svg);
+    let mut writer = Writer::new(Cursor::new(Vec::new()));
+    let mut svg_depth = 0usize;
+
+    loop {
+        match reader.read_event() {
+            Ok(Event::Start(e)) if e.name().as_ref() == b"svg" => {
+                svg_depth += 1;
+                if svg_depth > 1 {
+                    let _ = writer.write_event(Event::Start(e));
+                    continue;
+                }
+
+                let mut svg_start = BytesStart::new("svg");
+                for attr in e.attributes().flatten() {
+                    let key_str = std::str::from_utf8(attr.key.as_ref()).unwrap_or("");
+                    let value_str = std::str::from_utf8(&attr.value).unwrap_or("");
+                    let delta = match key_str {
+                        "width" => Some(left + right),
+                        "height" => Some(top + bottom),
+                        _ => None,
+                    };
+
+                    if let Some((number, unit)) = delta.and_then(|_| split_svg_length(value_str)) {
+                        let padded = format!("{}{}", number + delta.unwrap_or(0.0), unit);
+                        svg_start.push_attribute((key_str, padded.as_str()));
+                    } else if key_str == "viewBox" {
+                        let parts: Vec<f32> = value_str
+                            .split(|c: char| c == ',' || c.is_whitespace())
+                            .filter(|p| !p.is_empty())
+                            .filter_map(|p| p.parse::<f32>().ok())
+                            .collect();
+                        if parts.len() == 4 {
+                            let viewbox = format!(
+                                "{} {} {} {}",
+                                parts[0],
+                                parts[1],
+                                parts[2] + left + right,
+                                parts[3] + top + bottom
+                            );
+                            svg_start.push_attribute(("viewBox", viewbox.as_str()));
+                        } else {
+                            svg_start.push_attribute((key_str, value_str));
+                        }
+                    } else {
+                        svg_start.push_attribute((key_str, value_str));
+                    }
+                }
+
+                let _ = writer.write_event(Event::Start(svg_start));
+                if wrap_content {
+                    let mut g_start = BytesStart::new("g");
+                    g_start.push_attribute(("transform", transform_str.as_str()));
+                    let _ = writer.write_event(Event::Start(g_start));
+                }
+            }
+            Ok(Event::End(e)) if e.name().as_ref() == b"svg" => {
+                if svg_depth == 1 && wrap_content {
+                    let _ = writer.write_event(Event::End(BytesEnd::new("g")));
+                }
+                svg_depth = svg_depth.saturating_sub(1);
+                let _ = writer.write_event(Event::End(e));
+            }
+            Ok(Event::Eof) => break,
+            Ok(event) => {
+                let _ = writer.write_event(event);
+            }
+            Err(_) => return svg.to_string(),
+        }
+    }
+
+    String::from_utf8_lossy(&writer.into_inner().into_inner()).to_string()
+}
+
+/// Moves the origin of an SVG's `viewBox` to `0 0` without changing how it
+/// looks.
+///
+/// For a root `viewBox="min_x min_y width height"` with a non-zero origin,
+/// the `viewBox` becomes `0 0 width height` and the content is wrapped in a
+/// `<g>` translated by `(-min_x, -min_y)`, for consumers that ignore the
+/// `viewBox` origin.
+///
+/// # Returns
+///
+/// The normalized SVG, or the original SVG if the origin is already `0 0` or
+/// the root has no valid `viewBox`.
+///
+/// # Example
+///
+/// ```rust
+/// use microtex_rs::normalize_viewbox_origin;
+///
+/// let svg = r#"<svg width="100" height="50" viewBox="10 5 100 50"><path d="M 10 5 Z"/></svg>"#;
+/// let normalized = normalize_viewbox_origin(svg);
+/// assert!(normalized.contains(r#"viewBox="0 0 100 50""#));
+/// assert!(normalized.contains(r#"<g transform="translate(-10, -5)">"#));
+/// ```
+pub fn normalize_viewbox_origin(svg: &str) -> String {
+    use quick_xml::events::{BytesEnd, BytesStart, Event};
+    use quick_xml::Reader;
+    use quick_xml::Writer;
+    use std::io::Cursor;
+
+    let viewbox: Vec<f32> = svg_root_attribute(svg, "viewBox")
+        .unwrap_or_default()
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|p| !p.is_empty())
+        .filter_map(|p| p.parse::<f32>().ok())
+        .collect();
+    let [min_x, min_y, width, height] = viewbox[..] else {
+        return svg.to_string();
+    };
+    if min_x == 0.0 && min_y == 0.0 {
+        return svg.to_string();
+    }
+
+    let viewbox_str = format!("0 0 {} {}", width, height);
+    let transform_str = format!("translate({}, {})", -min_x, -min_y);
+
+    let mut reader = Reader::from_str(svg);
+    let mut writer = Writer::new(Cursor::new(Vec::new()));
+    let mut svg_depth = 0usize;
+
+    loop {
+        match reader.read_event() {
+            Ok(Event::Start(e)) if e.name().as_ref() == b"svg" => {
+                svg_depth += 1;
+                if svg_depth > 1 {
+                    let _ = writer.write_event(Event::Start(e));
+                    continue;
+                }
+
+                let mut svg_start = BytesStart::new("svg");
+                for attr in e.attributes().flatten() {
+                    let key_str = std::str::from_utf8(attr.key.as_ref()).unwrap_or("");
+                    let value_str = std::str::from_utf8(&attr.value).unwrap_or("");
+                    if key_str == "viewBox" {
+                        svg_start.push_attribute(("viewBox", viewbox_str.as_str()));
+                    } else {
+                        svg_start.push_attribute((key_str, value_str));
+                    }
+                }
+                let _ = writer.write_event(Event::Start(svg_start));
+
+                let mut g_start = BytesStart::new("g");
+                g_start.push_attribute(("transform", transform_str.as_str()));
+                let _ = writer.write_event(Event::Start(g_start));
+            }
+            Ok(Event::End(e)) if e.name().as_ref() == b"svg" => {
+                if svg_depth == 1 {
+                    let _ = writer.write_event(Event::End(BytesEnd::new("g")));
+                }
+                svg_depth = svg_depth.saturating_sub(1);
+                let _ = writer.write_event(Event::End(e));
+            }
+            Ok(Event::Eof) => break,
+            Ok(event) => {
+                let _ = writer.write_event(event);
+            }
+            Err(_) => return svg.to_string(),
+        }
+    }
+
+    String::from_utf8_lossy(&writer.into_inner().into_inner()).to_string()
+}
+
+/// Splits a multi-line SVG into one SVG per line.
+///
+/// Every `<path>` is assigned to a horizontal band of `line_height_px` user
+/// units by the vertical center of its bounds, which for a stroked path
+/// include half the stroke width around its points: the first band covers
+/// `0..line_height_px`, the second `line_height_px..2 * line_height_px`, and
+/// so on. Each non-empty band becomes a standalone SVG whose `width`,
+/// `height` and `viewBox` fit the paths it contains. The transforms of
+/// enclosing groups are folded into each path's `transform`, and the other
+/// root attributes (such as `xmlns`) are copied.
+///
+/// This is a heuristic for when the renderer only produces one SVG. It
+/// assumes lines are `line_height_px` apart starting at the top: a glyph
+/// reaching far into the next line (a tall integral, a big delimiter) may
+/// be assigned to the wrong line, and elements other than `<path>` are
+/// dropped.
+///
+/// # Returns
+///
+/// The lines from top to bottom. Empty if the SVG has no paths or
+/// `line_height_px` is not a positive number.
+///
+/// # Example
+///
+/// ```rust
+/// use microtex_rs::split_svg_lines;
+///
+/// let svg = r#"<svg width="100" height="40" viewBox="0 0 100 40">
+///   <path d="M 0 2 L 10 8 Z"/>
+///   <path d="M 0 22 L 10 28 Z"/>
+/// </svg>"#;
+/// let lines = split_svg_lines(svg, 20.0);
+/// assert_eq!(lines.len(), 2);
+/// assert!(lines[1].contains(r#"viewBox="0 22 10 6""#));
+/// ```
+pub fn split_svg_lines(svg: &str, line_height_px: f32) -> Vec<String> {
+    use quick_xml::events::{BytesEnd, BytesStart, Event};
+    use quick_xml::Reader;
+    use quick_xml::Writer;
+    use std::collections::BTreeMap;
+    use std::io::Cursor;
+
+    if !(line_height_px.is_finite() && line_height_px > 0.0) {
+        return Vec::new();
+    }
+
+    /// A path with its transform resolved and the bounds of its points.
+    struct LinePath {
+        attributes: Vec<(String, String)>,
+        bounds: (f32, f32, f32, f32),
+    }
+
+    let mut reader = Reader::from_str(svg);
+    let mut transforms: Vec<AffineMatrix> = Vec::new();
+    let mut root_attributes: Option<Vec<(String, String)>> = None;
+    let mut bands: BTreeMap<i64, Vec<LinePath>> = BTreeMap::new();
+
+    loop {
+        let (element, is_empty) = match reader.read_event() {
+            Ok(Event::Start(e)) => (e, false),
+            Ok(Event::Empty(e)) => (e, true),
+            Ok(Event::End(_)) => {
+                transforms.pop();
+                continue;
+            }
+            Ok(Event::Eof) | Err(_) => break,
+            Ok(_) => continue,
+        };
+
+        let attributes: Vec<(String, String)> = element
+            .attributes()
+            .flatten()
+            .map(|attr| {
+                (
+                    String::from_utf8_lossy(attr.key.as_ref()).into_owned(),
+                    String::from_utf8_lossy(&attr.value).into_owned(),
+                )
+            })
+            .collect();
+        let parent = transforms.last().copied().unwrap_or(IDENTITY_MATRIX);
+        let matrix = attributes
+            .iter()
+            .find(|(key, _)| key == "transform")
+            .map_or(parent, |(_, value)| {
+                compose_matrices(parent, parse_transform(value))
+            });
+        if !is_empty {
+            transforms.push(matrix);
+        }
+
+        match element.name().as_ref() {
+            b"svg" if root_attributes.is_none() => {
+                root_attributes = Some(
+                    attributes
+                        .into_iter()
+                        .filter(|(key, _)| !matches!(key.as_str(), "width" | "height" | "viewBox"))
+                        .collect(),
+                );
+            }
+            b"path" => {
+                let d_content = attributes
+                    .iter()
+                    .find(|(key, _)| key == "d")
+                    .map_or("", |(_, value)| value.as_str());
+                let points = path_points(d_content, Some(matrix));
+                let Some(bounds) = points.iter().fold(None, |bounds, &(x, y)| {
+                    Some(match bounds {
+                        Some((min_x, min_y, max_x, max_y)) => (
+                            f32::min(min_x, x),
+                            f32::min(min_y, y),
+                            f32::max(max_x, x),
+                            f32::max(max_y, y),
+                        ),
+                        None => (x, y, x, y),
+                    })
+                }) else {
+                    continue;
+                };
+                // A rule is a stroked line whose points have no height
+                let stroked = attributes
+                    .iter()
+                    .any(|(key, value)| key == "stroke" && value.trim() != "none");
+                let bounds = if stroked {
+                    let (a, b, c, d, _, _) = matrix;
+                    let half_width = attributes
+                        .iter()
+                        .find(|(key, _)| key == "stroke-width")
+                        .and_then(|(_, value)| value.trim().parse::<f32>().ok())
+                        .unwrap_or(1.0)
+                        * (a * d - b * c).abs().sqrt()
+                        / 2.0;
+                    (
+                        bounds.0 - half_width,
+                        bounds.1 - half_width,
+                        bounds.2 + half_width,
+                        bounds.3 + half_width,
+                    )
+                } else {
+                    bounds
+                };
+
+                let mut attributes: Vec<(String, String)> = attributes
+                    .into_iter()
+                    .filter(|(key, _)| key != "transform")
+                    .collect();
+                if matrix != IDENTITY_MATRIX {
+                    let (a, b, c, d, e, f) = matrix;
+                    attributes.push((
+                        "transform".to_string(),
+                        format!("matrix({}, {}, {}, {}, {}, {})", a, b, c, d, e, f),
+                    ));
+                }
+                let band = ((bounds.1 + bounds.3) / 2.0 / line_height_px).floor() as i64;
+                bands
+                    .entry(band)
+                    .or_default()
+                    .push(LinePath { attributes, bounds });
+            }
+            _ => {}
+        }
+    }
+
+    let root_attributes = root_attributes.unwrap_or_default();
+    bands
+        .into_values()
+        .map(|paths| {
+            let (min_x, min_y, max_x, max_y) =
+                paths
+                    .iter()
+                    .skip(1)
+                    .fold(paths[0].bounds, |(min_x, min_y, max_x, max_y), path| {
+                        (
+                            min_x.min(path.bounds.0),
+                            min_y.min(path.bounds.1),
+                            max_x.max(path.bounds.2),
+                            max_y.max(path.bounds.3),
+                        )
+                    });
+            let width = (max_x - min_x).to_string();
+            let height = (max_y - min_y).to_string();
+            let viewbox = format!("{} {} {} {}", min_x, min_y, width, height);
+
+            let mut writer = Writer::new(Cursor::new(Vec::new()));
+            let mut svg_start = BytesStart::new("svg");
+            for (key, value) in &root_attributes {
+                svg_start.push_attribute((key.as_str(), value.as_str()));
+            }
+            svg_start.push_attribute(("width", width.as_str()));
+            svg_start.push_attribute(("height", height.as_str()));
+            svg_start.push_attribute(("viewBox", viewbox.as_str()));
+            let _ = writer.write_event(Event::Start(svg_start));
+            for path in &paths {
+                let mut path_start = BytesStart::new("path");
+                for (key, value) in &path.attributes {
+                    path_start.push_attribute((key.as_str(), value.as_str()));
+                }
+                let _ = writer.write_event(Event::Empty(path_start));
+            }
+            let _ = writer.write_event(Event::End(BytesEnd::new("svg")));
+            String::from_utf8_lossy(&writer.into_inner().into_inner()).to_string()
+        })
+        .collect()
+}
+
+/// Sets the root `width` and `height` of an SVG to `width` and `height`
+/// pixels, written without a unit.
+///
+/// If the root has no `viewBox`, one is added from the original dimensions
+/// converted to pixels, the user units of an SVG without a `viewBox`, so the
+/// content is scaled with the canvas instead of being cropped.
+fn resize_svg(svg: &str, width: f32, height: f32) -> String {
+    use quick_xml::events::{BytesStart, Event};
+    use quick_xml::Reader;
+    use quick_xml::Writer;
+    use std::io::Cursor;
+
+    let mut reader = Reader::from_str(svg);
+    let mut writer = Writer::new(Cursor::new(Vec::new()));
+    let mut root_done = false;
+
+    loop {
+        match reader.read_event() {
+            Ok(Event::Start(e)) if !root_done && e.name().as_ref() == b"svg" => {
+                root_done = true;
+                let mut svg_start = BytesStart::new("svg");
+                let mut original = (None, None);
+                let mut has_viewbox = false;
+                for attr in e.attributes().flatten() {
+                    let key_str = std::str::from_utf8(attr.key.as_ref()).unwrap_or("");
+                    let value_str = std::str::from_utf8(&attr.value).unwrap_or("");
+                    let (new_value, slot) = match key_str {
+                        "width" => (width, &mut original.0),
+                        "height" => (height, &mut original.1),
+                        _ => {
+                            has_viewbox |= key_str == "viewBox";
+                            svg_start.push_attribute((key_str, value_str));
+                            continue;
+                        }
+                    };
+                    match split_svg_length(value_str) {
+                        Some((number, unit)) => {
+                            *slot = Some(number * svg_unit_to_px(unit).unwrap_or(1.0));
+                            svg_start.push_attribute((key_str, new_value.to_string().as_str()));
+                        }
+                        None => svg_start.push_attribute((key_str, value_str)),
+                    }
+                }
+                if let (false, (Some(w), Some(h))) = (has_viewbox, original) {
+                    let viewbox = format!("0 0 {} {}", w, h);
+                    svg_start.push_attribute(("viewBox", viewbox.as_str()));
+                }
+                let _ = writer.write_event(Event::Start(svg_start));
+            }
+            Ok(Event::Eof) => break,
+            Ok(event) => {
+                let _ = writer.write_event(event);
+            }
+            Err(_) => return svg.to_string(),
+        }
+    }
+
+    String::from_utf8_lossy(&writer.into_inner().into_inner()).to_string()
+}
+
+/// Converts stroked straight lines into filled rectangles.
+///
+/// MicroTeX draws rules (fraction bars, overlines, underlines, `\rule`) as
+/// single-segment stroked paths (`M x1 y1 L x2 y2` with a `stroke-width`).
+/// Pen plotters and laser cutters need fill-only geometry, so each such path
+/// is replaced by a filled path outlining the area the stroke covers. The
+/// path's `transform` is applied to the outline and dropped, so the new
+/// coordinates are in the parent's user units.
+///
+/// Only strokes with `butt` (the default) or `square` line caps are
+/// converted; other paths, including curves and round-capped lines, are left
+/// unchanged.
+///
+/// # Example
+///
+/// ```rust
+/// use microtex_rs::strokes_to_fills;
+///
+/// let svg = r#"<svg><path fill="none" stroke="black" stroke-width="2" d="M 0 5 L 10 5"/></svg>"#;
+/// let filled = strokes_to_fills(svg);
+/// assert!(filled.contains(r#"fill="black""#));
+/// assert!(filled.contains(r#"d="M 0 4 L 10 4 L 10 6 L 0 6 Z""#));
+/// assert!(!filled.contains("stroke"));
+/// ```
+pub fn strokes_to_fills(svg: &str) -> String {
+    use quick_xml::events::Event;
+    use quick_xml::Reader;
+    use quick_xml::Writer;
+    use std::io::Cursor;
+
+    let mut reader = Reader::from_str(svg);
+    let mut writer = Writer::new(Cursor::new(Vec::new()));
+
+    loop {
+        match reader.read_event() {
+            Ok(Event::Empty(e)) if e.name().as_ref() == b"path" => {
+                let filled = stroke_line_to_fill(&e);
+                let _ = writer.write_event(Event::Empty(filled.unwrap_or(e)));
+            }
+            Ok(Event::Start(e)) if e.name().as_ref() == b"path" => {
+                let filled = stroke_line_to_fill(&e);
+                let _ = writer.write_event(Event::Start(filled.unwrap_or(e)));
+            }
+            Ok(Event::Eof) => break,
+            Ok(event) => {
+                let _ = writer.write_event(event);
+            }
+            Err(_) => return svg.to_string(),
+        }
+    }
+
+    String::from_utf8_lossy(&writer.into_inner().into_inner()).to_string()
+}
+
+/// Builds the filled replacement of a stroked single-segment `<path>`, or
+/// `None` if the path is not a convertible straight stroke.
+fn stroke_line_to_fill(
+    element: &quick_xml::events::BytesStart,
+) -> Option<quick_xml::events::BytesStart<'static>> {
+    let attributes: Vec<(String, String)> = element
+        .attributes()
+        .flatten()
+        .map(|attr| {
+            (
+                String::from_utf8_lossy(attr.key.as_ref()).into_owned(),
+                String::from_utf8_lossy(&attr.value).into_owned(),
+            )
+        })
+        .collect();
+    let attribute = |key: &str| {
+        attributes
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.trim())
+    };
+
+    let stroke = attribute("stroke").filter(|stroke| *stroke != "none")?;
+    if attribute("fill").is_some_and(|fill| fill != "none") {
+        return None;
+    }
+    let square_cap = match attribute("stroke-linecap").unwrap_or("butt") {
+        "butt" => false,
+        "square" => true,
+        _ => return None,
+    };
+    let d = attribute("d")?;
+    let commands: String = d.chars().filter(|c| c.is_ascii_alphabetic()).collect();
+    let (x1, y1, x2, y2) = match (commands.as_str(), parse_path_numbers(d).as_slice()) {
+        ("ML", [x1, y1, x2, y2]) => (*x1, *y1, *x2, *y2),
+        _ => return None,
+    };
+    let half_width = attribute("stroke-width")
+        .and_then(|width| width.parse::<f32>().ok())
+        .unwrap_or(1.0)
+        / 2.0;
+
+    let length = ((x2 - x1).powi(2) + (y2 - y1).powi(2)).sqrt();
+    if length == 0.0 || half_width <= 0.0 {
+        return None;
+    }
+    // Unit direction of the line, and its normal scaled to half the width
+    let (ux, uy) = ((x2 - x1) / length, (y2 - y1) / length);
+    let (nx, ny) = (-uy * half_width, ux * half_width);
+    let cap = if square_cap { half_width } else { 0.0 };
+    let (sx, sy) = (x1 - ux * cap, y1 - uy * cap);
+    let (ex, ey) = (x2 + ux * cap, y2 + uy * cap);
+
+    let corners = [
+        sx - nx,
+        sy - ny,
+        ex - nx,
+        ey - ny,
+        ex + nx,
+        ey + ny,
+        sx + nx,
+        sy + ny,
+    ];
+    let matrix = attribute("transform").map(parse_transform);
+    let points = pair_path_points(&corners, matrix);
+    let outline = format!(
+        "M {} {} L {} {} L {} {} L {} {} Z",
+        points[0].0,
+        points[0].1,
+        points[1].0,
+        points[1].1,
+        points[2].0,
+        points[2].1,
+        points[3].0,
+        points[3].1
+    );
+
+    let mut filled = quick_xml::events::BytesStart::new("path");
+    for (key, value) in &attributes {
+        let dropped = key.starts_with("stroke")
+            || matches!(key.as_str(), "fill" | "fill-opacity" | "d" | "transform");
+        if !dropped {
+            filled.push_attribute((key.as_str(), value.as_str()));
+        }
+    }
+    filled.push_attribute(("fill", stroke));
+    if let Some(opacity) = attribute("stroke-opacity") {
+        filled.push_attribute(("fill-opacity", opacity));
+    }
+    filled.push_attribute(("d", outline.as_str()));
+    Some(filled)
+}
+
+/// Widens strokes thinner than `min_px` pixels, so that rules such as
+/// fraction bars do not vanish when rasterized, see
+/// [`RenderConfig::min_stroke_width_px`].
+///
+/// A stroke's width in pixels is its `stroke-width` scaled by the transforms
+/// of the path and its ancestors, and by the root `viewBox` to `width` and
+/// `height` ratio. Transforms that do not scale uniformly use the geometric
+/// mean of their scale factors.
+fn enforce_min_stroke_width(svg: &str, min_px: f32) -> String {
+    use quick_xml::events::{BytesStart, Event};
+    use quick_xml::Reader;
+    use quick_xml::Writer;
+    use std::io::Cursor;
+
+    // The transform from an element's user units to pixels
+    let element_matrix = |e: &BytesStart, parent: AffineMatrix| {
+        let mut matrix = parent;
+        if e.name().as_ref() == b"svg" {
+            let attribute = |key: &str| {
+                e.try_get_attribute(key)
+                    .ok()
+                    .flatten()
+                    .map(|attr| String::from_utf8_lossy(&attr.value).into_owned())
+            };
+            let length = |key| {
+                attribute(key)
+                    .as_deref()
+                    .and_then(split_svg_length)
+                    .map(|(value, _)| value)
+            };
+            let viewbox: Vec<f32> = attribute("viewBox")
+                .unwrap_or_default()
+                .split(|c: char| c == ',' || c.is_whitespace())
+                .filter_map(|v| v.parse().ok())
+                .collect();
+            if let ([_, _, vb_width, vb_height], Some(width), Some(height)) =
+                (viewbox.as_slice(), length("width"), length("height"))
+            {
+                if *vb_width > 0.0 && *vb_height > 0.0 {
+                    let scale = (width / vb_width, 0.0, 0.0, height / vb_height, 0.0, 0.0);
+                    matrix = compose_matrices(matrix, scale);
+                }
+            }
+        }
+        if let Ok(Some(transform)) = e.try_get_attribute("transform") {
+            let transform = parse_transform(&String::from_utf8_lossy(&transform.value));
+            matrix = compose_matrices(matrix, transform);
+        }
+        matrix
+    };
+    let widen = |e: BytesStart<'static>, matrix: AffineMatrix| {
+        let (a, b, c, d, _, _) = matrix;
+        let scale = (a * d - b * c).abs().sqrt();
+        let width = e
+            .try_get_attribute("stroke-width")
+            .ok()
+            .flatten()
+            .and_then(|attr| {
+                String::from_utf8_lossy(&attr.value)
+                    .trim()
+                    .parse::<f32>()
+                    .ok()
+            });
+        let stroked = e
+            .try_get_attribute("stroke")
+            .ok()
+            .flatten()
+            .is_some_and(|attr| attr.value.as_ref() != b"none");
+        match width {
+            Some(width) if stroked && scale > 0.0 && width * scale < min_px => {
+                let widened = format_canonical_number((min_px / scale) as f64);
+                let mut path = BytesStart::new("path");
+                for attr in e.attributes().flatten() {
+                    if attr.key.as_ref() == b"stroke-width" {
+                        path.push_attribute(("stroke-width", widened.as_str()));
+                    } else {
+                        path.push_attribute(attr);
+                    }
+                }
+                path
+            }
+            _ => e,
+        }
+    };
+
+    let mut reader = Reader::from_str(svg);
+    let mut writer = Writer::new(Cursor::new(Vec::new()));
+    let mut matrices = vec![IDENTITY_MATRIX];
+
+    loop {
+        let parent = *matrices.last().unwrap_or(&IDENTITY_MATRIX);
+        match reader.read_event() {
+            Ok(Event::Start(e)) => {
+                let matrix = element_matrix(&e, parent);
+                matrices.push(matrix);
+                let e = e.into_owned();
+                let e = if e.name().as_ref() == b"path" {
+                    widen(e, matrix)
+                } else {
+                    e
+                };
+                let _ = writer.write_event(Event::Start(e));
+            }
+            Ok(Event::Empty(e)) if e.name().as_ref() == b"path" => {
+                let matrix = element_matrix(&e, parent);
+                let _ = writer.write_event(Event::Empty(widen(e.into_owned(), matrix)));
+            }
+            Ok(Event::End(e)) => {
+                matrices.pop();
+                let _ = writer.write_event(Event::End(e));
+            }
+            Ok(Event::Eof) => break,
+            Ok(event) => {
+                let _ = writer.write_event(event);
+            }
+            Err(_) => return svg.to_string(),
+        }
+    }
+
+    String::from_utf8_lossy(&writer.into_inner().into_inner()).to_string()
+}
+
+/// Embeds a rendered formula into an existing SVG at the given coordinates.
+///
+/// The children of the formula's root `<svg>` are wrapped in a
+/// `<g transform="translate(x, y)">` group and inserted just before the
+/// closing tag of the base SVG. Nothing else in the base SVG is modified, so
+/// the caller is responsible for making sure the canvas is large enough.
+///
+/// A group is used rather than a nested `<svg>` element so the formula shares
+/// the coordinate system of the base canvas and is never clipped to its own
+/// viewport.
+///
+/// # Arguments
+///
+/// * `base_svg` - The SVG canvas to draw into
+/// * `formula_svg` - A rendered formula (e.g. from [`MicroTex::render`])
+/// * `x`, `y` - Position of the formula's top-left corner in base SVG units
+///
+/// # Returns
+///
+/// The base SVG with the formula inserted, or the base SVG unchanged if either
+/// input cannot be parsed.
+///
+/// # Example
+///
+/// ```rust
+/// use microtex_rs::embed_formula_into_svg;
+///
+/// let base = r#"<svg width="200" height="100"><rect width="200" height="100"/></svg>"#;
+/// let formula = r#"<svg width="20" height="10"><path d="M 0 0 L 5 5 Z"/></svg>"#;
+/// let combined = embed_formula_into_svg(base, formula, 40.0, 25.0);
+/// assert!(combined.contains(r#"<g transform="translate(40, 25)"><path d="M 0 0 L 5 5 Z"/></g></svg>"#));
+/// ```
+pub fn embed_formula_into_svg(base_svg: &str, formula_svg: &str, x: f32, y: f32) -> String {
+    use quick_xml::events::{BytesEnd, BytesStart, Event};
+    use quick_xml::Reader;
+    use quick_xml::Writer;
+    use std::io::Cursor;
+
+    // Collect the children of the formula's root <svg>
+    let mut formula_reader = Reader::from_str(formula_svg);
+    let mut formula_events = Vec::new();
+    let mut svg_depth = 0usize;
+    loop {
+        match formula_reader.read_event() {
+            Ok(Event::Start(e)) if e.name().as_ref() == b"svg" => {
+                svg_depth += 1;
+                if svg_depth > 1 {
+                    formula_events.push(Event::Start(e).into_owned());
+                }
+            }
+            Ok(Event::End(e)) if e.name().as_ref() == b"svg" => {
+                svg_depth = svg_depth.saturating_sub(1);
+                if svg_depth == 0 {
+                    break;
+                }
+                formula_events.push(Event::End(e).into_owned());
+            }
+            Ok(Event::Eof) => break,
+            Ok(event) => {
+                if svg_depth > 0 {
+                    formula_events.push(event.into_owned());
+                }
+            }
+            Err(_) => return base_svg.to_string(),
+        }
+    }
+
+    let transform_str = format!("translate({}, {})", x, y);
+    let mut reader = Reader::from_str(base_svg);
+    let mut writer = Writer::new(Cursor::new(Vec::new()));
+    let mut svg_depth = 0usize;
+
+    loop {
+        match reader.read_event() {
+            Ok(Event::Start(e)) if e.name().as_ref() == b"svg" => {
+                svg_depth += 1;
+                let _ = writer.write_event(Event::Start(e));
+            }
+            Ok(Event::End(e)) if e.name().as_ref() == b"svg" => {
+                if svg_depth == 1 {
+                    let mut g_start = BytesStart::new("g");
+                    g_start.push_attribute(("transform", transform_str.as_str()));
+                    let _ = writer.write_event(Event::Start(g_start));
+                    for event in formula_events.drain(..) {
+                        let _ = writer.write_event(event);
+                    }
+                    let _ = writer.write_event(Event::End(BytesEnd::new("g")));
+                }
+                svg_depth = svg_depth.saturating_sub(1);
+                let _ = writer.write_event(Event::End(e));
+            }
+            Ok(Event::Eof) => break,
+            Ok(event) => {
+                let _ = writer.write_event(event);
+            }
+            Err(_) => return base_svg.to_string(),
+        }
+    }
+
+    String::from_utf8_lossy(&writer.into_inner().into_inner()).to_string()
+}
+
+/// Converts a standalone SVG into a `<symbol>` definition with the given id.
+///
+/// The root `<svg>` element is replaced by `<symbol>`, keeping only its
+/// `viewBox` (derived from `width`/`height` if missing). Children are copied
+/// unchanged.
+fn svg_to_symbol(svg: &str, id: &str) -> String {
+    use quick_xml::events::{BytesEnd, BytesStart, Event};
+    use quick_xml::Reader;
+    use quick_xml::Writer;
+    use std::io::Cursor;
+
+    let mut reader = Reader::from_str(svg);
+    let mut writer = Writer::new(Cursor::new(Vec::new()));
+    let mut svg_depth = 0usize;
+
+    loop {
+        match reader.read_event() {
+            Ok(Event::Start(e)) if e.name().as_ref() == b"svg" => {
+                svg_depth += 1;
+                if svg_depth > 1 {
+                    let _ = writer.write_event(Event::Start(e));
+                    continue;
+                }
+
+                let attribute = |key: &[u8]| {
+                    e.attributes()
+                        .flatten()
+                        .find(|attr| attr.key.as_ref() == key)
+                        .and_then(|attr| String::from_utf8(attr.value.into_owned()).ok())
+                };
+                let viewbox = attribute(b"viewBox").or_else(|| {
+                    let (width, _) = split_svg_length(&attribute(b"width")?)?;
+                    let (height, _) = split_svg_length(&attribute(b"height")?)?;
+                    Some(format!("0 0 {} {}", width, height))
+                });
+
+                let mut symbol_start = BytesStart::new("symbol");
+                symbol_start.push_attribute(("id", id));
+                if let Some(viewbox) = viewbox.as_deref() {
+                    symbol_start.push_attribute(("viewBox", viewbox));
+                }
+                let _ = writer.write_event(Event::Start(symbol_start));
+            }
+            Ok(Event::End(e)) if e.name().as_ref() == b"svg" => {
+                svg_depth = svg_depth.saturating_sub(1);
+                if svg_depth == 0 {
+                    let _ = writer.write_event(Event::End(BytesEnd::new("symbol")));
+                } else {
+                    let _ = writer.write_event(Event::End(e));
+                }
+            }
+            // Only the root element's content belongs in the symbol
+            Ok(Event::Decl(_)) | Ok(Event::DocType(_)) => {}
+            Ok(Event::Eof) => break,
+            Ok(event) => {
+                let _ = writer.write_event(event);
+            }
+            Err(_) => break,
+        }
+    }
+
+    String::from_utf8_lossy(&writer.into_inner().into_inner()).to_string()
+}
+
+/// Builds a `<use>` element referencing a symbol produced by
+/// [`MicroTex::render_to_symbol`].
+///
+/// The `href` points at the symbol's `id`, and `width`/`height` are taken
+/// from its `viewBox` so the formula is displayed at its rendered size.
+///
+/// # Arguments
+///
+/// * `symbol` - The `<symbol>` definition
+///
+/// # Returns
+///
+/// The `<use>` element, or `None` if the symbol has no `id` attribute.
+///
+/// # Example
+///
+/// ```rust
+/// use microtex_rs::symbol_use_reference;
+///
+/// let symbol = r#"<symbol id="formula-1" viewBox="0 0 120 40"><path d="M 0 0 Z"/></symbol>"#;
+/// assert_eq!(
+///     symbol_use_reference(symbol).unwrap(),
+///     r##"<use href="#formula-1" width="120" height="40"/>"##
+/// );
+/// ```
+pub fn symbol_use_reference(symbol: &str) -> Option<String> {
+    use quick_xml::events::Event;
+    use quick_xml::Reader;
+
+    let mut reader = Reader::from_str(symbol);
+    let start = loop {
+        match reader.read_event() {
+            Ok(Event::Start(e)) | Ok(Event::Empty(e)) if e.name().as_ref() == b"symbol" => {
+                break e.into_owned()
+            }
+            Ok(Event::Eof) | Err(_) => return None,
+            Ok(_) => {}
+        }
+    };
+
+    let attribute = |key: &[u8]| {
+        start
+            .attributes()
+            .flatten()
+            .find(|attr| attr.key.as_ref() == key)
+            .and_then(|attr| attr.unescape_value().ok().map(|v| v.into_owned()))
+    };
+
+    let id = attribute(b"id")?;
+    let mut reference = format!(
+        r##"<use href="#{}""##,
+        quick_xml::escape::escape(id.as_str())
+    );
+    if let Some(viewbox) = attribute(b"viewBox") {
+        let parts: Vec<&str> = viewbox.split_whitespace().collect();
+        if parts.len() == 4 {
+            reference.push_str(&format!(r#" width="{}" height="{}""#, parts[2], parts[3]));
+        }
+    }
+    reference.push_str("/>");
+    Some(reference)
+}
+
+/// Decimal places kept for numbers in geometry attributes by [`canonicalize_svg`].
+const CANONICAL_PRECISION: usize = 3;
+
+/// Attributes whose numbers are rounded to [`CANONICAL_PRECISION`].
+const GEOMETRY_ATTRIBUTES: &[&str] = &[
+    "d",
+    "transform",
+    "viewBox",
+    "points",
+    "x",
+    "y",
+    "x1",
+    "y1",
+    "x2",
+    "y2",
+    "cx",
+    "cy",
+    "r",
+    "rx",
+    "ry",
+    "width",
+    "height",
+    "stroke-width",
+];
+
+/// Rewrites an SVG into a stable canonical form suitable for hashing.
+///
+/// Two SVGs that draw the same thing with the same attributes canonicalize to
+/// the same bytes, even if they differ in formatting or in incidental
+/// metadata. Unlike minification, the goal is stability rather than size.
+///
+/// Preserved:
+/// - the element tree, element names and text content (trimmed)
+/// - all attributes except those listed below, sorted by name, with runs of
+///   whitespace collapsed to a single space and comma separators normalized
+///   to `", "`
+/// - the default `xmlns` declaration and `xmlns:` prefixes that are used
+///
+/// Dropped or normalized:
+/// - the XML declaration, doctype, comments and processing instructions
+/// - whitespace-only text between elements
+/// - the `version` attribute and unused `xmlns:` prefix declarations
+/// - numbers in geometry attributes (`d`, `transform`, `viewBox`, `width`,
+///   `height`, coordinates...) are rounded to 3 decimals without trailing
+///   zeros, and `-0` becomes `0`
+/// - empty elements are always written self-closing (`<g/>`)
+///
+/// Returns the input unchanged if it is not well-formed XML.
+///
+/// # Example
+///
+/// ```rust
+/// use microtex_rs::canonicalize_svg;
+///
+/// let a = r#"<?xml version="1.0"?>
+/// <svg version="1.1" width="10" height="5"><path d="M 1.00001 2 L 3 4"></path></svg>"#;
+/// let b = r#"<svg height="5.0" width="10"> <path d="M 1  2 L 3 4"/> </svg>"#;
+/// assert_eq!(canonicalize_svg(a), canonicalize_svg(b));
+/// assert_eq!(
+///     canonicalize_svg(b),
+///     r#"<svg height="5" width="10"><path d="M 1 2 L 3 4"/></svg>"#
+/// );
+/// ```
+pub fn canonicalize_svg(svg: &str) -> String {
+    use quick_xml::events::{BytesStart, BytesText, Event};
+    use quick_xml::Reader;
+    use quick_xml::Writer;
+    use std::io::Cursor;
+
+    // First pass: namespace prefixes used by elements or attributes
+    let mut used_prefixes: Vec<Vec<u8>> = Vec::new();
+    let mut reader = Reader::from_str(svg);
+    loop {
+        match reader.read_event() {
+            Ok(Event::Start(e)) | Ok(Event::Empty(e)) => {
+                let attributes = e.attributes().flatten();
+                let keys = attributes
+                    .filter(|attr| !attr.key.as_ref().starts_with(b"xmlns"))
+                    .map(|attr| attr.key.prefix().map(|p| p.as_ref().to_vec()));
+                for prefix in keys.chain([e.name().prefix().map(|p| p.as_ref().to_vec())]) {
+                    if let Some(prefix) = prefix.filter(|p| !used_prefixes.contains(p)) {
+                        used_prefixes.push(prefix);
+                    }
+                }
+            }
+            Ok(Event::Eof) => break,
+            Err(_) => return svg.to_string(),
+            Ok(_) => {}
+        }
+    }
+
+    let canonical_start = |e: &BytesStart| {
+        let mut attributes: Vec<(String, String)> = e
+            .attributes()
+            .flatten()
+            .filter(|attr| {
+                let key = attr.key.as_ref();
+                match key.strip_prefix(b"xmlns:") {
+                    Some(prefix) => used_prefixes.iter().any(|used| used == prefix),
+                    None => key != b"version",
+                }
+            })
+            .map(|attr| {
+                let key = String::from_utf8_lossy(attr.key.as_ref()).into_owned();
+                let value = String::from_utf8_lossy(&attr.value);
+                let value =
+                    canonical_attribute_value(&value, GEOMETRY_ATTRIBUTES.contains(&key.as_str()));
+                (key, value)
+            })
+            .collect();
+        attributes.sort();
+
+        let name = String::from_utf8_lossy(e.name().as_ref()).into_owned();
+        let mut start = BytesStart::new(name);
+        for (key, value) in &attributes {
+            start.push_attribute((key.as_str(), value.as_str()));
+        }
+        start
+    };
+
+    let mut reader = Reader::from_str(svg);
+    let mut writer = Writer::new(Cursor::new(Vec::new()));
+    // A start tag is held back until we know whether the element is empty
+    let mut pending: Option<BytesStart> = None;
+
+    loop {
+        let event = match reader.read_event() {
+            Ok(Event::Eof) => break,
+            Ok(event) => event,
+            Err(_) => return svg.to_string(),
+        };
+        if let Event::End(_) = &event {
+            if let Some(start) = pending.take() {
+                let _ = writer.write_event(Event::Empty(start));
+                continue;
+            }
+        }
+        let is_content = matches!(
+            &event,
+            Event::Start(_)
+                | Event::Empty(_)
+                | Event::End(_)
+                | Event::CData(_)
+                | Event::GeneralRef(_)
+        ) || matches!(&event, Event::Text(t) if !t.trim_ascii().is_empty());
+        if !is_content {
+            continue;
+        }
+        if let Some(start) = pending.take() {
+            let _ = writer.write_event(Event::Start(start));
+        }
+
+        match event {
+            Event::Start(e) => pending = Some(canonical_start(&e)),
+            Event::Empty(e) => {
+                let _ = writer.write_event(Event::Empty(canonical_start(&e)));
+            }
+            Event::Text(t) => {
+                let text = String::from_utf8_lossy(t.trim_ascii()).into_owned();
+                let _ = writer.write_event(Event::Text(BytesText::from_escaped(text)));
+            }
+            event => {
+                let _ = writer.write_event(event);
+            }
+        }
+    }
+
+    String::from_utf8_lossy(&writer.into_inner().into_inner()).to_string()
+}
+
+/// Attributes holding a color, shortened by [`minify_svg`].
+const COLOR_ATTRIBUTES: [&str; 6] = [
+    "fill",
+    "stroke",
+    "color",
+    "stop-color",
+    "flood-color",
+    "lighting-color",
+];
+
+/// Inherited opacities dropped by [`minify_svg`] when they hold 1 and so
+/// does the value they would inherit. `opacity`, which is not inherited, is
+/// dropped whenever it holds 1.
+const INHERITED_OPACITY_ATTRIBUTES: [&str; 2] = ["fill-opacity", "stroke-opacity"];
+
+/// Shrinks an SVG without changing what it draws.
+///
+/// Unlike [`canonicalize_svg`], the goal is size: the output is not meant to
+/// be stable across equivalent inputs.
+///
+/// - whitespace-only text between tags is dropped, except inside `<text>`
+///   elements, where it is part of the content
+/// - `opacity` attributes equal to 1, their default, are dropped, and so
+///   are `fill-opacity` and `stroke-opacity` attributes equal to 1 when no
+///   ancestor sets them to anything else (they are inherited, so a 1 under
+///   a translucent group is kept)
+/// - colors in `fill`, `stroke` and the other color attributes are written
+///   in their shortest hexadecimal form: `rgb(0%, 0%, 0%)` becomes `#000`
+///   and `#ff8800` becomes `#f80`
+///
+/// Every other attribute, including path `d` data, is kept byte for byte.
+/// Returns the input unchanged if it is not well-formed XML.
+///
+/// # Example
+///
+/// ```rust
+/// use microtex_rs::minify_svg;
+///
+/// let svg = "<svg>\n  <path fill=\"rgb(0%, 0%, 0%)\" fill-opacity=\"1\" d=\"M 1 2 L 3 4 Z\"/>\n</svg>";
+/// assert_eq!(minify_svg(svg), r##"<svg><path fill="#000" d="M 1 2 L 3 4 Z"/></svg>"##);
+/// ```
+pub fn minify_svg(svg: &str) -> String {
+    use quick_xml::events::{BytesStart, Event};
+    use quick_xml::Reader;
+    use quick_xml::Writer;
+    use std::io::Cursor;
+
+    // Takes whether each of `INHERITED_OPACITY_ATTRIBUTES` inherits 1, and
+    // also returns whether it does for the children of the element
+    let minify_start = |e: &BytesStart, inherits_one: [bool; 2]| {
+        let mut start = BytesStart::new(String::from_utf8_lossy(e.name().as_ref()).into_owned());
+        let mut children_inherit_one = inherits_one;
+        for attr in e.attributes().flatten() {
+            let key = std::str::from_utf8(attr.key.as_ref()).unwrap_or("");
+            let value = std::str::from_utf8(&attr.value).unwrap_or("");
+            let is_one = value
+                .trim()
+                .parse::<f32>()
+                .is_ok_and(|opacity| opacity == 1.0);
+            let inherited = INHERITED_OPACITY_ATTRIBUTES
+                .iter()
+                .position(|name| *name == key);
+            if let Some(index) = inherited {
+                children_inherit_one[index] = is_one;
+            }
+            if key == "style" {
+                // Don't work out the cascade; assume the property is changed
+                for (index, name) in INHERITED_OPACITY_ATTRIBUTES.iter().enumerate() {
+                    if value.contains(name) {
+                        children_inherit_one[index] = false;
+                    }
+                }
+            }
+            let is_default = match inherited {
+                Some(index) => inherits_one[index],
+                None => key == "opacity",
+            };
+            if is_default && is_one {
+                continue;
+            }
+            match short_color(value).filter(|_| COLOR_ATTRIBUTES.contains(&key)) {
+                Some(color) => start.push_attribute((key, color.as_str())),
+                None => start.push_attribute(attr),
+            }
+        }
+        (start, children_inherit_one)
+    };
+
+    let mut reader = Reader::from_str(svg);
+    let mut writer = Writer::new(Cursor::new(Vec::new()));
+    let mut text_depth = 0;
+    // Whether the inherited opacities are 1, for each open element
+    let mut inherits_one: Vec<[bool; 2]> = Vec::new();
+
+    loop {
+        let event = match reader.read_event() {
+            Ok(Event::Eof) => break,
+            Ok(event) => event,
+            Err(_) => return svg.to_string(),
+        };
+        let event = match event {
+            Event::Start(e) => {
+                if e.name().as_ref() == b"text" {
+                    text_depth += 1;
+                }
+                let parent = inherits_one.last().copied().unwrap_or([true; 2]);
+                let (start, children) = minify_start(&e, parent);
+                inherits_one.push(children);
+                Event::Start(start)
+            }
+            Event::End(e) => {
+                if e.name().as_ref() == b"text" {
+                    text_depth -= 1;
+                }
+                inherits_one.pop();
+                Event::End(e)
+            }
+            Event::Empty(e) => {
+                let parent = inherits_one.last().copied().unwrap_or([true; 2]);
+                Event::Empty(minify_start(&e, parent).0)
+            }
+            Event::Text(t) if text_depth == 0 && t.trim_ascii().is_empty() => continue,
+            event => event,
+        };
+        let _ = writer.write_event(event);
+    }
+
+    String::from_utf8_lossy(&writer.into_inner().into_inner()).to_string()
+}
+
+/// Removes active content from an SVG, so it can be embedded in a page
+/// without running anything.
+///
+/// - `<script>` and `<foreignObject>` elements are dropped with their
+///   content, and so are foreign elements: those with a namespace prefix
+///   other than `svg:`, such as `<html:iframe>`
+/// - event handler attributes (`onload`, `onclick`...) are dropped, and so
+///   are attributes holding a `javascript:` URL, such as `href`, or an
+///   animation value list (`values`, `from`, `to`, `by`) with one
+/// - attributes whose value cannot be unescaped, such as one using an HTML
+///   entity like `&colon;`, are dropped
+/// - processing instructions and doctypes are dropped
+///
+/// Everything else is kept byte for byte. MicroTeX writes none of the
+/// removed content itself; sanitizing guards against what
+/// [`RenderConfig::svg_attributes`] or later processing adds. If the SVG is
+/// not well-formed XML, everything from the first error on is dropped, so
+/// the result never holds content that was not checked.
+///
+/// # Example
+///
+/// ```rust
+/// use microtex_rs::sanitize_svg;
+///
+/// let svg = r#"<svg onload="alert(1)"><script>alert(2)</script><path d="M 0 0"/></svg>"#;
+/// assert_eq!(sanitize_svg(svg), r#"<svg><path d="M 0 0"/></svg>"#);
+/// ```
+pub fn sanitize_svg(svg: &str) -> String {
+    use quick_xml::events::{BytesStart, Event};
+    use quick_xml::Reader;
+    use quick_xml::Writer;
+    use std::io::Cursor;
+
+    let is_dropped_element = |e: &BytesStart| {
+        let name = e.name();
+        let local = name.local_name();
+        local.as_ref().eq_ignore_ascii_case(b"script")
+            || local.as_ref().eq_ignore_ascii_case(b"foreignObject")
+            || name
+                .prefix()
+                .is_some_and(|prefix| prefix.as_ref() != b"svg")
+    };
+    let sanitize_start = |e: &BytesStart| {
+        let mut start = BytesStart::new(String::from_utf8_lossy(e.name().as_ref()).into_owned());
+        for attr in e.attributes().flatten() {
+            let name = attr.key.local_name();
+            let is_handler =
+                name.as_ref().len() > 2 && name.as_ref()[..2].eq_ignore_ascii_case(b"on");
+            // An HTML parser may still decode what XML cannot
+            let Ok(value) = attr.unescape_value() else {
+                continue;
+            };
+            // Browsers ignore whitespace and control characters in URLs
+            let value: String = value
+                .chars()
+                .filter(|c| !c.is_ascii_whitespace() && !c.is_control())
+                .collect::<String>()
+                .to_ascii_lowercase();
+            // `<animate>` and `<set>` can set an `href` to any item of these
+            let is_value_list = matches!(name.as_ref(), b"values" | b"from" | b"to" | b"by");
+            let is_script_url = if is_value_list {
+                value.split(';').any(|item| item.starts_with("javascript:"))
+            } else {
+                value.starts_with("javascript:")
+            };
+            if !is_handler && !is_script_url {
+                start.push_attribute(attr);
+            }
+        }
+        start
+    };
+
+    let mut reader = Reader::from_str(svg);
+    let mut writer = Writer::new(Cursor::new(Vec::new()));
+    // Depth of the elements open inside a dropped element
+    let mut dropped_depth = 0;
+
+    loop {
+        let event = match reader.read_event() {
+            Ok(Event::Eof) | Err(_) => break,
+            Ok(event) => event,
+        };
+        let event = match event {
+            Event::Start(e) if dropped_depth > 0 || is_dropped_element(&e) => {
+                dropped_depth += 1;
+                continue;
+            }
+            Event::End(_) if dropped_depth > 0 => {
+                dropped_depth -= 1;
+                continue;
+            }
+            _ if dropped_depth > 0 => continue,
+            Event::Empty(e) if is_dropped_element(&e) => continue,
+            Event::PI(_) | Event::DocType(_) => continue,
+            Event::Start(e) => Event::Start(sanitize_start(&e)),
+            Event::Empty(e) => Event::Empty(sanitize_start(&e)),
+            event => event,
+        };
+        let _ = writer.write_event(event);
+    }
+
+    String::from_utf8_lossy(&writer.into_inner().into_inner()).to_string()
+}
+
+/// Returns the shortest hexadecimal form of an `rgb(...)` or `#rrggbb`
+/// color, or `None` for any other value.
+///
+/// `rgb` components may be percentages or integers from 0 to 255.
+fn short_color(value: &str) -> Option<String> {
+    let value = value.trim();
+    let channels: Vec<u8> = if let Some(hex) = value.strip_prefix('#') {
+        if hex.len() != 6 || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return None;
+        }
+        (0..3)
+            .map(|i| u8::from_str_radix(&hex[2 * i..2 * i + 2], 16).ok())
+            .collect::<Option<_>>()?
+    } else {
+        let inner = value.strip_prefix("rgb(")?.strip_suffix(')')?;
+        let channels = inner
+            .split(',')
+            .map(|component| {
+                let component = component.trim();
+                let channel = match component.strip_suffix('%') {
+                    Some(percent) => percent.parse::<f32>().ok()? * 2.55,
+                    None => component.parse::<f32>().ok()?,
+                };
+                (0.0..=255.0)
+                    .contains(&channel)
+                    .then(|| channel.round() as u8)
+            })
+            .collect::<Option<Vec<u8>>>()?;
+        if channels.len() != 3 {
+            return None;
+        }
+        channels
+    };
+
+    // `#rgb` when each channel repeats its digit
+    if channels.iter().all(|c| c >> 4 == c & 0xf) {
+        Some(format!(
+            "#{:x}{:x}{:x}",
+            channels[0] & 0xf,
+            channels[1] & 0xf,
+            channels[2] & 0xf
+        ))
+    } else {
+        Some(format!(
+            "#{:02x}{:02x}{:02x}",
+            channels[0], channels[1], channels[2]
+        ))
+    }
+}
+
+/// Normalizes separators in an attribute value and, for geometry attributes,
+/// rounds its numbers to [`CANONICAL_PRECISION`] decimals.
+fn canonical_attribute_value(value: &str, round_numbers: bool) -> String {
+    let mut result = String::with_capacity(value.len());
+    let mut chars = value.trim().chars().peekable();
+
+    while let Some(ch) = chars.next() {
+        if ch.is_whitespace() || ch == ',' {
+            let mut has_comma = ch == ',';
+            while let Some(&next) = chars.peek().filter(|c| c.is_whitespace() || **c == ',') {
+                has_comma |= next == ',';
+                chars.next();
+            }
+            result.push_str(if has_comma { ", " } else { " " });
+        } else if round_numbers && (ch.is_ascii_digit() || ch == '.' || ch == '-') {
+            let mut number = String::from(ch);
+            let mut has_dot = ch == '.';
+            while let Some(&next) = chars.peek() {
+                if next.is_ascii_digit() || (next == '.' && !has_dot) {
+                    has_dot |= next == '.';
+                    number.push(next);
+                    chars.next();
+                } else {
+                    break;
+                }
+            }
+            match number.parse::<f64>() {
+                Ok(value) => result.push_str(&format_canonical_number(value)),
+                Err(_) => result.push_str(&number),
+            }
+        } else {
+            result.push(ch);
+        }
+    }
+
+    result
+}
+
+/// Formats a number with [`CANONICAL_PRECISION`] decimals and no trailing zeros.
+fn format_canonical_number(value: f64) -> String {
+    let formatted = format!("{:.*}", CANONICAL_PRECISION, value);
+    let trimmed = formatted.trim_end_matches('0').trim_end_matches('.');
+    match trimmed {
+        "-0" | "" => "0".to_string(),
+        trimmed => trimmed.to_string(),
+    }
+}
+
+/// Resolution of an SVG pixel, the CSS reference of 96 pixels per inch.
+#[cfg(feature = "png")]
+const SVG_PIXELS_PER_INCH: f32 = 96.0;
+
+/// Finds the highest DPI in `1..=max_dpi` whose PNG, produced by `encode`,
+/// is at most `max_bytes` long, see [`MicroTex::render_png_under_size`].
+///
+/// `max_dpi` is tried first, then the lower DPIs are binary searched, which
+/// assumes the PNG size grows with the DPI.
+#[cfg(any(feature = "png", test))]
+fn highest_dpi_under_size(
+    max_dpi: i32,
+    max_bytes: usize,
+    mut encode: impl FnMut(i32) -> Result<Vec<u8>, RenderError>,
+) -> Result<(Vec<u8>, i32), RenderError> {
+    let png = encode(max_dpi)?;
+    if png.len() <= max_bytes {
+        return Ok((png, max_dpi));
+    }
+
+    let (mut low, mut high) = (1, max_dpi - 1);
+    let mut best = None;
+    while low <= high {
+        let dpi = low + (high - low) / 2;
+        let png = encode(dpi)?;
+        if png.len() <= max_bytes {
+            best = Some((png, dpi));
+            low = dpi + 1;
+        } else {
+            high = dpi - 1;
+        }
+    }
+    best.ok_or_else(|| {
+        RenderError::Rasterization(format!("no DPI fits the PNG in {} bytes", max_bytes))
+    })
+}
+
+/// Packs rectangles of the given `(width, height)` sizes on shelves, see
+/// [`MicroTex::render_sprite_sheet`].
+///
+/// Returns the sheet size and the top-left corner of each rectangle, in the
+/// order of `sizes`.
+#[cfg(any(feature = "png", test))]
+fn shelf_pack(sizes: &[(u32, u32)], padding: u32) -> ((u32, u32), Vec<(u32, u32)>) {
+    let widest = sizes.iter().map(|&(width, _)| width).max().unwrap_or(0);
+    let area: f64 = sizes
+        .iter()
+        .map(|&(width, height)| f64::from(width + padding) * f64::from(height + padding))
+        .sum();
+    let sheet_width = (widest + 2 * padding).max(area.sqrt().ceil() as u32 + padding);
+
+    // Tallest first, so each shelf wastes little height
+    let mut order: Vec<usize> = (0..sizes.len()).collect();
+    order.sort_by_key(|&i| std::cmp::Reverse(sizes[i].1));
+
+    let mut positions = vec![(0, 0); sizes.len()];
+    let (mut x, mut y) = (padding, padding);
+    let mut shelf_height = 0;
+    let mut used_width = 2 * padding;
+    for i in order {
+        let (width, height) = sizes[i];
+        if x > padding && x + width + padding > sheet_width {
+            // Open a new shelf
+            y += shelf_height + padding;
+            x = padding;
+            shelf_height = 0;
+        }
+        positions[i] = (x, y);
+        x += width + padding;
+        used_width = used_width.max(x);
+        shelf_height = shelf_height.max(height);
+    }
+    ((used_width, y + shelf_height + padding), positions)
+}
+
+/// Returns the `(x, y, width, height)` of the smallest rectangle containing
+/// every non-transparent pixel of `pixmap`.
+#[cfg(feature = "png")]
+fn opaque_bounds(pixmap: &resvg::tiny_skia::Pixmap) -> Option<(u32, u32, u32, u32)> {
+    let width = pixmap.width();
+    let mut bounds: Option<(u32, u32, u32, u32)> = None;
+    for (i, pixel) in pixmap.pixels().iter().enumerate() {
+        if pixel.alpha() == 0 {
+            continue;
+        }
+        let (x, y) = (i as u32 % width, i as u32 / width);
+        bounds = Some(match bounds {
+            None => (x, y, x, y),
+            Some((min_x, min_y, max_x, max_y)) => {
+                (min_x.min(x), min_y.min(y), max_x.max(x), max_y.max(y))
+            }
+        });
+    }
+    bounds.map(|(min_x, min_y, max_x, max_y)| (min_x, min_y, max_x - min_x + 1, max_y - min_y + 1))
+}
+
+/// Rasterizes an SVG to PNG bytes, scaling its intrinsic size by `scale`.
+#[cfg(feature = "png")]
+fn rasterize_svg(svg: &str, scale: f32) -> Result<Vec<u8>, RenderError> {
+    rasterize_svg_pixmap(svg, scale)?
+        .encode_png()
+        .map_err(|e| RenderError::Rasterization(e.to_string()))
+}
+
+/// Rasterizes an SVG to a pixmap, scaling its intrinsic size by `scale`.
+#[cfg(feature = "png")]
+fn rasterize_svg_pixmap(svg: &str, scale: f32) -> Result<resvg::tiny_skia::Pixmap, RenderError> {
+    use resvg::tiny_skia::{Pixmap, Transform};
+    use resvg::usvg::{Options, Tree};
+
+    let tree = Tree::from_str(svg, &Options::default())
+        .map_err(|e| RenderError::Rasterization(e.to_string()))?;
+    let size = tree
+        .size()
+        .to_int_size()
+        .scale_by(scale)
+        .ok_or_else(|| RenderError::Rasterization(format!("invalid scale {}", scale)))?;
+    let mut pixmap = Pixmap::new(size.width(), size.height()).ok_or_else(|| {
+        RenderError::Rasterization(format!(
+            "invalid image size {}x{}",
+            size.width(),
+            size.height()
+        ))
+    })?;
+
+    resvg::render(
+        &tree,
+        Transform::from_scale(scale, scale),
+        &mut pixmap.as_mut(),
+    );
+    Ok(pixmap)
+}
+
+/// Expands argument-less macros (see [`RenderConfig::macros`]) in a LaTeX source.
+fn expand_macros(latex: &str, macros: &[(String, String)]) -> String {
+    let mut expanded = latex.to_string();
+
+    for (name, expansion) in macros {
+        let command = format!("\\{}", name.trim_start_matches('\\'));
+        let mut result = String::with_capacity(expanded.len());
+        let mut rest = expanded.as_str();
+
+        while let Some(pos) = rest.find(&command) {
+            let after = &rest[pos + command.len()..];
+            result.push_str(&rest[..pos]);
+            // `\RR` must not match the start of `\RRx`
+            if after.starts_with(|c: char| c.is_ascii_alphabetic()) {
+                result.push_str(&command);
+            } else {
+                result.push_str(expansion);
+            }
+            rest = after;
+        }
+        result.push_str(rest);
+        expanded = result;
+    }
+
+    expanded
+}
+
+/// Returns the Unicode Mathematical Alphanumeric Symbols character for a
+/// letter or digit in a math alphabet command, see
+/// [`RenderConfig::unicode_math_alphabets`].
+fn math_alphabet_char(command: &str, c: char) -> Option<char> {
+    // (first capital, first small letter, first digit, Letterlike Symbols)
+    let (upper, lower, digit, letterlike): (u32, u32, Option<u32>, &[(char, char)]) = match command
+    {
+        "mathbb" => (
+            0x1D538,
+            0x1D552,
+            Some(0x1D7D8),
+            &[
+                ('C', 'ℂ'),
+                ('H', 'ℍ'),
+                ('N', 'ℕ'),
+                ('P', 'ℙ'),
+                ('Q', 'ℚ'),
+                ('R', 'ℝ'),
+                ('Z', 'ℤ'),
+            ],
+        ),
+        "mathcal" | "mathscr" => (
+            0x1D49C,
+            0x1D4B6,
+            None,
+            &[
+                ('B', 'ℬ'),
+                ('E', 'ℰ'),
+                ('F', 'ℱ'),
+                ('H', 'ℋ'),
+                ('I', 'ℐ'),
+                ('L', 'ℒ'),
+                ('M', 'ℳ'),
+                ('R', 'ℛ'),
+                ('e', 'ℯ'),
+                ('g', 'ℊ'),
+                ('o', 'ℴ'),
+            ],
+        ),
+        "mathfrak" => (
+            0x1D504,
+            0x1D51E,
+            None,
+            &[('C', 'ℭ'), ('H', 'ℌ'), ('I', 'ℑ'), ('R', 'ℜ'), ('Z', 'ℨ')],
+        ),
+        _ => return None,
+    };
+
+    if let Some(&(_, symbol)) = letterlike.iter().find(|(letter, _)| *letter == c) {
+        return Some(symbol);
+    }
+    let code = match c {
+        'A'..='Z' => upper + (c as u32 - 'A' as u32),
+        'a'..='z' => lower + (c as u32 - 'a' as u32),
+        '0'..='9' => digit? + (c as u32 - '0' as u32),
+        _ => return None,
+    };
+    char::from_u32(code)
+}
+
+/// Replaces math alphabet commands on a single character with Unicode math
+/// characters the font has, see [`RenderConfig::unicode_math_alphabets`].
+fn substitute_math_alphabets(latex: &str, has_glyph: impl Fn(char) -> bool) -> String {
+    let mut result = String::with_capacity(latex.len());
+    let mut rest = latex;
+
+    while let Some(pos) = rest.find('\\') {
+        result.push_str(&rest[..pos]);
+        let after = &rest[pos + 1..];
+        let name_len = after
+            .find(|c: char| !c.is_ascii_alphabetic())
+            .unwrap_or(after.len());
+        let (name, tail) = after.split_at(name_len);
+
+        let mut chars = tail.chars();
+        let substituted = match (chars.next(), chars.next(), chars.next()) {
+            (Some('{'), Some(c), Some('}')) => {
+                math_alphabet_char(name, c).filter(|symbol| has_glyph(*symbol))
+            }
+            _ => None,
+        };
+        match substituted {
+            Some(symbol) => {
+                result.push('{');
+                result.push(symbol);
+                result.push('}');
+                rest = chars.as_str();
+            }
+            None => {
+                // Keep the command, and a `\\` pair as a whole
+                let len = if name.is_empty() {
+                    1 + after.chars().next().map_or(0, char::len_utf8)
+                } else {
+                    1 + name_len
+                };
+                result.push_str(&rest[pos..pos + len]);
+                rest = &rest[pos + len..];
+            }
+        }
+    }
+    result.push_str(rest);
+    result
+}
+
+/// Swaps opening and closing delimiters, see
+/// [`RenderConfig::mirror_delimiters`].
+fn mirror_delimiters(latex: &str) -> String {
+    const MIRRORED_COMMANDS: [(&str, &str); 6] = [
+        ("lbrace", "rbrace"),
+        ("lbrack", "rbrack"),
+        ("langle", "rangle"),
+        ("lfloor", "rfloor"),
+        ("lceil", "rceil"),
+        ("{", "}"),
+    ];
+    const SIZING_COMMANDS: [&str; 15] = [
+        "left", "right", "middle", "big", "Big", "bigg", "Bigg", "bigl", "bigr", "Bigl", "Bigr",
+        "biggl", "biggr", "Biggl", "Biggr",
+    ];
+
+    let mut result = String::with_capacity(latex.len());
+    let mut rest = latex;
+    while let Some(c) = rest.chars().next() {
+        match c {
+            '\\' => {
+                let after = &rest[1..];
+                let name_len = match after.find(|c: char| !c.is_ascii_alphabetic()) {
+                    Some(0) => after.chars().next().map_or(0, char::len_utf8),
+                    Some(len) => len,
+                    None => after.len(),
+                };
+                let name = &after[..name_len];
+                let mirrored = MIRRORED_COMMANDS.iter().find_map(|&(left, right)| {
+                    if name == left {
+                        Some(right)
+                    } else if name == right {
+                        Some(left)
+                    } else {
+                        None
+                    }
+                });
+                result.push('\\');
+                result.push_str(mirrored.unwrap_or(name));
+                rest = &after[name_len..];
+
+                // The environment name is skipped, so that a bracket after it
+                // is taken as the environment's optional argument
+                if name == "begin" && rest.starts_with('{') {
+                    let end = rest.find('}').map_or(rest.len(), |i| i + 1);
+                    result.push_str(&rest[..end]);
+                    rest = &rest[end..];
+                }
+
+                // A bracket right after a command opens an optional argument,
+                // kept as is, unless the command sizes a delimiter
+                if rest.starts_with('[') && !SIZING_COMMANDS.contains(&name) && name != "[" {
+                    let mut depth = 0;
+                    let end = rest
+                        .char_indices()
+                        .find(|&(_, c)| {
+                            match c {
+                                '[' => depth += 1,
+                                ']' => depth -= 1,
+                                _ => {}
+                            }
+                            depth == 0
+                        })
+                        .map_or(rest.len(), |(i, _)| i + 1);
+                    result.push_str(&rest[..end]);
+                    rest = &rest[end..];
+                }
+                continue;
+            }
+            '(' => result.push(')'),
+            ')' => result.push('('),
+            '[' => result.push(']'),
+            ']' => result.push('['),
+            _ => result.push(c),
+        }
+        rest = &rest[c.len_utf8()..];
+    }
+    result
+}
+
+/// Commands that are never wrapped in a highlight color by
+/// [`MicroTex::render_diff`]: they delimit the formula, size a delimiter,
+/// or modify the atom next to them.
+const DIFF_STRUCTURAL_COMMANDS: [&str; 14] = [
+    "\\\\",
+    "\\[",
+    "\\]",
+    "\\(",
+    "\\)",
+    "\\limits",
+    "\\nolimits",
+    "\\over",
+    "\\atop",
+    "\\choose",
+    "\\displaystyle",
+    "\\textstyle",
+    "\\scriptstyle",
+    "\\scriptscriptstyle",
+];
+
+/// Commands sizing the delimiter that follows them.
+const DIFF_SIZING_COMMANDS: [&str; 15] = [
+    "\\left", "\\right", "\\middle", "\\big", "\\Big", "\\bigg", "\\Bigg", "\\bigl", "\\bigr",
+    "\\Bigl", "\\Bigr", "\\biggl", "\\biggr", "\\Biggl", "\\Biggr",
+];
+
+/// Commands whose brace argument is text rather than math; the command and
+/// its argument form a single diff token.
+const DIFF_TEXT_COMMANDS: [&str; 6] = [
+    "\\text",
+    "\\mbox",
+    "\\textrm",
+    "\\textit",
+    "\\textbf",
+    "\\operatorname",
+];
+
+/// Splits a LaTeX source into diff tokens: a control word or control
+/// symbol, or a single non-whitespace character.
+///
+/// A text command such as `\text` and its brace argument form one token, as
+/// do `\begin` or `\end` and the brace groups right after them. Each token
+/// is returned with its byte offset in `latex`.
+fn diff_tokens(latex: &str) -> Vec<(usize, &str)> {
+    let mut tokens = Vec::new();
+    let mut iter = latex.char_indices().peekable();
+    while let Some((start, c)) = iter.next() {
+        if c.is_whitespace() {
+            continue;
+        }
+        let mut end = start + c.len_utf8();
+        if c == '\\' {
+            let after = &latex[end..];
+            end += match after.find(|c: char| !c.is_ascii_alphabetic()) {
+                Some(0) => after.chars().next().map_or(0, char::len_utf8),
+                Some(len) => len,
+                None => after.len(),
+            };
+            let command = &latex[start..end];
+            let groups = if DIFF_TEXT_COMMANDS.contains(&command) {
+                1
+            } else if command == "\\begin" || command == "\\end" {
+                usize::MAX
+            } else {
+                0
+            };
+            for _ in 0..groups {
+                if !latex[end..].starts_with('{') {
+                    break;
+                }
+                let mut depth = 0;
+                end += latex[end..]
+                    .char_indices()
+                    .find(|&(_, c)| {
+                        match c {
+                            '{' => depth += 1,
+                            '}' => depth -= 1,
+                            _ => {}
+                        }
+                        depth == 0
+                    })
+                    .map_or(latex.len() - end, |(i, _)| i + 1);
+            }
+            while iter.peek().is_some_and(|&(i, _)| i < end) {
+                iter.next();
+            }
+        }
+        tokens.push((start, &latex[start..end]));
+    }
+    tokens
+}
+
+/// Returns true if the token at `index` can be wrapped in `\textcolor`
+/// without changing the structure of the formula.
+fn diff_token_is_atom(tokens: &[(usize, &str)], index: usize) -> bool {
+    let token = tokens[index].1;
+    if matches!(token, "{" | "}" | "^" | "_" | "&" | "#" | "%" | "$" | "'")
+        || token.starts_with("\\begin")
+        || token.starts_with("\\end")
+        || DIFF_STRUCTURAL_COMMANDS.contains(&token)
+        || DIFF_SIZING_COMMANDS.contains(&token)
+    {
+        return false;
+    }
+    if index > 0 && DIFF_SIZING_COMMANDS.contains(&tokens[index - 1].1) {
+        // The delimiter of a sizing command
+        return false;
+    }
+    // A command followed by an argument
+    let takes_argument = token.starts_with('\\')
+        && !token.ends_with('}')
+        && tokens
+            .get(index + 1)
+            .is_some_and(|&(_, next)| next == "{" || next == "[");
+    !takes_argument
+}
+
+/// Largest LCS table [`diff_token_changes`] allocates, in cells (16 MiB).
+const MAX_DIFF_TABLE_CELLS: usize = 1 << 22;
+
+/// Returns, for each token of `new`, whether it is absent from `old`, and
+/// for each token of `old`, whether it is absent from `new`.
+///
+/// Tokens are matched along a longest common subsequence. Above
+/// [`MAX_DIFF_TABLE_CELLS`], every token is reported as changed.
+fn diff_token_changes(old: &[(usize, &str)], new: &[(usize, &str)]) -> (Vec<bool>, Vec<bool>) {
+    let width = new.len() + 1;
+    let cells = (old.len() + 1).saturating_mul(width);
+    if cells > MAX_DIFF_TABLE_CELLS {
+        return (vec![true; old.len()], vec![true; new.len()]);
+    }
+    // lengths[i * width + j]: LCS length of old[i..] and new[j..]
+    let mut lengths = vec![0u32; (old.len() + 1) * width];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lengths[i * width + j] = if old[i].1 == new[j].1 {
+                lengths[(i + 1) * width + j + 1] + 1
+            } else {
+                lengths[(i + 1) * width + j].max(lengths[i * width + j + 1])
+            };
+        }
+    }
+
+    let mut removed = vec![true; old.len()];
+    let mut added = vec![true; new.len()];
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() && j < new.len() {
+        if old[i].1 == new[j].1 {
+            removed[i] = false;
+            added[j] = false;
+            i += 1;
+            j += 1;
+        } else if lengths[(i + 1) * width + j] >= lengths[i * width + j + 1] {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+    (removed, added)
+}
+
+/// Rewrites `new` with the tokens absent from `old` wrapped in
+/// `\textcolor{green}`, and, if `show_removed` is set, the tokens absent
+/// from `new` inserted as `\textcolor{red}{\cancel{...}}`.
+///
+/// See [`MicroTex::render_diff`] for the diff granularity.
+fn highlight_diff(old: &str, new: &str, show_removed: bool) -> String {
+    let old_tokens = diff_tokens(old);
+    let new_tokens = diff_tokens(new);
+    let (removed, added) = diff_token_changes(&old_tokens, &new_tokens);
+
+    // Group the removed atoms by the number of matched new tokens preceding
+    // them, which is where they are inserted
+    let mut removed_runs: Vec<Vec<&str>> = vec![Vec::new(); new_tokens.len() + 1];
+    if show_removed {
+        let mut matched = 0;
+        let mut new_index = 0;
+        for (index, &(_, token)) in old_tokens.iter().enumerate() {
+            if !removed[index] {
+                // Advance to the new token matched with this one
+                while added[new_index] {
+                    new_index += 1;
+                }
+                new_index += 1;
+                matched = new_index;
+            } else if diff_token_is_atom(&old_tokens, index) {
+                removed_runs[matched].push(token);
+            }
+        }
+    }
+
+    let mut result = String::with_capacity(new.len() * 2);
+    let mut copied = 0;
+    let mut index = 0;
+    let mut pending_removed: Vec<&str> = Vec::new();
+    while index <= new_tokens.len() {
+        pending_removed.append(&mut removed_runs[index]);
+        let previous = index.checked_sub(1).map(|i| new_tokens[i].1);
+        let binds_previous = previous.is_some_and(|previous| {
+            previous == "^" || previous == "_" || DIFF_SIZING_COMMANDS.contains(&previous)
+        });
+        let binds_next = new_tokens.get(index).is_some_and(|&(_, next)| {
+            matches!(
+                next,
+                "{" | "[" | "^" | "_" | "'" | "\\limits" | "\\nolimits"
+            )
+        });
+        if !pending_removed.is_empty() && !binds_previous && !binds_next {
+            let at = new_tokens.get(index).map_or(new.len(), |&(start, _)| start);
+            result.push_str(&new[copied..at]);
+            copied = at;
+            result.push_str("\\textcolor{red}{\\cancel{");
+            result.push_str(&pending_removed.join(" "));
+            result.push_str("}}");
+            pending_removed.clear();
+        }
+        let Some(&(start, _)) = new_tokens.get(index) else {
+            break;
+        };
+        if !added[index] || !diff_token_is_atom(&new_tokens, index) {
+            index += 1;
+            continue;
+        }
+
+        // Wrap the run of added atoms starting here; an atom in a
+        // superscript or subscript position is wrapped alone, in braces
+        let scripted = previous == Some("^") || previous == Some("_");
+        let mut last = index;
+        while !scripted
+            && last + 1 < new_tokens.len()
+            && added[last + 1]
+            && diff_token_is_atom(&new_tokens, last + 1)
+            && removed_runs[last + 1].is_empty()
+        {
+            last += 1;
+        }
+        let end = new_tokens[last].0 + new_tokens[last].1.len();
+        result.push_str(&new[copied..start]);
+        if scripted {
+            result.push('{');
+        }
+        result.push_str("\\textcolor{green}{");
+        result.push_str(&new[start..end]);
+        result.push('}');
+        if scripted {
+            result.push('}');
+        }
+        copied = end;
+        index = last + 1;
+    }
+    result.push_str(&new[copied..]);
+    result
+}
+
+/// Returns the deepest brace group nesting in a LaTeX source.
+///
+/// Escaped braces (`\{`, `\}`) are not counted; unbalanced closing braces
+/// never bring the depth below zero.
+fn brace_nesting_depth(latex: &str) -> u32 {
+    let mut depth = 0u32;
+    let mut max_depth = 0u32;
+    let mut escaped = false;
+
+    for ch in latex.chars() {
+        match ch {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '{' => {
+                depth += 1;
+                max_depth = max_depth.max(depth);
+            }
+            '}' => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+    }
+
+    max_depth
+}
+
+/// Splits an aligned environment into its trimmed, non-empty rows.
+///
+/// See [`MicroTex::render_align_rows`] for the accepted input.
+fn split_align_rows(latex: &str) -> Vec<&str> {
+    let mut body = latex.trim();
+    if let Some(inner) = body
+        .strip_prefix("\\[")
+        .and_then(|rest| rest.strip_suffix("\\]"))
+    {
+        body = inner.trim();
+    }
+    // Strip the outer \begin{env} ... \end{env}
+    if let Some((name, rest)) = body
+        .strip_prefix("\\begin{")
+        .and_then(|rest| rest.split_once('}'))
+    {
+        if let Some(inner) = rest.trim_end().strip_suffix(&format!("\\end{{{}}}", name)) {
+            body = inner;
+        }
+    }
+
+    let mut rows = Vec::new();
+    let mut brace_depth = 0u32;
+    let mut env_depth = 0u32;
+    let mut row_start = 0;
+    let mut i = 0;
+    while i < body.len() {
+        let rest = &body[i..];
+        if rest.starts_with("\\\\") {
+            if brace_depth == 0 && env_depth == 0 {
+                rows.push(&body[row_start..i]);
+                i += 2;
+                // Skip an optional vertical skip such as `\\[2pt]`
+                let after = &body[i..];
+                if after.trim_start().starts_with('[') {
+                    if let Some(end) = after.find(']') {
+                        i += end + 1;
+                    }
+                }
+                row_start = i;
+                continue;
+            }
+            i += 2;
+            continue;
+        }
+        if rest.starts_with("\\begin{") {
+            env_depth += 1;
+        } else if rest.starts_with("\\end{") {
+            env_depth = env_depth.saturating_sub(1);
+        } else if rest.starts_with("\\{") || rest.starts_with("\\}") {
+            i += 2;
+            continue;
+        }
+        match rest.as_bytes()[0] {
+            b'{' => brace_depth += 1,
+            b'}' => brace_depth = brace_depth.saturating_sub(1),
+            _ => {}
+        }
+        i += rest.chars().next().map_or(1, char::len_utf8);
+    }
+    rows.push(&body[row_start..]);
+
+    rows.into_iter()
+        .map(str::trim)
+        .filter(|row| !row.is_empty())
+        .collect()
+}
+
+/// Rejects sources over [`RenderConfig::max_source_len`], using a
+/// [`RenderConfig::disallowed_commands`] entry, or nested deeper than
+/// [`RenderConfig::max_nesting_depth`].
+fn check_source_limits(latex: &str, config: &RenderConfig) -> Result<(), RenderError> {
+    if let Some(max) = config.max_source_len {
+        if latex.len() > max {
+            return Err(RenderError::SourceTooLong {
+                len: latex.len(),
+                max,
+            });
+        }
+    }
+    if !config.disallowed_commands.is_empty() {
+        for command in latex_commands(latex) {
+            let disallowed = config
+                .disallowed_commands
+                .iter()
+                .any(|name| name.trim_start_matches('\\') == command);
+            if disallowed {
+                return Err(RenderError::DisallowedCommand(command.to_string()));
+            }
+        }
+    }
+    check_nesting_depth(latex, config)
+}
+
+/// Returns the names (without backslash) of the control words in a source.
+///
+/// Control symbols such as `\\` or `\{` are skipped, so the `newcommand` in
+/// `\\newcommand` is text after a line break, not a command.
+fn latex_commands(latex: &str) -> impl Iterator<Item = &str> {
+    let bytes = latex.as_bytes();
+    let mut i = 0;
+    std::iter::from_fn(move || {
+        while i < bytes.len() {
+            if bytes[i] != b'\\' {
+                i += 1;
+                continue;
+            }
+            let start = i + 1;
+            let end = start
+                + bytes[start..]
+                    .iter()
+                    .take_while(|b| b.is_ascii_alphabetic())
+                    .count();
+            if end == start {
+                // Control symbol: skip the escaped character
+                i = start + 1;
+                continue;
+            }
+            i = end;
+            return Some(&latex[start..end]);
+        }
+        None
+    })
+}
+
+/// Rejects sources nested deeper than [`RenderConfig::max_nesting_depth`].
+fn check_nesting_depth(latex: &str, config: &RenderConfig) -> Result<(), RenderError> {
+    if let Some(max) = config.max_nesting_depth {
+        let depth = brace_nesting_depth(latex);
+        if depth > max {
+            return Err(RenderError::NestingTooDeep { depth, max });
+        }
+    }
+    Ok(())
+}
+
+/// Environments from packages MicroTeX cannot render.
+const UNSUPPORTED_ENVIRONMENTS: &[(&str, &str)] = &[
+    ("tikzpicture", "TikZ/pgfplots not supported"),
+    ("axis", "TikZ/pgfplots not supported"),
+];
+
+/// Converts a LaTeX source to the C string handed to MicroTeX.
+///
+/// A NUL byte would cut the source short, so it is reported as
+/// [`RenderError::InvalidInput`] with its byte position in `latex`.
+fn to_c_source(latex: &str) -> Result<std::ffi::CString, RenderError> {
+    std::ffi::CString::new(latex).map_err(|e| {
+        RenderError::InvalidInput(format!("NUL byte at position {}", e.nul_position()))
+    })
+}
+
+/// Rejects sources using environments from [`UNSUPPORTED_ENVIRONMENTS`].
+fn check_supported(latex: &str) -> Result<(), RenderError> {
+    for (environment, message) in UNSUPPORTED_ENVIRONMENTS {
+        if latex.contains(&format!("\\begin{{{}}}", environment)) {
+            return Err(RenderError::Unsupported(message.to_string()));
+        }
+    }
+    Ok(())
+}
+
+/// Text-mode font MicroTeX is initialized with and falls back to when
+/// [`RenderConfig::main_font`] is `None`.
+const DEFAULT_MAIN_FONT: &str = "Serif";
+
+/// Forwards the global MicroTeX settings of `config` before a parse.
+///
+/// # Safety
+///
+/// MicroTeX must be initialized. `config` must have passed
+/// [`RenderConfig::validate`], so option strings contain no NUL bytes.
+unsafe fn apply_global_options(config: &RenderConfig) {
+    shim::microtex_set_math_spacing(config.op_spacing, config.rel_spacing);
+    shim::microtex_set_limits_mode(match config.limits_mode {
+        LimitsMode::Default => 0,
+        LimitsMode::Always => 1,
+        LimitsMode::Never => 2,
+    });
+    shim::microtex_set_bold_math(config.bold);
+    if let Ok(main_font) =
+        std::ffi::CString::new(config.main_font.as_deref().unwrap_or(DEFAULT_MAIN_FONT))
+    {
+        shim::microtex_set_default_main_font(main_font.as_ptr());
+    }
+    // Settings only reachable through `extra_options`, back to their
+    // defaults
+    shim::microtex_set_render_glyph_use_path(true);
+    if let Some(math_font) = DEFAULT_MATH_FONT.get() {
+        shim::microtex_set_default_math_font(math_font.as_ptr());
+    }
+
+    let mut options: Vec<_> = config.extra_options.iter().collect();
+    options.sort();
+    for (key, value) in options {
+        if let (Ok(key), Ok(value)) = (
+            std::ffi::CString::new(key.as_str()),
+            std::ffi::CString::new(value.as_str()),
+        ) {
+            shim::microtex_set_option(key.as_ptr(), value.as_ptr());
+        }
+    }
+}
+
+/// Converts `\r\n` and lone `\r` line endings to `\n`.
+fn normalize_line_endings(text: &str) -> String {
+    if text.contains('\r') {
+        text.replace("\r\n", "\n").replace('\r', "\n")
+    } else {
+        text.to_string()
+    }
+}
+
+/// Pads an SVG so the formula baseline, `baseline_y` units below the top
+/// edge, sits at exactly half of its height.
+///
+/// The shorter side (above or below the baseline) is padded to match the
+/// longer one, so the baseline position only depends on the final height.
+fn pad_svg_for_depth(svg: &str, baseline_y: f32) -> String {
+    let height = match svg_root_attribute(svg, "height")
+        .as_deref()
+        .and_then(split_svg_length)
+    {
+        Some((height, _)) => height,
+        None => return svg.to_string(),
+    };
+
+    let above = baseline_y;
+    let below = height - baseline_y;
+    if above > below {
+        pad_svg(svg, 0.0, 0.0, above - below, 0.0)
+    } else {
+        pad_svg(svg, below - above, 0.0, 0.0, 0.0)
+    }
+}
+
+/// Moves the content of an SVG vertically so the baseline, `baseline_y`
+/// units below the top edge, sits `ascent` units below it instead.
+///
+/// The top edge is extended (or cropped when the forced ascent is smaller
+/// than the natural one) and the bottom edge is kept.
+fn force_svg_ascent(svg: &str, baseline_y: f32, ascent: i32) -> String {
+    pad_svg(svg, ascent as f32 - baseline_y, 0.0, 0.0, 0.0)
+}
+
+/// Checks that a font about to be used for math rendering is a math font,
+/// logging a warning if it is not.
+///
+/// The `is_math` flag of the CLM header is used when the data parses;
+/// otherwise the font is assumed to be a math font if its file name contains
+/// `math`. A non-math font such as `XITS-Regular` (instead of
+/// `XITSMath-Regular`) has no `MATH` table, so formulas would render with
+/// wrong glyphs and spacing without any error.
+fn check_math_font(font_name: &str, data: &[u8]) -> bool {
+    let is_math = FontInfo::from_clm(font_name, data).is_math;
+    if !is_math {
+        log::warn!(
+            "font {} is not a math font; formulas may render with wrong glyphs",
+            font_name
+        );
+    }
+    is_math
+}
+
+impl MicroTex {
+    /// Creates a new MicroTeX renderer instance with embedded fonts.
+    ///
+    /// This initializes the MicroTeX library with the XITS Math font
+    /// that is embedded at compile time. The renderer will automatically
+    /// clean up resources when dropped.
+    ///
+    /// # Errors
+    ///
+
+    /// Returns [`RenderError::InitializationFailed`] if the font metadata
+    /// cannot be loaded or the MicroTeX library initialization fails.
+    ///
+    /// # Example
+    ///
+    /// The MicroTeX renderer must only be initialized once; prefer using a global
+    /// singleton (for example, `OnceLock`) to avoid multiple initializations. Example:
+    ///
+    /// ```rust
+    /// use std::sync::OnceLock;
+    /// use microtex_rs::{MicroTex, RenderError};
+    ///
+    /// static MICROTEX_RENDERER: OnceLock<MicroTex> = OnceLock::new();
+    ///
+    /// fn get_microtex_renderer() -> Result<&'static MicroTex, RenderError> {
+    ///     if let Some(r) = MICROTEX_RENDERER.get() { return Ok(r); }
+    ///     let renderer = MicroTex::new()?;
+    ///     MICROTEX_RENDERER.set(renderer).map_err(|_| RenderError::InitializationFailed)?;
+    ///     Ok(MICROTEX_RENDERER.get().unwrap())
+    /// }
+    ///
+    /// let _renderer = get_microtex_renderer()?;
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn new() -> Result<Self, RenderError> {
+        let (font_name, clm_data) = Self::default_font()?;
+        Self::init_with_font(&mut lock_engine(), font_name, clm_data, false)
+    }
+
+    /// Creates a renderer that reuses the font already loaded by an earlier
+    /// call, instead of initializing MicroTeX again.
+    ///
+    /// The first call initializes MicroTeX with the same font as
+    /// [`new`](Self::new). Later calls find that font still loaded and only
+    /// create the Rust handle, which skips parsing the CLM font data and
+    /// makes construction nearly free. Renderers created this way share one
+    /// MicroTeX state.
+    ///
+    /// Dropping a cached renderer does not release MicroTeX: the font stays
+    /// in memory (a few megabytes for the embedded math fonts) for the rest of
+    /// the process, or until a renderer created with [`new`](Self::new)
+    /// replaces it. MicroTeX is then released once the last renderer, cached
+    /// or not, is dropped.
+    ///
+    /// # Errors
+    ///
+    /// Returns the same errors as [`new`](Self::new).
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use microtex_rs::{MicroTex, RenderConfig};
+    ///
+    /// for formula in [r#"\[x^2\]"#, r#"\[y^2\]"#] {
+    ///     // Only the first iteration loads the font
+    ///     let renderer = MicroTex::new_cached()?;
+    ///     let svg = renderer.render(formula, &RenderConfig::default())?;
+    ///     assert!(svg.contains("<svg"));
+    /// }
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn new_cached() -> Result<Self, RenderError> {
+        let mut engine = lock_engine();
+        let (font_name, clm_data) = Self::default_font()?;
+
+        let inited = {
+            let _ffi = lock_ffi();
+            unsafe { shim::microtex_is_inited() }
+        };
+        if engine.cached_font == Some(font_name) && inited {
+            engine.handles += 1;
+            return Ok(MicroTex {
+                config: RenderConfig::default(),
+                math_font: check_math_font(font_name, clm_data),
+                font_unicodes: parse_clm_unicodes(clm_data).unwrap_or_default(),
+                renders: Default::default(),
+            });
+        }
+
+        let renderer = Self::init_with_font(&mut engine, font_name, clm_data, true)?;
+        engine.cached_font = Some(font_name);
+        Ok(renderer)
+    }
+
+    /// Returns the process-wide renderer, initializing it on the first call.
+    ///
+    /// Every call returns a clone of the same [`Arc`](std::sync::Arc), so
+    /// MicroTeX is initialized once however often and from however many
+    /// threads this is called, without the hand-written `OnceLock` singleton
+    /// suggested in the [`MicroTex`] docs.
+    ///
+    /// The renderer is created like [`new_cached`](Self::new_cached) and the
+    /// `Arc` is also held by a static, so dropping the returned clones only
+    /// decrements the reference count: the renderer is never dropped and
+    /// MicroTeX stays initialized for the rest of the process. Renderers
+    /// created with [`new`](Self::new) alongside it don't release MicroTeX
+    /// when dropped either, since the shared renderer is still alive.
+    ///
+    /// # Errors
+    ///
+    /// Returns the same errors as [`new`](Self::new). A failed
+    /// initialization is not cached; the next call tries again.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use microtex_rs::{MicroTex, RenderConfig};
+    /// use std::sync::Arc;
+    ///
+    /// let renderer = MicroTex::shared()?;
+    /// assert!(Arc::ptr_eq(&renderer, &MicroTex::shared()?));
+    /// let svg = renderer.render(r#"\[x^2\]"#, &RenderConfig::default())?;
+    /// assert!(svg.contains("<svg"));
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn shared() -> Result<std::sync::Arc<MicroTex>, RenderError> {
+        static SHARED: std::sync::OnceLock<std::sync::Arc<MicroTex>> = std::sync::OnceLock::new();
+
+        if let Some(renderer) = SHARED.get() {
+            return Ok(renderer.clone());
+        }
+        // A thread losing the race drops its renderer, which leaves MicroTeX
+        // initialized for the winner
+        let renderer = std::sync::Arc::new(Self::new_cached()?);
+        Ok(SHARED.get_or_init(|| renderer).clone())
+    }
+
+    /// Creates a new MicroTeX renderer using a specific embedded font.
+    ///
+    /// `name` is the file name of an embedded CLM font, as listed by
+    /// [`available_embedded_clms`]. Use [`new`](Self::new) to pick the first
+    /// available math font automatically.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`RenderError::InitializationFailed`] if no embedded font has
+    /// this name, or if the MicroTeX library initialization fails.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use microtex_rs::{available_embedded_clms, MicroTex};
+    ///
+    /// if available_embedded_clms().contains(&"FiraMath-Regular.clm2") {
+    ///     let renderer = MicroTex::with_font("FiraMath-Regular.clm2")?;
+    ///     assert!(renderer.is_math_font());
+    /// }
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn with_font(name: &str) -> Result<Self, RenderError> {
+        let clm_data = get_embedded_clm(name).ok_or_else(|| {
+            eprintln!(
+                "Font {} not found in embedded CLM files. Available: {:?}",
+                name,
+                available_embedded_clms()
+            );
+            RenderError::InitializationFailed
+        })?;
+        Self::init_with_font(&mut lock_engine(), name, clm_data, false)
+    }
+
+    /// Returns the first embedded math font of the default search order.
+    fn default_font() -> Result<(&'static str, &'static [u8]), RenderError> {
+        // Try to find a suitable math font from the embedded CLM files
+        // Note: We search in a specific order, preferring XITS which is well-tested
+        // IMPORTANT: Math fonts must come before non-math fonts!
+        // XITSMath-Regular is the math font version, not XITS-Regular
+        let font_candidates = [
+            "XITSMath-Regular.clm2",
+            "FiraMath-Regular.clm2",
+            "latinmodern-math.clm2",
+            "texgyredejavu-math.clm2",
+        ];
+
+        let mut clm_data = None;
+        for font_name in &font_candidates {
+            if let Some(data) = get_embedded_clm(font_name) {
+                clm_data = Some((*font_name, data));
+                break;
+            }
+        }
+
+        clm_data.ok_or_else(|| {
+            eprintln!(
+                "No suitable math fonts found in embedded CLM files. Available: {:?}",
+                available_embedded_clms()
+            );
+            RenderError::InitializationFailed
+        })
+    }
+
+    /// Initializes MicroTeX with the given font and counts the new handle in
+    /// `engine`, which the caller holds locked.
+    fn init_with_font(
+        engine: &mut Engine,
+        font_name: &str,
+        clm_data: &[u8],
+        cached: bool,
+    ) -> Result<Self, RenderError> {
+        let math_font = check_math_font(font_name, clm_data);
+        if !cached {
+            // Initializing replaces the font the cache refers to
+            engine.cached_font = None;
+        }
+
+        let _ffi = lock_ffi();
+        unsafe {
+            // Critical: Initialize MicroTeX with font data (via shim)
+            // This call may throw C++ exceptions if the data is invalid
+            let meta = shim::microtex_init(clm_data.len() as u64, clm_data.as_ptr());
+            if meta.is_null() {
+                eprintln!("microtex_init returned null");
+                return Err(RenderError::InitializationFailed);
+            }
+
+            // Set reasonable defaults
+            let default_font = std::ffi::CString::new(DEFAULT_MAIN_FONT).unwrap();
+            shim::microtex_set_default_main_font(default_font.as_ptr());
+            shim::microtex_set_render_glyph_use_path(true);
+
+            let math_font = std::ffi::CStr::from_ptr(shim::microtex_get_font_name(meta));
+            if !math_font.is_empty() {
+                let _ = DEFAULT_MATH_FONT.set(math_font.to_owned());
+            }
+
+            // Important: release the font metadata after initialization
+            shim::microtex_release_font_meta(meta);
+        }
+        engine.handles += 1;
+
+        static STARTUP_LOG: std::sync::Once = std::sync::Once::new();
+        STARTUP_LOG.call_once(|| {
+            log::info!(
+                "MicroTeX initialized with font {}: {}",
+                font_name,
+                build_info()
+            );
+        });
+
+        Ok(MicroTex {
+            config: RenderConfig::default(),
+            math_font,
+            font_unicodes: parse_clm_unicodes(clm_data).unwrap_or_default(),
+            renders: Default::default(),
+        })
+    }
+
+    /// Expands the macros of `config`, then substitutes Unicode math
+    /// alphabets and mirrors delimiters if enabled.
+    fn expand_source(&self, latex_source: &str, config: &RenderConfig) -> String {
+        let mut expanded = expand_macros(latex_source, &config.macros);
+        if config.unicode_math_alphabets {
+            expanded = substitute_math_alphabets(&expanded, |c| {
+                self.font_unicodes.binary_search(&(c as u32)).is_ok()
+            });
+        }
+        if config.mirror_delimiters {
+            expanded = mirror_delimiters(&expanded);
+        }
+        expanded
+    }
+
+    /// Returns true if the font MicroTeX was initialized with is a math font.
+    ///
+    /// Rendering with a non-math font does not fail, but produces wrong
+    /// glyphs; a warning is logged when such a font is selected.
+    pub fn is_math_font(&self) -> bool {
+        self.math_font
+    }
+
+    /// Returns the number of formulas this instance has parsed.
+    ///
+    /// Every render method parses the formula once, including renders that
+    /// fail after parsing; failures before parsing, such as
+    /// [`RenderError::EmptyInput`], are not counted.
+    pub fn render_count(&self) -> u64 {
+        self.renders.load(std::sync::atomic::Ordering::Relaxed)
+    }
+
+    /// Parses `latex_cstr` with [`render_parsed`], counting it in
+    /// [`render_count`](Self::render_count), and returns what `read` returns.
+    ///
+    /// With a [`RenderConfig::render_timeout`], the render runs on a worker
+    /// thread (see [`run_with_timeout`]), so `read` must not borrow anything.
+    fn with_parsed_render<T: Send + 'static>(
+        &self,
+        latex_cstr: &std::ffi::CStr,
+        config: &RenderConfig,
+        read: impl FnOnce(*mut std::ffi::c_void, &RenderConfig) -> Result<T, RenderError>
+            + Send
+            + 'static,
+    ) -> Result<T, RenderError> {
+        self.renders
+            .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        match config.render_timeout {
+            None => render_parsed(latex_cstr, config, read),
+            Some(timeout) => {
+                let latex_cstr = latex_cstr.to_owned();
+                let config = config.clone();
+                run_with_timeout(timeout, move || render_parsed(&latex_cstr, &config, read))
+            }
+        }
+    }
+
+    /// Renders a LaTeX formula string to SVG format.
+    ///
+    /// # Arguments
+    ///
+    /// * `latex_source` - The LaTeX source string to render.
+    /// * `config` - Rendering configuration parameters.
+    ///
+    /// # Returns
+    ///
+    /// A string containing the SVG representation of the rendered formula,
+    /// or an error if parsing/rendering fails.
+    ///
+    /// # Errors
+    ///
+    /// Returns errors if:
+    /// - The LaTeX source is empty or whitespace-only ([`RenderError::EmptyInput`])
+    /// - The LaTeX source contains a `tikzpicture` or pgfplots `axis`
+    ///   environment ([`RenderError::Unsupported`])
+    /// - The LaTeX source contains a NUL byte ([`RenderError::InvalidInput`],
+    ///   with its byte position after macro expansion)
+    /// - The LaTeX source cannot be parsed
+    /// - The rendering process fails
+    /// - The SVG output is empty
+    /// - The SVG buffer cannot be converted to valid UTF-8
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use microtex_rs::{MicroTex, RenderConfig};
+    ///
+    /// let renderer = MicroTex::new()?;
+    /// let config = RenderConfig::default();
+    /// let svg = renderer.render(r#"\[x = \frac{-b \pm \sqrt{b^2-4ac}}{2a}\]"#, &config)?;
+    /// assert!(svg.contains("<svg"));
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn render(&self, latex_source: &str, config: &RenderConfig) -> Result<String, RenderError> {
+        let svg = self.render_unadjusted(latex_source, config)?;
+
+        // Adjust SVG height and center content to prevent glyph clipping
+        Ok(adjust_svg_height_and_center(&svg))
+    }
+
+    /// Sets the configuration used by [`render_default`](Self::render_default).
+    ///
+    /// Methods that take an explicit [`RenderConfig`], such as
+    /// [`render`](Self::render), ignore this default and always use the
+    /// configuration they are given.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use microtex_rs::{MicroTex, RenderConfig};
+    ///
+    /// let mut renderer = MicroTex::new()?;
+    /// renderer.set_default_config(RenderConfig {
+    ///     dpi: 1440,
+    ///     ..Default::default()
+    /// });
+    /// let svg = renderer.render_default(r#"\[x^2\]"#)?;
+    /// assert!(svg.contains("<svg"));
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn set_default_config(&mut self, config: RenderConfig) {
+        self.config = config;
+    }
+
+    /// Returns the configuration used by [`render_default`](Self::render_default).
+    pub fn default_config(&self) -> &RenderConfig {
+        &self.config
+    }
+
+    /// Renders a LaTeX formula to SVG, choosing inline or display math from
+    /// its delimiters.
+    ///
+    /// The whole source, ignoring surrounding whitespace, is matched against
+    /// these rules, in order:
+    ///
+    /// 1. `\[...\]` or `$$...$$`: display math.
+    /// 2. `\(...\)` or `$...$`: inline math. A `$...$` body must be non-empty
+    ///    and contain no other `$`, so `$a$ and $b$` is not inline math.
+    /// 3. Anything else is a bare formula, rendered as display math.
+    ///
+    /// The source is then rendered with [`render`](Self::render), using the
+    /// `\[...\]` or `$...$` delimiters MicroTeX understands.
+    ///
+    /// # Errors
+    ///
+    /// Returns the same errors as [`render`](Self::render).
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use microtex_rs::{MicroTex, RenderConfig};
+    ///
+    /// let renderer = MicroTex::new()?;
+    /// let config = RenderConfig::default();
+    /// let inline = renderer.render_auto(r"\(\sum_i x_i\)", &config)?;
+    /// let display = renderer.render_auto(r"\sum_i x_i", &config)?;
+    /// assert!(inline.contains("<svg") && display.contains("<svg"));
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn render_auto(&self, source: &str, config: &RenderConfig) -> Result<String, RenderError> {
+        self.render(&normalize_math_delimiters(source), config)
+    }
+
+    /// Renders a formula without delimiters as inline (text style) math.
+    ///
+    /// The expression is trimmed and wrapped in `$...$` with
+    /// [`wrap_in_delimiters`], then rendered with [`render`](Self::render).
+    ///
+    /// # Errors
+    ///
+    /// Returns [`RenderError::InvalidInput`] if `expr` is empty or only
+    /// whitespace, without calling into MicroTeX, or the same errors as
+    /// [`render`](Self::render).
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use microtex_rs::{MicroTex, RenderConfig};
+    ///
+    /// let renderer = MicroTex::new()?;
+    /// let svg = renderer.render_inline(r"\sum_i x_i", &RenderConfig::default())?;
+    /// assert!(svg.contains("<svg"));
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn render_inline(&self, expr: &str, config: &RenderConfig) -> Result<String, RenderError> {
+        self.render_wrapped(expr, false, config)
+    }
+
+    /// Renders a formula without delimiters as display math.
+    ///
+    /// The expression is trimmed and wrapped in `\[...\]` with
+    /// [`wrap_in_delimiters`], then rendered with [`render`](Self::render).
+    ///
+    /// # Errors
+    ///
+    /// Returns [`RenderError::InvalidInput`] if `expr` is empty or only
+    /// whitespace, without calling into MicroTeX, or the same errors as
+    /// [`render`](Self::render).
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use microtex_rs::{MicroTex, RenderConfig};
+    ///
+    /// let renderer = MicroTex::new()?;
+    /// let svg = renderer.render_display(r"\sum_i x_i", &RenderConfig::default())?;
+    /// assert!(svg.contains("<svg"));
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn render_display(&self, expr: &str, config: &RenderConfig) -> Result<String, RenderError> {
+        self.render_wrapped(expr, true, config)
+    }
+
+    /// Wraps `expr` with [`wrap_in_delimiters`] and renders it, see
+    /// [`render_inline`](Self::render_inline).
+    fn render_wrapped(
+        &self,
+        expr: &str,
+        display: bool,
+        config: &RenderConfig,
+    ) -> Result<String, RenderError> {
+        if expr.trim().is_empty() {
+            return Err(RenderError::InvalidInput("expression is empty".to_string()));
+        }
+        self.render(&wrap_in_delimiters(expr, display), config)
+    }
+
+    /// Renders a LaTeX formula to SVG with the instance's default configuration.
+    ///
+    /// Equivalent to `self.render(latex_source, self.default_config())`. The
+    /// default is [`RenderConfig::default`] until changed with
+    /// [`set_default_config`](Self::set_default_config).
+    ///
+    /// # Errors
+    ///
+    /// Returns the same errors as [`render`](Self::render).
+    pub fn render_default(&self, latex_source: &str) -> Result<String, RenderError> {
+        self.render(latex_source, &self.config)
+    }
+
+    /// Renders a LaTeX formula to SVG without centering it, and returns the
+    /// centering transform separately.
+    ///
+    /// The SVG is what [`render`](Self::render) produces before
+    /// [`adjust_svg_height_and_center`] runs; the [`SvgTransform`] describes
+    /// what that adjustment would do, so compositors can apply it in their
+    /// own coordinate system.
+    ///
+    /// # Arguments
+    ///
+    /// * `latex_source` - The LaTeX formula to render
+    /// * `config` - Rendering configuration
+    ///
+    /// # Errors
+    ///
+    /// Returns the same errors as [`render`](Self::render).
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use microtex_rs::{MicroTex, RenderConfig};
+    ///
+    /// let renderer = MicroTex::new()?;
+    /// let (svg, transform) =
+    ///     renderer.render_with_transform_info(r#"\[x^2\]"#, &RenderConfig::default())?;
+    /// println!("translate by {} and resize to {}", transform.translate_y, transform.new_height);
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn render_with_transform_info(
+        &self,
+        latex_source: &str,
+        config: &RenderConfig,
+    ) -> Result<(String, SvgTransform), RenderError> {
+        let svg = self.render_unadjusted(latex_source, config)?;
+        let transform = svg_centering_transform(&svg);
+        Ok((svg, transform))
+    }
+
+    /// Renders a LaTeX formula to SVG into a caller-provided buffer.
+    ///
+    /// Produces the same SVG as [`render`](Self::render), but the DPI and
+    /// centering post-processing is done in a single pass written straight
+    /// into `out` (see [`postprocess_svg_into`]). This suits
+    /// allocation-sensitive deployments rendering many formulas.
+    ///
+    /// `out` is cleared first and keeps its capacity, so reusing one buffer
+    /// across calls avoids reallocating the output. On error, `out` is left
+    /// untouched.
+    ///
+    /// # Errors
+    ///
+    /// Returns the same errors as [`render`](Self::render).
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use microtex_rs::{MicroTex, RenderConfig};
+    ///
+    /// let renderer = MicroTex::new()?;
+    /// let config = RenderConfig::default();
+    /// let mut svg = String::new();
+    /// for formula in [r#"\[x^2\]"#, r#"\[y^2\]"#] {
+    ///     renderer.render_into(formula, &config, &mut svg)?;
+    ///     println!("{}", svg);
+    /// }
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn render_into(
+        &self,
+        latex_source: &str,
+        config: &RenderConfig,
+        out: &mut String,
+    ) -> Result<(), RenderError> {
+        let svg = self.render_raw(latex_source, config)?;
+        let svg = apply_config_postprocessing(svg, latex_source, config);
+        postprocess_svg_into(&svg, config.dpi, out);
+        Ok(())
+    }
+
+    /// Renders a LaTeX formula to SVG scaled to an exact pixel width.
+    ///
+    /// The formula is rendered once with `config`, exactly as
+    /// [`render`](Self::render) does, then scaled: the root `width` is set to
+    /// `target_width_px` and the `height` is scaled by the same factor, both
+    /// written in pixels whatever unit MicroTeX used, while
+    /// the `viewBox` keeps the original coordinates. The formula is not
+    /// re-rendered, since MicroTeX lays it out from the text size rather
+    /// than the DPI; as SVG is a vector format, scaling loses no sharpness.
+    ///
+    /// # Arguments
+    ///
+    /// * `latex_source` - The LaTeX formula to render
+    /// * `config` - Rendering configuration
+    /// * `target_width_px` - Width of the output SVG, in pixels
+    ///
+    /// # Errors
+    ///
+    /// Returns the same errors as [`render`](Self::render), or
+    /// [`RenderError::Scaling`] if `target_width_px` is 0 or the rendered SVG
+    /// has no positive width and height.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use microtex_rs::{MicroTex, RenderConfig};
+    ///
+    /// let renderer = MicroTex::new()?;
+    /// let svg = renderer.render_to_width(r#"\[E = mc^2\]"#, &RenderConfig::default(), 400)?;
+    /// assert!(svg.contains(r#"width="400""#));
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn render_to_width(
+        &self,
+        latex_source: &str,
+        config: &RenderConfig,
+        target_width_px: u32,
+    ) -> Result<String, RenderError> {
+        let svg = self.render(latex_source, config)?;
+
+        let dimension = |key| {
+            svg_root_attribute(&svg, key)
+                .as_deref()
+                .and_then(split_svg_length)
+                .map(|(value, _)| value)
+        };
+        let (width, height) = match (dimension("width"), dimension("height")) {
+            (Some(width), Some(height)) if width > 0.0 && height > 0.0 => (width, height),
+            _ => {
+                return Err(RenderError::Scaling(
+                    "SVG has no positive width or height".to_string(),
+                ))
+            }
+        };
+        if target_width_px == 0 {
+            return Err(RenderError::Scaling("target width is 0".to_string()));
+        }
+
+        let target_width = target_width_px as f32;
+        Ok(resize_svg(
+            &svg,
+            target_width,
+            height * target_width / width,
+        ))
+    }
+
+    /// Checks a LaTeX source and converts it once for repeated rendering with
+    /// [`render_prepared`](Self::render_prepared).
+    ///
+    /// The checks that only depend on the source (empty input, unsupported
+    /// environments, NUL bytes) run here, once.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`RenderError::EmptyInput`], [`RenderError::Unsupported`] or
+    /// [`RenderError::InvalidInput`].
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use microtex_rs::{MicroTex, RenderConfig};
+    ///
+    /// let renderer = MicroTex::new()?;
+    /// let source = renderer.prepare_source(r#"\[x^2 + y^2\]"#)?;
+    /// for dpi in [360, 720, 1440] {
+    ///     let config = RenderConfig {
+    ///         dpi,
+    ///         ..Default::default()
+    ///     };
+    ///     let svg = renderer.render_prepared(&source, &config)?;
+    ///     assert!(svg.contains(&format!(r#"data-dpi="{}""#, dpi)));
+    /// }
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn prepare_source(&self, latex_source: &str) -> Result<PreparedSource, RenderError> {
+        if latex_source.trim().is_empty() {
+            return Err(RenderError::EmptyInput);
+        }
+        check_supported(latex_source)?;
+
+        let c_source = to_c_source(latex_source)?;
+        Ok(PreparedSource {
+            source: latex_source.to_string(),
+            c_source,
+        })
+    }
+
+    /// Renders a prepared LaTeX source to SVG.
+    ///
+    /// Produces the same SVG as [`render`](Self::render) with the source
+    /// given to [`prepare_source`](Self::prepare_source), without
+    /// re-checking or re-converting the source. When `config` defines
+    /// [`macros`](RenderConfig::macros), the expanded source is still
+    /// converted on each call.
+    ///
+    /// # Errors
+    ///
+    /// Returns the same errors as [`render`](Self::render), except those
+    /// already reported by [`prepare_source`](Self::prepare_source).
+    pub fn render_prepared(
+        &self,
+        source: &PreparedSource,
+        config: &RenderConfig,
+    ) -> Result<String, RenderError> {
+        config.validate()?;
+        let svg = self.render_raw_prepared(source, config)?;
+        let svg = apply_config_postprocessing(svg, source.as_str(), config);
+        Ok(adjust_svg_height_and_center(&add_dpi_to_svg(
+            &svg, config.dpi,
+        )))
+    }
+
+    /// Renders a LaTeX formula to SVG, collecting only the measurements
+    /// selected by `opts`.
+    ///
+    /// With the default options this is [`render`](Self::render). Setting
+    /// [`RenderOptions::metrics`] renders like
+    /// [`render_to_svg_with_metrics`](Self::render_to_svg_with_metrics),
+    /// so options that depend on the metrics, such as
+    /// [`RenderConfig::pad_for_depth`], take effect. The key character
+    /// metrics are read from the same parse only when
+    /// [`RenderOptions::key_char_metrics`] is set, which saves a call into
+    /// MicroTeX and a JSON parse otherwise; they are `None` if MicroTeX does
+    /// not report them.
+    ///
+    /// # Errors
+    ///
+    /// Returns the same errors as [`render`](Self::render), or as
+    /// [`render_to_svg_with_metrics`](Self::render_to_svg_with_metrics) when
+    /// metrics are requested.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use microtex_rs::{MicroTex, RenderConfig, RenderOptions};
+    ///
+    /// let renderer = MicroTex::new()?;
+    /// let opts = RenderOptions {
+    ///     metrics: true,
+    ///     ..Default::default()
+    /// };
+    /// let output = renderer.render_with_options(r#"\[x^2\]"#, &RenderConfig::default(), opts)?;
+    /// assert!(output.metrics.is_some() && output.key_char_metrics.is_none());
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn render_with_options(
+        &self,
+        latex_source: &str,
+        config: &RenderConfig,
+        opts: RenderOptions,
+    ) -> Result<RenderOutput, RenderError> {
+        if opts.metrics {
+            let result = self.render_measured(latex_source, config, opts.key_char_metrics)?;
+            return Ok(RenderOutput {
+                svg: result.svg,
+                metrics: Some(result.metrics),
+                key_char_metrics: result.key_char_metrics,
+            });
+        }
+
+        config.validate()?;
+        let source = self.prepare_source(latex_source)?;
+        let (svg, key_char_metrics) =
+            self.render_raw_prepared_with_key_chars(&source, config, opts.key_char_metrics)?;
+        let svg = apply_config_postprocessing(svg, latex_source, config);
+        Ok(RenderOutput {
+            svg: adjust_svg_height_and_center(&add_dpi_to_svg(&svg, config.dpi)),
+            metrics: None,
+            key_char_metrics,
+        })
+    }
+
+    /// Renders to SVG with DPI metadata and the configured root attributes,
+    /// before height adjustment and centering.
+    fn render_unadjusted(
+        &self,
+        latex_source: &str,
+        config: &RenderConfig,
+    ) -> Result<String, RenderError> {
+        let svg = self.render_raw(latex_source, config)?;
+        let svg = apply_config_postprocessing(svg, latex_source, config);
+        Ok(add_dpi_to_svg(&svg, config.dpi))
+    }
+
+    /// Renders to SVG as produced by MicroTeX, with normalized line endings
+    /// and no post-processing.
+    fn render_raw(&self, latex_source: &str, config: &RenderConfig) -> Result<String, RenderError> {
+        config.validate()?;
+        let source = self.prepare_source(latex_source)?;
+        self.render_raw_prepared(&source, config)
+    }
+
+    /// Renders a prepared source like [`render_raw`](Self::render_raw), for
+    /// a configuration that has already been validated.
+    fn render_raw_prepared(
+        &self,
+        source: &PreparedSource,
+        config: &RenderConfig,
+    ) -> Result<String, RenderError> {
+        self.render_raw_prepared_with_key_chars(source, config, false)
+            .map(|(svg, _)| svg)
+    }
+
+    /// Renders a prepared source like
+    /// [`render_raw_prepared`](Self::render_raw_prepared), also reading the
+    /// key character metrics of the render if `key_char_metrics` is set.
+    fn render_raw_prepared_with_key_chars(
+        &self,
+        source: &PreparedSource,
+        config: &RenderConfig,
+        key_char_metrics: bool,
+    ) -> Result<(String, Option<KeyCharMetrics>), RenderError> {
+        // Macros and source rewrites depend on the configuration, so their
+        // expansion is checked and converted per render
+        let expanded;
+        let (latex_source, latex_cstr) = if config.macros.is_empty()
+            && !config.unicode_math_alphabets
+            && !config.mirror_delimiters
+        {
+            (source.source.as_str(), &source.c_source)
+        } else {
+            let latex_source = self.expand_source(&source.source, config);
+            check_supported(&latex_source)?;
+            let latex_cstr = to_c_source(&latex_source)?;
+            expanded = (latex_source, latex_cstr);
+            (expanded.0.as_str(), &expanded.1)
+        };
+        check_source_limits(latex_source, config)?;
+
+        self.with_parsed_render(latex_cstr, config, move |render_ptr, config| {
+            let svg_string = unsafe {
+                let mut out_len = 0u64;
+                let out_buf = shim::microtex_render_to_svg(render_ptr, &mut out_len);
+                take_output(out_buf, out_len, config)?
+            };
+
+            let key_char_metrics = if key_char_metrics {
+                get_key_char_metrics(render_ptr).ok()
+            } else {
+                None
+            };
+
+            // Keep line endings consistent regardless of platform
+            Ok((normalize_line_endings(&svg_string), key_char_metrics))
+        })
+    }
+
+    /// Renders a LaTeX formula to SVG together with a PNG thumbnail.
+    ///
+    /// The formula is parsed and rendered once; the thumbnail is rasterized
+    /// from the resulting SVG. It keeps the SVG's aspect ratio and is scaled
+    /// so that its longer side is `thumb_max_px` pixels (small formulas are
+    /// scaled up, large ones down). Requires the `png` feature.
+    ///
+    /// # Arguments
+    ///
+    /// * `latex_source` - The LaTeX source string to render.
+    /// * `config` - Rendering configuration parameters.
+    /// * `thumb_max_px` - Size of the thumbnail's longer side, in pixels.
+    ///
+    /// # Returns
+    ///
+    /// A tuple of the full SVG string and the encoded PNG thumbnail.
+    ///
+    /// # Errors
+    ///
+    /// Returns the same errors as [`render()`](Self::render), or
+    /// [`RenderError::Rasterization`] if the thumbnail cannot be produced
+    /// (including when `thumb_max_px` is 0).
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use microtex_rs::{MicroTex, RenderConfig};
+    ///
+    /// let renderer = MicroTex::new()?;
+    /// let (svg, png) = renderer.render_with_thumbnail(r#"\[E = mc^2\]"#, &RenderConfig::default(), 128)?;
+    /// std::fs::write("formula.svg", svg)?;
+    /// std::fs::write("formula-thumb.png", png)?;
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    #[cfg(feature = "png")]
+    pub fn render_with_thumbnail(
+        &self,
+        latex_source: &str,
+        config: &RenderConfig,
+        thumb_max_px: u32,
+    ) -> Result<(String, Vec<u8>), RenderError> {
+        let svg = self.render(latex_source, config)?;
+
+        let dimension = |key| {
+            svg_root_attribute(&svg, key)
+                .as_deref()
+                .and_then(split_svg_length)
+                .map(|(value, _)| value)
+        };
+        let longer_side = match (dimension("width"), dimension("height")) {
+            (Some(width), Some(height)) => width.max(height),
+            _ => {
+                return Err(RenderError::Rasterization(
+                    "SVG has no width or height".to_string(),
+                ))
+            }
+        };
+        if longer_side <= 0.0 || thumb_max_px == 0 {
+            return Err(RenderError::Rasterization(format!(
+                "cannot fit a {} px formula into {} px",
+                longer_side, thumb_max_px
+            )));
+        }
+
+        let thumbnail = rasterize_svg(&svg, thumb_max_px as f32 / longer_side)?;
+        Ok((svg, thumbnail))
+    }
+
+    /// Renders a LaTeX formula to a PNG image. Requires the `png` feature.
+    ///
+    /// The formula is rendered to SVG as [`render`](Self::render) does, then
+    /// rasterized at `config.dpi`: the SVG size is read as CSS pixels (96 per
+    /// inch), so the image is `dpi / 96` times larger. The default 720 DPI
+    /// gives a bitmap 7.5 times the SVG size, and doubling the DPI doubles
+    /// both image dimensions.
+    ///
+    /// # Errors
+    ///
+    /// Returns the same errors as [`render()`](Self::render), or
+    /// [`RenderError::Rasterization`] if the SVG cannot be rasterized or
+    /// encoded.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use microtex_rs::{MicroTex, RenderConfig};
+    ///
+    /// let renderer = MicroTex::new()?;
+    /// let png = renderer.render_to_png(r#"\[E = mc^2\]"#, &RenderConfig::default())?;
+    /// std::fs::write("formula.png", png)?;
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    #[cfg(feature = "png")]
+    pub fn render_to_png(
+        &self,
+        latex_source: &str,
+        config: &RenderConfig,
+    ) -> Result<Vec<u8>, RenderError> {
+        let svg = self.render(latex_source, config)?;
+        rasterize_svg(&svg, config.dpi as f32 / SVG_PIXELS_PER_INCH)
+    }
+
+    /// Renders a LaTeX formula to the largest PNG image that fits in
+    /// `max_bytes`. Requires the `png` feature.
+    ///
+    /// The formula is rendered to SVG once, then rasterized as
+    /// [`render_to_png`](Self::render_to_png) does. `config.dpi` is tried
+    /// first; if the PNG is too large, the DPI is binary searched between 1
+    /// and `config.dpi`, keeping the highest DPI whose PNG fits. This takes
+    /// about `log2(config.dpi)` rasterizations, and assumes the PNG size
+    /// grows with the DPI, which holds for formulas in practice.
+    ///
+    /// # Returns
+    ///
+    /// The PNG bytes and the DPI they were rasterized at.
+    ///
+    /// # Errors
+    ///
+    /// Returns the same errors as [`render_to_png`](Self::render_to_png), or
+    /// [`RenderError::Rasterization`] if the PNG exceeds `max_bytes` even at
+    /// 1 DPI.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use microtex_rs::{MicroTex, RenderConfig};
+    ///
+    /// let renderer = MicroTex::new()?;
+    /// let (png, dpi) =
+    ///     renderer.render_png_under_size(r#"\[E = mc^2\]"#, &RenderConfig::default(), 16 * 1024)?;
+    /// assert!(png.len() <= 16 * 1024 && dpi <= 720);
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    #[cfg(feature = "png")]
+    pub fn render_png_under_size(
+        &self,
+        latex_source: &str,
+        config: &RenderConfig,
+        max_bytes: usize,
+    ) -> Result<(Vec<u8>, i32), RenderError> {
+        let svg = self.render(latex_source, config)?;
+        highest_dpi_under_size(config.dpi, max_bytes, |dpi| {
+            rasterize_svg(&svg, dpi as f32 / SVG_PIXELS_PER_INCH)
+        })
+    }
+
+    /// Renders several formulas into one PNG sprite sheet. Requires the
+    /// `png` feature.
+    ///
+    /// Each formula is rasterized as [`render_to_png`](Self::render_to_png)
+    /// does, then cropped to its non-transparent pixels, so the sprites carry
+    /// no margin of their own.
+    ///
+    /// # Packing
+    ///
+    /// Sprites are packed on shelves: sorted by decreasing height, they are
+    /// placed left to right, and a new shelf opens below the tallest sprite
+    /// of the current one when the next sprite does not fit. The sheet is
+    /// about as wide as the square root of the sprites' total area, and never
+    /// narrower than the widest sprite. `padding` pixels separate sprites
+    /// from each other and from the sheet edges. A formula with no visible
+    /// pixel gets an empty rectangle.
+    ///
+    /// # Returns
+    ///
+    /// The PNG bytes of the sheet and its [`SpriteAtlas`], with one
+    /// [`Sprite`] per item, in the order of `items`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first error of [`render`](Self::render) on an item, or
+    /// [`RenderError::Rasterization`] if an item or the sheet cannot be
+    /// rasterized or encoded.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use microtex_rs::{MicroTex, RenderConfig};
+    ///
+    /// let renderer = MicroTex::new()?;
+    /// let (png, atlas) = renderer.render_sprite_sheet(
+    ///     &[r#"\[x^2\]"#, r#"\[\alpha + \beta\]"#],
+    ///     &RenderConfig::default(),
+    ///     2,
+    /// )?;
+    /// std::fs::write("sheet.png", png)?;
+    /// std::fs::write("sheet.json", serde_json::to_string(&atlas)?)?;
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    #[cfg(feature = "png")]
+    pub fn render_sprite_sheet(
+        &self,
+        items: &[&str],
+        config: &RenderConfig,
+        padding: u32,
+    ) -> Result<(Vec<u8>, SpriteAtlas), RenderError> {
+        use resvg::tiny_skia::Pixmap;
+
+        let scale = config.dpi as f32 / SVG_PIXELS_PER_INCH;
+        let mut images = Vec::with_capacity(items.len());
+        for latex in items {
+            let pixmap = rasterize_svg_pixmap(&self.render(latex, config)?, scale)?;
+            let bounds = opaque_bounds(&pixmap).unwrap_or((0, 0, 0, 0));
+            images.push((pixmap, bounds));
+        }
+
+        let sizes: Vec<(u32, u32)> = images
+            .iter()
+            .map(|&(_, (_, _, width, height))| (width, height))
+            .collect();
+        let ((width, height), positions) = shelf_pack(&sizes, padding);
+        let mut sheet = Pixmap::new(width.max(1), height.max(1)).ok_or_else(|| {
+            RenderError::Rasterization(format!("invalid sheet size {}x{}", width, height))
+        })?;
+
+        let sheet_width = sheet.width() as usize;
+        let mut sprites = Vec::with_capacity(items.len());
+        for ((latex, (pixmap, bounds)), &(x, y)) in items.iter().zip(&images).zip(&positions) {
+            let &(crop_x, crop_y, crop_width, crop_height) = bounds;
+            // Copy the cropped rows, 4 bytes per pixel
+            let row_len = crop_width as usize * 4;
+            for row in 0..crop_height as usize {
+                let src = ((crop_y as usize + row) * pixmap.width() as usize + crop_x as usize) * 4;
+                let dst = ((y as usize + row) * sheet_width + x as usize) * 4;
+                sheet.data_mut()[dst..dst + row_len]
+                    .copy_from_slice(&pixmap.data()[src..src + row_len]);
+            }
+            sprites.push(Sprite {
+                source: latex.to_string(),
+                x,
+                y,
+                width: crop_width,
+                height: crop_height,
+            });
+        }
+
+        let png = sheet
+            .encode_png()
+            .map_err(|e| RenderError::Rasterization(e.to_string()))?;
+        Ok((
+            png,
+            SpriteAtlas {
+                width: sheet.width(),
+                height: sheet.height(),
+                sprites,
+            },
+        ))
+    }
+
+    /// Renders a LaTeX formula to a base64 `data:image/svg+xml` URI.
+    ///
+    /// The SVG is rendered with [`render`](Self::render) and encoded with
+    /// [`svg_to_data_uri`].
+    ///
+    /// # Errors
+    ///
+    /// Returns the same errors as [`render`](Self::render).
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use microtex_rs::{MicroTex, RenderConfig};
+    ///
+    /// let renderer = MicroTex::new()?;
+    /// let uri = renderer.render_to_data_uri(r#"\[E = mc^2\]"#, &RenderConfig::default())?;
+    /// let img = format!(r#"<img src="{}" alt="E = mc^2">"#, uri);
+    /// assert!(img.starts_with(r#"<img src="data:image/svg+xml;base64,"#));
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn render_to_data_uri(
+        &self,
+        latex_source: &str,
+        config: &RenderConfig,
+    ) -> Result<String, RenderError> {
+        self.render(latex_source, config)
+            .map(|svg| svg_to_data_uri(&svg))
+    }
+
+    /// Renders a LaTeX formula as a standalone HTML5 page, for previews.
+    ///
+    /// The page has a `<!DOCTYPE html>`, a UTF-8 charset and `title` as its
+    /// `<title>`. The formula is inlined as the
+    /// [`to_html_figure`](RenderResult::to_html_figure) snippet, using `title`
+    /// as its accessible label and caption, and centered in the viewport.
+    ///
+    /// # Arguments
+    ///
+    /// * `latex_source` - The LaTeX source string to render.
+    /// * `config` - Rendering configuration parameters.
+    /// * `title` - Page title and formula description (HTML-escaped on output)
+    ///
+    /// # Errors
+    ///
+    /// Returns the same errors as
+    /// [`render_to_svg_with_metrics`](Self::render_to_svg_with_metrics).
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use microtex_rs::{MicroTex, RenderConfig};
+    ///
+    /// let renderer = MicroTex::new()?;
+    /// let html = renderer.render_to_html_page(
+    ///     r#"\[E = mc^2\]"#,
+    ///     &RenderConfig::default(),
+    ///     "Mass-energy equivalence",
+    /// )?;
+    /// std::fs::write("formula.html", html)?;
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn render_to_html_page(
+        &self,
+        latex_source: &str,
+        config: &RenderConfig,
+        title: &str,
+    ) -> Result<String, RenderError> {
+        let result = self.render_to_svg_with_metrics(latex_source, config)?;
+        Ok(format!(
+            concat!(
+                "<!DOCTYPE html>\n",
+                "<html>\n",
+                "<head>\n",
+                "<meta charset=\"utf-8\">\n",
+                "<title>{}</title>\n",
+                "<style>body {{ margin: 0; min-height: 100vh; display: flex; ",
+                "align-items: center; justify-content: center; }}</style>\n",
+                "</head>\n",
+                "<body>\n",
+                "{}\n",
+                "</body>\n",
+                "</html>\n"
+            ),
+            quick_xml::escape::escape(title),
+            result.to_html_figure(title)
+        ))
+    }
+
+    /// Renders a LaTeX formula and writes the SVG to a file.
+    ///
+    /// The file is written with the line endings selected by
+    /// [`RenderConfig::line_ending`].
+    ///
+    /// # Arguments
+    ///
+    /// * `latex_source` - The LaTeX source string to render.
+    /// * `config` - Rendering configuration parameters.
+    /// * `path` - Destination file, created or truncated.
+    ///
+    /// # Errors
+    ///
+    /// Returns the same errors as [`render()`](Self::render), or
+    /// [`RenderError::Io`] if the file cannot be written.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use microtex_rs::{LineEnding, MicroTex, RenderConfig};
+    ///
+    /// let renderer = MicroTex::new()?;
+    /// let config = RenderConfig {
+    ///     line_ending: LineEnding::CrLf,
+    ///     ..Default::default()
+    /// };
+    /// renderer.render_to_path(r#"\[E = mc^2\]"#, &config, "formula.svg")?;
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn render_to_path(
+        &self,
+        latex_source: &str,
+        config: &RenderConfig,
+        path: impl AsRef<std::path::Path>,
+    ) -> Result<(), RenderError> {
+        let svg = self.render(latex_source, config)?;
+        std::fs::write(path, config.line_ending.apply(&svg))?;
+        Ok(())
+    }
+
+    /// Renders a LaTeX formula as an SVG `<symbol>` definition for `<use>` reuse.
+    ///
+    /// The formula is rendered with [`render()`](Self::render) and its root
+    /// `<svg>` element is turned into `<symbol id="..." viewBox="...">`. Place
+    /// the symbol once in a (hidden) `<svg>` of the document and reference it
+    /// with [`symbol_use_reference`] wherever the formula appears.
+    ///
+    /// # Arguments
+    ///
+    /// * `latex_source` - The LaTeX source string to render.
+    /// * `config` - Rendering configuration parameters.
+    /// * `id` - The `id` attribute of the symbol, unique within the document.
+    ///
+    /// # Errors
+    ///
+    /// Returns the same errors as [`render()`](Self::render).
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use microtex_rs::{symbol_use_reference, MicroTex, RenderConfig};
+    ///
+    /// let renderer = MicroTex::new()?;
+    /// let symbol = renderer.render_to_symbol(r#"\[E = mc^2\]"#, &RenderConfig::default(), "formula-1")?;
+    /// assert!(symbol.starts_with(r#"<symbol id="formula-1""#));
+    /// let reference = symbol_use_reference(&symbol);
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn render_to_symbol(
+        &self,
+        latex_source: &str,
+        config: &RenderConfig,
+        id: &str,
+    ) -> Result<String, RenderError> {
+        let svg = self.render(latex_source, config)?;
+        Ok(svg_to_symbol(&svg, id))
+    }
+
+    /// Renders a LaTeX formula string to SVG format with dimensional metrics.
+    ///
+    /// This function is similar to [`render()`](Self::render), but also returns
+    /// precise dimensional information (width, height, depth, ascent) extracted
+    /// from the MicroTeX BOX TREE before SVG rendering. This is useful for
+    /// accurate scaling and positioning of the rendered formula.
+    ///
+    /// # Arguments
+    ///
+    /// * `latex_source` - The LaTeX source string to render.
+    /// * `config` - Rendering configuration parameters.
+    ///
+    /// # Returns
+    ///
+    /// A [`RenderResult`] containing both the SVG string and the metrics,
+    /// or an error if parsing/rendering fails.
+    ///
+    /// # Errors
+    ///
+    /// Returns errors if:
+    /// - The LaTeX source is empty or whitespace-only ([`RenderError::EmptyInput`])
+    /// - The LaTeX source contains a `tikzpicture` or pgfplots `axis`
+    ///   environment ([`RenderError::Unsupported`])
+    /// - The LaTeX source contains a NUL byte ([`RenderError::InvalidInput`],
+    ///   with its byte position after macro expansion)
+    /// - The LaTeX source cannot be parsed
+    /// - The rendering process fails
+    /// - The output is empty
+    /// - The SVG or metrics JSON cannot be parsed
+    /// - Invalid UTF-8 is encountered
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use microtex_rs::{MicroTex, RenderConfig};
+    ///
+    /// let renderer = MicroTex::new()?;
+    /// let config = RenderConfig::default();
+    /// let result = renderer.render_to_svg_with_metrics(r#"\[x^2\]"#, &config)?;
+    /// println!("Width: {}, Height: {}", result.metrics.width, result.metrics.height);
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn render_to_svg_with_metrics(
+        &self,
+        latex_source: &str,
+        config: &RenderConfig,
+    ) -> Result<RenderResult, RenderError> {
+        self.render_measured(latex_source, config, true)
+    }
+
+    /// Renders like [`render_to_svg_with_metrics`](Self::render_to_svg_with_metrics),
+    /// reading the key character metrics only if `key_char_metrics` is set.
+    fn render_measured(
+        &self,
+        latex_source: &str,
+        config: &RenderConfig,
+        key_char_metrics: bool,
+    ) -> Result<RenderResult, RenderError> {
+        config.validate()?;
+        if latex_source.trim().is_empty() {
+            return Err(RenderError::EmptyInput);
+        }
+        let expanded_source = self.expand_source(latex_source, config);
+        check_supported(&expanded_source)?;
+        check_source_limits(&expanded_source, config)?;
+
+        let latex_cstr = to_c_source(&expanded_source)?;
+
+        let (json_string, key_char_metrics) =
+            self.with_parsed_render(&latex_cstr, config, move |render_ptr, config| {
+                let json_string = unsafe {
+                    let mut out_len = 0u64;
+                    let out_buf =
+                        shim::microtex_render_to_svg_with_metrics(render_ptr, &mut out_len);
+                    take_output(out_buf, out_len, config)?
+                };
+
+                // Try to extract key character metrics
+                let key_char_metrics = if key_char_metrics {
+                    get_key_char_metrics(render_ptr).ok()
+                } else {
+                    None
+                };
+                Ok((json_string, key_char_metrics))
+            })?;
+
+        // Attach the raw payload to JSON errors when requested
+        let json_error = |error: String| {
+            if config.capture_raw_json {
+                RenderError::ParseJsonFailedRaw {
+                    error,
+                    raw: json_string.clone(),
+                }
+            } else {
+                RenderError::ParseJsonFailed(error)
+            }
+        };
+
+        // Parse the JSON response from C++
+        let json_value: serde_json::Value =
+            serde_json::from_str(&json_string).map_err(|e| json_error(e.to_string()))?;
+
+        // Extract SVG content
+        let mut svg = json_value
+            .get("svg")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| json_error("missing 'svg' field".to_string()))?
+            .to_string();
+
+        // Keep line endings consistent regardless of platform
+        svg = normalize_line_endings(&svg);
+
+        // Apply the configured post-processing and DPI metadata to SVG
+        svg = apply_config_postprocessing(svg, latex_source, config);
+        svg = add_dpi_to_svg(&svg, config.dpi);
+
+        // Extract metrics
+        let metrics_obj = json_value
+            .get("metrics")
+            .and_then(|v| v.as_object())
+            .ok_or_else(|| json_error("missing 'metrics' field".to_string()))?;
+
+        let width = metrics_obj
+            .get("width")
+            .and_then(|v| v.as_i64())
+            .ok_or_else(|| json_error("missing or invalid 'width'".to_string()))?
+            as i32;
+
+        let height = metrics_obj
+            .get("height")
+            .and_then(|v| v.as_i64())
+            .ok_or_else(|| json_error("missing or invalid 'height'".to_string()))?
+            as i32;
+
+        let depth = metrics_obj
+            .get("depth")
+            .and_then(|v| v.as_i64())
+            .ok_or_else(|| json_error("missing or invalid 'depth'".to_string()))?
+            as i32;
+
+        let ascent = metrics_obj
+            .get("ascent")
+            .and_then(|v| v.as_i64())
+            .ok_or_else(|| json_error("missing or invalid 'ascent'".to_string()))?
+            as i32;
+
+        let mut metrics = RenderMetrics::new(width, height, depth, ascent);
+
+        // Older C wrappers don't report the math axis, keep it at 0 then
+        if let Some(axis) = metrics_obj.get("math_axis_height").and_then(|v| v.as_i64()) {
+            metrics.math_axis_height = axis as i32;
+        }
+
+        // MicroTeX puts the baseline `depth` units above the bottom edge
+        let baseline_y = svg_root_attribute(&svg, "height")
+            .as_deref()
+            .and_then(split_svg_length)
+            .map(|(height, _)| height - metrics.depth as f32);
+
+        // Adjust SVG height and center content to prevent glyph clipping,
+        // which moves the baseline with the content
+        let (adjusted, report) = adjust_svg_height_and_center_report(&svg);
+        svg = adjusted;
+        let baseline_y = baseline_y.map(|y| y + report.translate_y);
+
+        // Move the baseline to the forced ascent, or pad vertically so
+        // it sits at half the height
+        match (baseline_y, config.force_ascent_px) {
+            (Some(baseline_y), Some(ascent)) => {
+                svg = force_svg_ascent(&svg, baseline_y, ascent);
+            }
+            (Some(baseline_y), None) if config.pad_for_depth => {
+                svg = pad_svg_for_depth(&svg, baseline_y);
+            }
+            _ => {}
+        }
+
+        if let Some(limit) = config.max_output_dimensions {
+            if metrics.width > limit.0 || metrics.height > limit.1 {
+                return Err(RenderError::OutputTooLarge {
+                    width: metrics.width,
+                    height: metrics.height,
+                    limit,
+                });
+            }
+        }
+
+        let result = match key_char_metrics {
+            Some(kcm) => RenderResult::with_key_char_metrics(svg, metrics, kcm),
+            None => RenderResult::new(svg, metrics),
+        };
+
+        Ok(result)
+    }
+
+    /// Renders a formula and measures it in a single parse, without copying
+    /// the SVG.
+    ///
+    /// This is the lowest-overhead way to get both an SVG and its metrics,
+    /// meant for servers rendering many formulas. Compared to
+    /// [`render_to_svg_with_metrics`](Self::render_to_svg_with_metrics), the
+    /// formula is still parsed once, but the SVG is not escaped into and
+    /// parsed back out of a JSON payload, not copied into a Rust `String`,
+    /// and not post-processed: it is read in place from the buffer MicroTeX
+    /// allocated, which is validated as UTF-8 once and freed when the
+    /// returned [`BorrowedSvg`] is dropped. The metrics are read directly
+    /// from the render.
+    ///
+    /// The SVG is MicroTeX's raw output, so it is sized to the formula box
+    /// and has no `data-dpi` attribute, centering or padding, and no
+    /// post-processing is applied: neither [`strokes_to_fills`] nor the
+    /// configuration options that post-process the SVG (such as
+    /// [`RenderConfig::svg_attributes`] or [`RenderConfig::sanitize`]). Key
+    /// character metrics are not collected. Use
+    /// [`render_to_svg_with_metrics`](Self::render_to_svg_with_metrics) when
+    /// any of these is needed.
+    ///
+    /// The `borrowed_render` example compares the throughput of both methods.
+    ///
+    /// # Errors
+    ///
+    /// Returns the same errors as
+    /// [`render_to_svg_with_metrics`](Self::render_to_svg_with_metrics),
+    /// except for the JSON parsing errors.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use microtex_rs::{MicroTex, RenderConfig};
+    ///
+    /// let renderer = MicroTex::new()?;
+    /// let svg = renderer.render_measured_borrowed(r#"\[x^2\]"#, &RenderConfig::default())?;
+    /// assert!(svg.contains("<svg"));
+    /// println!("Width: {}", svg.metrics().width);
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn render_measured_borrowed(
+        &self,
+        latex_source: &str,
+        config: &RenderConfig,
+    ) -> Result<BorrowedSvg, RenderError> {
+        config.validate()?;
+        if latex_source.trim().is_empty() {
+            return Err(RenderError::EmptyInput);
+        }
+        let expanded_source = self.expand_source(latex_source, config);
+        check_supported(&expanded_source)?;
+        check_source_limits(&expanded_source, config)?;
+
+        let latex_cstr = to_c_source(&expanded_source)?;
+
+        let svg = self.with_parsed_render(&latex_cstr, config, |render_ptr, _| unsafe {
+            let (width, ascent, depth, axis) = shim::microtex_get_render_dimensions(render_ptr);
+            let mut out_len = 0u64;
+            let out_buf = shim::microtex_render_to_svg(render_ptr, &mut out_len);
+
+            let ptr = match std::ptr::NonNull::new(out_buf) {
+                Some(ptr) if out_len > 0 => ptr,
+                _ => {
+                    if !out_buf.is_null() {
+                        shim::microtex_free_buffer(out_buf);
+                    }
+                    return Err(RenderError::EmptyOutput);
+                }
+            };
+
+            // From here on the buffer is freed by `BorrowedSvg`, even on error.
+            // Its drop takes `FFI_LOCK`, held here, so it must be returned
+            // as soon as it is built and only checked after the lock is
+            // released
+            let mut metrics = RenderMetrics::new(width, ascent + depth, depth, ascent);
+            metrics.math_axis_height = axis;
+            Ok(BorrowedSvg {
+                ptr,
+                len: out_len as usize,
+                metrics,
+            })
+        })?;
+        check_output_len(svg.len as u64, config)?;
+
+        // The only UTF-8 check of the buffer
+        // SAFETY: `ptr` points to the `len` bytes of the buffer `svg` owns
+        let bytes = unsafe { std::slice::from_raw_parts(svg.ptr.as_ptr(), svg.len) };
+        if std::str::from_utf8(bytes).is_err() {
+            // Only copied to build the error
+            return Err(String::from_utf8(bytes.to_vec()).unwrap_err().into());
+        }
+
+        if let Some(limit) = config.max_output_dimensions {
+            if svg.metrics.width > limit.0 || svg.metrics.height > limit.1 {
+                return Err(RenderError::OutputTooLarge {
+                    width: svg.metrics.width,
+                    height: svg.metrics.height,
+                    limit,
+                });
+            }
+        }
+
+        Ok(svg)
+    }
+
+    /// Renders each row of an aligned environment separately, with metrics.
+    ///
+    /// The source may be an `align`, `align*`, `aligned`, `eqnarray` or
+    /// similar environment (optionally inside `\[...\]`), or just its body.
+    /// It is split into rows on the top-level `\\` separators (ignoring those
+    /// nested in braces or inner environments, and an optional `[<skip>]`
+    /// after them). Each non-empty row is rendered as
+    /// `\[\begin{aligned}row\end{aligned}\]` with
+    /// [`render_to_svg_with_metrics`](Self::render_to_svg_with_metrics).
+    ///
+    /// # Alignment columns
+    ///
+    /// The `&` markers are kept, so columns are aligned *within* a row, but
+    /// each row is laid out on its own: column widths are not shared across
+    /// rows. Use the per-row metrics to align rows in your own layout.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first error encountered while rendering a row, with the
+    /// same variants as [`render_to_svg_with_metrics`](Self::render_to_svg_with_metrics).
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use microtex_rs::{MicroTex, RenderConfig};
+    ///
+    /// let renderer = MicroTex::new()?;
+    /// let rows = renderer.render_align_rows(
+    ///     r"\begin{align} a &= b + c \\ d &= e \end{align}",
+    ///     &RenderConfig::default(),
+    /// )?;
+    /// for row in &rows {
+    ///     println!("row width: {}", row.metrics.width);
+    /// }
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn render_align_rows(
+        &self,
+        latex_source: &str,
+        config: &RenderConfig,
+    ) -> Result<Vec<RenderResult>, RenderError> {
+        split_align_rows(latex_source)
+            .into_iter()
+            .map(|row| {
+                let row_source = format!("\\[\\begin{{aligned}}{}\\end{{aligned}}\\]", row);
+                self.render_to_svg_with_metrics(&row_source, config)
+            })
+            .collect()
+    }
+
+    /// Renders `new` with the tokens that are not in `old` highlighted in
+    /// green.
+    ///
+    /// This is [`render_diff_with`](Self::render_diff_with) without the
+    /// removed tokens overlay.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use microtex_rs::{MicroTex, RenderConfig};
+    ///
+    /// let renderer = MicroTex::new()?;
+    /// let svg = renderer.render_diff(r"\[a + b\]", r"\[a + c\]", &RenderConfig::default())?;
+    /// assert!(svg.contains("<svg"));
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn render_diff(
+        &self,
+        old: &str,
+        new: &str,
+        config: &RenderConfig,
+    ) -> Result<String, RenderError> {
+        self.render_diff_with(old, new, config, false)
+    }
+
+    /// Renders `new` with the tokens that are not in `old` highlighted in
+    /// green and, if `show_removed` is set, the tokens of `old` that are not
+    /// in `new` struck through in red.
+    ///
+    /// # Diff granularity
+    ///
+    /// Both sources are split into tokens, compared along a longest common
+    /// subsequence:
+    /// - a control word (`\alpha`) or control symbol (`\,`) is one token,
+    /// - any other non-whitespace character is one token, so `12` and `13`
+    ///   differ by the `3` only,
+    /// - a text command such as `\text` or `\operatorname` and its argument
+    ///   form one token, highlighted as a whole.
+    ///
+    /// Whitespace is ignored. Tokens are highlighted by wrapping them in
+    /// `\textcolor{green}{...}` before rendering; tokens that cannot be
+    /// wrapped without changing the formula, such as braces, `^`, `_`, `&`,
+    /// `\left` and its delimiter, or a command taking arguments, are not
+    /// highlighted, but the changed tokens inside their arguments are.
+    ///
+    /// Removed tokens are inserted as `\textcolor{red}{\cancel{...}}` where
+    /// they were, unless that would change the structure of `new` (for
+    /// instance between `^` and its script), in which case they move to the
+    /// next position where they fit. Removed tokens that cannot be wrapped
+    /// are not shown.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`RenderError::SourceTooLong`],
+    /// [`RenderError::DisallowedCommand`] or
+    /// [`RenderError::NestingTooDeep`] if either source exceeds the limits
+    /// of `config`, and otherwise the same errors as
+    /// [`render`](Self::render) for the highlighted source.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use microtex_rs::{MicroTex, RenderConfig};
+    ///
+    /// let renderer = MicroTex::new()?;
+    /// let svg = renderer.render_diff_with(
+    ///     r"\[x^2 + y\]",
+    ///     r"\[x^3 + y\]",
+    ///     &RenderConfig::default(),
+    ///     true,
+    /// )?;
+    /// assert!(svg.contains("<svg"));
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn render_diff_with(
+        &self,
+        old: &str,
+        new: &str,
+        config: &RenderConfig,
+        show_removed: bool,
+    ) -> Result<String, RenderError> {
+        check_source_limits(old, config)?;
+        check_source_limits(new, config)?;
+        self.render(&highlight_diff(old, new, show_removed), config)
+    }
+
+    /// Renders several formulas, applying the same post-processing to each.
+    ///
+    /// Each item is rendered with [`render`](Self::render) and its own
+    /// [`RenderConfig`], so settings such as the DPI may vary between
+    /// formulas. The SVG is then post-processed with `options`, which keeps
+    /// padding and formatting consistent across a document.
+    ///
+    /// # Override precedence
+    ///
+    /// A setting given in an item's [`overrides`](BatchItem::overrides) wins
+    /// over the same setting in `options`; settings the item leaves unset
+    /// fall back to `options`. Settings unset in both are not applied.
+    ///
+    /// # Returns
+    ///
+    /// One result per item, in order. A failure on one item does not stop
+    /// the others.
+    ///
+    /// # Thread safety
+    ///
+    /// MicroTeX keeps its state in C++ globals, so each render holds an
+    /// internal lock while it calls into MicroTeX. Renders from other threads
+    /// wait for it rather than interleave with the batch's formulas.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use microtex_rs::{BatchItem, BatchOptions, MicroTex, RenderConfig};
+    ///
+    /// let renderer = MicroTex::new()?;
+    /// let options = BatchOptions {
+    ///     padding: Some((4.0, 4.0, 4.0, 4.0)),
+    ///     ..Default::default()
+    /// };
+    /// let items = [
+    ///     BatchItem::new(r"\[x^2\]", RenderConfig::default()),
+    ///     BatchItem::new(r"\[y^2\]", RenderConfig { dpi: 1440, ..Default::default() }),
+    /// ];
+    /// for svg in renderer.render_batch(&items, &options) {
+    ///     assert!(svg?.contains("<svg"));
+    /// }
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn render_batch(
+        &self,
+        items: &[BatchItem],
+        options: &BatchOptions,
+    ) -> Vec<Result<String, RenderError>> {
+        items
+            .iter()
+            .map(|item| {
+                let svg = self.render(&item.latex, &item.config)?;
+                Ok(item.overrides.or(options).apply(svg))
+            })
+            .collect()
+    }
+}
+
+/// Renders every `.tex` file of a directory to a matching `.svg` file.
+///
+/// Each `<name>.tex` file directly inside `dir` (subdirectories are not
+/// visited) is read, rendered by `renderer` with `config`, and written to
+/// `<out_dir>/<name>.svg` using [`MicroTex::render_to_path`]. `out_dir` is
+/// created if needed. Files are processed in path order and a failure on one
+/// file does not stop the others.
+///
+/// # Arguments
+///
+/// * `renderer` - Renderer used for every file
+/// * `dir` - Directory containing the `.tex` snippets
+/// * `out_dir` - Directory receiving the `.svg` files
+/// * `config` - Rendering configuration applied to every file
+///
+/// # Returns
+///
+/// One `(source path, result)` pair per `.tex` file. If the directory cannot
+/// be read or `out_dir` cannot be created, a single pair holding `dir` and
+/// the error is returned.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use microtex_rs::{render_directory, MicroTex, RenderConfig};
+/// use std::path::Path;
+///
+/// let renderer = MicroTex::new()?;
+/// let results = render_directory(
+///     &renderer,
+///     Path::new("formulas"),
+///     Path::new("out"),
+///     &RenderConfig::default(),
+/// );
+/// for (path, result) in results {
+///     if let Err(e) = result {
+///         eprintln!("{}: {}", path.display(), e);
+///     }
+/// }
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn render_directory(
+    renderer: &MicroTex,
+    dir: &std::path::Path,
+    out_dir: &std::path::Path,
+    config: &RenderConfig,
+) -> Vec<(std::path::PathBuf, Result<(), RenderError>)> {
+    let mut sources = match std::fs::read_dir(dir) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|path| path.is_file() && path.extension().is_some_and(|ext| ext == "tex"))
+            .collect::<Vec<_>>(),
+        Err(e) => return vec![(dir.to_path_buf(), Err(e.into()))],
+    };
+    sources.sort();
+
+    if let Err(e) = std::fs::create_dir_all(out_dir) {
+        return vec![(dir.to_path_buf(), Err(e.into()))];
+    }
+
+    sources
+        .into_iter()
+        .map(|source| {
+            let result = std::fs::read_to_string(&source)
+                .map_err(RenderError::from)
+                .and_then(|latex| {
+                    let stem = source.file_stem().unwrap_or_default();
+                    let target = out_dir.join(stem).with_extension("svg");
+                    renderer.render_to_path(&latex, config, target)
+                });
+            (source, result)
+        })
+        .collect()
+}
+
+/// The outcome of one formula rendered through a [`RenderSession`].
+#[derive(Debug, Clone)]
+pub struct RenderDiagnostic {
+    /// The LaTeX source that was rendered.
+    pub source: String,
+
+    /// The error message if rendering failed, `None` on success.
+    pub error: Option<String>,
+
+    /// Problems that did not prevent rendering, such as an output without
+    /// any glyph.
+    pub warnings: Vec<String>,
+
+    /// The formula metrics, on success.
+    pub metrics: Option<RenderMetrics>,
+
+    /// Time spent rendering the formula.
+    pub duration: std::time::Duration,
+}
+
+impl RenderDiagnostic {
+    /// Returns true if the formula rendered successfully.
+    pub fn is_success(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+impl std::fmt::Display for RenderDiagnostic {
+    /// Formats the diagnostic as one report line, followed by one indented
+    /// line per warning.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (&self.error, &self.metrics) {
+            (Some(error), _) => write!(f, "error {:?}: {}", self.source, error)?,
+            (None, Some(m)) => write!(f, "ok {:?}: {}x{} px", self.source, m.width, m.height)?,
+            (None, None) => write!(f, "ok {:?}", self.source)?,
+        }
+        write!(f, " in {:?}", self.duration)?;
+        for warning in &self.warnings {
+            write!(f, "\n  warning: {}", warning)?;
+        }
+        Ok(())
+    }
+}
+
+/// Renders formulas while recording a [`RenderDiagnostic`] for each one, to
+/// report on a whole document build.
+///
+/// Formulas are rendered with
+/// [`MicroTex::render_to_svg_with_metrics`], so each diagnostic carries the
+/// formula metrics. Failures are recorded too, and still returned to the
+/// caller.
+///
+/// # Example
+///
+/// ```rust
+/// use microtex_rs::{MicroTex, RenderConfig, RenderSession};
+///
+/// let renderer = MicroTex::new()?;
+/// let mut session = RenderSession::new(&renderer);
+/// let config = RenderConfig::default();
+/// for formula in [r"\[x^2\]", r"\[\frac{a}{b}\]"] {
+///     let _ = session.render(formula, &config);
+/// }
+/// for diagnostic in session.diagnostics() {
+///     println!("{}", diagnostic);
+/// }
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub struct RenderSession<'a> {
+    renderer: &'a MicroTex,
+    diagnostics: Vec<RenderDiagnostic>,
+}
+
+impl<'a> RenderSession<'a> {
+    /// Creates a session rendering with `renderer`.
+    pub fn new(renderer: &'a MicroTex) -> Self {
+        RenderSession {
+            renderer,
+            diagnostics: Vec::new(),
+        }
+    }
+
+    /// Renders a formula like
+    /// [`MicroTex::render_to_svg_with_metrics`] and records its diagnostic.
+    ///
+    /// # Errors
+    ///
+    /// Returns the same errors as
+    /// [`MicroTex::render_to_svg_with_metrics`].
+    pub fn render(
+        &mut self,
+        latex_source: &str,
+        config: &RenderConfig,
+    ) -> Result<RenderResult, RenderError> {
+        let start = std::time::Instant::now();
+        let result = self
+            .renderer
+            .render_to_svg_with_metrics(latex_source, config);
+        let duration = start.elapsed();
+
+        let mut warnings = Vec::new();
+        if !self.renderer.is_math_font() {
+            warnings.push("rendered with a font that is not a math font".to_string());
+        }
+        if matches!(&result, Ok(r) if r.rendered_glyph_count == 0) {
+            warnings.push("output contains no glyph".to_string());
+        }
+
+        self.diagnostics.push(RenderDiagnostic {
+            source: latex_source.to_string(),
+            error: result.as_ref().err().map(ToString::to_string),
+            warnings,
+            metrics: result.as_ref().ok().map(|r| r.metrics.clone()),
+            duration,
+        });
+        result
+    }
+
+    /// Returns the diagnostics recorded so far, in render order.
+    pub fn diagnostics(&self) -> &[RenderDiagnostic] {
+        &self.diagnostics
+    }
+
+    /// Ends the session, returning its diagnostics.
+    pub fn into_diagnostics(self) -> Vec<RenderDiagnostic> {
+        self.diagnostics
+    }
+}
+
+/// Get metrics of key characters in a rendered formula.
+///
+/// This function extracts the heights of actual character boxes at the
+/// top level of the formula structure, excluding decorative elements.
+/// This is useful for calculating more accurate scaling factors that
+/// account for formula complexity (fractions, subscripts, etc.).
+///
+/// # Arguments
+///
+/// * `render_ptr` - The render pointer from `parse_render`
+///
+/// # Returns
+///
+/// A `KeyCharMetrics` struct containing the heights of key characters
+/// and statistical information about them.
+///
+/// # Errors
+///
+/// Returns [`RenderError`] if the rendering operation fails or the
+/// JSON parsing fails.
+pub fn get_key_char_metrics(
+    render_ptr: *mut std::ffi::c_void,
+) -> Result<KeyCharMetrics, RenderError> {
+    if render_ptr.is_null() {
+        return Err(RenderError::ParseRenderFailed(String::new()));
+    }
+
+    unsafe {
+        let mut out_len = 0u64;
+        let out_buf = shim::microtex_get_key_char_metrics(render_ptr, &mut out_len);
+
+        if out_buf.is_null() || out_len == 0 {
+            return Err(RenderError::EmptyOutput);
+        }
+
+        // Convert the buffer to a Rust string
+        let json_slice = std::slice::from_raw_parts(out_buf as *const u8, out_len as usize);
+        let json_string = String::from_utf8(json_slice.to_vec())?;
+
+        // Parse the JSON response
+        let metrics = KeyCharMetrics::from_json(&json_string)
+            .map_err(|e| RenderError::ParseJsonFailed(e.to_string()))?;
+
+        // Clean up
+        shim::microtex_free_buffer(out_buf);
+
+        Ok(metrics)
+    }
+}
+
+impl Drop for MicroTex {
+    fn drop(&mut self) {
+        let mut engine = lock_engine();
+        engine.handles -= 1;
+        // Other renderers still use MicroTeX, and a cached font stays loaded
+        // for later ones
+        if engine.handles > 0 || engine.cached_font.is_some() {
+            return;
+        }
+        let _ffi = lock_ffi();
+        unsafe {
+            shim::microtex_release();
+        }
+    }
+}
+
+// SAFETY: MicroTeX keeps its state in process-wide C++ globals rather than
+// in this handle, and every call into it (initialization, rendering,
+// freeing output buffers, including in `BorrowedSvg::drop`, release) holds
+// `FFI_LOCK`, so calls from different threads are serialized
+// and never run concurrently.
+unsafe impl Send for MicroTex {}
+unsafe impl Sync for MicroTex {}
+
+impl fmt::Debug for MicroTex {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MicroTex").finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COMPLEXE_SVG: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<svg xmlns="http://www.w3.org/2000/svg" xmlns:xlink="http://www.w3.org/1999/xlink" width="188" height="39" viewBox="0 0 188 39" data-dpi="720">
+<path fill-rule="nonzero" fill="rgb(0%, 0%, 0%)" fill-opacity="1" d="M 10.480469 23.28125 L 6.621094 14.480469 L 2.71875 23.28125 Z M 13.5 25.121094 L 0.960938 25.121094 L 6.941406 11.640625 L 7.339844 11.640625 Z M 13.5 25.121094 "/>
+<path fill-rule="nonzero" fill="rgb(0%, 0%, 0%)" fill-opacity="1" d="M 19.398438 16.378906 L 20.140625 16.378906 C 21.398438 16.378906 21.300781 14.839844 22.160156 13.558594 C 22.78125 12.621094 23.640625 11.761719 25.160156 11.761719 C 26.160156 11.761719 26.738281 12.238281 26.738281 12.941406 C 26.738281 13.5 26.320312 13.738281 25.921875 13.738281 C 25.359375 13.738281 25.21875 13.519531 25.21875 13.238281 C 25.21875 12.960938 25.398438 12.660156 25.398438 12.5 C 25.398438 12.398438 25.339844 12.339844 25.101562 12.339844 C 24.101562 12.339844 23.320312 13.421875 22.898438 14.980469 L 22.519531 16.378906 L 24.121094 16.378906 L 23.878906 17.140625 L 22.359375 17.140625 L 20.78125 23.261719 C 20.640625 23.800781 20.480469 24.558594 20.179688 25.300781 C 19.519531 26.960938 18.441406 28.859375 16.679688 28.859375 C 15.71875 28.859375 15.238281 28.378906 15.238281 27.78125 C 15.238281 27.300781 15.519531 26.800781 16.101562 26.800781 C 16.640625 26.800781 16.839844 27.160156 16.839844 27.460938 C 16.839844 27.839844 16.519531 27.859375 16.519531 28.101562 C 16.519531 28.21875 16.640625 28.28125 16.820312 28.28125 C 18.121094 28.28125 18.71875 25.121094 19.019531 23.980469 L 20.761719 17.140625 L 19.21875 17.140625 Z M 19.398438 16.378906 "/>
+<path fill-rule="nonzero" fill="rgb(0%, 0%, 0%)" fill-opacity="1" d="M 43.835938 22.71875 L 32.054688 22.71875 L 32.054688 21.398438 L 43.835938 21.398438 Z M 43.835938 18.71875 L 32.054688 18.71875 L 32.054688 17.398438 L 43.835938 17.398438 Z M 43.835938 18.71875 "/>
+<path fill-rule="nonzero" fill="rgb(0%, 0%, 0%)" fill-opacity="1" d="M 56.191406 8.898438 L 56.191406 2.039062 L 51.390625 8.898438 Z M 59.8125 10.179688 L 57.75 10.179688 L 57.75 13.519531 L 56.191406 13.519531 L 56.191406 10.179688 L 50.589844 10.179688 L 50.589844 8.898438 L 56.871094 0 L 57.75 0 L 57.75 8.898438 L 59.8125 8.898438 Z M 59.8125 10.179688 "/>
+<path fill-rule="nonzero" fill="rgb(0%, 0%, 0%)" fill-opacity="1" d="M 65.769531 8.078125 L 64.589844 7.28125 C 63.410156 8.238281 62.992188 9.058594 62.992188 10.359375 C 62.992188 12.199219 64.011719 13.238281 65.53125 13.238281 C 66.832031 13.238281 67.730469 12.339844 67.730469 11.039062 C 67.730469 9.800781 67.132812 9 65.769531 8.078125 Z M 67.449219 2.859375 C 67.449219 1.480469 66.632812 0.558594 65.269531 0.558594 C 63.929688 0.558594 63.070312 1.300781 63.070312 2.539062 C 63.070312 3.78125 63.8125 4.699219 65.570312 5.738281 C 66.929688 4.941406 67.449219 4.101562 67.449219 2.859375 Z M 69.25 10.421875 C 69.25 12.480469 67.710938 13.800781 65.3125 13.800781 C 63.050781 13.800781 61.472656 12.421875 61.472656 10.539062 C 61.472656 9.160156 61.929688 8.378906 64.070312 6.878906 C 62.011719 5.179688 61.589844 4.421875 61.589844 3.121094 C 61.589844 1.199219 63.25 0 65.472656 0 C 67.449219 0 68.832031 1.300781 68.832031 2.859375 C 68.832031 4.359375 68.132812 5.039062 66.152344 6.101562 C 68.609375 7.738281 69.25 8.820312 69.25 10.421875 Z M 69.25 10.421875 "/>
+<path fill-rule="nonzero" fill="rgb(0%, 0%, 0%)" fill-opacity="1" d="M 77.949219 7.019531 C 77.949219 2.859375 77.070312 0.519531 75.3125 0.519531 C 73.652344 0.519531 72.75 2.878906 72.75 6.941406 C 72.75 11 73.632812 13.28125 75.351562 13.28125 C 77.050781 13.28125 77.949219 10.980469 77.949219 7.019531 Z M 79.871094 6.921875 C 79.871094 10.359375 78.609375 13.800781 75.351562 13.800781 C 71.929688 13.800781 70.832031 10.078125 70.832031 6.800781 C 70.832031 3.261719 72.210938 0 75.429688 0 C 78.050781 0 79.871094 2.820312 79.871094 6.921875 Z M 79.871094 6.921875 "/>
+<path fill-rule="nonzero" fill="rgb(0%, 0%, 0%)" fill-opacity="1" d="M 87.949219 7.019531 C 87.949219 2.859375 87.070312 0.519531 85.3125 0.519531 C 83.652344 0.519531 82.75 2.878906 82.75 6.941406 C 82.75 11 83.632812 13.28125 85.351562 13.28125 C 87.050781 13.28125 87.949219 10.980469 87.949219 7.019531 Z M 89.871094 6.921875 C 89.871094 10.359375 88.609375 13.800781 85.351562 13.800781 C 81.929688 13.800781 80.832031 10.078125 80.832031 6.800781 C 80.832031 3.261719 82.210938 0 85.429688 0 C 88.050781 0 89.871094 2.820312 89.871094 6.921875 Z M 89.871094 6.921875 "/>
+<path fill-rule="nonzero" fill="rgb(0%, 0%, 0%)" fill-opacity="1" d="M 97.949219 7.019531 C 97.949219 2.859375 97.070312 0.519531 95.3125 0.519531 C 93.652344 0.519531 92.75 2.878906 92.75 6.941406 C 92.75 11 93.632812 13.28125 95.351562 13.28125 C 97.050781 13.28125 97.949219 10.980469 97.949219 7.019531 Z M 99.871094 6.921875 C 99.871094 10.359375 98.609375 13.800781 95.351562 13.800781 C 91.929688 13.800781 90.832031 10.078125 90.832031 6.800781 C 90.832031 3.261719 92.210938 0 95.429688 0 C 98.050781 0 99.871094 2.820312 99.871094 6.921875 Z M 99.871094 6.921875 "/>
+<path fill="none" stroke-width="66" stroke-linecap="butt" stroke-linejoin="bevel" stroke="rgb(0%, 0%, 0%)" stroke-opacity="1" stroke-miterlimit="0" d="M 2517.578181 1006.05471 L 5017.578237 1006.05471 " transform="matrix(0.02, 0, 0, 0.02, 0, 0)"/>
+<path fill-rule="nonzero" fill="rgb(0%, 0%, 0%)" fill-opacity="1" d="M 61.191406 34.5 L 61.191406 27.640625 L 56.390625 34.5 Z M 64.8125 35.78125 L 62.75 35.78125 L 62.75 39.121094 L 61.191406 39.121094 L 61.191406 35.78125 L 55.589844 35.78125 L 55.589844 34.5 L 61.871094 25.601562 L 62.75 25.601562 L 62.75 34.5 L 64.8125 34.5 Z M 64.8125 35.78125 "/>
+<path fill-rule="nonzero" fill="rgb(0%, 0%, 0%)" fill-opacity="1" d="M 72.949219 32.621094 C 72.949219 28.460938 72.070312 26.121094 70.3125 26.121094 C 68.652344 26.121094 67.75 28.480469 67.75 32.539062 C 67.75 36.601562 68.632812 38.878906 70.351562 38.878906 C 72.050781 38.878906 72.949219 36.578125 72.949219 32.621094 Z M 74.871094 32.519531 C 74.871094 35.960938 73.609375 39.398438 70.351562 39.398438 C 66.929688 39.398438 65.832031 35.679688 65.832031 32.398438 C 65.832031 28.859375 67.210938 25.601562 70.429688 25.601562 C 73.050781 25.601562 74.871094 28.421875 74.871094 32.519531 Z M 74.871094 32.519531 "/>
+<path fill-rule="nonzero" fill="rgb(0%, 0%, 0%)" fill-opacity="1" d="M 82.589844 32.019531 L 82.589844 31.238281 C 82.589844 27.878906 81.691406 26.160156 79.949219 26.160156 C 79.351562 26.160156 78.832031 26.398438 78.492188 26.839844 C 78.089844 27.378906 77.792969 28.558594 77.792969 29.640625 C 77.792969 32.019531 78.75 33.519531 80.25 33.519531 C 81.132812 33.519531 82.589844 33.078125 82.589844 32.019531 Z M 76.53125 39.558594 L 76.472656 39.160156 C 79.511719 38.621094 81.75 36.519531 82.550781 33.238281 C 81.691406 34.078125 80.730469 34.378906 79.550781 34.378906 C 77.390625 34.378906 75.949219 32.761719 75.949219 30.320312 C 75.949219 27.621094 77.730469 25.601562 80.109375 25.601562 C 81.390625 25.601562 82.472656 26.160156 83.25 27.121094 C 84.050781 28.121094 84.53125 29.558594 84.53125 31.238281 C 84.53125 33.539062 83.730469 35.71875 82.132812 37.179688 C 80.429688 38.71875 79.132812 39.199219 76.53125 39.558594 Z M 76.53125 39.558594 "/>
+<path fill-rule="nonzero" fill="rgb(0%, 0%, 0%)" fill-opacity="1" d="M 92.910156 35.359375 C 92.910156 32.800781 92.070312 31.480469 90.210938 31.480469 C 89.070312 31.480469 87.890625 31.960938 87.890625 33.800781 C 87.890625 36.839844 88.851562 38.839844 90.730469 38.839844 C 92.171875 38.839844 92.910156 37.398438 92.910156 35.359375 Z M 94.269531 25.441406 L 94.3125 25.761719 C 91.171875 26.28125 88.929688 28.441406 88.390625 31.460938 C 89.371094 30.699219 90.050781 30.558594 90.929688 30.558594 C 93.269531 30.558594 94.710938 32.160156 94.710938 34.738281 C 94.710938 36.019531 94.351562 37.140625 93.691406 37.941406 C 92.949219 38.859375 91.832031 39.398438 90.511719 39.398438 C 88.929688 39.398438 87.671875 38.660156 86.972656 37.378906 C 86.410156 36.359375 86.03125 34.941406 86.03125 33.539062 C 86.03125 31.378906 86.792969 29.480469 88.210938 28.019531 C 89.929688 26.21875 91.511719 25.738281 94.269531 25.441406 Z M 94.269531 25.441406 "/>
+<path fill-rule="nonzero" fill="rgb(0%, 0%, 0%)" fill-opacity="1" d="M 117.988281 20.339844 L 118.648438 20.339844 C 118.527344 22.039062 117.445312 24.421875 115.488281 24.421875 C 113.566406 24.421875 111.425781 21.859375 109.988281 21.859375 C 108.585938 21.859375 107.847656 23.199219 107.527344 24.621094 L 106.867188 24.621094 C 106.964844 22.621094 108.167969 20.539062 110.125 20.539062 C 112.046875 20.539062 114.1875 23.101562 115.648438 23.101562 C 117.027344 23.101562 117.6875 21.761719 117.988281 20.339844 Z M 117.988281 15.621094 L 118.648438 15.621094 C 118.527344 17.320312 117.445312 19.699219 115.488281 19.699219 C 113.566406 19.699219 111.425781 17.140625 109.988281 17.140625 C 108.585938 17.140625 107.847656 18.480469 107.527344 19.898438 L 106.867188 19.898438 C 106.964844 17.898438 108.167969 15.820312 110.125 15.820312 C 112.046875 15.820312 114.1875 18.378906 115.648438 18.378906 C 117.027344 18.378906 117.6875 17.039062 117.988281 15.621094 Z M 117.988281 15.621094 "/>
+<path fill-rule="nonzero" fill="rgb(0%, 0%, 0%)" fill-opacity="1" d="M 133.042969 25.121094 L 127.523438 25.121094 L 127.523438 24.820312 C 129.003906 24.738281 129.421875 24.320312 129.421875 23.21875 L 129.421875 14.238281 C 129.421875 13.558594 129.242188 13.261719 128.820312 13.261719 C 128.621094 13.261719 128.28125 13.359375 127.921875 13.5 L 127.382812 13.699219 L 127.382812 13.421875 L 130.960938 11.601562 L 131.140625 11.660156 L 131.140625 23.601562 C 131.140625 24.460938 131.542969 24.820312 133.042969 24.820312 Z M 133.042969 25.121094 "/>
+<path fill-rule="nonzero" fill="rgb(0%, 0%, 0%)" fill-opacity="1" d="M 143.042969 25.121094 L 137.523438 25.121094 L 137.523438 24.820312 C 139.003906 24.738281 139.421875 24.320312 139.421875 23.21875 L 139.421875 14.238281 C 139.421875 13.558594 139.242188 13.261719 138.820312 13.261719 C 138.621094 13.261719 138.28125 13.359375 137.921875 13.5 L 137.382812 13.699219 L 137.382812 13.421875 L 140.960938 11.601562 L 141.140625 11.660156 L 141.140625 23.601562 C 141.140625 24.460938 141.542969 24.820312 143.042969 24.820312 Z M 143.042969 25.121094 "/>
+<path fill-rule="nonzero" fill="rgb(0%, 0%, 0%)" fill-opacity="1" d="M 148.78125 24.261719 C 148.78125 24.839844 148.261719 25.339844 147.664062 25.339844 C 147.042969 25.339844 146.5625 24.859375 146.5625 24.238281 C 146.5625 23.621094 147.0625 23.121094 147.683594 23.121094 C 148.261719 23.121094 148.78125 23.660156 148.78125 24.261719 Z M 148.78125 24.261719 "/>
+<path fill-rule="nonzero" fill="rgb(0%, 0%, 0%)" fill-opacity="1" d="M 159.140625 12.199219 L 154.902344 25.28125 L 153.601562 25.28125 L 157.5625 13.359375 L 153.261719 13.359375 C 152.101562 13.359375 151.761719 13.640625 150.921875 15 L 150.5625 14.820312 L 151.761719 11.878906 L 159.140625 11.878906 Z M 159.140625 12.199219 "/>
+<path fill-rule="nonzero" fill="rgb(0%, 0%, 0%)" fill-opacity="1" d="M 179.222656 25.121094 L 173.640625 25.121094 L 173.640625 24.738281 C 175.203125 24.640625 175.402344 24.320312 175.402344 22.640625 L 175.402344 18.820312 L 169.34375 18.820312 L 169.34375 22.859375 C 169.34375 24.320312 169.601562 24.660156 171.082031 24.738281 L 171.101562 25.121094 L 165.523438 25.121094 L 165.523438 24.738281 C 167.082031 24.640625 167.300781 24.398438 167.300781 22.679688 L 167.300781 14.160156 C 167.300781 12.601562 167.0625 12.378906 165.523438 12.261719 L 165.523438 11.878906 L 171.121094 11.878906 L 171.121094 12.261719 C 169.664062 12.378906 169.34375 12.601562 169.34375 14.160156 L 169.34375 17.941406 L 175.402344 17.941406 L 175.402344 14.160156 C 175.402344 12.578125 175.140625 12.378906 173.621094 12.261719 L 173.621094 11.878906 L 179.222656 11.878906 L 179.222656 12.261719 C 177.742188 12.378906 177.441406 12.621094 177.441406 14.160156 L 177.441406 22.898438 C 177.441406 24.320312 177.722656 24.621094 179.222656 24.738281 Z M 179.222656 25.121094 "/>
+<path fill-rule="nonzero" fill="rgb(0%, 0%, 0%)" fill-opacity="1" d="M 187.960938 22.398438 L 187.664062 25.121094 L 180.140625 25.121094 L 180.140625 24.820312 L 185.460938 16.71875 L 182.761719 16.71875 C 181.503906 16.71875 181.203125 17.019531 181.023438 18.480469 L 180.664062 18.480469 L 180.742188 16.121094 L 187.664062 16.121094 L 187.664062 16.421875 L 182.28125 24.519531 L 184.941406 24.519531 C 186.101562 24.519531 186.78125 24.320312 187.0625 23.980469 C 187.34375 23.640625 187.402344 23.320312 187.601562 22.320312 Z M 187.960938 22.398438 "/>
+</svg>
+"#;
+
+    #[test]
+    fn test_available_clms() {
+        let clms = available_embedded_clms();
+        assert!(!clms.is_empty());
+        // At least one math font should be available
+        let has_math = clms.iter().any(|&name| {
+            name.contains("Math")
+                || name.contains("math")
+                || name.contains("XITS")
+                || name.contains("Fira")
+        });
+        assert!(
+            has_math,
+            "No suitable math fonts found. Available: {:?}",
+            clms
+        );
+    }
+
+    #[test]
+    fn test_new_cached_reuses_loaded_font() {
+        let _g = crate::shim::lock_test();
+        crate::shim::set_init_succeed(true);
+        crate::shim::set_parse_succeed(true);
+        crate::shim::set_return_empty(false);
+
+        let count = |construct: fn() -> Result<MicroTex, RenderError>| {
+            let calls = crate::test_control::get_init_calls();
+            let renderers: Vec<MicroTex> = (0..10).map(|_| construct().expect("init ok")).collect();
+            let inits = crate::test_control::get_init_calls() - calls;
+            (inits, renderers)
+        };
+
+        let (uncached_inits, renderers) = count(MicroTex::new);
+        drop(renderers);
+        let (cached_inits, renderers) = count(MicroTex::new_cached);
+        assert_eq!(uncached_inits, 10);
+        assert_eq!(cached_inits, 1);
+
+        // Dropping cached renderers keeps the font loaded for the next one
+        drop(renderers);
+        assert!(crate::test_control::get_inited());
+        let calls = crate::test_control::get_init_calls();
+        let renderer = MicroTex::new_cached().expect("init ok");
+        assert_eq!(crate::test_control::get_init_calls(), calls);
+        renderer
+            .render("x", &RenderConfig::default())
+            .expect("render ok");
+
+        // An uncached renderer replaces the font and empties the cache
+        drop(MicroTex::new().expect("init ok"));
+        assert!(lock_engine().cached_font.is_none());
+        drop(MicroTex::new_cached().expect("init ok"));
+        assert_eq!(crate::test_control::get_init_calls(), calls + 2);
+    }
+
+    #[test]
+    fn test_drop_keeps_microtex_for_live_renderers() {
+        let _g = crate::shim::lock_test();
+        crate::shim::set_init_succeed(true);
+        crate::shim::set_parse_succeed(true);
+        crate::shim::set_return_empty(false);
+        crate::shim::set_buffer(b"<svg></svg>");
+
+        let first = MicroTex::new().expect("init ok");
+        drop(MicroTex::new().expect("init ok"));
+        assert!(crate::test_control::get_inited());
+        first
+            .render("x", &RenderConfig::default())
+            .expect("render ok");
+
+        // An uncached renderer does not release the one a cached renderer uses
+        let cached = MicroTex::new_cached().expect("init ok");
+        drop(first);
+        drop(MicroTex::new().expect("init ok"));
+        assert!(crate::test_control::get_inited());
+        cached
+            .render("x", &RenderConfig::default())
+            .expect("render ok");
+    }
+
+    #[test]
+    fn test_shared_returns_same_renderer() {
+        let _g = crate::shim::lock_test();
+        crate::shim::set_init_succeed(true);
+        crate::shim::set_parse_succeed(true);
+        crate::shim::set_return_empty(false);
+
+        let first = MicroTex::shared().expect("init ok");
+        let calls = crate::test_control::get_init_calls();
+        let second = MicroTex::shared().expect("init ok");
+        assert!(std::sync::Arc::ptr_eq(&first, &second));
+        assert_eq!(crate::test_control::get_init_calls(), calls);
+
+        // Dropping the clones keeps the shared renderer alive
+        drop(first);
+        drop(second);
+        assert!(std::sync::Arc::ptr_eq(
+            &MicroTex::shared().expect("init ok"),
+            &MicroTex::shared().expect("init ok")
+        ));
+
+        // Dropping another renderer doesn't release it either
+        drop(MicroTex::new().expect("init ok"));
+        assert!(crate::test_control::get_inited());
+        crate::shim::set_buffer(b"<svg></svg>");
+        MicroTex::shared()
+            .expect("init ok")
+            .render("x", &RenderConfig::default())
+            .expect("render ok");
+    }
+
+    #[test]
+    fn test_render_batch_shares_padding() {
+        let _g = crate::shim::lock_test();
+        crate::shim::set_init_succeed(true);
+        crate::shim::set_parse_succeed(true);
+        crate::shim::set_return_empty(false);
+        crate::shim::set_buffer(
+            br#"<svg width="40" height="20" viewBox="0 0 40 20"><path d="M 0 0 L 40 20"/></svg>"#,
+        );
+
+        let m = MicroTex::new().expect("init ok");
+        let options = BatchOptions {
+            padding: Some((5.0, 0.0, 5.0, 0.0)),
+            ..Default::default()
+        };
+        let mut items = vec![
+            BatchItem::new("x", RenderConfig::default()),
+            BatchItem::new(
+                "y",
+                RenderConfig {
+                    dpi: 1440,
+                    ..Default::default()
+                },
+            ),
+            BatchItem::new("z", RenderConfig::default()),
+        ];
+        items[2].overrides.padding = Some((0.0, 0.0, 0.0, 0.0));
+
+        let results = m.render_batch(&items, &options);
+        assert_eq!(results.len(), 3);
+        for (item, result) in items.iter().zip(&results) {
+            let svg = result.as_ref().expect("render ok");
+            let unpadded = m.render(&item.latex, &item.config).expect("render ok");
+            let height =
+                |svg: &str| -> f32 { svg_root_attribute(svg, "height").unwrap().parse().unwrap() };
+            let expected = if item.overrides.padding.is_some() {
+                0.0
+            } else {
+                10.0
+            };
+            assert_eq!(height(svg) - height(&unpadded), expected);
+            assert!(svg.contains(&format!(r#"data-dpi="{}""#, item.config.dpi)));
+        }
+    }
+
+    #[test]
+    fn test_normalize_math_delimiters() {
+        // Display math
+        assert_eq!(normalize_math_delimiters(r"\[x^2\]"), r"\[x^2\]");
+        assert_eq!(normalize_math_delimiters(" $$ x^2 $$\n"), r"\[x^2\]");
+        // Inline math
+        assert_eq!(normalize_math_delimiters("$x^2$"), "$x^2$");
+        assert_eq!(normalize_math_delimiters(r"\( x^2 \)"), "$x^2$");
+        // Bare formulas default to display math
+        assert_eq!(normalize_math_delimiters(" x^2 "), r"\[x^2\]");
+        assert_eq!(normalize_math_delimiters("$a$ and $b$"), r"\[$a$ and $b$\]");
+        assert_eq!(normalize_math_delimiters("$"), r"\[$\]");
+    }
+
+    #[test]
+    fn test_render_session_collects_diagnostics() {
+        let _g = crate::shim::lock_test();
+        crate::shim::set_init_succeed(true);
+        crate::shim::set_parse_succeed(true);
+        crate::shim::set_return_empty(false);
+        crate::shim::set_buffer(
+            br#"{"svg": "<svg><path d=\"M 0 0 L 1 1\"/></svg>", "metrics": {"width": 100, "height": 50, "depth": 10, "ascent": 40}}"#,
+        );
+
+        let m = MicroTex::new().expect("init ok");
+        let config = RenderConfig::default();
+        let mut session = RenderSession::new(&m);
+        assert!(session.render("x", &config).is_ok());
+        assert!(session.render(" ", &config).is_err());
+        crate::shim::set_buffer(
+            br#"{"svg": "<svg></svg>", "metrics": {"width": 0, "height": 0, "depth": 0, "ascent": 0}}"#,
+        );
+        assert!(session.render("y", &config).is_ok());
+
+        let diagnostics = session.into_diagnostics();
+        let sources: Vec<&str> = diagnostics.iter().map(|d| d.source.as_str()).collect();
+        assert_eq!(sources, ["x", " ", "y"]);
+
+        assert!(diagnostics[0].is_success());
+        assert!(diagnostics[0].warnings.is_empty());
+        assert_eq!(diagnostics[0].metrics.as_ref().unwrap().width, 100);
+
+        assert!(!diagnostics[1].is_success());
+        assert_eq!(
+            diagnostics[1].error.as_deref(),
+            Some(RenderError::EmptyInput.to_string().as_str())
+        );
+        assert!(diagnostics[1].metrics.is_none());
+        assert!(diagnostics[1].to_string().starts_with(r#"error " ": "#));
+
+        assert!(diagnostics[2].is_success());
+        assert_eq!(diagnostics[2].warnings, ["output contains no glyph"]);
+        assert!(diagnostics[2]
+            .to_string()
+            .contains("\n  warning: output contains no glyph"));
+    }
+
+    #[test]
+    fn test_render_batch_in_order() {
+        let _g = crate::shim::lock_test();
+        crate::shim::set_init_succeed(true);
+        crate::shim::set_parse_succeed(true);
+        crate::shim::set_return_empty(false);
+        crate::shim::set_buffer(br#"<svg width="40" height="20" viewBox="0 0 40 20"></svg>"#);
+
+        let m = MicroTex::new().expect("init ok");
+        let items: Vec<BatchItem> = [("a", 360), ("b", 720), ("c", 1440)]
+            .into_iter()
+            .map(|(latex, dpi)| {
+                (
+                    latex,
+                    RenderConfig {
+                        dpi,
+                        ..Default::default()
+                    },
+                )
+                    .into()
+            })
+            .collect();
+
+        let results = m.render_batch(&items, &BatchOptions::default());
+        assert_eq!(results.len(), 3);
+        for (item, result) in items.iter().zip(results) {
+            let svg = result.expect("render ok");
+            assert_eq!(svg, m.render(&item.latex, &item.config).expect("render ok"));
+            assert!(svg.contains(&format!(r#"data-dpi="{}""#, item.config.dpi)));
+        }
+    }
+
+    #[test]
+    fn test_enforce_min_stroke_width() {
+        let stroke_width = |svg: &str| -> f32 {
+            let start = svg.find(r#"stroke-width=""#).unwrap() + r#"stroke-width=""#.len();
+            svg[start..start + svg[start..].find('"').unwrap()]
+                .parse()
+                .unwrap()
+        };
+
+        // stroke-width="66" under matrix(0.02, ...) is 1.32 px wide
+        assert_eq!(stroke_width(COMPLEXE_SVG), 66.0);
+        let widened = enforce_min_stroke_width(COMPLEXE_SVG, 2.0);
+        assert!((stroke_width(&widened) * 0.02 - 2.0).abs() < 1e-4);
+        assert_eq!(count_svg_paths(&widened), count_svg_paths(COMPLEXE_SVG));
+        // Wide enough strokes are kept
+        let kept = enforce_min_stroke_width(COMPLEXE_SVG, 1.0);
+        assert_eq!(stroke_width(&kept), 66.0);
+
+        // Ancestor transforms and the viewBox scale count too
+        let svg = r#"<svg width="20" height="20" viewBox="0 0 10 10"><g transform="scale(0.5)"><path stroke="black" stroke-width="1" d="M 0 0 L 10 0"/></g></svg>"#;
+        assert_eq!(stroke_width(&enforce_min_stroke_width(svg, 1.0)), 1.0);
+        assert_eq!(stroke_width(&enforce_min_stroke_width(svg, 2.0)), 2.0);
+
+        let config = RenderConfig {
+            min_stroke_width_px: Some(0.0),
+            ..Default::default()
+        };
+        assert_eq!(
+            config.validate(),
+            Err(ConfigError::InvalidMinStrokeWidth(0.0))
+        );
+    }
+
+    #[test]
+    fn test_render_from_threads() {
+        let _g = crate::shim::lock_test();
+        crate::shim::set_init_succeed(true);
+        crate::shim::set_parse_succeed(true);
+        crate::shim::set_return_empty(false);
+        crate::shim::set_buffer(br#"<svg width="40" height="20" viewBox="0 0 40 20"></svg>"#);
+
+        let m = std::sync::Arc::new(MicroTex::new().expect("init ok"));
+        let handles: Vec<_> = (1..=8)
+            .map(|i| {
+                let m = std::sync::Arc::clone(&m);
+                std::thread::spawn(move || {
+                    let config = RenderConfig {
+                        dpi: 100 * i,
+                        bold: i % 2 == 0,
+                        ..Default::default()
+                    };
+                    (0..20)
+                        .map(|_| m.render("x", &config).expect("render ok"))
+                        .all(|svg| svg.contains(&format!(r#"data-dpi="{}""#, 100 * i)))
+                })
+            })
+            .collect();
+        for handle in handles {
+            assert!(handle.join().expect("no panic"));
+        }
+    }
+
+    #[test]
+    fn test_detect_missing_glyph_boxes() {
+        let svg = r#"<svg width="40" height="20" viewBox="0 0 40 20">
+<g fill="rgb(0%,0%,0%)">
+<path d="M 1 10 C 1 4 9 4 9 10 C 9 16 1 16 1 10 Z M 3 10 L 7 10 L 7 11 L 3 11 Z"/>
+<path d="M 10 10 L 20 10 L 20 11 L 10 11 Z"/>
+</g>
+<g fill="rgb(100%,0%,0%)">
+<path d="M 22.5 2 L 30.5 2 L 30.5 14 L 22.5 14 Z M 23.5 3 L 23.5 13 L 29.5 13 L 29.5 3 Z"/>
+</g>
+<path fill="none" stroke="black" d="M 32 2 L 38 2 L 38 14 L 32 14 Z M 31 1 L 39 1 L 39 15 L 31 15 L 31 1 Z"/>
+<path d="M 0 0 L 8 0 L 8 8 L 0 8 Z M 4 4 L 12 4 L 12 12 L 4 12 Z"/>
+<path d="M 0 0 L 8 0 L 8 8 L 0 8 Z M 1 1 L 1 7 L 7 7 L 7 1 Z M 2 2 L 2 3 L 3 3 Z"/>
+</svg>"#;
+        // The curved glyph, the rule and the overlapping or three-part
+        // outlines are not placeholders; nested rectangles are, in any order
+        assert_eq!(detect_missing_glyph_boxes(svg), vec![2, 3]);
+        assert!(detect_missing_glyph_boxes(r#"<svg><path d="M 0 0 L 1 1"/></svg>"#).is_empty());
+    }
+
+    #[test]
+    fn test_render_measured_borrowed() {
+        let _g = crate::shim::lock_test();
+        crate::shim::set_init_succeed(true);
+        crate::shim::set_parse_succeed(true);
+        crate::shim::set_return_empty(false);
+        crate::shim::set_buffer(br#"<svg width="40" height="20"></svg>"#);
+
+        let m = MicroTex::new().expect("init ok");
+        let svg = m
+            .render_measured_borrowed(r"\[x^2\]", &RenderConfig::default())
+            .expect("render ok");
+        assert_eq!(svg.as_str(), r#"<svg width="40" height="20"></svg>"#);
+        assert!(svg.starts_with("<svg"));
+
+        let metrics = svg.metrics();
+        assert_eq!(
+            (metrics.width, metrics.height, metrics.depth, metrics.ascent),
+            (40, 20, 4, 16)
+        );
+        assert_eq!(metrics.math_axis_height, 5);
+
+        let result = svg.into_render_result();
+        assert_eq!(result.svg, r#"<svg width="40" height="20"></svg>"#);
+        assert_eq!(result.metrics.height, 20);
+
+        crate::shim::set_buffer(&[b'<', 0xff, b'>']);
+        assert!(matches!(
+            m.render_measured_borrowed("x", &RenderConfig::default()),
+            Err(RenderError::InvalidUtf8(_))
+        ));
+
+        crate::shim::set_return_empty(true);
+        assert!(matches!(
+            m.render_measured_borrowed("x", &RenderConfig::default()),
+            Err(RenderError::EmptyOutput)
+        ));
+        crate::shim::set_return_empty(false);
+    }
+
+    #[test]
+    fn test_borrowed_svg_drop_takes_ffi_lock() {
+        let _g = crate::shim::lock_test();
+        crate::shim::set_init_succeed(true);
+        crate::shim::set_parse_succeed(true);
+        crate::shim::set_return_empty(false);
+        crate::shim::set_buffer(b"<svg></svg>");
+
+        let m = MicroTex::new().expect("init ok");
+        let live = crate::test_control::live_buffers();
+        let svg = m
+            .render_measured_borrowed("x", &RenderConfig::default())
+            .expect("render ok");
+        assert_eq!(crate::test_control::live_buffers(), live + 1);
+
+        // The buffer is not freed while another call into MicroTeX runs
+        let ffi = lock_ffi();
+        let dropper = std::thread::spawn(move || drop(svg));
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        assert_eq!(crate::test_control::live_buffers(), live + 1);
+        drop(ffi);
+        dropper.join().expect("drop ok");
+        assert_eq!(crate::test_control::live_buffers(), live);
+    }
+
+    #[test]
+    fn test_color_argb_round_trip() {
+        let color = Color::from_argb(0xff112233);
+        assert_eq!(color, Color::rgb(0x11, 0x22, 0x33));
+        assert_eq!(color.to_argb(), 0xff112233);
+        assert_eq!(Color::rgba(0x11, 0x22, 0x33, 0x44).to_argb(), 0x44112233);
+        assert_eq!(u32::from(Color::from(0x00abcdef_u32)), 0x00abcdef);
+
+        let config = RenderConfig {
+            text_color: 0xff0000ffu32.into(),
+            ..Default::default()
+        };
+        assert_eq!(config.text_color, Color::rgb(0, 0, 0xff));
+        assert_eq!(config.text_color_argb(), 0xff0000ff);
+        assert_eq!(RenderConfig::default().text_color_argb(), 0xff000000);
+    }
+
+    #[test]
+    fn test_render_forwards_background_color() {
+        let _g = crate::shim::lock_test();
+        crate::shim::set_init_succeed(true);
+        crate::shim::set_parse_succeed(true);
+        crate::shim::set_return_empty(false);
+        crate::shim::set_buffer(b"<svg>ok</svg>");
+
+        assert_eq!(RenderConfig::default().background_color, Color::TRANSPARENT);
+        let m = MicroTex::new().expect("init ok");
+        let mut config = RenderConfig {
+            background_color: Color::rgb(0xf0, 0xe0, 0xd0),
+            ..Default::default()
+        };
+        m.render("x", &config).expect("render ok");
+        assert_eq!(crate::test_control::get_background(), None);
+
+        config.has_background = true;
+        assert_eq!(config.background_color_argb(), 0xfff0e0d0);
+        m.render("x", &config).expect("render ok");
+        assert_eq!(crate::test_control::get_background(), Some(0xfff0e0d0));
+    }
+
+    #[test]
+    fn test_render_config_serde_round_trip() {
+        let config = RenderConfig::default();
+        let json = serde_json::to_string(&config).expect("serialize");
+        let parsed: RenderConfig = serde_json::from_str(&json).expect("deserialize");
+        assert_eq!(format!("{:?}", parsed), format!("{:?}", config));
+
+        let config = RenderConfig {
+            dpi: 1440,
+            line_ending: LineEnding::CrLf,
+            limits_mode: LimitsMode::Never,
+            macros: vec![("RR".to_string(), r"\mathbb{R}".to_string())],
+            max_output_dimensions: Some((800, 600)),
+            ..Default::default()
+        };
+        let json = serde_json::to_string(&config).expect("serialize");
+        let parsed: RenderConfig = serde_json::from_str(&json).expect("deserialize");
+        assert_eq!(format!("{:?}", parsed), format!("{:?}", config));
+    }
+
+    #[test]
+    fn test_render_config_deserialize_missing_fields() {
+        // As saved before `enable_formula_numbering` and later fields existed
+        let json = r#"{"dpi": 300, "line_width": 12.0, "text_color": 4278190335,
+            "background_color": 4293976272}"#;
+        let config: RenderConfig = serde_json::from_str(json).expect("deserialize");
+        assert_eq!(config.dpi, 300);
+        assert_eq!(config.line_width, 12.0);
+        assert_eq!(config.text_color, 0xff0000ff);
+        assert_eq!(config.background_color, Color::rgb(0xf0, 0xe0, 0xd0));
+        assert!(!config.enable_formula_numbering);
+        assert_eq!(config.line_height, RenderConfig::default().line_height);
+    }
+
+    #[test]
+    fn test_render_result_serde_shape() {
+        let result = RenderResult::new(
+            r#"<svg><path d="M 1 2 L 3 4"/></svg>"#.to_string(),
+            RenderMetrics::new(100, 50, 10, 40),
+        );
+        let json = serde_json::to_value(&result).expect("serialize");
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "svg": r#"<svg><path d="M 1 2 L 3 4"/></svg>"#,
+                "metrics": {
+                    "width": 100,
+                    "height": 50,
+                    "depth": 10,
+                    "ascent": 40,
+                    "math_axis_height": 0
+                },
+                "key_char_metrics": null,
+                "precise_bounds": [1.0, 2.0, 3.0, 4.0],
+                "rendered_glyph_count": 1
+            })
+        );
+        let parsed: RenderResult = serde_json::from_value(json).expect("deserialize");
+        assert_eq!(format!("{:?}", parsed), format!("{:?}", result));
+
+        let key_chars = KeyCharMetrics::from_json(
+            r#"{"key_char_heights": [10, 12], "key_char_count": 2, "average_char_height": 11.0, "max_char_height": 12, "min_char_height": 10, "box_tree_height": 40.0}"#,
+        )
+        .expect("key char metrics");
+        let result = RenderResult::with_key_char_metrics(
+            result.svg.clone(),
+            result.metrics.clone(),
+            key_chars,
+        );
+        let json = serde_json::to_value(&result).expect("serialize");
+        assert_eq!(
+            json["key_char_metrics"]["key_char_heights"],
+            serde_json::json!([10, 12])
+        );
+        assert_eq!(json["key_char_metrics"]["key_char_count"], 2);
+        assert!(json["metrics"].get("aspect_ratio").is_none());
+    }
+
+    #[test]
+    fn test_substitute_math_alphabets() {
+        let all = |_| true;
+        assert_eq!(
+            substitute_math_alphabets(r"f: \mathbb{R}^2 \to \mathbb{R}", all),
+            "f: {ℝ}^2 \\to {ℝ}"
+        );
+        assert_eq!(
+            substitute_math_alphabets(
+                r"\mathcal{L} + \mathscr{A} + \mathfrak{g} + \mathbb{1}",
+                all
+            ),
+            "{ℒ} + {𝒜} + {𝔤} + {𝟙}"
+        );
+        // Not a single letter, unknown command, escaped backslash
+        for latex in [
+            r"\mathbb{AB}",
+            r"\mathbb R",
+            r"\mathrm{R}",
+            r"\\mathbb{R}",
+            r"\mathcal{1}",
+        ] {
+            assert_eq!(substitute_math_alphabets(latex, all), latex);
+        }
+        // Fallback: the font lacks the glyph
+        assert_eq!(
+            substitute_math_alphabets(r"\mathbb{R} \mathbb{A}", |c| c == 'ℝ'),
+            r"{ℝ} \mathbb{A}"
+        );
+    }
+
+    #[test]
+    fn test_highlight_diff() {
+        // Changed tokens are wrapped, runs of added atoms together
+        assert_eq!(
+            highlight_diff(r"\[a + b\]", r"\[a + c\]", false),
+            r"\[a + \textcolor{green}{c}\]"
+        );
+        assert_eq!(
+            highlight_diff(r"\[x\]", r"\[x + \alpha y\]", false),
+            r"\[x \textcolor{green}{+ \alpha y}\]"
+        );
+        // Scripts are braced, structural tokens are not wrapped
+        assert_eq!(
+            highlight_diff(r"\[x^2\]", r"\[x^3\]", false),
+            r"\[x^{\textcolor{green}{3}}\]"
+        );
+        assert_eq!(
+            highlight_diff(r"\[a\]", r"\[\frac{a}{b}\]", false),
+            r"\[\frac{a}{\textcolor{green}{b}}\]"
+        );
+        assert_eq!(
+            highlight_diff(r"\[a\]", r"\[\left( a \right)\]", false),
+            r"\[\left( a \right)\]"
+        );
+        assert_eq!(
+            highlight_diff(r"\[\text{old}\]", r"\[\text{new}\]", false),
+            r"\[\textcolor{green}{\text{new}}\]"
+        );
+        assert_eq!(
+            highlight_diff(
+                r"\begin{aligned}a\end{aligned}",
+                r"\begin{aligned}b\end{aligned}",
+                false
+            ),
+            r"\begin{aligned}\textcolor{green}{b}\end{aligned}"
+        );
+        // Identical sources are unchanged
+        assert_eq!(highlight_diff(r"\[x^2\]", r"\[x^2\]", true), r"\[x^2\]");
+    }
+
+    #[test]
+    fn test_diff_token_changes_caps_table() {
+        let old: Vec<(usize, &str)> = (0..3000).map(|i| (i, "a")).collect();
+        let new: Vec<(usize, &str)> = (0..3000).map(|i| (i, "a")).collect();
+        let (removed, added) = diff_token_changes(&old, &new);
+        assert!(removed.iter().all(|&changed| changed));
+        assert!(added.iter().all(|&changed| changed));
+
+        let (removed, added) = diff_token_changes(&old[..10], &new[..10]);
+        assert!(!removed.iter().any(|&changed| changed));
+        assert!(!added.iter().any(|&changed| changed));
+    }
+
+    #[test]
+    fn test_highlight_diff_removed() {
+        assert_eq!(
+            highlight_diff(r"\[a + b\]", r"\[a + c\]", true),
+            r"\[a + \textcolor{red}{\cancel{b}}\textcolor{green}{c}\]"
+        );
+        assert_eq!(
+            highlight_diff(r"\[a + b\]", r"\[a\]", true),
+            r"\[a\textcolor{red}{\cancel{+ b}}\]"
+        );
+        // Not inserted between `^` and its script
+        assert_eq!(
+            highlight_diff(r"\[x^2 y\]", r"\[x^3 y\]", true),
+            r"\[x^{\textcolor{green}{3}} \textcolor{red}{\cancel{2}}y\]"
+        );
+    }
+
+    #[test]
+    fn test_render_diff() {
+        let _g = crate::shim::lock_test();
+        crate::shim::set_init_succeed(true);
+        crate::shim::set_parse_succeed(true);
+        crate::shim::set_return_empty(false);
+        crate::shim::set_buffer(br#"<svg width="10" height="10"></svg>"#);
+
+        let m = MicroTex::new().expect("init ok");
+        let config = RenderConfig::default();
+        assert!(m.render_diff(r"\[a + b\]", r"\[a + c\]", &config).is_ok());
+        assert!(matches!(
+            m.render_diff(r"\[a\]", "  ", &config),
+            Err(RenderError::EmptyInput)
+        ));
+        // The limits apply to both sources before they are diffed
+        let limited = RenderConfig {
+            max_source_len: Some(8),
+            ..RenderConfig::default()
+        };
+        assert!(matches!(
+            m.render_diff(r"\[a + b + c\]", r"\[a\]", &limited),
+            Err(RenderError::SourceTooLong { .. })
+        ));
+    }
+
+    #[test]
+    fn test_mirror_delimiters() {
+        assert_eq!(mirror_delimiters("f(x) + [a, b]"), "f)x( + ]a, b[");
+        assert_eq!(
+            mirror_delimiters(r"\left( \frac{1}{2} \right] \bigl\{ x \bigr\}"),
+            r"\left) \frac{1}{2} \right[ \bigl\} x \bigr\{"
+        );
+        assert_eq!(
+            mirror_delimiters(r"\langle u, v \rangle \lfloor x \rceil \lbrace"),
+            r"\rangle u, v \langle \rfloor x \lceil \rbrace"
+        );
+        // Display delimiters, optional arguments and groups are kept
+        assert_eq!(
+            mirror_delimiters(r"\[\sqrt[3]{(x)} \\[2pt] \left[x\right)\]"),
+            r"\[\sqrt[3]{)x(} \\[2pt] \left]x\right(\]"
+        );
+        assert_eq!(
+            mirror_delimiters(r"a < b \leftarrow c"),
+            r"a < b \leftarrow c"
+        );
+        // So is the optional argument of an environment
+        assert_eq!(
+            mirror_delimiters(
+                r"\begin{aligned}[t] (a) \end{aligned} \begin{array}[b]{c}[x\end{array}"
+            ),
+            r"\begin{aligned}[t] )a( \end{aligned} \begin{array}[b]{c}]x\end{array}"
+        );
+    }
+
+    #[test]
+    fn test_render_mirrors_delimiters() {
+        let _g = crate::shim::lock_test();
+        crate::shim::set_init_succeed(true);
+        crate::shim::set_parse_succeed(true);
+        crate::shim::set_return_empty(false);
+        crate::shim::set_buffer(br#"<svg width="10" height="10"></svg>"#);
+
+        // Only the source is mirrored here; that MicroTeX lays the swapped
+        // delimiters out like the originals is checked by
+        // c++/mini_tests/test_mirrored_delimiters.cpp
+        let m = MicroTex::new().expect("init ok");
+        let config = RenderConfig {
+            mirror_delimiters: true,
+            ..Default::default()
+        };
+        assert_eq!(m.expand_source(r"\[(a+b)\]", &config), r"\[)a+b(\]");
+        assert!(m.render(r"\[(a+b)\]", &config).is_ok());
+        assert_eq!(
+            m.expand_source(r"\[(a+b)\]", &RenderConfig::default()),
+            r"\[(a+b)\]"
+        );
+    }
+
+    #[test]
+    fn test_render_substitutes_math_alphabets() {
+        let _g = crate::shim::lock_test();
+        crate::shim::set_init_succeed(true);
+
+        let m = MicroTex::with_font("XITSMath-Regular.clm2").expect("init ok");
+        let config = RenderConfig {
+            unicode_math_alphabets: true,
+            ..Default::default()
+        };
+        // XITS Math has a glyph for ℝ, so no fallback box is drawn for it
+        assert_eq!(m.expand_source(r"\mathbb{R}", &config), "{ℝ}");
+        assert_eq!(
+            m.expand_source(r"\mathbb{R}", &RenderConfig::default()),
+            r"\mathbb{R}"
+        );
+    }
+
+    #[test]
+    fn test_build_info() {
+        let _g = crate::shim::lock_test();
+        crate::shim::set_init_succeed(true);
+        // Logging is not captured here, but the same information is available
+        let _m = MicroTex::new().expect("init ok");
+
+        let info = build_info();
+        assert_eq!(info.crate_version, env!("CARGO_PKG_VERSION"));
+        assert!(!info.vendored);
+        assert_eq!(info.png, cfg!(feature = "png"));
+        assert!(["pkg-config", "homebrew", "vcpkg", "unknown"].contains(&info.dependency_source));
+        assert_ne!(info.cairo_version, Some(""));
+
+        let line = info.to_string();
+        assert!(line.starts_with(&format!("microtex_rs {}, Cairo ", info.crate_version)));
+        assert!(line.contains("(system, "));
+    }
+
+    #[test]
+    fn test_with_font() {
+        let _g = crate::shim::lock_test();
+        crate::shim::set_init_succeed(true);
+
+        let renderer = MicroTex::with_font("FiraMath-Regular.clm2").expect("init ok");
+        assert!(renderer.is_math_font());
+        drop(renderer);
+
+        assert!(matches!(
+            MicroTex::with_font("NoSuchFont.clm2"),
+            Err(RenderError::InitializationFailed)
+        ));
+    }
+
+    #[test]
+    fn test_check_math_font() {
+        let math = get_embedded_clm("XITSMath-Regular.clm2").expect("XITS Math embedded");
+        assert!(check_math_font("XITSMath-Regular.clm2", math));
+
+        let text = get_embedded_clm("XITS-Regular.clm2").expect("XITS embedded");
+        assert!(!check_math_font("XITS-Regular.clm2", text));
+
+        // Without a parsable header, the file name decides
+        assert!(check_math_font("custom-math.clm2", b""));
+        assert!(!check_math_font("custom-serif.clm2", b""));
+    }
+
+    #[test]
+    fn test_embedded_font_info() {
+        let fonts = embedded_font_info();
+        let filenames: Vec<&str> = fonts.iter().map(|font| font.filename.as_str()).collect();
+        assert_eq!(filenames, available_embedded_clms());
+        assert!(fonts.iter().any(|font| font.is_math));
+
+        let xits = fonts
+            .iter()
+            .find(|font| font.filename == "XITSMath-Regular.clm2")
+            .expect("XITS Math embedded");
+        assert_eq!(xits.family.as_deref(), Some("XITS Math"));
+        assert!(xits.is_math);
+
+        // Without a parsable header, the file name decides
+        let font = FontInfo::from_clm("Custom-Math.clm2", b"");
+        assert_eq!(font.family.as_deref(), Some("Custom"));
+        assert!(font.is_math);
+    }
+
+    #[test]
+    fn test_get_embedded_clm_fuzzy() {
+        let xits = get_embedded_clm("XITSMath-Regular.clm2").expect("XITS Math embedded");
+        for name in [
+            "XITSMath-Regular.clm2",
+            "XITSMath-Regular",
+            "xitsmath-regular",
+            "XITSMATH-REGULAR.CLM2",
+        ] {
+            assert_eq!(get_embedded_clm_fuzzy(name), Some(xits), "{}", name);
+        }
+        // The strict lookup is unchanged
+        assert_eq!(get_embedded_clm("xitsmath-regular"), None);
+        assert_eq!(get_embedded_clm_fuzzy("XITSMath"), None);
+        assert_eq!(get_embedded_clm_fuzzy(""), None);
+    }
+
+    #[test]
+    fn test_get_embedded_clm() {
+        let clms = available_embedded_clms();
+        for clm_name in clms {
+            let result = get_embedded_clm(clm_name);
+            assert!(
+                result.is_some(),
+                "Failed to get embedded CLM for {}",
+                clm_name
+            );
+            let data = result.unwrap();
+            assert!(!data.is_empty(), "CLM data is empty for {}", clm_name);
+        }
+    }
+
+    // The rendering tests are commented out because MicroTeX may throw C++ exceptions
+    // that Rust cannot catch. This is a known limitation of the C bindings.
+    // Tests are best run with the C++ test suite: c++/mini_tests/test_math_svg.cpp
+    //
+    // To test rendering manually:
+    // 1. Run the C++ test: cd c++/mini_tests && ./test_math_svg
+    // 2. Or use the examples: cargo run --example simple_formula
+
+    #[test]
+    fn test_microtex_new_success() {
+        let _g = crate::shim::lock_test();
+        crate::shim::set_init_succeed(true);
+        let r = MicroTex::new();
+        assert!(r.is_ok());
+    }
+
+    #[test]
+    fn test_microtex_new_init_fail() {
+        let _g = crate::shim::lock_test();
+        crate::shim::set_init_succeed(false);
+        let r = MicroTex::new();
+        assert!(matches!(r, Err(RenderError::InitializationFailed)));
+        crate::shim::set_init_succeed(true);
+    }
+
+    #[test]
+    fn test_render_parse_fail() {
+        let _g = crate::shim::lock_test();
+        crate::shim::set_init_succeed(true);
+        crate::shim::set_parse_succeed(false);
+        let m = MicroTex::new().expect("init should succeed");
+        let r = m.render("x", &RenderConfig::default());
+        assert!(matches!(r, Err(RenderError::ParseRenderFailed(_))));
+        crate::shim::set_parse_succeed(true);
+    }
+
+    #[test]
+    fn test_parse_failure_carries_microtex_message() {
+        let _g = crate::shim::lock_test();
+        crate::shim::set_init_succeed(true);
+        crate::shim::set_parse_succeed(false);
+        crate::test_control::set_last_error("'foo' is not defined as a symbol");
+
+        let m = MicroTex::new().expect("init should succeed");
+        let err = m
+            .render("\\foo", &RenderConfig::default())
+            .expect_err("parse should fail");
+        match &err {
+            RenderError::ParseRenderFailed(message) => {
+                assert_eq!(message, "'foo' is not defined as a symbol")
+            }
+            other => panic!("unexpected error: {:?}", other),
+        }
+        assert!(err.to_string().contains("'foo' is not defined as a symbol"));
+
+        crate::test_control::set_last_error("");
+        let err = m
+            .render("\\foo", &RenderConfig::default())
+            .expect_err("parse should fail");
+        assert!(matches!(&err, RenderError::ParseRenderFailed(m) if m.is_empty()));
+        assert_eq!(err.to_string(), "failed to parse and render LaTeX source");
+        crate::shim::set_parse_succeed(true);
+    }
+
+    #[test]
+    fn test_render_empty_output() {
+        let _g = crate::shim::lock_test();
+        crate::shim::set_init_succeed(true);
+        crate::shim::set_parse_succeed(true);
+        crate::shim::set_return_empty(true);
+        let m = MicroTex::new().expect("init should succeed");
+        let r = m.render("x", &RenderConfig::default());
+        assert!(matches!(r, Err(RenderError::EmptyOutput)));
+        crate::shim::set_return_empty(false);
+    }
+
+    #[test]
+    fn test_render_invalid_utf8() {
+        let _g = crate::shim::lock_test();
+        crate::shim::set_init_succeed(true);
+        crate::shim::set_parse_succeed(true);
+        crate::shim::set_return_empty(false);
+        crate::shim::set_buffer(&[0xff, 0xff, 0xff]);
+        let m = MicroTex::new().expect("init ok");
+        let r = m.render("x", &RenderConfig::default());
+        assert!(matches!(r, Err(RenderError::InvalidUtf8(_))));
+    }
+
+    #[test]
+    fn test_render_success() {
+        let _g = crate::shim::lock_test();
+        crate::shim::set_init_succeed(true);
+        crate::shim::set_parse_succeed(true);
+        crate::shim::set_return_empty(false);
+        crate::shim::set_buffer(b"<svg>ok</svg>");
+        let m = MicroTex::new().expect("init ok");
+        let r = m.render("x", &RenderConfig::default());
+        assert!(r.is_ok());
+        assert!(r.unwrap().contains("<svg"));
+    }
+
+    #[test]
+    fn test_multiple_renders_same_instance() {
+        // This test reproduces the SIGSEGV crash when calling render() multiple times
+        // on the same MicroTex instance. The issue is related to resource cleanup
+        // or reuse of the underlying C++ MicroTeX library.
+        let _g = crate::shim::lock_test();
+        crate::shim::set_init_succeed(true);
+        crate::shim::set_parse_succeed(true);
+        crate::shim::set_return_empty(false);
+        crate::shim::set_buffer(b"<svg>result1</svg>");
+
+        let m = MicroTex::new().expect("init ok");
+
+        // First render - should succeed
+        let r1 = m.render("x^2", &RenderConfig::default());
+        assert!(r1.is_ok());
+        assert!(r1.unwrap().contains("result1"));
+
+        // Update buffer for second render
+        crate::shim::set_buffer(b"<svg>result2</svg>");
+
+        // Second render on the SAME instance - this triggers the crash
+        let r2 = m.render("y^2", &RenderConfig::default());
+        assert!(r2.is_ok());
+        assert!(r2.unwrap().contains("result2"));
+
+        // Third render - verify the issue persists with multiple calls
+        crate::shim::set_buffer(b"<svg>result3</svg>");
+        let r3 = m.render("z^2", &RenderConfig::default());
+        assert!(r3.is_ok());
+        assert!(r3.unwrap().contains("result3"));
+    }
+
+    #[test]
+    fn test_many_renders_same_instance() {
+        let _g = crate::shim::lock_test();
+        crate::shim::set_init_succeed(true);
+        crate::shim::set_parse_succeed(true);
+        crate::shim::set_return_empty(false);
+
+        let m = MicroTex::new().expect("init ok");
+        let config = RenderConfig::default();
+        let prepared = m.prepare_source("x^2").unwrap();
+        for i in 0..1000 {
+            crate::shim::set_buffer(format!("<svg>result{}</svg>", i).as_bytes());
+            let svg = match i % 3 {
+                0 => m.render("x^2", &config).unwrap(),
+                1 => m.render_prepared(&prepared, &config).unwrap(),
+                _ => m
+                    .render_measured_borrowed("x^2", &config)
+                    .unwrap()
+                    .to_string(),
+            };
+            assert!(
+                svg.contains(&format!(">result{}<", i)),
+                "render {} returned {}",
+                i,
+                svg
+            );
+        }
+        assert_eq!(m.render_count(), 1000);
+
+        // Renders failing before the parse are not counted
+        let count = m.render_count();
+        assert!(m.render("  ", &config).is_err());
+        assert_eq!(m.render_count(), count);
+    }
+
+    #[test]
+    fn test_render_metrics_to_sp() {
+        // 7227 px at 100 dpi is 72.27 in = 5222.9529 pt
+        let sp = RenderMetrics::new(7227, 100, 10, 90).to_sp(100);
+        assert_eq!(sp.width, (72.27 * 72.27 * 65536.0_f64).round() as i64);
+        // 100 px at 100 dpi is exactly 1 in = 72.27 pt = 4736286.72 sp
+        assert_eq!(sp.height, 4_736_287);
+        assert_eq!(sp.depth, 473_629);
+        assert_eq!(sp.ascent, 4_262_658);
+        assert_eq!(
+            RenderMetrics::new(0, 0, 0, 0).to_sp(720),
+            SpDimensions {
+                width: 0,
+                height: 0,
+                depth: 0,
+                ascent: 0,
+            }
+        );
+    }
+
+    #[test]
+    fn test_render_metrics_to_physical_units() {
+        let metrics = RenderMetrics::new(720, 1440, 360, 1080);
+        assert_eq!(metrics.to_points(720), (72.0, 144.0));
+        let (width, height) = metrics.to_millimeters(720);
+        assert!((width - 25.4).abs() < 1e-4);
+        assert!((height - 50.8).abs() < 1e-4);
+
+        assert_eq!(metrics.to_points(0), (0.0, 0.0));
+        assert_eq!(metrics.to_millimeters(-72), (0.0, 0.0));
+    }
+
+    #[test]
+    fn test_split_align_rows() {
+        assert_eq!(
+            split_align_rows(r"\begin{align} a &= b + c \\ d &= \frac{e}{f} \\ \end{align}"),
+            vec!["a &= b + c", r"d &= \frac{e}{f}"]
+        );
+        assert_eq!(
+            split_align_rows(r"\[\begin{align*}x &= 1 \\[2pt] y &= 2\end{align*}\]"),
+            vec!["x &= 1", "y &= 2"]
+        );
+        // Separators nested in braces or inner environments belong to the row
+        assert_eq!(
+            split_align_rows(
+                r"a &= \begin{matrix} 1 \\ 2 \end{matrix} \\ b &= \text{x \\ y} \\ c &= \{ 0 \}"
+            ),
+            vec![
+                r"a &= \begin{matrix} 1 \\ 2 \end{matrix}",
+                r"b &= \text{x \\ y}",
+                r"c &= \{ 0 \}"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_render_align_rows() {
+        let _g = crate::shim::lock_test();
+        crate::shim::set_init_succeed(true);
+        crate::shim::set_parse_succeed(true);
+        crate::shim::set_return_empty(false);
+        crate::shim::set_buffer(
+            br#"{"svg": "<svg>row</svg>", "metrics": {"width": 80, "height": 20, "depth": 5, "ascent": 15}}"#,
+        );
+
+        let m = MicroTex::new().expect("init ok");
+        let rows = m
+            .render_align_rows(
+                r"\begin{align} a &= b + c \\ d &= e \end{align}",
+                &RenderConfig::default(),
+            )
+            .expect("render ok");
+        assert_eq!(rows.len(), 2);
+        assert!(rows.iter().all(|row| row.metrics.width == 80));
+    }
+
+    #[test]
+    fn test_render_to_svg_with_metrics_success() {
+        let _g = crate::shim::lock_test();
+        crate::shim::set_init_succeed(true);
+        crate::shim::set_parse_succeed(true);
+        crate::shim::set_return_empty(false);
+
+        // Create a valid JSON response with SVG and metrics
+        let json_response = br#"{
+            "svg": "<svg>test formula</svg>",
+            "metrics": {
+                "width": 100,
+                "height": 50,
+                "depth": 10,
+                "ascent": 40
+            }
+        }"#;
+
+        crate::shim::set_buffer(json_response);
+
+        let m = MicroTex::new().expect("init ok");
+        let r = m.render_to_svg_with_metrics("x^2", &RenderConfig::default());
+
+        assert!(r.is_ok());
+        let result = r.unwrap();
+        assert!(result.svg.contains("<svg"));
+        assert_eq!(result.metrics.width, 100);
+        assert_eq!(result.metrics.height, 50);
+        assert_eq!(result.metrics.depth, 10);
+        assert_eq!(result.metrics.ascent, 40);
+        assert_eq!(result.metrics.math_axis_height, 0);
+    }
+
+    #[test]
+    fn test_render_to_html_page() {
+        let _g = crate::shim::lock_test();
+        crate::shim::set_init_succeed(true);
+        crate::shim::set_parse_succeed(true);
+        crate::shim::set_return_empty(false);
+        crate::shim::set_buffer(
+            br#"{
+            "svg": "<svg viewBox=\"0 0 100 50\"><path d=\"M 0 0 Z\"/></svg>",
+            "metrics": { "width": 100, "height": 50, "depth": 10, "ascent": 40 }
+        }"#,
+        );
+
+        let m = MicroTex::new().expect("init ok");
+        let html = m
+            .render_to_html_page("x^2", &RenderConfig::default(), "x & y")
+            .expect("render ok");
+        assert!(html.starts_with("<!DOCTYPE html>\n"));
+        assert!(html.contains(r#"<meta charset="utf-8">"#));
+        assert!(html.contains("<title>x &amp; y</title>"));
+        assert!(html.contains(r#"<figure><svg viewBox="0 0 100 50""#));
+        assert!(html.contains(r#"aria-label="x &amp; y""#));
+        assert!(html.ends_with("</html>\n"));
+    }
+
+    #[test]
+    fn test_render_to_svg_with_metrics_math_axis_height() {
+        let _g = crate::shim::lock_test();
+        crate::shim::set_init_succeed(true);
+        crate::shim::set_parse_succeed(true);
+        crate::shim::set_return_empty(false);
+        crate::shim::set_buffer(
+            br#"{"svg": "<svg>x</svg>", "metrics": {"width": 100, "height": 50, "depth": 10, "ascent": 40, "math_axis_height": 12}}"#,
+        );
+
+        let m = MicroTex::new().expect("init ok");
+        let result = m
+            .render_to_svg_with_metrics("a = b", &RenderConfig::default())
+            .unwrap();
+
+        assert_eq!(result.metrics.math_axis_height, 12);
+        assert_eq!(result.metrics.ascent, 40);
+    }
+
+    #[test]
+    fn test_render_with_options() {
+        let _g = crate::shim::lock_test();
+        crate::shim::set_init_succeed(true);
+        crate::shim::set_parse_succeed(true);
+        crate::shim::set_return_empty(false);
+
+        let m = MicroTex::new().expect("init ok");
+        let config = RenderConfig::default();
+        let render = |metrics, key_char_metrics| {
+            crate::test_control::take_ffi_calls();
+            let opts = RenderOptions {
+                metrics,
+                key_char_metrics,
+            };
+            let output = m.render_with_options("x", &config, opts).unwrap();
+            (output, crate::test_control::take_ffi_calls())
+        };
+
+        crate::shim::set_buffer(
+            br#"<svg width="10" height="10"><path d="M 0 0 L 10 10 Z"/></svg>"#,
+        );
+        let (output, calls) = render(false, false);
+        assert!(output.svg.contains("<svg"));
+        assert!(output.metrics.is_none() && output.key_char_metrics.is_none());
+        assert_eq!(calls, ["render_to_svg"]);
+        assert_eq!(output.svg, m.render("x", &config).unwrap());
+
+        let (output, calls) = render(false, true);
+        assert!(output.svg.contains("<svg"));
+        assert!(output.metrics.is_none());
+        assert_eq!(calls, ["render_to_svg", "get_key_char_metrics"]);
+
+        crate::shim::set_buffer(
+            br#"{"svg": "<svg>x</svg>", "metrics": {"width": 100, "height": 50, "depth": 10, "ascent": 40}}"#,
+        );
+        let (output, calls) = render(true, false);
+        assert_eq!(output.metrics.map(|metrics| metrics.width), Some(100));
+        assert!(output.key_char_metrics.is_none());
+        assert_eq!(calls, ["render_to_svg_with_metrics"]);
+
+        let (output, calls) = render(true, true);
+        assert_eq!(output.metrics.map(|metrics| metrics.width), Some(100));
+        assert_eq!(
+            calls,
+            ["render_to_svg_with_metrics", "get_key_char_metrics"]
+        );
+
+        // The metrics path still reads the key character metrics
+        crate::test_control::take_ffi_calls();
+        m.render_to_svg_with_metrics("x", &config).unwrap();
+        assert_eq!(
+            crate::test_control::take_ffi_calls(),
+            ["render_to_svg_with_metrics", "get_key_char_metrics"]
+        );
+    }
+
+    #[test]
+    fn test_render_to_svg_with_metrics_pad_for_depth() {
+        let _g = crate::shim::lock_test();
+        crate::shim::set_init_succeed(true);
+        crate::shim::set_parse_succeed(true);
+        crate::shim::set_return_empty(false);
+
+        let m = MicroTex::new().expect("init ok");
+        let config = RenderConfig {
+            pad_for_depth: true,
+            ..Default::default()
+        };
+
+        // Deep formula: 20 above the baseline, 30 below -> pad 10 at the top
+        crate::shim::set_buffer(
+            br#"{"svg": "<svg width=\"100\" height=\"50\" viewBox=\"0 0 100 50\"><path d=\"M 0 0 L 10 50 Z\"/></svg>",
+                "metrics": {"width": 100, "height": 50, "depth": 30, "ascent": 20}}"#,
+        );
+        let result = m.render_to_svg_with_metrics("x", &config).unwrap();
+        assert!(result.svg.contains(r#"height="60""#));
+        assert!(result.svg.contains(r#"viewBox="0 0 100 60""#));
+        assert!(result.svg.contains(r#"<g transform="translate(0, 10)">"#));
+
+        // Tall formula: 40 above the baseline, 10 below -> pad 30 at the bottom
+        crate::shim::set_buffer(
+            br#"{"svg": "<svg width=\"100\" height=\"50\" viewBox=\"0 0 100 50\"><path d=\"M 0 0 L 10 50 Z\"/></svg>",
+                "metrics": {"width": 100, "height": 50, "depth": 10, "ascent": 40}}"#,
+        );
+        let result = m.render_to_svg_with_metrics("x", &config).unwrap();
+        assert!(result.svg.contains(r#"height="80""#));
+        assert!(result.svg.contains(r#"viewBox="0 0 100 80""#));
+        assert!(!result.svg.contains("translate(0, 10)"));
+    }
+
+    #[test]
+    fn test_pad_for_depth_after_adjustment() {
+        let _g = crate::shim::lock_test();
+        crate::shim::set_init_succeed(true);
+        crate::shim::set_parse_succeed(true);
+        crate::shim::set_return_empty(false);
+
+        // The content overflows both edges, so the adjustment grows the SVG
+        // and moves the content down; the baseline starts at 40
+        crate::shim::set_buffer(
+            br#"{"svg": "<svg width=\"100\" height=\"50\" viewBox=\"0 0 100 50\"><path d=\"M 0 -6 L 10 57 Z\"/></svg>",
+                "metrics": {"width": 100, "height": 50, "depth": 10, "ascent": 40}}"#,
+        );
+        let m = MicroTex::new().expect("init ok");
+        let config = RenderConfig {
+            pad_for_depth: true,
+            ..Default::default()
+        };
+        let svg = m.render_to_svg_with_metrics("x", &config).unwrap().svg;
+
+        let height = svg_root_attribute(&svg, "height")
+            .as_deref()
+            .and_then(split_svg_length)
+            .unwrap()
+            .0;
+        let offset: f32 = svg
+            .split("translate(0, ")
+            .skip(1)
+            .map(|rest| rest[..rest.find(')').unwrap()].parse::<f32>().unwrap())
+            .sum();
+        assert_eq!((height, offset), (92.0, 6.0));
+        assert!((40.0 + offset - height / 2.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_render_to_symbol() {
+        let _g = crate::shim::lock_test();
+        crate::shim::set_init_succeed(true);
+        crate::shim::set_parse_succeed(true);
+        crate::shim::set_return_empty(false);
+        crate::shim::set_buffer(
+            br#"<svg xmlns="http://www.w3.org/2000/svg" width="120" height="40" viewBox="0 0 120 40"><path d="M 1 2 L 3 40 Z"/></svg>"#,
+        );
+
+        let m = MicroTex::new().expect("init ok");
+        let symbol = m
+            .render_to_symbol("x^2", &RenderConfig::default(), "formula-1")
+            .unwrap();
+
+        assert!(symbol.starts_with(r#"<symbol id="formula-1" viewBox="0 0 120 40">"#));
+        assert!(symbol.ends_with("</symbol>"));
+        assert!(symbol.contains(r#"<path d="M 1 2 L 3 40 Z"/>"#));
+        assert!(!symbol.contains("<svg"));
+
+        assert_eq!(
+            symbol_use_reference(&symbol).unwrap(),
+            r##"<use href="#formula-1" width="120" height="40"/>"##
+        );
+    }
+
+    #[test]
+    fn test_render_to_svg_with_metrics_precise_bounds() {
+        let _g = crate::shim::lock_test();
+        crate::shim::set_init_succeed(true);
+        crate::shim::set_parse_succeed(true);
+        crate::shim::set_return_empty(false);
+        crate::shim::set_buffer(
+            br#"{"svg": "<svg width=\"100\" height=\"50\" viewBox=\"0 0 100 50\"><path d=\"M 0.25 1.5 L 99.75 49.5 Z\"/></svg>",
+                "metrics": {"width": 100, "height": 50, "depth": 10, "ascent": 40}}"#,
+        );
+
+        let m = MicroTex::new().expect("init ok");
+        let result = m
+            .render_to_svg_with_metrics("x", &RenderConfig::default())
+            .unwrap();
+
+        // The centering group (translate(0, 0.25)) is taken into account
+        let (min_x, min_y, max_x, max_y) = result.precise_bounds.expect("bounds");
+        assert_eq!((min_x, min_y, max_x, max_y), (0.25, 1.75, 99.75, 49.75));
+
+        // Sub-pixel precision the integer metrics can't express
+        assert!((max_x - min_x).fract() > 0.0);
+        assert_ne!(max_x - min_x, result.metrics.width as f32);
+    }
+
+    #[cfg(feature = "png")]
+    #[test]
+    fn test_render_with_thumbnail() {
+        let _g = crate::shim::lock_test();
+        crate::shim::set_init_succeed(true);
+        crate::shim::set_parse_succeed(true);
+        crate::shim::set_return_empty(false);
+        crate::shim::set_buffer(
+            br#"<svg xmlns="http://www.w3.org/2000/svg" width="200" height="50" viewBox="0 0 200 50"><path d="M 0 0 L 200 50 L 0 50 Z"/></svg>"#,
+        );
+
+        let m = MicroTex::new().expect("init ok");
+        let (svg, png) = m
+            .render_with_thumbnail("x", &RenderConfig::default(), 64)
+            .unwrap();
+
+        assert!(svg.contains("<svg"));
+        assert!(png.starts_with(b"\x89PNG\r\n\x1a\n"));
+        // IHDR width/height: longer side scaled down to 64, ratio kept
+        assert_eq!(u32::from_be_bytes(png[16..20].try_into().unwrap()), 64);
+        assert_eq!(u32::from_be_bytes(png[20..24].try_into().unwrap()), 16);
+
+        assert!(matches!(
+            m.render_with_thumbnail("x", &RenderConfig::default(), 0),
+            Err(RenderError::Rasterization(_))
+        ));
+    }
+
+    #[cfg(feature = "png")]
+    #[test]
+    fn test_render_to_png_honors_dpi() {
+        let _g = crate::shim::lock_test();
+        crate::shim::set_init_succeed(true);
+        crate::shim::set_parse_succeed(true);
+        crate::shim::set_return_empty(false);
+        crate::shim::set_buffer(
+            br#"<svg xmlns="http://www.w3.org/2000/svg" width="200" height="50" viewBox="0 0 200 50"><path d="M 0 0 L 200 50 L 0 50 Z"/></svg>"#,
+        );
+
+        let m = MicroTex::new().expect("init ok");
+        let size = |dpi| {
+            let config = RenderConfig {
+                dpi,
+                ..Default::default()
+            };
+            let png = m.render_to_png("x", &config).unwrap();
+            assert!(png.starts_with(b"\x89PNG\r\n\x1a\n"));
+            (
+                u32::from_be_bytes(png[16..20].try_into().unwrap()),
+                u32::from_be_bytes(png[20..24].try_into().unwrap()),
+            )
+        };
+
+        // 720 DPI is 7.5 times the 96 DPI of SVG pixels
+        assert_eq!(size(720), (1500, 375));
+        assert_eq!(size(1440), (3000, 750));
+    }
+
+    #[test]
+    fn test_shelf_pack() {
+        let sizes = [(30, 10), (5, 40), (20, 20), (0, 0), (50, 5), (8, 8)];
+        let padding = 2;
+        let ((width, height), positions) = shelf_pack(&sizes, padding);
+
+        let rects: Vec<(u32, u32, u32, u32)> = sizes
+            .iter()
+            .zip(&positions)
+            .map(|(&(w, h), &(x, y))| (x, y, w, h))
+            .collect();
+        for (i, &(x, y, w, h)) in rects.iter().enumerate() {
+            // Covers the item, inside the sheet and its padding
+            assert!(x >= padding && y >= padding);
+            assert!(x + w + padding <= width && y + h + padding <= height);
+            // Overlaps no other item, padding included
+            for &(x2, y2, w2, h2) in &rects[i + 1..] {
+                if w == 0 || h == 0 || w2 == 0 || h2 == 0 {
+                    continue;
+                }
+                let apart = x + w + padding <= x2
+                    || x2 + w2 + padding <= x
+                    || y + h + padding <= y2
+                    || y2 + h2 + padding <= y;
+                assert!(apart, "{:?} overlaps {:?}", (x, y, w, h), (x2, y2, w2, h2));
+            }
+        }
+        // Not a single shelf or column
+        assert!(width < 30 + 5 + 20 + 50 + 8);
+        assert!(height < 10 + 40 + 20 + 5 + 8);
+
+        assert_eq!(shelf_pack(&[], 3), ((6, 6), vec![]));
+    }
+
+    #[cfg(feature = "png")]
+    #[test]
+    fn test_render_sprite_sheet() {
+        let _g = crate::shim::lock_test();
+        crate::shim::set_init_succeed(true);
+        crate::shim::set_parse_succeed(true);
+        crate::shim::set_return_empty(false);
+        crate::shim::set_buffer(
+            br#"<svg xmlns="http://www.w3.org/2000/svg" width="20" height="10" viewBox="0 0 20 10"><rect x="5" y="2" width="10" height="4"/></svg>"#,
+        );
+
+        let m = MicroTex::new().expect("init ok");
+        let config = RenderConfig {
+            dpi: 96,
+            ..Default::default()
+        };
+        let (png, atlas) = m.render_sprite_sheet(&["a", "b"], &config, 1).unwrap();
+        assert!(png.starts_with(b"\x89PNG\r\n\x1a\n"));
+        assert_eq!(
+            (
+                u32::from_be_bytes(png[16..20].try_into().unwrap()),
+                u32::from_be_bytes(png[20..24].try_into().unwrap()),
+            ),
+            (atlas.width, atlas.height)
+        );
+
+        // Cropped to the rectangle, one sprite per item
+        let sources: Vec<&str> = atlas.sprites.iter().map(|s| s.source.as_str()).collect();
+        assert_eq!(sources, ["a", "b"]);
+        for sprite in &atlas.sprites {
+            assert!(sprite.width <= 11 && sprite.width >= 10);
+            assert!(sprite.height <= 5 && sprite.height >= 4);
+            assert!(sprite.x + sprite.width < atlas.width);
+            assert!(sprite.y + sprite.height < atlas.height);
+        }
+        let (a, b) = (&atlas.sprites[0], &atlas.sprites[1]);
+        assert!(
+            a.x + a.width < b.x
+                || b.x + b.width < a.x
+                || a.y + a.height < b.y
+                || b.y + b.height < a.y
+        );
+    }
+
+    #[cfg(feature = "png")]
+    #[test]
+    fn test_render_png_under_size() {
+        let _g = crate::shim::lock_test();
+        crate::shim::set_init_succeed(true);
+        crate::shim::set_parse_succeed(true);
+        crate::shim::set_return_empty(false);
+        crate::shim::set_buffer(
+            br#"<svg xmlns="http://www.w3.org/2000/svg" width="200" height="50" viewBox="0 0 200 50"><path d="M 0 0 L 200 50 L 0 50 Z"/></svg>"#,
+        );
+
+        let m = MicroTex::new().expect("init ok");
+        let config = RenderConfig::default();
+        let full = m.render_to_png("x", &config).unwrap();
+        let (png, dpi) = m.render_png_under_size("x", &config, full.len()).unwrap();
+        assert_eq!((png, dpi), (full.clone(), config.dpi));
+
+        let (png, dpi) = m
+            .render_png_under_size("x", &config, full.len() / 2)
+            .unwrap();
+        assert!(png.len() <= full.len() / 2);
+        assert!(dpi < config.dpi);
+
+        assert!(matches!(
+            m.render_png_under_size("x", &config, 10),
+            Err(RenderError::Rasterization(_))
+        ));
+    }
+
+    #[test]
+    fn test_highest_dpi_under_size_terminates() {
+        let mut calls = 0;
+        let encode = |dpi: i32| {
+            calls += 1;
+            Ok(vec![0; dpi as usize * 10])
+        };
+        let (png, dpi) = highest_dpi_under_size(720, 5005, encode).unwrap();
+        assert_eq!((png.len(), dpi), (5000, 500));
+        assert!(calls <= 12, "{} calls", calls);
+
+        let (_, dpi) =
+            highest_dpi_under_size(720, 7200, |dpi| Ok(vec![0; dpi as usize * 10])).unwrap();
+        assert_eq!(dpi, 720);
+
+        // Nothing fits: the search still ends
+        let r = highest_dpi_under_size(720, 5, |dpi| Ok(vec![0; dpi as usize * 10]));
+        assert!(matches!(r, Err(RenderError::Rasterization(_))));
+        let r = highest_dpi_under_size(1, 5, |dpi| Ok(vec![0; dpi as usize * 10]));
+        assert!(matches!(r, Err(RenderError::Rasterization(_))));
+    }
+
+    #[test]
+    fn test_render_to_path_line_endings() {
+        let _g = crate::shim::lock_test();
+        crate::shim::set_init_succeed(true);
+        crate::shim::set_parse_succeed(true);
+        crate::shim::set_return_empty(false);
+        crate::shim::set_buffer(b"<svg width=\"10\">\r\n<path d=\"M 0 0 Z\"/>\n</svg>\n");
+
+        let m = MicroTex::new().expect("init ok");
+        let path = std::env::temp_dir().join(format!(
+            "microtex_rs_line_endings_{}.svg",
+            std::process::id()
+        ));
+
+        // The rendered string itself only uses LF
+        let svg = m.render("x", &RenderConfig::default()).unwrap();
+        assert!(!svg.contains('\r'));
+
+        let config = RenderConfig {
+            line_ending: LineEnding::CrLf,
+            ..Default::default()
+        };
+        m.render_to_path("x", &config, &path).unwrap();
+        let written = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).ok();
+
+        assert!(written.contains("\r\n"));
+        assert_eq!(
+            written.matches('\n').count(),
+            written.matches("\r\n").count()
+        );
+    }
+
+    #[test]
+    fn test_safe_profile_rejects_untrusted_input() {
+        let _g = crate::shim::lock_test();
+        crate::shim::set_init_succeed(true);
+        crate::shim::set_parse_succeed(true);
+        crate::shim::set_return_empty(false);
+        crate::shim::set_buffer(b"<svg>safe</svg>");
+
+        let m = MicroTex::new().expect("init ok");
+        let config = RenderConfig::safe_profile();
+        assert!(config.validate().is_ok());
+
+        let oversized = "x+".repeat(4096);
+        assert!(matches!(
+            m.render(&oversized, &config),
+            Err(RenderError::SourceTooLong {
+                len: 8192,
+                max: 4096
+            })
+        ));
+
+        let nested = format!("{}x{}", "\\frac{1}{".repeat(40), "}".repeat(40));
+        assert!(matches!(
+            m.render(&nested, &config),
+            Err(RenderError::NestingTooDeep { depth: 40, max: 32 })
+        ));
+
+        assert!(matches!(
+            m.render_to_svg_with_metrics(r"\includegraphics{/etc/passwd}", &config),
+            Err(RenderError::DisallowedCommand(command)) if command == "includegraphics"
+        ));
+        // A macro cannot smuggle a disallowed command in
+        let config_with_macro = RenderConfig {
+            macros: vec![(r"\def".to_string(), r"\newcommand".to_string())],
+            ..RenderConfig::safe_profile()
+        };
+        assert!(matches!(
+            m.render(r"\def{\y}{y}", &config_with_macro),
+            Err(RenderError::DisallowedCommand(command)) if command == "newcommand"
+        ));
+
+        assert!(matches!(
+            m.render(r"\DeclareMathOperator{\x}{\x}\x", &config),
+            Err(RenderError::DisallowedCommand(command)) if command == "DeclareMathOperator"
+        ));
+
+        // `\\` followed by text is a line break, not the command
+        assert!(m.render(r"\frac{a}{b}\\newcommand", &config).is_ok());
+
+        // Event handlers added to the output are stripped
+        let config_with_handler = RenderConfig {
+            svg_attributes: vec![("onclick".to_string(), "alert(1)".to_string())],
+            ..RenderConfig::safe_profile()
+        };
+        let svg = m.render("x", &config_with_handler).expect("render ok");
+        assert!(svg.contains("safe") && !svg.contains("onclick"));
+    }
+
+    #[test]
+    fn test_render_timeout() {
+        let _g = crate::shim::lock_test();
+        crate::shim::set_init_succeed(true);
+        crate::shim::set_parse_succeed(true);
+        crate::shim::set_return_empty(false);
+        crate::shim::set_buffer(b"<svg>timed</svg>");
+
+        let m = MicroTex::new().expect("init ok");
+        let config = RenderConfig {
+            render_timeout: Some(std::time::Duration::from_millis(20)),
+            ..Default::default()
+        };
+        crate::test_control::set_parse_delay(std::time::Duration::from_millis(500));
+        assert!(matches!(
+            m.render("x", &config),
+            Err(RenderError::Timeout(timeout)) if timeout == std::time::Duration::from_millis(20)
+        ));
+        assert!(matches!(
+            m.render_to_svg_with_metrics("x", &config),
+            Err(RenderError::Timeout(_))
+        ));
+        crate::test_control::set_parse_delay(std::time::Duration::ZERO);
+
+        // Waits for the abandoned renders to release MicroTeX
+        assert!(m.render("x", &RenderConfig::default()).is_ok());
+        let config = RenderConfig {
+            render_timeout: Some(std::time::Duration::from_secs(5)),
+            ..Default::default()
+        };
+        assert!(m.render("x", &config).unwrap().contains("timed"));
+
+        let config = RenderConfig {
+            render_timeout: Some(std::time::Duration::ZERO),
+            ..Default::default()
+        };
+        assert_eq!(config.validate(), Err(ConfigError::InvalidRenderTimeout));
+    }
+
+    #[test]
+    fn test_sanitize_svg() {
+        let svg = r##"<?xml-stylesheet href="evil.css"?><svg xmlns:xlink="http://www.w3.org/1999/xlink" onload="alert(1)" ONCLICK="alert(2)">
+<script>alert(3)</script><foreignObject><div><p>text</p></div></foreignObject><html:iframe src="x"/>
+<a xlink:href=" java&#x09;script:alert(4)" href="https://example.com"><path d="M 0 0" fill="#000"/></a></svg>"##;
+        assert_eq!(
+            sanitize_svg(svg),
+            r##"<svg xmlns:xlink="http://www.w3.org/1999/xlink">
+
+<a href="https://example.com"><path d="M 0 0" fill="#000"/></a></svg>"##
+        );
+        // Clean SVGs are kept as they are
+        assert_eq!(sanitize_svg(COMPLEXE_SVG), COMPLEXE_SVG);
+        // Unchecked content after a syntax error is dropped
+        assert_eq!(sanitize_svg("<svg><path/></p><script>"), "<svg><path/>");
+        // Values XML cannot unescape, and script URLs set by animations
+        assert_eq!(
+            sanitize_svg(r#"<svg><a href="javascript&colon;alert(1)"><path/></a></svg>"#),
+            "<svg><a><path/></a></svg>"
+        );
+        assert_eq!(
+            sanitize_svg(
+                r#"<svg><a><animate attributeName="href" values="x;javascript:alert(1)"/></a></svg>"#
+            ),
+            r#"<svg><a><animate attributeName="href"/></a></svg>"#
+        );
+        assert_eq!(
+            sanitize_svg(
+                r#"<svg><a><set attributeName="href" to=" JavaScript:alert(1)"/></a></svg>"#
+            ),
+            r#"<svg><a><set attributeName="href"/></a></svg>"#
+        );
+    }
+
+    #[test]
+    fn test_latex_commands() {
+        let commands: Vec<&str> = latex_commands(r"\frac{\alpha}{2}\\\{x\} \sqrt").collect();
+        assert_eq!(commands, vec!["frac", "alpha", "sqrt"]);
+
+        let config = RenderConfig {
+            disallowed_commands: vec!["bad name".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(
+            config.validate(),
+            Err(ConfigError::InvalidCommandName("bad name".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_max_nesting_depth_continued_fraction() {
+        let _g = crate::shim::lock_test();
+        crate::shim::set_init_succeed(true);
+        crate::shim::set_parse_succeed(true);
+        crate::shim::set_return_empty(false);
+        crate::shim::set_buffer(b"<svg>deep</svg>");
+
+        // 1 + 1/(1 + 1/(1 + ...)), 50 levels deep
+        let latex = format!("{}x{}", "1+\\frac{1}{".repeat(50), "}".repeat(50));
+        assert_eq!(brace_nesting_depth(&latex), 50);
+
+        let m = MicroTex::new().expect("init ok");
+        let config = RenderConfig {
+            max_nesting_depth: Some(20),
+            ..Default::default()
+        };
+        assert!(matches!(
+            m.render(&latex, &config),
+            Err(RenderError::NestingTooDeep { depth: 50, max: 20 })
+        ));
+        assert!(matches!(
+            m.render_to_svg_with_metrics(&latex, &config),
+            Err(RenderError::NestingTooDeep { depth: 50, max: 20 })
+        ));
+
+        // Within the limit, and without a limit, it renders
+        let config = RenderConfig {
+            max_nesting_depth: Some(50),
+            ..Default::default()
+        };
+        assert!(m.render(&latex, &config).is_ok());
+        assert!(m.render(&latex, &RenderConfig::default()).is_ok());
+    }
+
+    #[test]
+    fn test_brace_nesting_depth_ignores_escaped_braces() {
+        assert_eq!(brace_nesting_depth(r"\{x\}"), 0);
+        assert_eq!(brace_nesting_depth(r"\frac{a}{\left\{b\right.}"), 1);
+        assert_eq!(brace_nesting_depth(r"x^{y^{z}}"), 2);
+        assert_eq!(brace_nesting_depth(r"}}{"), 1);
+    }
+
+    #[test]
+    fn test_render_to_svg_with_metrics_force_ascent() {
+        let _g = crate::shim::lock_test();
+        crate::shim::set_init_succeed(true);
+        crate::shim::set_parse_succeed(true);
+        crate::shim::set_return_empty(false);
+        // Natural baseline at 40 (height 50, depth 10)
+        crate::shim::set_buffer(
+            br#"{"svg": "<svg width=\"100\" height=\"50\" viewBox=\"0 0 100 50\"><path d=\"M 0 0 L 10 50 Z\"/></svg>",
+                "metrics": {"width": 100, "height": 50, "depth": 10, "ascent": 40}}"#,
+        );
+
+        let m = MicroTex::new().expect("init ok");
+
+        // Larger ascent: content moves down, depth is kept
+        let config = RenderConfig {
+            force_ascent_px: Some(45),
+            pad_for_depth: true,
+            ..Default::default()
+        };
+        let result = m.render_to_svg_with_metrics("x", &config).unwrap();
+        assert!(result.svg.contains(r#"height="55""#));
+        assert!(result.svg.contains(r#"viewBox="0 0 100 55""#));
+        assert!(result.svg.contains(r#"<g transform="translate(0, 5)">"#));
+
+        // Smaller ascent: the top is cropped
+        let config = RenderConfig {
+            force_ascent_px: Some(30),
+            ..Default::default()
+        };
+        let result = m.render_to_svg_with_metrics("x", &config).unwrap();
+        assert!(result.svg.contains(r#"height="40""#));
+        assert!(result.svg.contains(r#"<g transform="translate(0, -10)">"#));
+    }
+
+    #[test]
+    fn test_render_directory() {
+        let _g = crate::shim::lock_test();
+        crate::shim::set_init_succeed(true);
+        crate::shim::set_parse_succeed(true);
+        crate::shim::set_return_empty(false);
+        crate::shim::set_buffer(b"<svg>snippet</svg>");
+
+        let root = std::env::temp_dir().join(format!(
+            "microtex_rs_render_directory_{}",
+            std::process::id()
+        ));
+        let dir = root.join("tex");
+        let out_dir = root.join("svg");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("a.tex"), r"\[x^2\]").unwrap();
+        std::fs::write(dir.join("b.tex"), r"$E = mc^2$").unwrap();
+        std::fs::write(dir.join("notes.txt"), "not a formula").unwrap();
+
+        let m = MicroTex::new().expect("init ok");
+        let results = render_directory(&m, &dir, &out_dir, &RenderConfig::default());
+
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].0, dir.join("a.tex"));
+        assert_eq!(results[1].0, dir.join("b.tex"));
+        assert!(results.iter().all(|(_, r)| r.is_ok()));
+        assert!(std::fs::read_to_string(out_dir.join("a.svg"))
+            .unwrap()
+            .contains("snippet"));
+        assert!(out_dir.join("b.svg").exists());
+        assert!(!out_dir.join("notes.svg").exists());
+
+        // A missing directory is reported as a single I/O error
+        let results = render_directory(
+            &m,
+            &root.join("missing"),
+            &out_dir,
+            &RenderConfig::default(),
+        );
+        assert!(matches!(results.as_slice(), [(_, Err(RenderError::Io(_)))]));
+
+        std::fs::remove_dir_all(&root).ok();
+    }
+
+    #[test]
+    fn test_render_with_macros() {
+        let _g = crate::shim::lock_test();
+        crate::shim::set_init_succeed(true);
+        crate::shim::set_parse_succeed(true);
+        crate::shim::set_return_empty(false);
+        crate::shim::set_buffer(b"<svg>macro</svg>");
+
+        let m = MicroTex::new().expect("init ok");
+        let config = RenderConfig {
+            macros: vec![("RR".to_string(), r"\mathbb{R}".to_string())],
+            ..Default::default()
+        };
+        assert!(m.render(r"$x \in \RR$", &config).is_ok());
+    }
+
+    #[test]
+    fn test_expand_macros() {
+        let macros = vec![
+            ("RR".to_string(), r"\mathbb{R}".to_string()),
+            (r"\half".to_string(), r"\frac{1}{2}".to_string()),
+        ];
+        assert_eq!(
+            expand_macros(r"f: \RR^2 \to \RR, \half x", &macros),
+            r"f: \mathbb{R}^2 \to \mathbb{R}, \frac{1}{2} x"
+        );
+        // Longer command names are left alone
+        assert_eq!(expand_macros(r"\RRx + \RR", &macros), r"\RRx + \mathbb{R}");
+        assert_eq!(expand_macros(r"x", &[]), "x");
+    }
+
+    #[test]
+    fn test_render_to_svg_with_metrics_output_too_large() {
+        let _g = crate::shim::lock_test();
+        crate::shim::set_init_succeed(true);
+        crate::shim::set_parse_succeed(true);
+        crate::shim::set_return_empty(false);
+        // A runaway \rule: 5000 x 20 pixels
+        crate::shim::set_buffer(
+            br#"{"svg": "<svg>rule</svg>", "metrics": {"width": 5000, "height": 20, "depth": 0, "ascent": 20}}"#,
+        );
+
+        let m = MicroTex::new().expect("init ok");
+        let config = RenderConfig {
+            max_output_dimensions: Some((2000, 500)),
+            ..Default::default()
+        };
+        assert!(matches!(
+            m.render_to_svg_with_metrics(r"\rule{500em}{1pt}", &config),
+            Err(RenderError::OutputTooLarge {
+                width: 5000,
+                height: 20,
+                limit: (2000, 500)
+            })
+        ));
+
+        let config = RenderConfig {
+            max_output_dimensions: Some((5000, 20)),
+            ..Default::default()
+        };
+        assert!(m.render_to_svg_with_metrics("x", &config).is_ok());
+    }
+
+    #[test]
+    fn test_render_output_bytes_too_large() {
+        let _g = crate::shim::lock_test();
+        crate::shim::set_init_succeed(true);
+        crate::shim::set_parse_succeed(true);
+        crate::shim::set_return_empty(false);
+        let svg = format!("<svg>{}</svg>", "<path d=\"M0 0\"/>".repeat(1000));
+        crate::shim::set_buffer(svg.as_bytes());
+
+        let m = MicroTex::new().expect("init ok");
+        let config = RenderConfig {
+            max_output_bytes: Some(1024),
+            ..Default::default()
+        };
+        let live = crate::test_control::live_buffers();
+        assert!(matches!(
+            m.render(r"\frac{\frac{1}{2}}{3}", &config),
+            Err(RenderError::OutputBytesTooLarge { limit: 1024, actual })
+                if actual == svg.len()
+        ));
+        assert!(matches!(
+            m.render_measured_borrowed("x", &config),
+            Err(RenderError::OutputBytesTooLarge { limit: 1024, .. })
+        ));
+        // The buffers were freed on the error path
+        assert_eq!(crate::test_control::live_buffers(), live);
+
+        let json = serde_json::json!({
+            "svg": svg,
+            "metrics": {"width": 40, "height": 20, "depth": 4, "ascent": 16}
+        })
+        .to_string();
+        crate::shim::set_buffer(json.as_bytes());
+        assert!(matches!(
+            m.render_to_svg_with_metrics("x", &config),
+            Err(RenderError::OutputBytesTooLarge { limit: 1024, actual })
+                if actual == json.len()
+        ));
+        assert_eq!(crate::test_control::live_buffers(), live);
+
+        let config = RenderConfig {
+            max_output_bytes: Some(json.len()),
+            ..Default::default()
+        };
+        assert!(m.render_to_svg_with_metrics("x", &config).is_ok());
+    }
+
+    #[test]
+    fn test_adjust_svg_keeps_overbrace_label_in_view() {
+        // Shaped like \overbrace{x+y}^{n}: the label is drawn above the top edge
+        let svg = r#"<svg width="40" height="20" viewBox="0 0 40 20"><path d="M 16 -6.5 L 20 -2 L 24 -6.5 Z"/><path d="M 0 4 L 40 19.7 Z"/></svg>"#;
+        let adjusted = adjust_svg_height_and_center(svg);
+
+        let (_, min_y, _, max_y) = extract_bounding_box(&adjusted).expect("bounds");
+        let height = svg_root_attribute(&adjusted, "height").expect("height");
+        assert_eq!(height, "27");
+        assert!(min_y >= 0.0, "label clipped at the top: min_y = {}", min_y);
+        assert!(max_y <= 27.0);
+        // The slack is split evenly between top and bottom
+        assert!((min_y - (27.0 - max_y)).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_adjust_svg_height_negative_min_y() {
+        // An accent reaching 3 units above the top edge
+        let svg = r#"<svg width="30" height="16" viewBox="0 0 30 16"><path d="M 10 -3 L 14 0 Z"/><path d="M 0 2 L 30 15.5 Z"/></svg>"#;
+        let transform = svg_centering_transform(svg);
+        // The height covers -3..15.5, and the translate moves -3 down past the
+        // top edge plus half of the 0.5 rounding slack
+        assert_eq!(transform.new_height, 19);
+        assert_eq!(transform.translate_y, 3.25);
+
+        let adjusted = adjust_svg_height_and_center(svg);
+        assert!(adjusted.contains(r#"viewBox="0 0 30 19""#));
+        assert!(adjusted.contains("translate(0, 3.25)"));
+        let (_, min_y, _, max_y) = extract_bounding_box(&adjusted).expect("bounds");
+        assert_eq!((min_y, max_y), (0.25, 18.75));
+    }
+
+    #[test]
+    fn test_adjust_svg_height_and_center_with_tolerance() {
+        let svg =
+            r#"<svg width="30" height="1" viewBox="0 0 30 1"><path d="M 0 0 L 30 0.5 Z"/></svg>"#;
+        // The content reaches 0.5, within a tolerance of 1.0
+        assert_eq!(adjust_svg_height_and_center_with(svg, 1.0, true), svg);
+        assert_eq!(
+            adjust_svg_height_and_center_with(svg, 0.02, true),
+            adjust_svg_height_and_center(svg)
+        );
+        assert_ne!(adjust_svg_height_and_center(svg), svg);
+    }
 
-        unsafe {
-            let render_ptr = shim::microtex_parse_render(
-                latex_cstr.as_ptr(),
-                config.dpi,
-                config.line_width,
-                config.line_height,
-                config.text_color,
-                config.has_background,
-                config.render_glyph_use_path,
-            );
+    #[test]
+    fn test_adjust_svg_height_and_center_with_no_centering() {
+        let svg = r#"<svg width="188" height="39" viewBox="0 0 188 39"><path d="M 10 20 L 30 39.5 Z"/></svg>"#;
+        let adjusted = adjust_svg_height_and_center_with(svg, 0.02, false);
+        assert_eq!(
+            adjusted,
+            r#"<svg width="188" viewBox="0 0 188 40" height="40"><path d="M 10 20 L 30 39.5 Z"/></svg>"#
+        );
 
-            if render_ptr.is_null() {
-                return Err(RenderError::ParseRenderFailed);
-            }
+        // Content above the top edge moves the viewBox instead
+        let svg = r#"<svg width="30" height="16" viewBox="0 0 30 16"><path d="M 10 -3 L 14 0 Z"/><path d="M 0 2 L 30 15.5 Z"/></svg>"#;
+        let adjusted = adjust_svg_height_and_center_with(svg, 0.02, false);
+        assert!(!adjusted.contains("<g"));
+        assert!(adjusted.contains(r#"viewBox="0 -3 30 19""#));
+        assert!(adjusted.contains(r#"height="19""#));
+    }
 
-            let mut out_len = 0u64;
-            let out_buf = shim::microtex_render_to_svg_with_metrics(render_ptr, &mut out_len);
-
-            if out_buf.is_null() || out_len == 0 {
-                shim::microtex_delete_render(render_ptr);
-                return Err(RenderError::EmptyOutput);
-            }
-
-            // Convert the buffer to a Rust string
-            let json_slice = std::slice::from_raw_parts(out_buf as *const u8, out_len as usize);
-            let json_string = String::from_utf8(json_slice.to_vec())?;
-
-            // Parse the JSON response from C++
-            let json_value: serde_json::Value = serde_json::from_str(&json_string)
-                .map_err(|e| RenderError::ParseJsonFailed(e.to_string()))?;
-
-            // Extract SVG content
-            let mut svg = json_value
-                .get("svg")
-                .and_then(|v| v.as_str())
-                .ok_or_else(|| RenderError::ParseJsonFailed("missing 'svg' field".to_string()))?
-                .to_string();
-
-            // Add DPI metadata to SVG
-            svg = add_dpi_to_svg(&svg, config.dpi);
-
-            // Adjust SVG height and center content to prevent glyph clipping
-            svg = adjust_svg_height_and_center(&svg);
-
-            // Extract metrics
-            let metrics_obj = json_value
-                .get("metrics")
-                .and_then(|v| v.as_object())
-                .ok_or_else(|| {
-                    RenderError::ParseJsonFailed("missing 'metrics' field".to_string())
-                })?;
-
-            let width = metrics_obj
-                .get("width")
-                .and_then(|v| v.as_i64())
-                .ok_or_else(|| {
-                    RenderError::ParseJsonFailed("missing or invalid 'width'".to_string())
-                })? as i32;
-
-            let height = metrics_obj
-                .get("height")
-                .and_then(|v| v.as_i64())
-                .ok_or_else(|| {
-                    RenderError::ParseJsonFailed("missing or invalid 'height'".to_string())
-                })? as i32;
-
-            let depth = metrics_obj
-                .get("depth")
-                .and_then(|v| v.as_i64())
-                .ok_or_else(|| {
-                    RenderError::ParseJsonFailed("missing or invalid 'depth'".to_string())
-                })? as i32;
-
-            let ascent = metrics_obj
-                .get("ascent")
-                .and_then(|v| v.as_i64())
-                .ok_or_else(|| {
-                    RenderError::ParseJsonFailed("missing or invalid 'ascent'".to_string())
-                })? as i32;
-
-            let metrics = RenderMetrics::new(width, height, depth, ascent);
-
-            // Try to extract key character metrics
-            let key_char_metrics = get_key_char_metrics(render_ptr).ok();
-
-            // Clean up
-            shim::microtex_free_buffer(out_buf);
-            shim::microtex_delete_render(render_ptr);
-
-            let result = match key_char_metrics {
-                Some(kcm) => RenderResult::with_key_char_metrics(svg, metrics, kcm),
-                None => RenderResult::new(svg, metrics),
-            };
+    #[test]
+    fn test_svg_centering_transform() {
+        let svg = r#"<svg width="188" height="39" viewBox="0 0 188 39"><path d="M 10 20 L 30 39.5 Z"/></svg>"#;
+        let transform = svg_centering_transform(svg);
+        assert_eq!(
+            transform,
+            SvgTransform {
+                translate_y: 0.25,
+                new_height: 40,
+                original_height: 39.0,
+            }
+        );
 
-            Ok(result)
-        }
-    }
-}
+        // The adjusted SVG uses exactly the described height and translation
+        let adjusted = adjust_svg_height_and_center(svg);
+        assert!(adjusted.contains(r#"height="40""#));
+        assert!(adjusted.contains("translate(0, 0.25)"));
 
-/// Get metrics of key characters in a rendered formula.
-///
-/// This function extracts the heights of actual character boxes at the
-/// top level of the formula structure, excluding decorative elements.
-/// This is useful for calculating more accurate scaling factors that
-/// account for formula complexity (fractions, subscripts, etc.).
-///
-/// # Arguments
-///
-/// * `render_ptr` - The render pointer from `parse_render`
-///
-/// # Returns
-///
-/// A `KeyCharMetrics` struct containing the heights of key characters
-/// and statistical information about them.
-///
-/// # Errors
-///
-/// Returns [`RenderError`] if the rendering operation fails or the
-/// JSON parsing fails.
-pub fn get_key_char_metrics(
-    render_ptr: *mut std::ffi::c_void,
-) -> Result<KeyCharMetrics, RenderError> {
-    if render_ptr.is_null() {
-        return Err(RenderError::ParseRenderFailed);
+        // No paths: nothing to adjust
+        let transform = svg_centering_transform(r#"<svg height="12.5pt"></svg>"#);
+        assert_eq!(transform.translate_y, 0.0);
+        assert_eq!(transform.new_height, 13);
+        assert_eq!(transform.original_height, 12.5);
     }
 
-    unsafe {
-        let mut out_len = 0u64;
-        let out_buf = shim::microtex_get_key_char_metrics(render_ptr, &mut out_len);
+    #[test]
+    fn test_render_with_transform_info() {
+        let _g = crate::shim::lock_test();
+        crate::shim::set_init_succeed(true);
+        crate::shim::set_parse_succeed(true);
+        crate::shim::set_return_empty(false);
+        crate::shim::set_buffer(
+            br#"<svg width="100" height="39" viewBox="0 0 100 39"><path d="M 0 0 L 10 39.5 Z"/></svg>"#,
+        );
 
-        if out_buf.is_null() || out_len == 0 {
-            return Err(RenderError::EmptyOutput);
-        }
+        let m = MicroTex::new().expect("init ok");
+        let config = RenderConfig::default();
+        let (raw, transform) = m
+            .render_with_transform_info("x", &config)
+            .expect("render ok");
+
+        assert!(!raw.contains("<g transform"));
+        assert_eq!(transform, svg_centering_transform(&raw));
+        assert_eq!(transform.new_height, 40);
+        assert_eq!(
+            adjust_svg_height_and_center(&raw),
+            m.render("x", &config).expect("render ok")
+        );
+    }
 
-        // Convert the buffer to a Rust string
-        let json_slice = std::slice::from_raw_parts(out_buf as *const u8, out_len as usize);
-        let json_string = String::from_utf8(json_slice.to_vec())?;
+    #[test]
+    fn test_render_forwards_math_spacing() {
+        let _g = crate::shim::lock_test();
+        crate::shim::set_init_succeed(true);
+        crate::shim::set_parse_succeed(true);
+        crate::shim::set_return_empty(false);
 
-        // Parse the JSON response
-        let metrics = KeyCharMetrics::from_json(&json_string)
-            .map_err(|e| RenderError::ParseJsonFailed(e.to_string()))?;
+        let m = MicroTex::new().expect("init ok");
+        m.render("a = b", &RenderConfig::default())
+            .expect("render ok");
+        assert_eq!(crate::test_control::get_math_spacing(), (4.0, 5.0));
+
+        let config = RenderConfig {
+            op_spacing: 3.0,
+            rel_spacing: 8.0,
+            ..Default::default()
+        };
+        let _ = m.render_to_svg_with_metrics("a = b", &config);
+        assert_eq!(crate::test_control::get_math_spacing(), (3.0, 8.0));
+    }
 
-        // Clean up
-        shim::microtex_free_buffer(out_buf);
+    #[test]
+    fn test_render_forwards_limits_mode() {
+        let _g = crate::shim::lock_test();
+        crate::shim::set_init_succeed(true);
+        crate::shim::set_parse_succeed(true);
+        crate::shim::set_return_empty(false);
 
-        Ok(metrics)
+        let m = MicroTex::new().expect("init ok");
+        for (mode, code) in [
+            (LimitsMode::Always, 1),
+            (LimitsMode::Never, 2),
+            (LimitsMode::Default, 0),
+        ] {
+            let config = RenderConfig {
+                limits_mode: mode,
+                ..Default::default()
+            };
+            m.render(r"\sum_{i=1}^n i", &config).expect("render ok");
+            assert_eq!(crate::test_control::get_limits_mode(), code);
+        }
     }
-}
 
-impl Drop for MicroTex {
-    fn drop(&mut self) {
-        unsafe {
-            shim::microtex_release();
+    #[test]
+    fn test_render_prepared_at_two_dpis() {
+        let _g = crate::shim::lock_test();
+        crate::shim::set_init_succeed(true);
+        crate::shim::set_parse_succeed(true);
+        crate::shim::set_return_empty(false);
+        crate::shim::set_buffer(
+            br#"<svg width="100" height="40" viewBox="0 0 100 40"><path d="M 0 10 L 20 40 Z"/></svg>"#,
+        );
+
+        let m = MicroTex::new().expect("init ok");
+        let source = m.prepare_source(r"\[x^2\]").expect("prepare ok");
+        assert_eq!(source.as_str(), r"\[x^2\]");
+        for dpi in [300, 1440] {
+            let config = RenderConfig {
+                dpi,
+                ..Default::default()
+            };
+            let svg = m.render_prepared(&source, &config).expect("render ok");
+            assert!(svg.contains(&format!(r#"data-dpi="{}""#, dpi)));
+            assert_eq!(svg, m.render(r"\[x^2\]", &config).expect("render ok"));
         }
+
+        assert!(matches!(
+            m.prepare_source("  "),
+            Err(RenderError::EmptyInput)
+        ));
+        assert!(matches!(
+            m.prepare_source(r"\begin{tikzpicture}\end{tikzpicture}"),
+            Err(RenderError::Unsupported(_))
+        ));
     }
-}
 
-impl Default for MicroTex {
-    fn default() -> Self {
-        Self::new().expect("failed to create default MicroTex instance")
+    #[test]
+    fn test_render_forwards_bold() {
+        let _g = crate::shim::lock_test();
+        crate::shim::set_init_succeed(true);
+        crate::shim::set_parse_succeed(true);
+        crate::shim::set_return_empty(false);
+
+        let m = MicroTex::new().expect("init ok");
+        let config = RenderConfig {
+            bold: true,
+            ..Default::default()
+        };
+        m.render("x", &config).expect("render ok");
+        assert!(crate::test_control::get_bold_math());
+        m.render("x", &RenderConfig::default()).expect("render ok");
+        assert!(!crate::test_control::get_bold_math());
     }
-}
 
-impl fmt::Debug for MicroTex {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        f.debug_struct("MicroTex").finish()
+    #[test]
+    fn test_render_forwards_main_font() {
+        let _g = crate::shim::lock_test();
+        crate::shim::set_init_succeed(true);
+        crate::shim::set_parse_succeed(true);
+        crate::shim::set_return_empty(false);
+
+        let m = MicroTex::new().expect("init ok");
+        let config = RenderConfig {
+            main_font: Some("Sans".to_string()),
+            ..Default::default()
+        };
+        m.render("\\text{x}", &config).expect("render ok");
+        assert_eq!(crate::test_control::get_main_font(), "Sans");
+        m.render("\\text{x}", &RenderConfig::default())
+            .expect("render ok");
+        assert_eq!(crate::test_control::get_main_font(), "Serif");
+
+        let config = RenderConfig {
+            main_font: Some("Sa\0ns".to_string()),
+            ..Default::default()
+        };
+        assert!(matches!(
+            m.render("x", &config),
+            Err(RenderError::InvalidConfig(ConfigError::InvalidMainFont(_)))
+        ));
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    #[test]
+    fn test_render_into_matches_render() {
+        let _g = crate::shim::lock_test();
+        crate::shim::set_init_succeed(true);
+        crate::shim::set_parse_succeed(true);
+        crate::shim::set_return_empty(false);
+        crate::shim::set_buffer(
+            br#"<svg width="100" height="39" viewBox="0 0 100 39"><path d="M 0 0 L 10 39.5 Z"/></svg>"#,
+        );
 
-    const COMPLEXE_SVG: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
-<svg xmlns="http://www.w3.org/2000/svg" xmlns:xlink="http://www.w3.org/1999/xlink" width="188" height="39" viewBox="0 0 188 39" data-dpi="720">
-<path fill-rule="nonzero" fill="rgb(0%, 0%, 0%)" fill-opacity="1" d="M 10.480469 23.28125 L 6.621094 14.480469 L 2.71875 23.28125 Z M 13.5 25.121094 L 0.960938 25.121094 L 6.941406 11.640625 L 7.339844 11.640625 Z M 13.5 25.121094 "/>
-<path fill-rule="nonzero" fill="rgb(0%, 0%, 0%)" fill-opacity="1" d="M 19.398438 16.378906 L 20.140625 16.378906 C 21.398438 16.378906 21.300781 14.839844 22.160156 13.558594 C 22.78125 12.621094 23.640625 11.761719 25.160156 11.761719 C 26.160156 11.761719 26.738281 12.238281 26.738281 12.941406 C 26.738281 13.5 26.320312 13.738281 25.921875 13.738281 C 25.359375 13.738281 25.21875 13.519531 25.21875 13.238281 C 25.21875 12.960938 25.398438 12.660156 25.398438 12.5 C 25.398438 12.398438 25.339844 12.339844 25.101562 12.339844 C 24.101562 12.339844 23.320312 13.421875 22.898438 14.980469 L 22.519531 16.378906 L 24.121094 16.378906 L 23.878906 17.140625 L 22.359375 17.140625 L 20.78125 23.261719 C 20.640625 23.800781 20.480469 24.558594 20.179688 25.300781 C 19.519531 26.960938 18.441406 28.859375 16.679688 28.859375 C 15.71875 28.859375 15.238281 28.378906 15.238281 27.78125 C 15.238281 27.300781 15.519531 26.800781 16.101562 26.800781 C 16.640625 26.800781 16.839844 27.160156 16.839844 27.460938 C 16.839844 27.839844 16.519531 27.859375 16.519531 28.101562 C 16.519531 28.21875 16.640625 28.28125 16.820312 28.28125 C 18.121094 28.28125 18.71875 25.121094 19.019531 23.980469 L 20.761719 17.140625 L 19.21875 17.140625 Z M 19.398438 16.378906 "/>
-<path fill-rule="nonzero" fill="rgb(0%, 0%, 0%)" fill-opacity="1" d="M 43.835938 22.71875 L 32.054688 22.71875 L 32.054688 21.398438 L 43.835938 21.398438 Z M 43.835938 18.71875 L 32.054688 18.71875 L 32.054688 17.398438 L 43.835938 17.398438 Z M 43.835938 18.71875 "/>
-<path fill-rule="nonzero" fill="rgb(0%, 0%, 0%)" fill-opacity="1" d="M 56.191406 8.898438 L 56.191406 2.039062 L 51.390625 8.898438 Z M 59.8125 10.179688 L 57.75 10.179688 L 57.75 13.519531 L 56.191406 13.519531 L 56.191406 10.179688 L 50.589844 10.179688 L 50.589844 8.898438 L 56.871094 0 L 57.75 0 L 57.75 8.898438 L 59.8125 8.898438 Z M 59.8125 10.179688 "/>
-<path fill-rule="nonzero" fill="rgb(0%, 0%, 0%)" fill-opacity="1" d="M 65.769531 8.078125 L 64.589844 7.28125 C 63.410156 8.238281 62.992188 9.058594 62.992188 10.359375 C 62.992188 12.199219 64.011719 13.238281 65.53125 13.238281 C 66.832031 13.238281 67.730469 12.339844 67.730469 11.039062 C 67.730469 9.800781 67.132812 9 65.769531 8.078125 Z M 67.449219 2.859375 C 67.449219 1.480469 66.632812 0.558594 65.269531 0.558594 C 63.929688 0.558594 63.070312 1.300781 63.070312 2.539062 C 63.070312 3.78125 63.8125 4.699219 65.570312 5.738281 C 66.929688 4.941406 67.449219 4.101562 67.449219 2.859375 Z M 69.25 10.421875 C 69.25 12.480469 67.710938 13.800781 65.3125 13.800781 C 63.050781 13.800781 61.472656 12.421875 61.472656 10.539062 C 61.472656 9.160156 61.929688 8.378906 64.070312 6.878906 C 62.011719 5.179688 61.589844 4.421875 61.589844 3.121094 C 61.589844 1.199219 63.25 0 65.472656 0 C 67.449219 0 68.832031 1.300781 68.832031 2.859375 C 68.832031 4.359375 68.132812 5.039062 66.152344 6.101562 C 68.609375 7.738281 69.25 8.820312 69.25 10.421875 Z M 69.25 10.421875 "/>
-<path fill-rule="nonzero" fill="rgb(0%, 0%, 0%)" fill-opacity="1" d="M 77.949219 7.019531 C 77.949219 2.859375 77.070312 0.519531 75.3125 0.519531 C 73.652344 0.519531 72.75 2.878906 72.75 6.941406 C 72.75 11 73.632812 13.28125 75.351562 13.28125 C 77.050781 13.28125 77.949219 10.980469 77.949219 7.019531 Z M 79.871094 6.921875 C 79.871094 10.359375 78.609375 13.800781 75.351562 13.800781 C 71.929688 13.800781 70.832031 10.078125 70.832031 6.800781 C 70.832031 3.261719 72.210938 0 75.429688 0 C 78.050781 0 79.871094 2.820312 79.871094 6.921875 Z M 79.871094 6.921875 "/>
-<path fill-rule="nonzero" fill="rgb(0%, 0%, 0%)" fill-opacity="1" d="M 87.949219 7.019531 C 87.949219 2.859375 87.070312 0.519531 85.3125 0.519531 C 83.652344 0.519531 82.75 2.878906 82.75 6.941406 C 82.75 11 83.632812 13.28125 85.351562 13.28125 C 87.050781 13.28125 87.949219 10.980469 87.949219 7.019531 Z M 89.871094 6.921875 C 89.871094 10.359375 88.609375 13.800781 85.351562 13.800781 C 81.929688 13.800781 80.832031 10.078125 80.832031 6.800781 C 80.832031 3.261719 82.210938 0 85.429688 0 C 88.050781 0 89.871094 2.820312 89.871094 6.921875 Z M 89.871094 6.921875 "/>
-<path fill-rule="nonzero" fill="rgb(0%, 0%, 0%)" fill-opacity="1" d="M 97.949219 7.019531 C 97.949219 2.859375 97.070312 0.519531 95.3125 0.519531 C 93.652344 0.519531 92.75 2.878906 92.75 6.941406 C 92.75 11 93.632812 13.28125 95.351562 13.28125 C 97.050781 13.28125 97.949219 10.980469 97.949219 7.019531 Z M 99.871094 6.921875 C 99.871094 10.359375 98.609375 13.800781 95.351562 13.800781 C 91.929688 13.800781 90.832031 10.078125 90.832031 6.800781 C 90.832031 3.261719 92.210938 0 95.429688 0 C 98.050781 0 99.871094 2.820312 99.871094 6.921875 Z M 99.871094 6.921875 "/>
-<path fill="none" stroke-width="66" stroke-linecap="butt" stroke-linejoin="bevel" stroke="rgb(0%, 0%, 0%)" stroke-opacity="1" stroke-miterlimit="0" d="M 2517.578181 1006.05471 L 5017.578237 1006.05471 " transform="matrix(0.02, 0, 0, 0.02, 0, 0)"/>
-<path fill-rule="nonzero" fill="rgb(0%, 0%, 0%)" fill-opacity="1" d="M 61.191406 34.5 L 61.191406 27.640625 L 56.390625 34.5 Z M 64.8125 35.78125 L 62.75 35.78125 L 62.75 39.121094 L 61.191406 39.121094 L 61.191406 35.78125 L 55.589844 35.78125 L 55.589844 34.5 L 61.871094 25.601562 L 62.75 25.601562 L 62.75 34.5 L 64.8125 34.5 Z M 64.8125 35.78125 "/>
-<path fill-rule="nonzero" fill="rgb(0%, 0%, 0%)" fill-opacity="1" d="M 72.949219 32.621094 C 72.949219 28.460938 72.070312 26.121094 70.3125 26.121094 C 68.652344 26.121094 67.75 28.480469 67.75 32.539062 C 67.75 36.601562 68.632812 38.878906 70.351562 38.878906 C 72.050781 38.878906 72.949219 36.578125 72.949219 32.621094 Z M 74.871094 32.519531 C 74.871094 35.960938 73.609375 39.398438 70.351562 39.398438 C 66.929688 39.398438 65.832031 35.679688 65.832031 32.398438 C 65.832031 28.859375 67.210938 25.601562 70.429688 25.601562 C 73.050781 25.601562 74.871094 28.421875 74.871094 32.519531 Z M 74.871094 32.519531 "/>
-<path fill-rule="nonzero" fill="rgb(0%, 0%, 0%)" fill-opacity="1" d="M 82.589844 32.019531 L 82.589844 31.238281 C 82.589844 27.878906 81.691406 26.160156 79.949219 26.160156 C 79.351562 26.160156 78.832031 26.398438 78.492188 26.839844 C 78.089844 27.378906 77.792969 28.558594 77.792969 29.640625 C 77.792969 32.019531 78.75 33.519531 80.25 33.519531 C 81.132812 33.519531 82.589844 33.078125 82.589844 32.019531 Z M 76.53125 39.558594 L 76.472656 39.160156 C 79.511719 38.621094 81.75 36.519531 82.550781 33.238281 C 81.691406 34.078125 80.730469 34.378906 79.550781 34.378906 C 77.390625 34.378906 75.949219 32.761719 75.949219 30.320312 C 75.949219 27.621094 77.730469 25.601562 80.109375 25.601562 C 81.390625 25.601562 82.472656 26.160156 83.25 27.121094 C 84.050781 28.121094 84.53125 29.558594 84.53125 31.238281 C 84.53125 33.539062 83.730469 35.71875 82.132812 37.179688 C 80.429688 38.71875 79.132812 39.199219 76.53125 39.558594 Z M 76.53125 39.558594 "/>
-<path fill-rule="nonzero" fill="rgb(0%, 0%, 0%)" fill-opacity="1" d="M 92.910156 35.359375 C 92.910156 32.800781 92.070312 31.480469 90.210938 31.480469 C 89.070312 31.480469 87.890625 31.960938 87.890625 33.800781 C 87.890625 36.839844 88.851562 38.839844 90.730469 38.839844 C 92.171875 38.839844 92.910156 37.398438 92.910156 35.359375 Z M 94.269531 25.441406 L 94.3125 25.761719 C 91.171875 26.28125 88.929688 28.441406 88.390625 31.460938 C 89.371094 30.699219 90.050781 30.558594 90.929688 30.558594 C 93.269531 30.558594 94.710938 32.160156 94.710938 34.738281 C 94.710938 36.019531 94.351562 37.140625 93.691406 37.941406 C 92.949219 38.859375 91.832031 39.398438 90.511719 39.398438 C 88.929688 39.398438 87.671875 38.660156 86.972656 37.378906 C 86.410156 36.359375 86.03125 34.941406 86.03125 33.539062 C 86.03125 31.378906 86.792969 29.480469 88.210938 28.019531 C 89.929688 26.21875 91.511719 25.738281 94.269531 25.441406 Z M 94.269531 25.441406 "/>
-<path fill-rule="nonzero" fill="rgb(0%, 0%, 0%)" fill-opacity="1" d="M 117.988281 20.339844 L 118.648438 20.339844 C 118.527344 22.039062 117.445312 24.421875 115.488281 24.421875 C 113.566406 24.421875 111.425781 21.859375 109.988281 21.859375 C 108.585938 21.859375 107.847656 23.199219 107.527344 24.621094 L 106.867188 24.621094 C 106.964844 22.621094 108.167969 20.539062 110.125 20.539062 C 112.046875 20.539062 114.1875 23.101562 115.648438 23.101562 C 117.027344 23.101562 117.6875 21.761719 117.988281 20.339844 Z M 117.988281 15.621094 L 118.648438 15.621094 C 118.527344 17.320312 117.445312 19.699219 115.488281 19.699219 C 113.566406 19.699219 111.425781 17.140625 109.988281 17.140625 C 108.585938 17.140625 107.847656 18.480469 107.527344 19.898438 L 106.867188 19.898438 C 106.964844 17.898438 108.167969 15.820312 110.125 15.820312 C 112.046875 15.820312 114.1875 18.378906 115.648438 18.378906 C 117.027344 18.378906 117.6875 17.039062 117.988281 15.621094 Z M 117.988281 15.621094 "/>
-<path fill-rule="nonzero" fill="rgb(0%, 0%, 0%)" fill-opacity="1" d="M 133.042969 25.121094 L 127.523438 25.121094 L 127.523438 24.820312 C 129.003906 24.738281 129.421875 24.320312 129.421875 23.21875 L 129.421875 14.238281 C 129.421875 13.558594 129.242188 13.261719 128.820312 13.261719 C 128.621094 13.261719 128.28125 13.359375 127.921875 13.5 L 127.382812 13.699219 L 127.382812 13.421875 L 130.960938 11.601562 L 131.140625 11.660156 L 131.140625 23.601562 C 131.140625 24.460938 131.542969 24.820312 133.042969 24.820312 Z M 133.042969 25.121094 "/>
-<path fill-rule="nonzero" fill="rgb(0%, 0%, 0%)" fill-opacity="1" d="M 143.042969 25.121094 L 137.523438 25.121094 L 137.523438 24.820312 C 139.003906 24.738281 139.421875 24.320312 139.421875 23.21875 L 139.421875 14.238281 C 139.421875 13.558594 139.242188 13.261719 138.820312 13.261719 C 138.621094 13.261719 138.28125 13.359375 137.921875 13.5 L 137.382812 13.699219 L 137.382812 13.421875 L 140.960938 11.601562 L 141.140625 11.660156 L 141.140625 23.601562 C 141.140625 24.460938 141.542969 24.820312 143.042969 24.820312 Z M 143.042969 25.121094 "/>
-<path fill-rule="nonzero" fill="rgb(0%, 0%, 0%)" fill-opacity="1" d="M 148.78125 24.261719 C 148.78125 24.839844 148.261719 25.339844 147.664062 25.339844 C 147.042969 25.339844 146.5625 24.859375 146.5625 24.238281 C 146.5625 23.621094 147.0625 23.121094 147.683594 23.121094 C 148.261719 23.121094 148.78125 23.660156 148.78125 24.261719 Z M 148.78125 24.261719 "/>
-<path fill-rule="nonzero" fill="rgb(0%, 0%, 0%)" fill-opacity="1" d="M 159.140625 12.199219 L 154.902344 25.28125 L 153.601562 25.28125 L 157.5625 13.359375 L 153.261719 13.359375 C 152.101562 13.359375 151.761719 13.640625 150.921875 15 L 150.5625 14.820312 L 151.761719 11.878906 L 159.140625 11.878906 Z M 159.140625 12.199219 "/>
-<path fill-rule="nonzero" fill="rgb(0%, 0%, 0%)" fill-opacity="1" d="M 179.222656 25.121094 L 173.640625 25.121094 L 173.640625 24.738281 C 175.203125 24.640625 175.402344 24.320312 175.402344 22.640625 L 175.402344 18.820312 L 169.34375 18.820312 L 169.34375 22.859375 C 169.34375 24.320312 169.601562 24.660156 171.082031 24.738281 L 171.101562 25.121094 L 165.523438 25.121094 L 165.523438 24.738281 C 167.082031 24.640625 167.300781 24.398438 167.300781 22.679688 L 167.300781 14.160156 C 167.300781 12.601562 167.0625 12.378906 165.523438 12.261719 L 165.523438 11.878906 L 171.121094 11.878906 L 171.121094 12.261719 C 169.664062 12.378906 169.34375 12.601562 169.34375 14.160156 L 169.34375 17.941406 L 175.402344 17.941406 L 175.402344 14.160156 C 175.402344 12.578125 175.140625 12.378906 173.621094 12.261719 L 173.621094 11.878906 L 179.222656 11.878906 L 179.222656 12.261719 C 177.742188 12.378906 177.441406 12.621094 177.441406 14.160156 L 177.441406 22.898438 C 177.441406 24.320312 177.722656 24.621094 179.222656 24.738281 Z M 179.222656 25.121094 "/>
-<path fill-rule="nonzero" fill="rgb(0%, 0%, 0%)" fill-opacity="1" d="M 187.960938 22.398438 L 187.664062 25.121094 L 180.140625 25.121094 L 180.140625 24.820312 L 185.460938 16.71875 L 182.761719 16.71875 C 181.503906 16.71875 181.203125 17.019531 181.023438 18.480469 L 180.664062 18.480469 L 180.742188 16.121094 L 187.664062 16.121094 L 187.664062 16.421875 L 182.28125 24.519531 L 184.941406 24.519531 C 186.101562 24.519531 186.78125 24.320312 187.0625 23.980469 C 187.34375 23.640625 187.402344 23.320312 187.601562 22.320312 Z M 187.960938 22.398438 "/>
-</svg>
-"#;
+        let m = MicroTex::new().expect("init ok");
+        let config = RenderConfig::default();
+        let mut out = String::from("previous content");
+        m.render_into("x", &config, &mut out).expect("render ok");
+        assert_eq!(out, m.render("x", &config).expect("render ok"));
+
+        // Without paths there is nothing to center, only the DPI is added
+        let svg = r#"<svg width="10" height="10"></svg>"#;
+        postprocess_svg_into(svg, 300, &mut out);
+        assert_eq!(out, add_dpi_to_svg(svg, 300));
+    }
 
     #[test]
-    fn test_available_clms() {
-        let clms = available_embedded_clms();
-        assert!(!clms.is_empty());
-        // At least one math font should be available
-        let has_math = clms.iter().any(|&name| {
-            name.contains("Math")
-                || name.contains("math")
-                || name.contains("XITS")
-                || name.contains("Fira")
-        });
-        assert!(
-            has_math,
-            "No suitable math fonts found. Available: {:?}",
-            clms
+    fn test_render_forwards_extra_options() {
+        let _g = crate::shim::lock_test();
+        crate::shim::set_init_succeed(true);
+        crate::shim::set_parse_succeed(true);
+        crate::shim::set_return_empty(false);
+
+        let m = MicroTex::new().expect("init ok");
+        let config = RenderConfig {
+            extra_options: HashMap::from([
+                ("thickMuSkip".to_string(), "6".to_string()),
+                ("futureOption".to_string(), "on".to_string()),
+            ]),
+            ..Default::default()
+        };
+        crate::test_control::take_options();
+        m.render("a = b", &config).expect("render ok");
+        assert_eq!(
+            crate::test_control::take_options(),
+            vec![
+                ("futureOption".to_string(), "on".to_string()),
+                ("thickMuSkip".to_string(), "6".to_string()),
+            ]
         );
+
+        let config = RenderConfig {
+            extra_options: HashMap::from([("bad".to_string(), "nul\0".to_string())]),
+            ..Default::default()
+        };
+        assert!(matches!(
+            m.render("a = b", &config),
+            Err(RenderError::InvalidConfig(ConfigError::InvalidOption(key))) if key == "bad"
+        ));
+        assert!(crate::test_control::take_options().is_empty());
     }
 
     #[test]
-    fn test_get_embedded_clm() {
-        let clms = available_embedded_clms();
-        for clm_name in clms {
-            let result = get_embedded_clm(clm_name);
-            assert!(
-                result.is_some(),
-                "Failed to get embedded CLM for {}",
-                clm_name
+    fn test_extra_options_do_not_carry_over() {
+        let _g = crate::shim::lock_test();
+        crate::shim::set_init_succeed(true);
+        crate::shim::set_parse_succeed(true);
+        crate::shim::set_return_empty(false);
+        crate::shim::set_buffer(b"<svg></svg>");
+
+        let m = MicroTex::new().expect("init ok");
+        // As left by a render with `defaultMathFont` and `renderGlyphUsePath`
+        // in its extra options
+        crate::test_control::set_math_font("Other Math");
+        crate::test_control::set_render_glyph_use_path(false);
+        m.render("x", &RenderConfig::default()).expect("render ok");
+        assert_eq!(crate::test_control::get_math_font(), "XITS Math");
+        assert!(crate::test_control::get_render_glyph_use_path());
+    }
+
+    #[test]
+    fn test_render_adds_svg_attributes_to_root() {
+        let _g = crate::shim::lock_test();
+        crate::shim::set_init_succeed(true);
+        crate::shim::set_parse_succeed(true);
+        crate::shim::set_return_empty(false);
+
+        let m = MicroTex::new().expect("init ok");
+        let config = RenderConfig {
+            svg_attributes: vec![
+                ("class".to_string(), "math".to_string()),
+                ("data-tex".to_string(), "a < b".to_string()),
+            ],
+            ..Default::default()
+        };
+        let svg = m.render("a < b", &config).expect("render ok");
+        let root = &svg[svg.find("<svg").unwrap()..];
+        let root = &root[..root.find('>').unwrap()];
+        assert!(root.contains(r#"class="math""#), "{}", root);
+        assert!(root.contains(r#"data-tex="a &lt; b""#), "{}", root);
+
+        for name in [
+            "1class",
+            "on click",
+            "",
+            "height",
+            "data-latex",
+            "xmlns:xlink",
+        ] {
+            let config = RenderConfig {
+                svg_attributes: vec![(name.to_string(), "x".to_string())],
+                ..Default::default()
+            };
+            assert_eq!(
+                config.validate(),
+                Err(ConfigError::InvalidSvgAttribute(name.to_string()))
             );
-            let data = result.unwrap();
-            assert!(!data.is_empty(), "CLM data is empty for {}", clm_name);
         }
     }
 
-    // The rendering tests are commented out because MicroTeX may throw C++ exceptions
-    // that Rust cannot catch. This is a known limitation of the C bindings.
-    // Tests are best run with the C++ test suite: c++/mini_tests/test_math_svg.cpp
-    //
-    // To test rendering manually:
-    // 1. Run the C++ test: cd c++/mini_tests && ./test_math_svg
-    // 2. Or use the examples: cargo run --example simple_formula
-
     #[test]
-    fn test_microtex_new_success() {
+    fn test_render_embeds_source() {
         let _g = crate::shim::lock_test();
         crate::shim::set_init_succeed(true);
-        let r = MicroTex::new();
-        assert!(r.is_ok());
+        crate::shim::set_parse_succeed(true);
+        crate::shim::set_return_empty(false);
+
+        let m = MicroTex::new().expect("init ok");
+        let source = r#"\text{"quoted" & 'single'} < \frac{a}{b}"#;
+        let svg = m
+            .render(source, &RenderConfig::default())
+            .expect("render ok");
+        assert!(!svg.contains("data-latex"));
+
+        let config = RenderConfig {
+            embed_source: true,
+            ..Default::default()
+        };
+        let svg = m.render(source, &config).expect("render ok");
+        let start = svg.find(r#"data-latex=""#).expect("data-latex") + r#"data-latex=""#.len();
+        let value = &svg[start..start + svg[start..].find('"').unwrap()];
+        assert_eq!(quick_xml::escape::unescape(value).unwrap(), source);
     }
 
     #[test]
-    fn test_microtex_new_init_fail() {
+    fn test_add_root_attributes_self_closing() {
+        let attributes = vec![("id".to_string(), "eq1".to_string())];
+        assert_eq!(
+            add_root_attributes("<svg width=\"1\"/>".to_string(), &attributes),
+            "<svg width=\"1\" id=\"eq1\"/>"
+        );
+        assert_eq!(
+            add_root_attributes("no svg here".to_string(), &attributes),
+            "no svg here"
+        );
+    }
+
+    #[test]
+    fn test_render_to_width_scales_root() {
         let _g = crate::shim::lock_test();
-        crate::shim::set_init_succeed(false);
-        let r = MicroTex::new();
-        assert!(matches!(r, Err(RenderError::InitializationFailed)));
         crate::shim::set_init_succeed(true);
+        crate::shim::set_parse_succeed(true);
+        crate::shim::set_return_empty(false);
+        crate::test_control::set_buffer(
+            br#"<svg width="200" height="40" viewBox="0 0 200 40"><path d="M 0 10 L 20 40 Z"/></svg>"#,
+        );
+
+        let m = MicroTex::new().expect("init ok");
+        let svg = m
+            .render_to_width("x", &RenderConfig::default(), 400)
+            .expect("render ok");
+        assert_eq!(svg_root_attribute(&svg, "width").as_deref(), Some("400"));
+        assert_eq!(svg_root_attribute(&svg, "height").as_deref(), Some("80"));
+        assert_eq!(
+            svg_root_attribute(&svg, "viewBox").as_deref(),
+            Some("0 0 200 40")
+        );
+
+        assert!(matches!(
+            m.render_to_width("x", &RenderConfig::default(), 0),
+            Err(RenderError::Scaling(_))
+        ));
+
+        // Point sizes, as Cairo writes them, are replaced with pixels
+        crate::test_control::set_buffer(
+            br#"<svg width="150pt" height="30pt" viewBox="0 0 150 30"><path d="M 0 10 L 20 30 Z"/></svg>"#,
+        );
+        let svg = m
+            .render_to_width("x", &RenderConfig::default(), 400)
+            .expect("render ok");
+        assert_eq!(svg_root_attribute(&svg, "width").as_deref(), Some("400"));
+        assert_eq!(svg_root_attribute(&svg, "height").as_deref(), Some("80"));
     }
 
     #[test]
-    fn test_render_parse_fail() {
+    fn test_resize_svg_adds_missing_viewbox() {
+        let resized = resize_svg(r#"<svg width="6pt" height="3pt"></svg>"#, 20.0, 10.0);
+        assert_eq!(
+            resized,
+            r#"<svg width="20" height="10" viewBox="0 0 8 4"></svg>"#
+        );
+        let resized = resize_svg(r#"<svg width="2in" height="1in"></svg>"#, 20.0, 10.0);
+        assert_eq!(
+            resized,
+            r#"<svg width="20" height="10" viewBox="0 0 192 96"></svg>"#
+        );
+    }
+
+    #[test]
+    fn test_render_default_uses_set_config() {
         let _g = crate::shim::lock_test();
         crate::shim::set_init_succeed(true);
-        crate::shim::set_parse_succeed(false);
-        let m = MicroTex::new().expect("init should succeed");
-        let r = m.render("x", &RenderConfig::default());
-        assert!(matches!(r, Err(RenderError::ParseRenderFailed)));
         crate::shim::set_parse_succeed(true);
+        crate::shim::set_return_empty(false);
+
+        let mut m = MicroTex::new().expect("init ok");
+        assert_eq!(m.default_config().dpi, RenderConfig::default().dpi);
+
+        m.set_default_config(RenderConfig {
+            dpi: 1440,
+            ..Default::default()
+        });
+        let svg = m.render_default("x").expect("render ok");
+        assert!(svg.contains(r#"data-dpi="1440""#));
+
+        // An explicit config still overrides the default
+        let svg = m.render("x", &RenderConfig::default()).expect("render ok");
+        assert!(svg.contains(r#"data-dpi="720""#));
     }
 
     #[test]
-    fn test_render_empty_output() {
+    fn test_render_rejects_tikz() {
         let _g = crate::shim::lock_test();
         crate::shim::set_init_succeed(true);
         crate::shim::set_parse_succeed(true);
-        crate::shim::set_return_empty(true);
-        let m = MicroTex::new().expect("init should succeed");
-        let r = m.render("x", &RenderConfig::default());
-        assert!(matches!(r, Err(RenderError::EmptyOutput)));
         crate::shim::set_return_empty(false);
+
+        let m = MicroTex::new().expect("init ok");
+        let config = RenderConfig::default();
+        let tikz = r"\begin{tikzpicture}\draw (0,0) -- (1,1);\end{tikzpicture}";
+        let pgfplots = r"\begin{axis}\addplot {x^2};\end{axis}";
+        for source in [tikz, pgfplots] {
+            let err = m.render(source, &config).unwrap_err();
+            assert!(
+                matches!(&err, RenderError::Unsupported(msg) if msg == "TikZ/pgfplots not supported")
+            );
+            assert!(matches!(
+                m.render_to_svg_with_metrics(source, &config),
+                Err(RenderError::Unsupported(_))
+            ));
+        }
     }
 
     #[test]
-    fn test_render_invalid_utf8() {
+    fn test_render_rejects_empty_input() {
         let _g = crate::shim::lock_test();
         crate::shim::set_init_succeed(true);
         crate::shim::set_parse_succeed(true);
         crate::shim::set_return_empty(false);
-        crate::shim::set_buffer(&[0xff, 0xff, 0xff]);
+
         let m = MicroTex::new().expect("init ok");
-        let r = m.render("x", &RenderConfig::default());
-        assert!(matches!(r, Err(RenderError::InvalidUtf8(_))));
+        let config = RenderConfig::default();
+        for source in ["", "   ", "\n\t"] {
+            assert!(matches!(
+                m.render(source, &config),
+                Err(RenderError::EmptyInput)
+            ));
+            assert!(matches!(
+                m.render_to_svg_with_metrics(source, &config),
+                Err(RenderError::EmptyInput)
+            ));
+        }
     }
 
     #[test]
-    fn test_render_success() {
+    fn test_render_inline_and_display() {
         let _g = crate::shim::lock_test();
         crate::shim::set_init_succeed(true);
         crate::shim::set_parse_succeed(true);
         crate::shim::set_return_empty(false);
-        crate::shim::set_buffer(b"<svg>ok</svg>");
+        crate::shim::set_buffer(br#"<svg width="10" height="10"></svg>"#);
+
         let m = MicroTex::new().expect("init ok");
-        let r = m.render("x", &RenderConfig::default());
-        assert!(r.is_ok());
-        assert!(r.unwrap().contains("<svg"));
+        let config = RenderConfig {
+            embed_source: true,
+            ..Default::default()
+        };
+        let inline = m.render_inline(" x^2 ", &config).unwrap();
+        assert!(inline.contains(r#"data-latex="$x^2$""#));
+        let display = m.render_display(" x^2 ", &config).unwrap();
+        assert!(display.contains(r#"data-latex="\[x^2\]""#));
+
+        let count = m.render_count();
+        for expr in ["", "  \n\t"] {
+            assert!(matches!(
+                m.render_inline(expr, &config),
+                Err(RenderError::InvalidInput(_))
+            ));
+            assert!(matches!(
+                m.render_display(expr, &config),
+                Err(RenderError::InvalidInput(_))
+            ));
+        }
+        assert_eq!(m.render_count(), count);
     }
 
     #[test]
-    fn test_multiple_renders_same_instance() {
-        // This test reproduces the SIGSEGV crash when calling render() multiple times
-        // on the same MicroTex instance. The issue is related to resource cleanup
-        // or reuse of the underlying C++ MicroTeX library.
+    fn test_render_rejects_nul_byte() {
         let _g = crate::shim::lock_test();
         crate::shim::set_init_succeed(true);
         crate::shim::set_parse_succeed(true);
         crate::shim::set_return_empty(false);
-        crate::shim::set_buffer(b"<svg>result1</svg>");
+        crate::shim::set_buffer(b"<svg>ok</svg>");
 
         let m = MicroTex::new().expect("init ok");
+        let config = RenderConfig::default();
+        let is_nul_at_1 =
+            |err| matches!(err, RenderError::InvalidInput(msg) if msg == "NUL byte at position 1");
+        assert!(is_nul_at_1(m.render("x\0y", &config).unwrap_err()));
+        assert!(is_nul_at_1(
+            m.render_to_svg_with_metrics("x\0y", &config).unwrap_err()
+        ));
+        assert!(is_nul_at_1(
+            m.render_measured_borrowed("x\0y", &config).unwrap_err()
+        ));
+        assert!(is_nul_at_1(m.prepare_source("x\0y").unwrap_err()));
+        assert_eq!(
+            RenderError::InvalidInput("NUL byte at position 1".to_string()).to_string(),
+            "invalid LaTeX source: NUL byte at position 1"
+        );
+    }
 
-        // First render - should succeed
-        let r1 = m.render("x^2", &RenderConfig::default());
-        assert!(r1.is_ok());
-        assert!(r1.unwrap().contains("result1"));
-
-        // Update buffer for second render
-        crate::shim::set_buffer(b"<svg>result2</svg>");
-
-        // Second render on the SAME instance - this triggers the crash
-        let r2 = m.render("y^2", &RenderConfig::default());
-        assert!(r2.is_ok());
-        assert!(r2.unwrap().contains("result2"));
+    #[test]
+    fn test_supported_output_formats() {
+        let formats = supported_output_formats();
+        assert_eq!(formats.first(), Some(&OutputFormat::Svg));
+        assert_eq!(formats.contains(&OutputFormat::Png), cfg!(feature = "png"));
+        assert_eq!(OutputFormat::Svg.extension(), "svg");
+    }
 
-        // Third render - verify the issue persists with multiple calls
-        crate::shim::set_buffer(b"<svg>result3</svg>");
-        let r3 = m.render("z^2", &RenderConfig::default());
-        assert!(r3.is_ok());
-        assert!(r3.unwrap().contains("result3"));
+    #[test]
+    fn test_render_config_validate() {
+        let invalid = |config: RenderConfig| config.validate().unwrap_err();
+
+        assert!(RenderConfig::default().validate().is_ok());
+        assert_eq!(
+            invalid(RenderConfig {
+                dpi: 0,
+                ..Default::default()
+            }),
+            ConfigError::InvalidDpi(0)
+        );
+        assert_eq!(
+            invalid(RenderConfig {
+                line_width: 0.0,
+                ..Default::default()
+            }),
+            ConfigError::InvalidLineWidth(0.0)
+        );
+        assert!(matches!(
+            invalid(RenderConfig {
+                line_width: f32::NAN,
+                ..Default::default()
+            }),
+            ConfigError::InvalidLineWidth(_)
+        ));
+        assert_eq!(
+            invalid(RenderConfig {
+                line_height: -2.0,
+                ..Default::default()
+            }),
+            ConfigError::InvalidLineHeight(-2.0)
+        );
+        assert_eq!(
+            invalid(RenderConfig {
+                force_ascent_px: Some(-5),
+                ..Default::default()
+            }),
+            ConfigError::InvalidForcedAscent(-5)
+        );
+        assert_eq!(
+            invalid(RenderConfig {
+                max_output_dimensions: Some((100, 0)),
+                ..Default::default()
+            }),
+            ConfigError::InvalidMaxOutputDimensions(100, 0)
+        );
+        assert_eq!(
+            invalid(RenderConfig {
+                max_output_bytes: Some(0),
+                ..Default::default()
+            }),
+            ConfigError::InvalidMaxOutputBytes
+        );
+        assert_eq!(
+            invalid(RenderConfig {
+                macros: vec![(r"\R2".to_string(), "x".to_string())],
+                ..Default::default()
+            }),
+            ConfigError::InvalidMacroName(r"\R2".to_string())
+        );
     }
 
     #[test]
-    fn test_render_to_svg_with_metrics_success() {
+    fn test_render_rejects_invalid_config() {
         let _g = crate::shim::lock_test();
         crate::shim::set_init_succeed(true);
         crate::shim::set_parse_succeed(true);
         crate::shim::set_return_empty(false);
 
-        // Create a valid JSON response with SVG and metrics
-        let json_response = br#"{
-            "svg": "<svg>test formula</svg>",
-            "metrics": {
-                "width": 100,
-                "height": 50,
-                "depth": 10,
-                "ascent": 40
-            }
-        }"#;
-
-        crate::shim::set_buffer(json_response);
-
         let m = MicroTex::new().expect("init ok");
-        let r = m.render_to_svg_with_metrics("x^2", &RenderConfig::default());
-
-        assert!(r.is_ok());
-        let result = r.unwrap();
-        assert!(result.svg.contains("<svg"));
-        assert_eq!(result.metrics.width, 100);
-        assert_eq!(result.metrics.height, 50);
-        assert_eq!(result.metrics.depth, 10);
-        assert_eq!(result.metrics.ascent, 40);
+        let config = RenderConfig {
+            dpi: -720,
+            ..Default::default()
+        };
+        assert!(matches!(
+            m.render("x", &config),
+            Err(RenderError::InvalidConfig(ConfigError::InvalidDpi(-720)))
+        ));
+        assert!(matches!(
+            m.render_to_svg_with_metrics("x", &config),
+            Err(RenderError::InvalidConfig(ConfigError::InvalidDpi(-720)))
+        ));
     }
 
     #[test]
@@ -1768,7 +11270,7 @@ mod tests {
         let m = MicroTex::new().expect("init should succeed");
         let r = m.render_to_svg_with_metrics("x", &RenderConfig::default());
 
-        assert!(matches!(r, Err(RenderError::ParseRenderFailed)));
+        assert!(matches!(r, Err(RenderError::ParseRenderFailed(_))));
         crate::shim::set_parse_succeed(true);
     }
 
@@ -1800,6 +11302,35 @@ mod tests {
         assert!(matches!(r, Err(RenderError::ParseJsonFailed(_))));
     }
 
+    #[test]
+    fn test_render_to_svg_with_metrics_capture_raw_json() {
+        let _g = crate::shim::lock_test();
+        crate::shim::set_init_succeed(true);
+        crate::shim::set_parse_succeed(true);
+        crate::shim::set_return_empty(false);
+        crate::shim::set_buffer(br#"{"svg": "<svg/>", "metrics": {"width": 1"#);
+
+        let m = MicroTex::new().expect("init ok");
+        let config = RenderConfig {
+            capture_raw_json: true,
+            ..Default::default()
+        };
+        match m.render_to_svg_with_metrics("x", &config) {
+            Err(RenderError::ParseJsonFailedRaw { error, raw }) => {
+                assert!(!error.is_empty());
+                assert_eq!(raw, r#"{"svg": "<svg/>", "metrics": {"width": 1"#);
+            }
+            other => panic!("expected ParseJsonFailedRaw, got {:?}", other),
+        }
+
+        // Well-formed JSON with a missing field is captured too
+        crate::shim::set_buffer(br#"{"svg": "<svg/>"}"#);
+        let r = m.render_to_svg_with_metrics("x", &config);
+        assert!(
+            matches!(r, Err(RenderError::ParseJsonFailedRaw { ref raw, .. }) if raw == r#"{"svg": "<svg/>"}"#)
+        );
+    }
+
     #[test]
     fn test_render_to_svg_with_metrics_missing_svg() {
         let _g = crate::shim::lock_test();
@@ -1882,6 +11413,41 @@ mod tests {
         assert_eq!(metrics.aspect_ratio(), 4.0);
     }
 
+    #[test]
+    fn test_render_metrics_display() {
+        let metrics = RenderMetrics::new(188, 39, 14, 25);
+        let text = metrics.to_string();
+        assert_eq!(text, "188x39 (ascent=25, depth=14, aspect=4.82)");
+        assert!(text.contains("188") && text.contains("39") && text.contains("4.82"));
+        assert_eq!(
+            RenderMetrics::new(10, 0, 0, 0).to_string(),
+            "10x0 (ascent=0, depth=0, aspect=1.00)"
+        );
+    }
+
+    #[test]
+    fn test_render_metrics_baseline_offset_fraction() {
+        assert_eq!(
+            RenderMetrics::new(100, 40, 10, 30).baseline_offset_fraction(),
+            0.25
+        );
+        assert_eq!(
+            RenderMetrics::new(100, 0, 0, 0).baseline_offset_fraction(),
+            0.0
+        );
+    }
+
+    #[test]
+    fn test_render_metrics_scale_to_cap_height() {
+        let metrics = RenderMetrics::new(100, 40, 10, 30);
+        assert_eq!(metrics.scale_to_cap_height(60.0), 2.0);
+        assert_eq!(metrics.scale_to_cap_height(30.0), 1.0);
+        assert_eq!(
+            RenderMetrics::new(100, 10, 10, 0).scale_to_cap_height(12.0),
+            1.0
+        );
+    }
+
     #[test]
     fn test_render_metrics_aspect_ratio_zero_height() {
         let metrics = RenderMetrics::new(100, 0, 0, 0);
@@ -1926,6 +11492,56 @@ mod tests {
         assert!(result_720.contains(r#"data-dpi="720""#));
     }
 
+    #[test]
+    fn test_add_dpi_to_svg_twice() {
+        let svg = r#"<svg width="100" height="50"><path d="M 0 0"/></svg>"#;
+        let result = add_dpi_to_svg(&add_dpi_to_svg(svg, 300), 720);
+        assert_eq!(result.matches("data-dpi=").count(), 1);
+        assert_eq!(
+            result,
+            r#"<svg width="100" height="50" data-dpi="720"><path d="M 0 0"/></svg>"#
+        );
+    }
+
+    #[test]
+    fn test_svg_to_data_uri() {
+        use base64::Engine;
+
+        let svg = "<svg xmlns=\"http://www.w3.org/2000/svg\"><text>é ∑</text></svg>";
+        let uri = svg_to_data_uri(svg);
+        let encoded = uri
+            .strip_prefix("data:image/svg+xml;base64,")
+            .expect("data URI prefix");
+        let decoded = base64::engine::general_purpose::STANDARD
+            .decode(encoded)
+            .unwrap();
+        assert_eq!(decoded, svg.as_bytes());
+    }
+
+    #[test]
+    fn test_render_to_data_uri() {
+        use base64::Engine;
+
+        let _g = crate::shim::lock_test();
+        crate::shim::set_init_succeed(true);
+        crate::shim::set_parse_succeed(true);
+        crate::shim::set_return_empty(false);
+        crate::shim::set_buffer(
+            br#"<svg width="10" height="10"><path d="M 0 0 L 10 10 Z"/></svg>"#,
+        );
+
+        let m = MicroTex::new().expect("init ok");
+        let config = RenderConfig::default();
+        let uri = m.render_to_data_uri("x", &config).unwrap();
+        let encoded = uri
+            .strip_prefix("data:image/svg+xml;base64,")
+            .expect("data URI prefix");
+        let decoded = base64::engine::general_purpose::STANDARD
+            .decode(encoded)
+            .unwrap();
+        assert_eq!(decoded, m.render("x", &config).unwrap().into_bytes());
+    }
+
     #[test]
     fn test_add_dpi_to_svg_no_svg_tag() {
         let svg = r#"<div>Not an SVG</div>"#;
@@ -1990,6 +11606,134 @@ mod tests {
         assert!(y_coords.contains(&35.0));
     }
 
+    #[test]
+    fn test_flatten_svg_transforms_adjusted() {
+        let svg = r#"<svg width="100" height="20" viewBox="0 0 100 20">
+<g fill="rgb(0%,0%,0%)" style="fill-opacity:1">
+<path fill="none" stroke-width="66" d="M 500 100 L 1500 100" transform="matrix(0.02, 0, 0, 0.02, 0, 1)"/>
+<path d="M 10 2 L 30 25.5 Z" style="stroke:none"/>
+</g>
+</svg>"#;
+        let adjusted = adjust_svg_height_and_center(svg);
+        let transform = svg_centering_transform(svg);
+        assert!(transform.translate_y != 0.0);
+        assert!(adjusted.contains("<g"));
+
+        let flat = flatten_svg_transforms(&adjusted);
+        assert!(!flat.contains("<g"), "{}", flat);
+        assert!(!flat.contains("</g>"));
+        assert!(!flat.contains("translate("));
+        // The translate group alone, and composed with the path's matrix
+        let ty = transform.translate_y;
+        assert!(flat.contains(&format!(
+            r#"<path d="M 10 2 L 30 25.5 Z" style="fill-opacity:1;stroke:none" fill="rgb(0%,0%,0%)" transform="matrix(1, 0, 0, 1, 0, {})"/>"#,
+            ty
+        )));
+        assert!(flat.contains(&format!(
+            r#"<path fill="none" stroke-width="66" d="M 500 100 L 1500 100" style="fill-opacity:1" transform="matrix(0.02, 0, 0, 0.02, 0, {})"/>"#,
+            1.0 + ty
+        )));
+        assert_eq!(extract_bounding_box(&flat), extract_bounding_box(&adjusted));
+    }
+
+    #[test]
+    fn test_flatten_svg_transforms_keeps_definitions() {
+        let svg = r##"<svg><defs><g id="glyph-0"><path d="M 0 0 L 1 1"/></g></defs><g transform="translate(2, 3)"><use href="#glyph-0" x="1"/><g clip-path="url(#c)" transform="scale(2)"><path d="M 0 0 L 1 1"/></g><g/></g></svg>"##;
+        assert_eq!(
+            flatten_svg_transforms(svg),
+            r##"<svg><defs><g id="glyph-0"><path d="M 0 0 L 1 1"/></g></defs><use href="#glyph-0" x="1" transform="matrix(1, 0, 0, 1, 2, 3)"/><g clip-path="url(#c)" transform="matrix(2, 0, 0, 2, 2, 3)"><path d="M 0 0 L 1 1"/></g></svg>"##
+        );
+    }
+
+    #[test]
+    fn test_extract_bounding_box_multiple_paths() {
+        let svg = r#"<svg>
+            <path d="M 10 20 L 30 40 Z"/>
+            <path d="M 5 25 C 8 -3 12 60 25 35"/>
+            <g><path d="M 40 22 h 5 v 3"/></g>
+        </svg>"#;
+        let (min_x, min_y, max_x, max_y) = extract_bounding_box(svg).expect("path bounds");
+        assert_eq!((min_x, min_y, max_x, max_y), (5.0, -3.0, 45.0, 60.0));
+        for (x, y) in [(10.0, 20.0), (30.0, 40.0), (25.0, 35.0), (45.0, 25.0)] {
+            assert!(min_x <= x && x <= max_x && min_y <= y && y <= max_y);
+        }
+
+        assert_eq!(
+            extract_bounding_box(r#"<svg><path d=""/><rect/></svg>"#),
+            None
+        );
+    }
+
+    #[test]
+    fn test_extract_bounding_box_matrix_transform() {
+        let path = r#"<path d="M 0 0 L 10 20"/>"#;
+        let shifted = r#"<path d="M 0 0 L 10 20" transform="matrix(1, 0, 0, 1, 5, -7)"/>"#;
+        assert_eq!(
+            extract_bounding_box(&format!("<svg>{}</svg>", path)),
+            Some((0.0, 0.0, 10.0, 20.0))
+        );
+        assert_eq!(
+            extract_bounding_box(&format!("<svg>{}</svg>", shifted)),
+            Some((5.0, -7.0, 15.0, 13.0))
+        );
+        // Scaled by the path's matrix, then shifted by the group's
+        let nested = r#"<svg><g transform="matrix(1, 0, 0, 1, 100, 0)">
+            <path d="M 0 0 L 10 20" transform="matrix(2, 0, 0, 0.5, 0, 0)"/>
+        </g></svg>"#;
+        assert_eq!(
+            extract_bounding_box(nested),
+            Some((100.0, 0.0, 120.0, 10.0))
+        );
+    }
+
+    #[test]
+    fn test_extract_y_coordinates_arc() {
+        // The radii, rotation and flags of the arc are not coordinates
+        let svg = r#"<svg><path d="M 0 10 A 25 26 30 0 1 50 12 a 5 5 0 1 0 4 -2"/></svg>"#;
+        assert_eq!(extract_y_coordinates(svg), vec![10.0, 12.0, 10.0]);
+        let bbox = extract_bounding_box(svg).expect("path bounds");
+        assert_eq!(bbox, (0.0, 10.0, 54.0, 12.0));
+    }
+
+    #[test]
+    fn test_extract_y_coordinates_horizontal_vertical() {
+        // H only moves X, V only Y, relative commands add to the current point
+        let svg = r#"<svg><path d="M 3 7 H 40 V 20 h -10 v 5.5 l 2 -1.5 Z m 1 1 L 5 6"/></svg>"#;
+        assert_eq!(
+            extract_y_coordinates(svg),
+            vec![7.0, 7.0, 20.0, 20.0, 25.5, 24.0, 8.0, 6.0]
+        );
+        assert_eq!(
+            path_points("M 3 7 H 40 V 20 h -10 v 5.5 l 2 -1.5 Z m 1 1 L 5 6", None),
+            vec![
+                (3.0, 7.0),
+                (40.0, 7.0),
+                (40.0, 20.0),
+                (30.0, 20.0),
+                (30.0, 25.5),
+                (32.0, 24.0),
+                (4.0, 8.0),
+                (5.0, 6.0)
+            ]
+        );
+        // Relative curve control points, and implicit lines after a move
+        assert_eq!(
+            path_points(
+                "m 1 1 2 2 c 1 0 1 1 2 2 s 1 1 2 0",
+                Some((1.0, 0.0, 0.0, 2.0, 0.0, 0.0))
+            ),
+            vec![
+                (1.0, 2.0),
+                (3.0, 6.0),
+                (4.0, 6.0),
+                (4.0, 8.0),
+                (5.0, 10.0),
+                (6.0, 12.0),
+                (7.0, 10.0)
+            ]
+        );
+    }
+
     #[test]
     fn test_adjust_svg_height_basic() {
         // Use single-line SVG to avoid text events
@@ -2013,6 +11757,176 @@ mod tests {
         assert!(adjusted.contains("</g></svg>"), "Missing </g></svg>");
     }
 
+    #[test]
+    fn test_pad_svg_all_sides() {
+        let svg = r#"<svg width="100" height="50" viewBox="0 0 100 50"><path d="M 10 20 L 30 40 Z"/></svg>"#;
+        let padded = pad_svg(svg, 5.0, 2.0, 15.0, 3.0);
+
+        assert!(padded.contains(r#"width="105""#));
+        assert!(padded.contains(r#"height="70""#));
+        assert!(padded.contains(r#"viewBox="0 0 105 70""#));
+        assert!(padded.contains(r#"<g transform="translate(3, 5)"><path"#));
+        assert!(padded.contains("</g></svg>"));
+    }
+
+    #[test]
+    fn test_normalize_viewbox_origin() {
+        let svg = r#"<svg width="100" height="50" viewBox="10 5 100 50"><path d="M 10 5 L 110 55 Z"/></svg>"#;
+        let normalized = normalize_viewbox_origin(svg);
+
+        assert_eq!(
+            normalized,
+            r#"<svg width="100" height="50" viewBox="0 0 100 50"><g transform="translate(-10, -5)"><path d="M 10 5 L 110 55 Z"/></g></svg>"#
+        );
+        // The content keeps its place relative to the canvas
+        assert_eq!(
+            extract_bounding_box(&normalized),
+            Some((0.0, 0.0, 100.0, 50.0))
+        );
+
+        let origin = r#"<svg viewBox="0 0 100 50"><path d="M 0 0 Z"/></svg>"#;
+        assert_eq!(normalize_viewbox_origin(origin), origin);
+        let no_viewbox = r#"<svg width="100"><path d="M 0 0 Z"/></svg>"#;
+        assert_eq!(normalize_viewbox_origin(no_viewbox), no_viewbox);
+    }
+
+    #[test]
+    fn test_split_svg_lines_two_lines() {
+        let svg = r#"<svg xmlns="http://www.w3.org/2000/svg" width="100" height="50" viewBox="0 0 100 50">
+<g transform="translate(0, 5)">
+<path fill="black" d="M 10 0 L 30 12 Z"/>
+<path fill="black" d="M 40 2 L 60 10 Z"/>
+<path fill="none" stroke="black" stroke-width="2" d="M 0 30 L 50 30"/>
+<path fill="black" d="M 5 25 L 20 40 Z"/>
+</g>
+</svg>"#;
+        let lines = split_svg_lines(svg, 20.0);
+        assert_eq!(lines.len(), 2);
+
+        assert_eq!(
+            lines[0],
+            concat!(
+                r#"<svg xmlns="http://www.w3.org/2000/svg" width="50" height="12" viewBox="10 5 50 12">"#,
+                r#"<path fill="black" d="M 10 0 L 30 12 Z" transform="matrix(1, 0, 0, 1, 0, 5)"/>"#,
+                r#"<path fill="black" d="M 40 2 L 60 10 Z" transform="matrix(1, 0, 0, 1, 0, 5)"/>"#,
+                "</svg>"
+            )
+        );
+        assert!(
+            lines[1].contains(r#"viewBox="-1 30 52 15""#),
+            "{}",
+            lines[1]
+        );
+        assert!(lines[1].contains(r#"stroke-width="2""#));
+        assert_eq!(count_svg_paths(&lines[1]), 2);
+
+        assert!(split_svg_lines(svg, 0.0).is_empty());
+        assert!(split_svg_lines("<svg></svg>", 20.0).is_empty());
+    }
+
+    #[test]
+    fn test_split_svg_lines_keeps_rule_only_line() {
+        // A fraction bar or `\hline` alone on its line, stroked as Cairo does
+        let svg = r#"<svg width="100" height="40" viewBox="0 0 100 40">
+<path fill="black" d="M 0 2 L 10 8 Z"/>
+<path fill="none" stroke="black" stroke-width="100" d="M 0 1500 L 2500 1500" transform="matrix(0.02, 0, 0, 0.02, 0, 0)"/>
+</svg>"#;
+        let lines = split_svg_lines(svg, 20.0);
+        assert_eq!(lines.len(), 2);
+        assert!(
+            lines[1].contains(r#"width="52" height="2" viewBox="-1 29 52 2""#),
+            "{}",
+            lines[1]
+        );
+    }
+
+    #[test]
+    fn test_pad_svg_zero_is_noop() {
+        let svg = r#"<svg width="100" height="50" viewBox="0 0 100 50"><path d="M 0 0 Z"/></svg>"#;
+        assert_eq!(pad_svg(svg, 0.0, 0.0, 0.0, 0.0), svg);
+
+        // Bottom/right padding only grows the canvas, no wrapper group
+        let padded = pad_svg(svg, 0.0, 0.0, 10.0, 0.0);
+        assert!(padded.contains(r#"height="60""#));
+        assert!(!padded.contains("<g"));
+    }
+
+    #[test]
+    fn test_embed_formula_into_svg() {
+        let base = r#"<svg width="300" height="200" viewBox="0 0 300 200"><rect x="0" y="0" width="300" height="200"/><circle cx="10" cy="10" r="5"/></svg>"#;
+        let formula = r#"<svg width="40" height="20" viewBox="0 0 40 20" data-dpi="720"><path d="M 1 2 L 3 4 Z"/><g><path d="M 5 6 Z"/></g></svg>"#;
+        let combined = embed_formula_into_svg(base, formula, 120.0, 45.5);
+
+        // Base canvas is untouched
+        assert!(combined.starts_with(r#"<svg width="300" height="200" viewBox="0 0 300 200">"#));
+        assert!(combined.contains(r#"<circle cx="10" cy="10" r="5"/>"#));
+
+        // Formula content is appended as a translated group, without its root <svg>
+        assert!(combined.contains(
+            r#"<g transform="translate(120, 45.5)"><path d="M 1 2 L 3 4 Z"/><g><path d="M 5 6 Z"/></g></g></svg>"#
+        ));
+        assert!(!combined.contains("data-dpi"));
+        assert_eq!(combined.matches("<svg").count(), 1);
+    }
+
+    #[test]
+    fn test_render_result_to_html_figure() {
+        let result = RenderResult::new(
+            r#"<?xml version="1.0"?><svg xmlns="http://www.w3.org/2000/svg" width="120" height="60" viewBox="0 0 120 60"><path d="M 1 2 Z"/></svg>"#.to_string(),
+            RenderMetrics::new(100, 50, 10, 40),
+        );
+        let html = result.to_html_figure("a < b");
+
+        assert!(html.starts_with("<figure><svg"));
+        assert!(!html.contains("<?xml"));
+        assert!(html.contains(
+            r#"width="120" height="60" viewBox="0 0 120 60" role="img" aria-label="a &lt; b">"#
+        ));
+        assert!(!html.contains("vertical-align"));
+        assert!(html.contains(r#"<path d="M 1 2 Z"/></svg>"#));
+        assert!(html.ends_with("<figcaption>a &lt; b</figcaption></figure>"));
+
+        // Empty alt text: no caption
+        let html = result.to_html_figure("");
+        assert!(html.ends_with("</svg></figure>"));
+    }
+
+    #[test]
+    fn test_render_result_to_web_component() {
+        let result = RenderResult::new(
+            r#"<?xml version="1.0"?><svg xmlns="http://www.w3.org/2000/svg" width="120" height="60" viewBox="0 0 120 60"><path d="M 1 2 Z"/></svg>"#.to_string(),
+            RenderMetrics::new(100, 50, 10, 40),
+        );
+        let html = result.to_web_component("math-formula");
+
+        assert!(html.starts_with(
+            r#"<math-formula width="100" height="50" depth="10" ascent="40" style="display: inline-block; vertical-align: -10px"><svg "#
+        ));
+        assert!(!html.contains("<?xml"));
+        assert!(html.contains(r#"viewBox="0 0 120 60" width="100" height="50">"#));
+        assert!(html.ends_with(r#"<path d="M 1 2 Z"/></svg></math-formula>"#));
+    }
+
+    #[test]
+    fn test_adjust_svg_height_preserves_entities_and_cdata() {
+        let svg = r#"<svg width="100" height="50" viewBox="0 0 100 50"><path d="M 10 20 L 30 55.5 Z"/><text>a &amp; b &#60; c</text><style><![CDATA[path { fill: #000; }]]></style></svg>"#;
+        let adjusted = adjust_svg_height_and_center(svg);
+
+        assert!(adjusted.contains(r#"height="56""#));
+        assert!(adjusted.contains("<text>a &amp; b &#60; c</text>"));
+        assert!(adjusted.contains("<style><![CDATA[path { fill: #000; }]]></style>"));
+        assert!(adjusted.ends_with("</g></svg>"));
+    }
+
+    #[test]
+    fn test_adjust_svg_height_unclosed_svg() {
+        // Must terminate even without a closing </svg>
+        let svg =
+            r#"<svg width="100" height="50" viewBox="0 0 100 50"><path d="M 10 20 L 30 55.5 Z"/>"#;
+        let adjusted = adjust_svg_height_and_center(svg);
+        assert!(adjusted.contains(r#"height="56""#));
+    }
+
     #[test]
     fn test_quick_xml_parsing() {
         use quick_xml::events::Event;
@@ -2076,6 +11990,35 @@ mod tests {
         assert_eq!(adjusted2, svg2);
     }
 
+    #[test]
+    fn test_adjust_svg_height_and_center_report() {
+        let svg = r#"<svg width="100" height="50" viewBox="0 0 100 50">
+<path d="M 10 0 L 30 0.01 Z"/>
+</svg>"#;
+        let (adjusted, report) = adjust_svg_height_and_center_report(svg);
+        assert_eq!(adjusted, svg);
+        assert_eq!(
+            report,
+            AdjustReport {
+                original_height: Some(50),
+                new_height: 50,
+                translate_y: 0.0,
+                changed: false,
+            }
+        );
+
+        let (adjusted, report) = adjust_svg_height_and_center_report(COMPLEXE_SVG);
+        assert_eq!(adjusted, adjust_svg_height_and_center(COMPLEXE_SVG));
+        let transform = svg_centering_transform(COMPLEXE_SVG);
+        assert!(report.changed);
+        assert_eq!(report.new_height, transform.new_height);
+        assert_eq!(report.translate_y, transform.translate_y);
+        assert_eq!(
+            report.original_height,
+            Some(transform.original_height.round() as i32)
+        );
+    }
+
     #[test]
     fn test_adjust_svg_height_complex() {
         let svg = r#"<svg width="188" height="39" viewBox="0 0 188 39">
@@ -2090,6 +12033,38 @@ mod tests {
         assert!(adjusted.contains(r#"<g transform="translate(0, "#));
     }
 
+    #[test]
+    fn test_parse_path_numbers_exponents() {
+        assert_eq!(
+            parse_path_numbers("M 1.5e2 2E-1 L 3e+1 .5e1"),
+            vec![150.0, 0.2, 30.0, 5.0]
+        );
+        let svg = r#"<svg><path d="M 0 1.5e2 L 10 -2.5e-1 Z"/></svg>"#;
+        assert_eq!(extract_y_coordinates(svg), vec![150.0, -0.25]);
+        // One value, not `1.23` then `-2`
+        let svg = r#"<svg><path d="M 4 1.23e-2"/></svg>"#;
+        assert_eq!(extract_y_coordinates(svg), vec![0.0123]);
+    }
+
+    #[test]
+    fn test_parse_path_numbers_compact_syntax() {
+        assert_eq!(parse_path_numbers("1.5.5"), vec![1.5, 0.5]);
+        assert_eq!(parse_path_numbers(".5.5"), vec![0.5, 0.5]);
+        assert_eq!(parse_path_numbers("1.2.3"), vec![1.2, 0.3]);
+        assert_eq!(parse_path_numbers("10-5"), vec![10.0, -5.0]);
+        assert_eq!(parse_path_numbers("1-2"), vec![1.0, -2.0]);
+        let svg = r#"<svg><path d="M.5.5L1-2"/></svg>"#;
+        assert_eq!(extract_y_coordinates(svg), vec![0.5, -2.0]);
+        assert_eq!(
+            parse_path_numbers("M10-5L.5.5-1e-2-3"),
+            vec![10.0, -5.0, 0.5, 0.5, -0.01, -3.0]
+        );
+        assert_eq!(
+            parse_path_numbers("M 1,2 L 3 4 Z"),
+            vec![1.0, 2.0, 3.0, 4.0]
+        );
+    }
+
     #[test]
     fn test_extract_complexe_svg() {
         let svg = COMPLEXE_SVG;
@@ -2101,6 +12076,173 @@ mod tests {
         assert!(max_y <= 40.0);
     }
 
+    #[test]
+    fn test_extract_svg_paths_complexe_svg() {
+        let paths = extract_svg_paths(COMPLEXE_SVG);
+        assert_eq!(paths.len(), 20);
+        assert!(paths.iter().all(|path| path.d.starts_with("M ")));
+
+        let strokes: Vec<&SvgPath> = paths.iter().filter(|path| path.stroke.is_some()).collect();
+        assert_eq!(strokes.len(), 1);
+        assert_eq!(strokes[0].fill.as_deref(), Some("none"));
+        assert_eq!(strokes[0].stroke.as_deref(), Some("rgb(0%, 0%, 0%)"));
+        assert_eq!(strokes[0].transform, Some((0.02, 0.0, 0.0, 0.02, 0.0, 0.0)));
+
+        assert_eq!(paths[0].fill.as_deref(), Some("rgb(0%, 0%, 0%)"));
+        assert_eq!(paths[0].transform, None);
+    }
+
+    #[test]
+    fn test_count_svg_paths_complexe_svg() {
+        // 19 glyphs and the fraction bar
+        assert_eq!(count_svg_paths(COMPLEXE_SVG), 20);
+
+        let result =
+            RenderResult::new(COMPLEXE_SVG.to_string(), RenderMetrics::new(188, 39, 0, 39));
+        assert_eq!(result.rendered_glyph_count, 20);
+        assert_eq!(count_svg_paths("<svg></svg>"), 0);
+    }
+
+    #[test]
+    fn test_strokes_to_fills_complexe_svg() {
+        let filled = strokes_to_fills(COMPLEXE_SVG);
+        let paths = extract_svg_paths(&filled);
+        assert_eq!(paths.len(), 20);
+        assert!(paths.iter().all(|path| path.stroke.is_none()));
+        assert!(!filled.contains("stroke-width"));
+
+        // The fraction bar (9th path): 50 x 1.32 in user units, centered on y = 20.1211
+        let bar = &paths[8];
+        assert_eq!(bar.fill.as_deref(), Some("rgb(0%, 0%, 0%)"));
+        assert_eq!(bar.d.matches('L').count(), 3);
+        assert_eq!(bar.transform, None);
+        let bounds = extract_bounding_box(&format!(r#"<svg><path d="{}"/></svg>"#, bar.d))
+            .expect("bar bounds");
+        assert!((bounds.0 - 50.3516).abs() < 1e-3);
+        assert!((bounds.2 - 100.3516).abs() < 1e-3);
+        assert!((bounds.1 - 19.4611).abs() < 1e-3);
+        assert!((bounds.3 - 20.7811).abs() < 1e-3);
+
+        // Filled paths are untouched
+        assert_eq!(extract_svg_paths(COMPLEXE_SVG)[0].d, paths[0].d);
+    }
+
+    #[test]
+    fn test_minify_svg() {
+        let minified = minify_svg(COMPLEXE_SVG);
+        // Each path loses ` fill-opacity="1"` and 11 bytes of its color
+        let paths = COMPLEXE_SVG.matches("<path").count();
+        assert!(COMPLEXE_SVG.len() - minified.len() >= paths * (17 + 11));
+        assert!(!minified.contains("fill-opacity"));
+        assert!(!minified.contains("rgb("));
+        assert!(!minified.contains(">\n<"));
+
+        // Path data is kept exactly
+        let original: Vec<String> = extract_svg_paths(COMPLEXE_SVG)
+            .into_iter()
+            .map(|path| path.d)
+            .collect();
+        let kept: Vec<String> = extract_svg_paths(&minified)
+            .into_iter()
+            .map(|path| path.d)
+            .collect();
+        assert!(!original.is_empty());
+        assert_eq!(kept, original);
+        assert_eq!(minify_svg(&minified), minified);
+    }
+
+    #[test]
+    fn test_minify_svg_attributes() {
+        assert_eq!(
+            minify_svg(
+                r##"<svg> <path fill="rgb(100%, 53.333333%, 0%)" stroke="#AABBCC" stroke-opacity="1.0" opacity="0.5" d="M 0 0"/> <text> a b </text> </svg>"##
+            ),
+            r##"<svg><path fill="#f80" stroke="#abc" opacity="0.5" d="M 0 0"/><text> a b </text></svg>"##
+        );
+        // Colors that are not rgb() or #rrggbb, and non-color attributes, are kept
+        assert_eq!(
+            minify_svg(r##"<svg><path fill="none" stroke="url(#g)" id="rgb(0, 0, 0)"/></svg>"##),
+            r##"<svg><path fill="none" stroke="url(#g)" id="rgb(0, 0, 0)"/></svg>"##
+        );
+        assert_eq!(short_color("rgb(12, 34, 56)").as_deref(), Some("#0c2238"));
+        assert_eq!(short_color("rgb(0%, 0%)"), None);
+        assert_eq!(short_color("rgb(300, 0, 0)"), None);
+        assert_eq!(minify_svg("<svg><path"), "<svg><path");
+    }
+
+    #[test]
+    fn test_minify_svg_keeps_inherited_opacity_resets() {
+        // A child resetting an inherited opacity to 1 keeps the reset
+        assert_eq!(
+            minify_svg(
+                r#"<svg><g fill-opacity="0.5" stroke-opacity="1"><path fill-opacity="1" stroke-opacity="1"/><g><path fill-opacity="1.0"/></g></g><path fill-opacity="1"/></svg>"#
+            ),
+            r#"<svg><g fill-opacity="0.5"><path fill-opacity="1"/><g><path fill-opacity="1.0"/></g></g><path/></svg>"#
+        );
+        // And so does one under a group setting it in a style
+        assert_eq!(
+            minify_svg(
+                r#"<svg><g style="stroke-opacity: 0.2"><path stroke-opacity="1" opacity="1"/></g></svg>"#
+            ),
+            r#"<svg><g style="stroke-opacity: 0.2"><path stroke-opacity="1"/></g></svg>"#
+        );
+    }
+
+    #[test]
+    fn test_render_minify() {
+        let _g = crate::shim::lock_test();
+        crate::shim::set_init_succeed(true);
+        crate::shim::set_parse_succeed(true);
+        crate::shim::set_return_empty(false);
+        crate::shim::set_buffer(COMPLEXE_SVG.as_bytes());
+
+        let m = MicroTex::new().expect("init ok");
+        let plain = m.render("x", &RenderConfig::default()).unwrap();
+        let config = RenderConfig {
+            minify: true,
+            ..Default::default()
+        };
+        let minified = m.render("x", &config).unwrap();
+        assert!(minified.len() < plain.len());
+        assert!(!minified.contains("fill-opacity"));
+        assert_eq!(
+            extract_svg_paths(&minified)
+                .into_iter()
+                .map(|path| path.d)
+                .collect::<Vec<_>>(),
+            extract_svg_paths(&plain)
+                .into_iter()
+                .map(|path| path.d)
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_canonicalize_svg_structurally_equal() {
+        let a = r#"<?xml version="1.0" encoding="UTF-8"?>
+<!-- rendered by MicroTeX -->
+<svg xmlns="http://www.w3.org/2000/svg" xmlns:xlink="http://www.w3.org/1999/xlink" version="1.1" width="188" height="39" viewBox="0 0 188 39" data-dpi="720">
+  <path fill="rgb(0%, 0%, 0%)" d="M 10.4804691 23.28125 L 6.621094 14.480469 Z"></path>
+  <path fill="none" stroke-width="66" stroke="rgb(0%, 0%, 0%)" d="M 2517.578181 1006.05471 L 5017.578237 1006.05471 " transform="matrix(0.02, 0, 0, 0.02, 0, 0)"/>
+</svg>
+"#;
+        let b = r#"<svg data-dpi="720" height="39.0" viewBox="0 0 188 39" width="188" xmlns="http://www.w3.org/2000/svg"><path d="M 10.480469 23.28125  L 6.621094 14.480469 Z" fill="rgb(0%,0%,0%)"/><path transform="matrix(0.02,0,0,0.02,-0,0)" d="M 2517.578181 1006.05471 L 5017.578237 1006.05471" stroke="rgb(0%, 0%, 0%)" stroke-width="66.000" fill="none"/></svg>"#;
+
+        let canonical = canonicalize_svg(a);
+        assert_eq!(canonical, canonicalize_svg(b));
+        // Canonicalization is idempotent
+        assert_eq!(canonicalize_svg(&canonical), canonical);
+
+        assert!(canonical.starts_with(r#"<svg data-dpi="720" height="39" viewBox="0 0 188 39" width="188" xmlns="http://www.w3.org/2000/svg">"#));
+        assert!(canonical.contains(r#"d="M 10.48 23.281 L 6.621 14.48 Z""#));
+        assert!(canonical.contains(r#"transform="matrix(0.02, 0, 0, 0.02, 0, 0)""#));
+        assert!(!canonical.contains("xlink"));
+        assert!(!canonical.contains("version"));
+
+        // A different drawing canonicalizes differently
+        assert_ne!(canonical, canonicalize_svg(&b.replace("10.480469", "11")));
+    }
+
     #[test]
     fn test_transformation_complete() {
         let svg = COMPLEXE_SVG;
@@ -0,0 +1,67 @@
+//! Allocation counts of the SVG post-processing passes.
+//!
+//! Uses a counting global allocator, so it lives in its own test binary.
+
+use microtex_rs::{add_dpi_to_svg, adjust_svg_height_and_center, postprocess_svg_into};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+struct CountingAllocator;
+
+thread_local! {
+    // Per thread, so tests running in parallel don't disturb each other
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+        System.realloc(ptr, layout, new_size)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+fn count_allocations(f: impl FnOnce()) -> usize {
+    let before = ALLOCATIONS.with(Cell::get);
+    f();
+    ALLOCATIONS.with(Cell::get) - before
+}
+
+const SVG: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<svg xmlns="http://www.w3.org/2000/svg" width="188" height="39" viewBox="0 0 188 39">
+<path fill="rgb(0%, 0%, 0%)" d="M 10.480469 23.28125 L 6.621094 14.480469 L 2.71875 23.28125 Z"/>
+<path fill="none" stroke="rgb(0%, 0%, 0%)" stroke-width="66" d="M 2517.578181 1006.05471 L 5017.578237 1006.05471 " transform="matrix(0.02, 0, 0, 0.02, 0, 0)"/>
+<path fill="rgb(0%, 0%, 0%)" d="M 61.191406 34.5 L 61.191406 27.640625 L 56.390625 39.121094 Z"/>
+</svg>
+"#;
+
+#[test]
+fn test_postprocess_into_reused_buffer_allocates_less() {
+    let mut out = String::new();
+    // Warm up: the first call grows the buffer to the output size
+    postprocess_svg_into(SVG, 720, &mut out);
+
+    let reused = count_allocations(|| postprocess_svg_into(SVG, 720, &mut out));
+    let chained = count_allocations(|| {
+        let svg = adjust_svg_height_and_center(&add_dpi_to_svg(SVG, 720));
+        assert_eq!(svg, out);
+    });
+
+    assert!(
+        reused < chained,
+        "reused buffer made {} allocations, chained passes {}",
+        reused,
+        chained
+    );
+}
@@ -46,7 +46,7 @@ fn test_render_config_customization() {
     let mut config = RenderConfig::default();
     config.dpi = 300;
     config.line_width = 15.0;
-    config.text_color = 0xffffffff;
+    config.text_color = 0xffffffff.into();
 
     assert_eq!(config.dpi, 300);
     assert_eq!(config.line_width, 15.0);
@@ -58,8 +58,14 @@ fn test_render_error_display() {
     let error = RenderError::InitializationFailed;
     assert!(format!("{}", error).contains("font metadata"));
 
-    let error = RenderError::ParseRenderFailed;
-    assert!(format!("{}", error).contains("parse and render"));
+    let error = RenderError::ParseRenderFailed(String::new());
+    assert_eq!(
+        format!("{}", error),
+        "failed to parse and render LaTeX source"
+    );
+
+    let error = RenderError::ParseRenderFailed("'foo' is not defined as a symbol".to_string());
+    assert!(format!("{}", error).ends_with(": 'foo' is not defined as a symbol"));
 
     let error = RenderError::EmptyOutput;
     assert!(format!("{}", error).contains("empty"));
@@ -111,3 +117,127 @@ fn test_multiple_renders_real_c_plus_plus() {
     // At least the first should succeed if no crash
     assert!(result1.is_ok(), "First render should succeed");
 }
+
+/// Wider relation spacing (`\thickmuskip`) must widen `a = b`.
+#[test]
+fn test_rel_spacing_widens_relations() {
+    let renderer = MicroTex::new().expect("MicroTex init");
+    let width = |rel_spacing: f32| {
+        let config = RenderConfig {
+            rel_spacing,
+            ..Default::default()
+        };
+        renderer
+            .render_to_svg_with_metrics(r#"\[a = b\]"#, &config)
+            .expect("render a = b")
+            .metrics
+            .width
+    };
+
+    let standard = width(5.0);
+    let wide = width(18.0);
+    assert!(
+        wide > standard,
+        "width with 18mu ({}) should exceed width with 5mu ({})",
+        wide,
+        standard
+    );
+}
+
+/// Forcing limits above and below an inline `\sum` must make it taller
+/// than forcing them beside it.
+#[test]
+fn test_limits_mode_stacks_inline_limits() {
+    let renderer = MicroTex::new().expect("MicroTex init");
+    let height = |limits_mode: LimitsMode| {
+        let config = RenderConfig {
+            limits_mode,
+            ..Default::default()
+        };
+        renderer
+            .render_to_svg_with_metrics(r#"$\sum_{i=1}^{n} i$"#, &config)
+            .expect("render inline sum")
+            .metrics
+            .height
+    };
+
+    let stacked = height(LimitsMode::Always);
+    let beside = height(LimitsMode::Never);
+    assert!(
+        stacked > beside,
+        "height with stacked limits ({}) should exceed height with limits beside ({})",
+        stacked,
+        beside
+    );
+}
+
+/// Bold glyphs are wider than regular ones, so a bold formula must be wider.
+#[test]
+fn test_bold_widens_formula() {
+    let renderer = MicroTex::new().expect("MicroTex init");
+    let width = |bold: bool| {
+        let config = RenderConfig {
+            bold,
+            ..Default::default()
+        };
+        renderer
+            .render_to_svg_with_metrics(r#"\[xyz = 123\]"#, &config)
+            .expect("render xyz = 123")
+            .metrics
+            .width
+    };
+
+    let regular = width(false);
+    let bold = width(true);
+    assert!(
+        bold > regular,
+        "bold width ({}) should exceed regular width ({})",
+        bold,
+        regular
+    );
+}
+
+/// The label of an `\overbrace` must not be clipped at the top of the SVG.
+#[test]
+fn test_overbrace_label_not_clipped() {
+    let renderer = MicroTex::new().expect("MicroTex init");
+    let result = renderer
+        .render_to_svg_with_metrics(r#"\[\overbrace{x+y}^{n}\]"#, &RenderConfig::default())
+        .expect("render overbrace");
+
+    let (_, min_y, _, _) = result.precise_bounds.expect("precise bounds");
+    assert!(min_y >= 0.0, "overbrace label clipped: min_y = {}", min_y);
+}
+
+/// The tokens added by `render_diff` must be filled in green, the others
+/// in the text color.
+#[test]
+fn test_render_diff_highlights_in_green() {
+    let renderer = MicroTex::new().expect("MicroTex init");
+    let config = RenderConfig::default();
+    // Cairo writes `rgb(0%, 100%, 0%)` or `rgb(0%,100%,0%)` depending on its version
+    let has_green = |svg: &str| svg.replace(' ', "").contains("rgb(0%,100%,0%)");
+
+    let svg = renderer
+        .render_diff(r#"\[a + b\]"#, r#"\[a + c\]"#, &config)
+        .expect("render diff");
+    assert!(has_green(&svg), "added token not highlighted: {}", svg);
+
+    let svg = renderer
+        .render_diff(r#"\[a + b\]"#, r#"\[a + b\]"#, &config)
+        .expect("render identical diff");
+    assert!(!has_green(&svg), "unchanged formula highlighted: {}", svg);
+}
+
+/// Formulas wrapped with `wrap_in_delimiters` render in both math modes.
+#[test]
+fn test_wrap_in_delimiters_renders() {
+    let renderer = MicroTex::new().expect("MicroTex init");
+    let config = RenderConfig::default();
+    for display in [true, false] {
+        let svg = renderer
+            .render(&wrap_in_delimiters(" x^2 + y ", display), &config)
+            .expect("render wrapped formula");
+        assert!(svg.contains("<svg"), "display = {}: {}", display, svg);
+    }
+}